@@ -0,0 +1,308 @@
+//! Extra Telegram accounts used as pooled storage targets.
+//!
+//! The account logged in during onboarding is account 0 and keeps its session in the
+//! app data dir. Every extra account gets its own session database under
+//! `accounts/<id>/`. The `account_placement` config key decides which account receives
+//! each upload, and mirrored items get a second copy on another account.
+
+use crate::database::{CloudCopy, Database};
+use crate::telegram::TelegramService;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+/// The onboarding account, whose session lives directly in the app data dir
+pub const PRIMARY_ACCOUNT: i64 = 0;
+
+/// "primary" (default), "round_robin", "album" or "mirror"
+pub const ACCOUNT_PLACEMENT_KEY: &str = "account_placement";
+/// Account receiving mirror copies; any other account when unset
+pub const ACCOUNT_MIRROR_KEY: &str = "account_mirror_target";
+/// How long opening the saved accounts waits for one to connect
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// How uploads are spread over the registered accounts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Placement {
+    /// Everything goes to the onboarding account
+    #[default]
+    Primary,
+    /// Accounts take turns by queue order
+    RoundRobin,
+    /// Items go to the account assigned to their album, else the primary one
+    Album,
+    /// Everything goes to the primary account and is copied to a second one
+    Mirror,
+}
+
+impl Placement {
+    fn parse(value: &str) -> Self {
+        match value {
+            "round_robin" => Self::RoundRobin,
+            "album" => Self::Album,
+            "mirror" => Self::Mirror,
+            _ => Self::Primary,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Primary => "primary",
+            Self::RoundRobin => "round_robin",
+            Self::Album => "album",
+            Self::Mirror => "mirror",
+        }
+    }
+}
+
+/// Placement settings as shown in the UI.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlacementSettings {
+    pub mode: Placement,
+    pub mirror_account_id: Option<i64>,
+}
+
+impl PlacementSettings {
+    pub fn load(db: &Database) -> Self {
+        let get = |key: &str| db.get_config(key).ok().flatten().filter(|v| !v.is_empty());
+        Self {
+            mode: get(ACCOUNT_PLACEMENT_KEY)
+                .map(|v| Placement::parse(&v))
+                .unwrap_or_default(),
+            mirror_account_id: get(ACCOUNT_MIRROR_KEY).and_then(|v| v.parse().ok()),
+        }
+    }
+
+    pub fn save(&self, db: &Database) -> Result<(), String> {
+        db.set_config(ACCOUNT_PLACEMENT_KEY, self.mode.as_str())
+            .map_err(|e| e.to_string())?;
+        match self.mirror_account_id {
+            Some(id) => db.set_config(ACCOUNT_MIRROR_KEY, &id.to_string()),
+            None => db.remove_config(ACCOUNT_MIRROR_KEY),
+        }
+        .map_err(|e| e.to_string())
+    }
+}
+
+/// Account for the main upload of the `seq`-th queue item. `accounts` is sorted and
+/// includes the primary account.
+pub fn choose_account(
+    mode: Placement,
+    accounts: &[i64],
+    seq: i64,
+    album_account: Option<i64>,
+) -> i64 {
+    match mode {
+        Placement::RoundRobin if !accounts.is_empty() => {
+            accounts[seq.rem_euclid(accounts.len() as i64) as usize]
+        }
+        Placement::Album => album_account
+            .filter(|id| accounts.contains(id))
+            .unwrap_or(PRIMARY_ACCOUNT),
+        _ => PRIMARY_ACCOUNT,
+    }
+}
+
+/// Account for the mirror copy of an upload to `main`: the configured one if it is
+/// registered and differs from `main`, else the first other account.
+pub fn mirror_account(accounts: &[i64], main: i64, configured: Option<i64>) -> Option<i64> {
+    configured
+        .filter(|id| *id != main && accounts.contains(id))
+        .or_else(|| accounts.iter().copied().find(|id| *id != main))
+}
+
+/// Directory holding the session database of an account.
+pub fn session_dir(app_data_dir: &Path, account_id: i64) -> PathBuf {
+    if account_id == PRIMARY_ACCOUNT {
+        app_data_dir.to_path_buf()
+    } else {
+        app_data_dir.join("accounts").join(account_id.to_string())
+    }
+}
+
+/// Session directories of every account saved in the library, primary first.
+pub fn session_dirs(db: &Database, app_data_dir: &Path) -> Vec<PathBuf> {
+    let mut dirs = vec![session_dir(app_data_dir, PRIMARY_ACCOUNT)];
+    match db.list_telegram_accounts() {
+        Ok(accounts) => dirs.extend(
+            accounts
+                .into_iter()
+                .map(|account| session_dir(app_data_dir, account.id)),
+        ),
+        Err(e) => log::warn!("Failed to load Telegram accounts: {}", e),
+    }
+    dirs
+}
+
+/// Connected services for every registered account.
+pub struct AccountPool {
+    primary: Arc<TelegramService>,
+    extra: Mutex<HashMap<i64, Arc<TelegramService>>>,
+}
+
+impl AccountPool {
+    pub fn new(primary: Arc<TelegramService>) -> Self {
+        Self {
+            primary,
+            extra: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn primary(&self) -> &Arc<TelegramService> {
+        &self.primary
+    }
+
+    pub async fn get(&self, account_id: i64) -> Option<Arc<TelegramService>> {
+        if account_id == PRIMARY_ACCOUNT {
+            Some(self.primary.clone())
+        } else {
+            self.extra.lock().await.get(&account_id).cloned()
+        }
+    }
+
+    /// IDs of every account, primary first.
+    pub async fn ids(&self) -> Vec<i64> {
+        let mut ids: Vec<i64> = self.extra.lock().await.keys().copied().collect();
+        ids.push(PRIMARY_ACCOUNT);
+        ids.sort_unstable();
+        ids
+    }
+
+    /// Create the service for an extra account and connect it with its own session.
+    pub async fn open(
+        &self,
+        account_id: i64,
+        app_data_dir: &Path,
+    ) -> Result<Arc<TelegramService>, String> {
+        let dir = session_dir(app_data_dir, account_id);
+        std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        let service = Arc::new(self.primary.linked().await);
+        self.extra.lock().await.insert(account_id, service.clone());
+        if service.has_credentials().await {
+            service.connect(dir).await.map_err(|e| e.to_string())?;
+        }
+        Ok(service)
+    }
+
+    pub async fn close(&self, account_id: i64) -> Option<Arc<TelegramService>> {
+        self.extra.lock().await.remove(&account_id)
+    }
+
+    /// Open every account saved in the library, reconnecting services that are already
+    /// open but offline. Accounts connect side by side, each given [`CONNECT_TIMEOUT`];
+    /// failures are logged so one broken session doesn't keep the others offline.
+    pub async fn open_saved(&self, db: &Database, app_data_dir: &Path) {
+        let accounts = match db.list_telegram_accounts() {
+            Ok(accounts) => accounts,
+            Err(e) => {
+                log::warn!("Failed to load Telegram accounts: {}", e);
+                return;
+            }
+        };
+        let connects = accounts.iter().map(|account| async move {
+            let connect = async {
+                match self.get(account.id).await {
+                    Some(service) if service.is_connected().await => Ok(()),
+                    Some(service) if service.has_credentials().await => service
+                        .connect(session_dir(app_data_dir, account.id))
+                        .await
+                        .map_err(|e| e.to_string()),
+                    Some(_) => Ok(()),
+                    None => self.open(account.id, app_data_dir).await.map(|_| ()),
+                }
+            };
+            // A timed-out account stays registered offline; the next call retries it
+            let result = tokio::time::timeout(CONNECT_TIMEOUT, connect)
+                .await
+                .unwrap_or_else(|_| {
+                    Err(format!(
+                        "no answer within {} seconds",
+                        CONNECT_TIMEOUT.as_secs()
+                    ))
+                });
+            if let Err(e) = result {
+                log::warn!(
+                    "Failed to connect Telegram account '{}': {}",
+                    account.label,
+                    e
+                );
+            }
+        });
+        futures_util::future::join_all(connects).await;
+    }
+
+    /// Disconnect every account, primary included, so their sessions can be sealed.
+    pub async fn disconnect_all(&self) {
+        self.primary.disconnect().await;
+        for service in self.extra.lock().await.values() {
+            service.disconnect().await;
+        }
+    }
+
+    pub async fn set_credentials(&self, api_id: i32, api_hash: &str) {
+        for service in self.extra.lock().await.values() {
+            service.set_credentials(api_id, api_hash.to_string()).await;
+        }
+    }
+
+    pub async fn set_proxy_url(&self, url: Option<String>) {
+        for service in self.extra.lock().await.values() {
+            service.set_proxy_url(url.clone()).await;
+        }
+    }
+
+    /// Delete cloud copies from the accounts holding them, returning the copies that
+    /// are gone.
+    pub async fn delete_copies(&self, copies: &[CloudCopy]) -> Vec<CloudCopy> {
+        let mut deleted = Vec::new();
+        for copy in copies {
+            let Some(service) = self.get(copy.account_id).await else {
+                log::warn!(
+                    "Telegram account {} is not connected; leaving {} message(s)",
+                    copy.account_id,
+                    copy.message_ids.len()
+                );
+                continue;
+            };
+            match service.delete_messages(&copy.message_ids).await {
+                Ok(_) => deleted.push(copy.clone()),
+                Err(e) => log::warn!(
+                    "Failed to delete messages from account {}: {}",
+                    copy.account_id,
+                    e
+                ),
+            }
+        }
+        deleted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_choose_account() {
+        let accounts = [0, 3, 7];
+        assert_eq!(choose_account(Placement::Primary, &accounts, 5, Some(3)), 0);
+        assert_eq!(choose_account(Placement::RoundRobin, &accounts, 4, None), 3);
+        assert_eq!(choose_account(Placement::RoundRobin, &accounts, 6, None), 0);
+        assert_eq!(choose_account(Placement::Album, &accounts, 1, Some(7)), 7);
+        // An album pointing at a removed account falls back to the primary one
+        assert_eq!(choose_account(Placement::Album, &accounts, 1, Some(9)), 0);
+        assert_eq!(choose_account(Placement::Mirror, &accounts, 2, None), 0);
+    }
+
+    #[test]
+    fn test_mirror_account() {
+        assert_eq!(mirror_account(&[0, 3, 7], 0, Some(7)), Some(7));
+        assert_eq!(mirror_account(&[0, 3, 7], 0, Some(9)), Some(3));
+        assert_eq!(mirror_account(&[0, 3, 7], 3, Some(3)), Some(0));
+        assert_eq!(mirror_account(&[0], 0, None), None);
+    }
+}
@@ -2,6 +2,8 @@ use crate::ai::arcface::ArcFace;
 use crate::ai::object_detection;
 use crate::ai::FaceDetector;
 use crate::database::Database;
+use crate::local_originals::PlainOriginal;
+use crate::security::RuntimeState;
 use image::GenericImageView;
 use std::sync::Arc;
 use std::time::{Duration as StdDuration, Instant};
@@ -14,6 +16,8 @@ pub struct AiWorker {
     detector: Option<Arc<Mutex<FaceDetector>>>,
     arcface: Arc<Mutex<Option<ArcFace>>>, // Lazy load or load at startup
     models_dir: std::path::PathBuf,
+    /// Keys for reading encrypted originals
+    security_runtime: Arc<Mutex<RuntimeState>>,
 }
 
 impl AiWorker {
@@ -21,12 +25,14 @@ impl AiWorker {
         db: Arc<Database>,
         detector: Option<Arc<Mutex<FaceDetector>>>,
         models_dir: std::path::PathBuf,
+        security_runtime: Arc<Mutex<RuntimeState>>,
     ) -> Self {
         Self {
             db,
             detector,
             arcface: Arc::new(Mutex::new(None)),
             models_dir,
+            security_runtime,
         }
     }
 
@@ -217,6 +223,23 @@ impl AiWorker {
                     continue;
                 }
 
+                // Encrypted originals are scanned from a decrypted copy
+                let keys = self.security_runtime.lock().await.read_keys();
+                let plain = match PlainOriginal::open(&path, &keys) {
+                    Ok(plain) => plain,
+                    Err(e) if keys.is_empty() => {
+                        log::debug!("Waiting for unlock to scan {}: {}", item.file_path, e);
+                        sleep(Duration::from_secs(5)).await;
+                        continue;
+                    }
+                    Err(e) => {
+                        log::error!("Failed to read {} for AI scan: {}", item.file_path, e);
+                        let _ = self.db.mark_media_scan_failed(item.id);
+                        continue;
+                    }
+                };
+                let path = plain.path().to_path_buf();
+
                 if face_enabled {
                     if let Some(detector) = &self.detector {
                         let detector = detector.clone();
@@ -236,7 +259,7 @@ impl AiWorker {
                                         println!("Failed to save faces to DB: {}", e);
                                     }
 
-                                    match image::open(&item.file_path) {
+                                    match image::open(&path) {
                                         Ok(img) => {
                                             if let Ok(db_faces) = self.db.get_all_faces_for_media(item.id) {
                                                 let arcface_clone = self.arcface.clone();
@@ -0,0 +1,237 @@
+//! Grouping of finished uploads into Telegram albums.
+//!
+//! Sending every file as its own message is what trips FLOOD_WAIT during bulk imports.
+//! Uploads whose chunks are done join an [`AlbumBatcher`] instead; the batch goes out as
+//! one grouped send once it holds [`MAX_ALBUM_ITEMS`], or when no upload has joined for
+//! the linger time. Each caller gets back only its own item's result, so a failure
+//! inside a batch is handled per item.
+
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::{oneshot, Notify};
+use tokio::time::Instant;
+
+/// Telegram's limit for media in one grouped message
+pub const MAX_ALBUM_ITEMS: usize = 10;
+
+struct Entry<T, R> {
+    item: T,
+    reply: oneshot::Sender<R>,
+}
+
+struct Pending<T, R> {
+    entries: Vec<Entry<T, R>>,
+    first_joined: Instant,
+    last_joined: Instant,
+}
+
+pub struct AlbumBatcher<T, R> {
+    pending: Mutex<Pending<T, R>>,
+    joined: Notify,
+    /// How long a batch waits for the next upload to join
+    linger: Duration,
+    /// Longest a batch is held back after its first item joined
+    max_wait: Duration,
+}
+
+impl<T, R> AlbumBatcher<T, R> {
+    pub fn new(linger: Duration, max_wait: Duration) -> Self {
+        let now = Instant::now();
+        Self {
+            pending: Mutex::new(Pending {
+                entries: Vec::new(),
+                first_joined: now,
+                last_joined: now,
+            }),
+            joined: Notify::new(),
+            linger,
+            max_wait,
+        }
+    }
+
+    /// Add `item` to the next batch and wait for its result. Whichever caller notices a
+    /// batch is due sends it with `flush`, which must return one result per item in
+    /// order. The send runs as a task of its own, so dropping that caller doesn't take
+    /// the rest of the batch with it. `None` means the item's result was lost because
+    /// the send panicked.
+    pub async fn submit<F, Fut>(&self, item: T, flush: F) -> Option<R>
+    where
+        F: Fn(Vec<T>) -> Fut,
+        Fut: Future<Output = Vec<R>> + Send + 'static,
+        R: Send + 'static,
+    {
+        let (reply, mut result) = oneshot::channel();
+        {
+            let mut pending = self.lock();
+            let now = Instant::now();
+            if pending.entries.is_empty() {
+                pending.first_joined = now;
+            }
+            pending.last_joined = now;
+            pending.entries.push(Entry { item, reply });
+        }
+        self.joined.notify_waiters();
+
+        loop {
+            let (batch, due) = self.take_due_batch();
+            if let Some(batch) = batch {
+                Self::send(batch, &flush);
+                continue;
+            }
+            tokio::select! {
+                biased;
+                received = &mut result => return received.ok(),
+                _ = tokio::time::sleep_until(due) => {}
+                _ = self.joined.notified() => {}
+            }
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Pending<T, R>> {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Take the pending batch if it is full or its wait is over, else report when it
+    /// will be due.
+    fn take_due_batch(&self) -> (Option<Vec<Entry<T, R>>>, Instant) {
+        let mut pending = self.lock();
+        // Callers dropped while waiting (cancelled uploads) leave the batch
+        pending.entries.retain(|entry| !entry.reply.is_closed());
+        let due = (pending.last_joined + self.linger).min(pending.first_joined + self.max_wait);
+        if pending.entries.is_empty() {
+            return (None, due);
+        }
+        if pending.entries.len() < MAX_ALBUM_ITEMS && Instant::now() < due {
+            return (None, due);
+        }
+        let count = pending.entries.len().min(MAX_ALBUM_ITEMS);
+        let batch: Vec<_> = pending.entries.drain(..count).collect();
+        // Leftovers start a fresh wait
+        let now = Instant::now();
+        pending.first_joined = now;
+        pending.last_joined = now;
+        (Some(batch), due)
+    }
+
+    fn send<F, Fut>(batch: Vec<Entry<T, R>>, flush: &F)
+    where
+        F: Fn(Vec<T>) -> Fut,
+        Fut: Future<Output = Vec<R>> + Send + 'static,
+        R: Send + 'static,
+    {
+        let (items, replies): (Vec<T>, Vec<oneshot::Sender<R>>) = batch
+            .into_iter()
+            .map(|entry| (entry.item, entry.reply))
+            .unzip();
+        let sent = flush(items);
+        tokio::spawn(async move {
+            for (reply, result) in replies.into_iter().zip(sent.await) {
+                let _ = reply.send(result);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    fn batcher() -> Arc<AlbumBatcher<u32, u32>> {
+        Arc::new(AlbumBatcher::new(
+            Duration::from_millis(50),
+            Duration::from_secs(5),
+        ))
+    }
+
+    /// Submit `items` concurrently, returning each result and the batch sizes sent.
+    async fn submit_all(
+        batcher: Arc<AlbumBatcher<u32, u32>>,
+        items: u32,
+    ) -> (Vec<u32>, Vec<usize>) {
+        let sizes = Arc::new(Mutex::new(Vec::new()));
+        let tasks: Vec<_> = (0..items)
+            .map(|item| {
+                let batcher = batcher.clone();
+                let sizes = sizes.clone();
+                tokio::spawn(async move {
+                    batcher
+                        .submit(item, |batch: Vec<u32>| {
+                            sizes.lock().unwrap().push(batch.len());
+                            async move { batch.into_iter().map(|i| i * 10).collect() }
+                        })
+                        .await
+                })
+            })
+            .collect();
+        let mut results = Vec::new();
+        for task in tasks {
+            results.push(task.await.unwrap().unwrap());
+        }
+        let sizes = sizes.lock().unwrap().clone();
+        (results, sizes)
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_items_share_a_batch() {
+        let (results, sizes) = submit_all(batcher(), 4).await;
+        assert_eq!(results, vec![0, 10, 20, 30]);
+        assert_eq!(sizes, vec![4]);
+    }
+
+    #[tokio::test]
+    async fn test_batches_are_capped() {
+        let (results, mut sizes) = submit_all(batcher(), 13).await;
+        assert_eq!(results.len(), 13);
+        sizes.sort_unstable();
+        assert_eq!(sizes, vec![3, MAX_ALBUM_ITEMS]);
+    }
+
+    #[tokio::test]
+    async fn test_dropped_caller_leaves_batch() {
+        let batcher = batcher();
+        let dropped = batcher.clone();
+        let abandoned = tokio::spawn(async move {
+            dropped
+                .submit(1, |batch: Vec<u32>| async move { batch })
+                .await
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        abandoned.abort();
+        let _ = abandoned.await;
+
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let recorded = sent.clone();
+        let result = batcher
+            .submit(2, move |batch: Vec<u32>| {
+                recorded.lock().unwrap().extend(batch.clone());
+                async move { batch }
+            })
+            .await;
+        assert_eq!(result, Some(2));
+        assert_eq!(*sent.lock().unwrap(), vec![2]);
+    }
+
+    #[tokio::test]
+    async fn test_batch_outlives_the_caller_sending_it() {
+        let batcher = batcher();
+        let slow_flush = |batch: Vec<u32>| async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            batch
+        };
+        let tasks: Vec<_> = (0..2)
+            .map(|item| {
+                let batcher = batcher.clone();
+                tokio::spawn(async move { batcher.submit(item, slow_flush).await })
+            })
+            .collect();
+        // Both items are in the batch being sent by now; drop one of the callers
+        tokio::time::sleep(Duration::from_millis(70)).await;
+        let mut tasks = tasks.into_iter();
+        let first = tasks.next().unwrap();
+        first.abort();
+        let _ = first.await;
+        assert_eq!(tasks.next().unwrap().await.unwrap(), Some(1));
+    }
+}
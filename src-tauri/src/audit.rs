@@ -0,0 +1,563 @@
+//! Cloud integrity audit: compare the library with what the storage backend holds.
+//!
+//! Every object in the backend is listed and matched against `media.telegram_media_id`
+//! and split-upload parts. The report lists uploaded items whose object is gone,
+//! uploads with no library entry, encryption flags that disagree with the uploaded
+//! file and size mismatches, each with a repair action when one is possible.
+
+use crate::caption::{self, CaptionMetadata};
+use crate::database::{CloudAuditRow, Database};
+use crate::recovery;
+use crate::security;
+use crate::storage::{self, ObjectId, StorageBackend, StoredObject};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static AUDIT_RUNNING: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditIssueKind {
+    /// Marked uploaded, but the object is gone
+    MissingInCloud,
+    /// Uploaded by the app, but no library item points at it
+    Orphan,
+    /// `is_encrypted` disagrees with the uploaded file
+    EncryptionMismatch,
+    /// The object's size differs from the recorded upload size
+    SizeMismatch,
+}
+
+/// One-click fix offered for an [`AuditIssue`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum AuditRepair {
+    /// Upload the local file again after deleting the `stale_ids` objects
+    #[serde(rename_all = "camelCase")]
+    Requeue {
+        media_id: i64,
+        file_path: String,
+        stale_ids: Vec<ObjectId>,
+    },
+    /// Register an orphaned upload as a cloud-only library item
+    #[serde(rename_all = "camelCase")]
+    Import {
+        object: StoredObject,
+        parts: Vec<ObjectId>,
+    },
+    /// Set the stored encryption flag to what the uploaded file actually is
+    #[serde(rename_all = "camelCase")]
+    FixFlag { media_id: i64, encrypted: bool },
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditIssue {
+    pub kind: AuditIssueKind,
+    pub media_id: Option<i64>,
+    pub file_path: Option<String>,
+    /// Objects involved, first part first
+    pub object_ids: Vec<ObjectId>,
+    pub detail: String,
+    pub repair: Option<AuditRepair>,
+}
+
+/// Summary returned once the audit finishes
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditReport {
+    pub scanned_objects: usize,
+    /// Library items with a cloud copy that were checked
+    pub checked_items: usize,
+    /// Objects without Wander(er) metadata, such as files posted by hand (not reported)
+    pub unidentified: usize,
+    pub issues: Vec<AuditIssue>,
+}
+
+/// Clears the running flag when the audit ends, including on early returns.
+struct RunningGuard;
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        AUDIT_RUNNING.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Audit the library against `backend`.
+///
+/// The encryption state of an upload is read from its caption metadata. With
+/// `verify_headers`, uploads without metadata are downloaded to `work_dir` once to
+/// check for the WBENC header instead of being skipped.
+pub async fn run_audit(
+    db: &Database,
+    backend: &dyn StorageBackend,
+    read_keys: Vec<[u8; 32]>,
+    verify_headers: bool,
+    work_dir: PathBuf,
+) -> Result<AuditReport, String> {
+    if AUDIT_RUNNING.swap(true, Ordering::SeqCst) {
+        return Err("An audit is already running".to_string());
+    }
+    let _running = RunningGuard;
+
+    // Items whose local file disappeared can't be requeued; bring the flags up to date.
+    db.reconcile_cloud_only_flags().map_err(|e| e.to_string())?;
+
+    let objects: BTreeMap<ObjectId, StoredObject> = storage::list_all(backend, 0)
+        .await?
+        .into_iter()
+        .map(|o| (o.id, o))
+        .collect();
+    let metas: HashMap<ObjectId, CaptionMetadata> = objects
+        .values()
+        .filter_map(|o| {
+            let meta =
+                caption::parse_caption_with_keys(&o.caption, &read_keys).unwrap_or_else(|e| {
+                    warn!("Audit: unreadable caption on object {}: {}", o.id, e);
+                    None
+                });
+            meta.map(|(m, _)| (o.id, m))
+        })
+        .collect();
+    let rows = db.get_cloud_audit_rows().map_err(|e| e.to_string())?;
+
+    let mut probed = HashMap::new();
+    if verify_headers {
+        std::fs::create_dir_all(&work_dir).map_err(|e| e.to_string())?;
+        for row in rows.iter().filter(|r| !r.is_deleted) {
+            let Some(first) = object_ids(row).first().copied() else {
+                continue;
+            };
+            if !objects.contains_key(&first) || metas.contains_key(&first) {
+                continue;
+            }
+            match probe_encryption(backend, first, &work_dir).await {
+                Ok(encrypted) => {
+                    probed.insert(first, encrypted);
+                }
+                Err(e) => warn!("Audit: could not check object {}: {}", first, e),
+            }
+        }
+    }
+
+    let mut report = AuditReport {
+        scanned_objects: objects.len(),
+        ..Default::default()
+    };
+    let (issues, checked) = check_rows(&rows, &objects, &metas, &probed);
+    report.issues = issues;
+    report.checked_items = checked;
+    let (orphans, unidentified) = find_orphans(&rows, &objects, &metas);
+    report.issues.extend(orphans);
+    report.unidentified = unidentified;
+
+    info!(
+        "Audit finished: {} objects, {} items checked, {} issues",
+        report.scanned_objects,
+        report.checked_items,
+        report.issues.len()
+    );
+    Ok(report)
+}
+
+/// Apply a repair from an audit report.
+pub async fn repair(
+    db: &Database,
+    backend: &dyn StorageBackend,
+    repair: AuditRepair,
+    read_keys: Vec<[u8; 32]>,
+    restore_dir: &Path,
+) -> Result<(), String> {
+    match repair {
+        AuditRepair::Requeue {
+            media_id,
+            file_path,
+            stale_ids,
+        } => {
+            if !Path::new(&file_path).exists() {
+                return Err("The local file no longer exists".to_string());
+            }
+            if !stale_ids.is_empty() {
+                if let Err(e) = backend.delete(&stale_ids).await {
+                    warn!(
+                        "Audit: failed to delete stale objects {:?}: {}",
+                        stale_ids, e
+                    );
+                }
+            }
+            db.requeue_missing_upload(media_id, &file_path)
+                .map_err(|e| e.to_string())
+        }
+        AuditRepair::Import { object, parts } => {
+            let meta = caption::parse_caption_with_keys(&object.caption, &read_keys)
+                .map_err(|e| e.to_string())?
+                .map(|(meta, _)| meta)
+                .ok_or("Upload carries no library metadata")?;
+            let restored = recovery::import_object(db, &object, meta, parts, restore_dir)?;
+            if !restored {
+                info!(
+                    "Audit: object {} matched an existing library item",
+                    object.id
+                );
+            }
+            Ok(())
+        }
+        AuditRepair::FixFlag {
+            media_id,
+            encrypted,
+        } => db
+            .set_media_encrypted_flag(media_id, encrypted)
+            .map(|_| ())
+            .map_err(|e| e.to_string()),
+    }
+}
+
+/// Objects making up a library item, first part first.
+fn object_ids(row: &CloudAuditRow) -> Vec<ObjectId> {
+    if row.parts.is_empty() {
+        row.telegram_media_id
+            .iter()
+            .filter_map(|id| id.parse().ok())
+            .collect()
+    } else {
+        row.parts
+            .iter()
+            .filter_map(|(id, _)| id.parse().ok())
+            .collect()
+    }
+}
+
+/// Download one object and report whether it is a WBENC container.
+async fn probe_encryption(
+    backend: &dyn StorageBackend,
+    id: ObjectId,
+    work_dir: &Path,
+) -> Result<bool, String> {
+    let path = work_dir.join(format!("audit_{}.download", id));
+    let result = async {
+        backend.download(id, &path).await?;
+        security::is_encrypted_file(&path).map_err(|e| e.to_string())
+    }
+    .await;
+    let _ = std::fs::remove_file(&path);
+    result
+}
+
+/// Check every non-trashed library item against the stored objects. `probed` holds
+/// encryption states read from file headers for uploads without caption metadata.
+/// Returns the issues found and the number of items checked.
+fn check_rows(
+    rows: &[CloudAuditRow],
+    objects: &BTreeMap<ObjectId, StoredObject>,
+    metas: &HashMap<ObjectId, CaptionMetadata>,
+    probed: &HashMap<ObjectId, bool>,
+) -> (Vec<AuditIssue>, usize) {
+    let mut issues = Vec::new();
+    let mut checked = 0;
+    for row in rows.iter().filter(|r| !r.is_deleted) {
+        checked += 1;
+        let ids = object_ids(row);
+        let has_local = !row.is_cloud_only && Path::new(&row.file_path).exists();
+        let requeue = |stale_ids: Vec<ObjectId>| {
+            has_local.then(|| AuditRepair::Requeue {
+                media_id: row.media_id,
+                file_path: row.file_path.clone(),
+                stale_ids,
+            })
+        };
+        let issue = |kind, detail: String, repair| AuditIssue {
+            kind,
+            media_id: Some(row.media_id),
+            file_path: Some(row.file_path.clone()),
+            object_ids: ids.clone(),
+            detail,
+            repair,
+        };
+
+        let missing: Vec<ObjectId> = ids
+            .iter()
+            .copied()
+            .filter(|id| !objects.contains_key(id))
+            .collect();
+        if ids.is_empty() || !missing.is_empty() {
+            let detail = if ids.is_empty() {
+                "Marked uploaded but linked to no cloud object".to_string()
+            } else {
+                format!("Cloud objects {:?} no longer exist", missing)
+            };
+            let present = ids
+                .iter()
+                .copied()
+                .filter(|id| objects.contains_key(id))
+                .collect();
+            issues.push(issue(
+                AuditIssueKind::MissingInCloud,
+                detail,
+                requeue(present),
+            ));
+            continue;
+        }
+
+        let actual_encrypted = metas
+            .get(&ids[0])
+            .map(|m| m.encrypted)
+            .or_else(|| probed.get(&ids[0]).copied());
+        if let Some(encrypted) = actual_encrypted.filter(|e| *e != row.is_encrypted) {
+            let detail = if encrypted {
+                "Uploaded file is encrypted but the item is not marked encrypted"
+            } else {
+                "Item is marked encrypted but the uploaded file is not"
+            };
+            issues.push(issue(
+                AuditIssueKind::EncryptionMismatch,
+                detail.to_string(),
+                Some(AuditRepair::FixFlag {
+                    media_id: row.media_id,
+                    encrypted,
+                }),
+            ));
+        }
+
+        // Sizes of 0 are unknown (e.g. Telegram photos, parts restored by recovery)
+        let expected: Vec<(ObjectId, u64)> = if row.parts.is_empty() {
+            let plain = row.size_bytes.unwrap_or(0).max(0) as u64;
+            // Uploads without caption metadata predate the v2 container
+            let container = metas.get(&ids[0]).map_or(1, |m| m.container_version());
+            let size = if actual_encrypted.unwrap_or(row.is_encrypted) && plain > 0 {
+                security::encrypted_file_size(container, plain)
+            } else {
+                plain
+            };
+            vec![(ids[0], size)]
+        } else {
+            ids.iter()
+                .zip(&row.parts)
+                .map(|(id, (_, size))| (*id, (*size).max(0) as u64))
+                .collect()
+        };
+        let mismatched: Vec<String> = expected
+            .iter()
+            .filter_map(|(id, size)| {
+                let stored = objects.get(id)?.size;
+                (*size > 0 && stored > 0 && stored != *size)
+                    .then(|| format!("object {} holds {} bytes, expected {}", id, stored, size))
+            })
+            .collect();
+        if !mismatched.is_empty() {
+            issues.push(issue(
+                AuditIssueKind::SizeMismatch,
+                format!("Size mismatch: {}", mismatched.join("; ")),
+                requeue(ids.clone()),
+            ));
+        }
+    }
+    (issues, checked)
+}
+
+/// Find uploads with app metadata that no library item points at. Split uploads are
+/// grouped; only complete ones can be imported. Also returns the number of objects
+/// without app metadata, which are not reported.
+fn find_orphans(
+    rows: &[CloudAuditRow],
+    objects: &BTreeMap<ObjectId, StoredObject>,
+    metas: &HashMap<ObjectId, CaptionMetadata>,
+) -> (Vec<AuditIssue>, usize) {
+    let known: HashSet<ObjectId> = rows.iter().flat_map(object_ids).collect();
+    let mut issues = Vec::new();
+    let mut unidentified = 0;
+    let mut splits: BTreeMap<&str, Vec<(&StoredObject, &CaptionMetadata)>> = BTreeMap::new();
+
+    for object in objects.values().filter(|o| !known.contains(&o.id)) {
+        let Some(meta) = metas.get(&object.id) else {
+            let is_backup = object
+                .file_name
+                .as_deref()
+                .map(|n| n.starts_with("library_backup_"))
+                .unwrap_or(false);
+            if !is_backup {
+                unidentified += 1;
+            }
+            continue;
+        };
+        if meta.is_library_backup() {
+            continue;
+        }
+        if meta.is_split() {
+            splits
+                .entry(meta.file_hash.as_str())
+                .or_default()
+                .push((object, meta));
+            continue;
+        }
+        issues.push(AuditIssue {
+            kind: AuditIssueKind::Orphan,
+            media_id: None,
+            file_path: None,
+            object_ids: vec![object.id],
+            detail: format!("{} is not in the library", meta.file_name),
+            repair: Some(AuditRepair::Import {
+                object: object.clone(),
+                parts: Vec::new(),
+            }),
+        });
+    }
+
+    for group in splits.into_values() {
+        let expected = group[0].1.part_count.unwrap_or(0);
+        // Objects come in ID order, so a part that was re-sent keeps its newest copy
+        let mut by_index: BTreeMap<u32, &StoredObject> = BTreeMap::new();
+        for (object, meta) in &group {
+            by_index.insert(meta.part_index.unwrap_or(0), object);
+        }
+        let complete =
+            by_index.len() as u32 == expected && (0..expected).all(|i| by_index.contains_key(&i));
+        let ids: Vec<ObjectId> = group.iter().map(|(o, _)| o.id).collect();
+        let name = &group[0].1.file_name;
+        let (detail, repair) = if complete {
+            (
+                format!("{} ({} parts) is not in the library", name, expected),
+                Some(AuditRepair::Import {
+                    object: by_index[&0].clone(),
+                    parts: by_index.values().map(|o| o.id).collect(),
+                }),
+            )
+        } else {
+            (
+                format!(
+                    "{} is not in the library and has only {} of {} parts",
+                    name,
+                    by_index.len(),
+                    expected
+                ),
+                None,
+            )
+        };
+        issues.push(AuditIssue {
+            kind: AuditIssueKind::Orphan,
+            media_id: None,
+            file_path: None,
+            object_ids: ids,
+            detail,
+            repair,
+        });
+    }
+    (issues, unidentified)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn object(id: ObjectId, size: u64) -> StoredObject {
+        StoredObject {
+            id,
+            file_name: Some(format!("file_{}.jpg", id)),
+            mime_type: Some("image/jpeg".to_string()),
+            size,
+            caption: String::new(),
+            uploaded_at: 0,
+        }
+    }
+
+    fn row(media_id: i64, telegram_id: &str, size: i64) -> CloudAuditRow {
+        CloudAuditRow {
+            media_id,
+            file_path: format!("/nonexistent/wanderer-audit/{}.jpg", media_id),
+            telegram_media_id: Some(telegram_id.to_string()),
+            size_bytes: Some(size),
+            uploaded: true,
+            is_encrypted: false,
+            is_cloud_only: false,
+            is_deleted: false,
+            parts: Vec::new(),
+        }
+    }
+
+    fn meta(encrypted: bool) -> CaptionMetadata {
+        CaptionMetadata::new("hash", "IMG_0001.jpg", None, None, encrypted)
+    }
+
+    #[test]
+    fn test_missing_object_without_local_file_has_no_repair() {
+        let objects = BTreeMap::new();
+        let (issues, checked) = check_rows(
+            &[row(1, "10", 5)],
+            &objects,
+            &HashMap::new(),
+            &HashMap::new(),
+        );
+        assert_eq!(checked, 1);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].kind, AuditIssueKind::MissingInCloud);
+        assert!(issues[0].repair.is_none());
+    }
+
+    #[test]
+    fn test_missing_object_with_local_file_is_requeued() {
+        let local = std::env::temp_dir().join(format!("wanderer-audit-{}", std::process::id()));
+        std::fs::write(&local, b"12345").unwrap();
+        let mut item = row(1, "10", 5);
+        item.file_path = local.to_string_lossy().to_string();
+
+        let (issues, _) = check_rows(&[item], &BTreeMap::new(), &HashMap::new(), &HashMap::new());
+        assert!(matches!(
+            issues[0].repair,
+            Some(AuditRepair::Requeue { media_id: 1, ref stale_ids, .. }) if stale_ids.is_empty()
+        ));
+        let _ = std::fs::remove_file(&local);
+    }
+
+    #[test]
+    fn test_encryption_flag_and_size_mismatch() {
+        let objects: BTreeMap<_, _> = [(10, object(10, 5)), (11, object(11, 7))].into();
+        let metas: HashMap<_, _> = [(10, meta(true))].into();
+        let rows = [row(1, "10", 5), row(2, "11", 5)];
+        let (issues, _) = check_rows(&rows, &objects, &metas, &HashMap::new());
+
+        // Item 1 is really encrypted, so the expected size includes the container overhead
+        let kinds: Vec<_> = issues.iter().map(|i| (i.media_id, i.kind)).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (Some(1), AuditIssueKind::EncryptionMismatch),
+                (Some(1), AuditIssueKind::SizeMismatch),
+                (Some(2), AuditIssueKind::SizeMismatch),
+            ]
+        );
+        assert_eq!(
+            issues[0].repair,
+            Some(AuditRepair::FixFlag {
+                media_id: 1,
+                encrypted: true
+            })
+        );
+    }
+
+    #[test]
+    fn test_orphans_group_split_parts() {
+        let rows = [row(1, "10", 5)];
+        let objects: BTreeMap<_, _> = (10..=14).map(|id| (id, object(id, 5))).collect();
+        let split = meta(false);
+        let metas: HashMap<_, _> = [
+            (11, meta(false)),
+            (12, split.for_part(1, 2)),
+            (13, split.for_part(0, 2)),
+        ]
+        .into();
+
+        let (issues, unidentified) = find_orphans(&rows, &objects, &metas);
+        assert_eq!(unidentified, 1);
+        assert_eq!(issues.len(), 2);
+        assert_eq!(issues[0].object_ids, vec![11]);
+        match &issues[1].repair {
+            Some(AuditRepair::Import { object, parts }) => {
+                assert_eq!(object.id, 13);
+                assert_eq!(parts, &vec![13, 12]);
+            }
+            other => panic!("unexpected repair {:?}", other),
+        }
+    }
+}
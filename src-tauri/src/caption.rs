@@ -0,0 +1,286 @@
+//! Machine-readable caption metadata attached to every uploaded message.
+//!
+//! The caption keeps a human-readable first line and adds a versioned payload line:
+//!
+//! ```text
+//! Uploaded via Wander(er)
+//! wanderer:v1:<base64url JSON>        (plaintext libraries)
+//! wanderer:v1e:<base64url AES-GCM>    (encrypted libraries)
+//! ```
+//!
+//! This lets the cloud copy be matched to its library entry (by blake3 hash) without
+//! the local `library.db`.
+
+use crate::database::Database;
+use crate::security;
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD as B64URL, Engine as _};
+use serde::{Deserialize, Serialize};
+
+pub const CAPTION_VERSION: u32 = 1;
+const CAPTION_HEADLINE: &str = "Uploaded via Wander(er)";
+const PLAIN_PREFIX: &str = "wanderer:v1:";
+const ENCRYPTED_PREFIX: &str = "wanderer:v1e:";
+/// `kind` of the caption on uploaded `library.db` backups.
+pub const KIND_LIBRARY_BACKUP: &str = "library_backup";
+const CAPTION_KEY_CONTEXT: &str = "wanderer 2026 caption metadata v1";
+/// Telegram's caption limit, in characters
+const MAX_CAPTION_CHARS: usize = 1024;
+
+/// Structured metadata stored in the caption of an uploaded message.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CaptionMetadata {
+    #[serde(rename = "v")]
+    pub version: u32,
+    #[serde(rename = "h")]
+    pub file_hash: String,
+    #[serde(rename = "n")]
+    pub file_name: String,
+    #[serde(rename = "d", default, skip_serializing_if = "Option::is_none")]
+    pub date_taken: Option<String>,
+    #[serde(rename = "m", default, skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+    /// Whether the attached file is a WBENC container
+    #[serde(rename = "e")]
+    pub encrypted: bool,
+    /// Zero-based index of this part for split uploads
+    #[serde(rename = "pi", default, skip_serializing_if = "Option::is_none")]
+    pub part_index: Option<u32>,
+    #[serde(rename = "pc", default, skip_serializing_if = "Option::is_none")]
+    pub part_count: Option<u32>,
+    /// Non-media payloads (e.g. [`KIND_LIBRARY_BACKUP`]); `None` for library media
+    #[serde(rename = "k", default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    /// WBENC container version of an encrypted file; absent on uploads from before v2
+    #[serde(rename = "c", default, skip_serializing_if = "Option::is_none")]
+    pub container: Option<u8>,
+}
+
+impl CaptionMetadata {
+    pub fn new(
+        file_hash: &str,
+        file_name: &str,
+        date_taken: Option<String>,
+        mime_type: Option<String>,
+        encrypted: bool,
+    ) -> Self {
+        Self {
+            version: CAPTION_VERSION,
+            file_hash: file_hash.to_string(),
+            file_name: file_name.to_string(),
+            date_taken,
+            mime_type,
+            encrypted,
+            part_index: None,
+            part_count: None,
+            kind: None,
+            container: encrypted.then_some(security::CONTAINER_VERSION),
+        }
+    }
+
+    /// Metadata for an uploaded `library.db` backup.
+    pub fn library_backup(file_hash: &str, file_name: &str, encrypted: bool) -> Self {
+        let mut meta = Self::new(file_hash, file_name, None, None, encrypted);
+        meta.kind = Some(KIND_LIBRARY_BACKUP.to_string());
+        meta
+    }
+
+    pub fn is_library_backup(&self) -> bool {
+        self.kind.as_deref() == Some(KIND_LIBRARY_BACKUP)
+    }
+
+    /// Copy of this metadata describing part `index` of `count`.
+    pub fn for_part(&self, index: u32, count: u32) -> Self {
+        let mut part = self.clone();
+        part.part_index = Some(index);
+        part.part_count = Some(count);
+        part
+    }
+
+    pub fn is_split(&self) -> bool {
+        self.part_count.map(|c| c > 1).unwrap_or(false)
+    }
+
+    /// Container version of the attached file when it is encrypted.
+    pub fn container_version(&self) -> u8 {
+        self.container.unwrap_or(1)
+    }
+}
+
+/// Caption metadata for a library file, filled from its media row when one matches the hash.
+pub fn metadata_for_file(
+    db: &Database,
+    file_path: &str,
+    file_hash: &str,
+    encrypted: bool,
+) -> CaptionMetadata {
+    let media = db.get_media_by_hash(file_hash).ok().flatten();
+    let file_name = std::path::Path::new(file_path)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("file");
+    let mime_type = media
+        .as_ref()
+        .and_then(|m| m.mime_type.clone())
+        .or_else(|| {
+            mime_guess::from_path(file_path)
+                .first()
+                .map(|m| m.to_string())
+        });
+    let date_taken = media.and_then(|m| m.date_taken);
+    CaptionMetadata::new(file_hash, file_name, date_taken, mime_type, encrypted)
+}
+
+/// Render the caption text. With a key, the payload is encrypted so file names and
+/// hashes are not visible in the chat. Names too long for Telegram's caption limit once
+/// encoded are shortened.
+pub fn build_caption(meta: &CaptionMetadata, key: Option<&[u8; 32]>) -> Result<String> {
+    let caption = render_caption(meta, key)?;
+    if caption.chars().count() <= MAX_CAPTION_CHARS {
+        return Ok(caption);
+    }
+
+    // Longest prefix of the name that fits; multibyte names grow when escaped and encoded
+    let name: Vec<char> = meta.file_name.chars().collect();
+    let with_name = |len: usize| -> Result<String> {
+        let mut shortened = meta.clone();
+        shortened.file_name = name[..len].iter().collect();
+        render_caption(&shortened, key)
+    };
+    let (mut fits, mut too_long) = (0, name.len());
+    while too_long - fits > 1 {
+        let mid = (fits + too_long) / 2;
+        if with_name(mid)?.chars().count() <= MAX_CAPTION_CHARS {
+            fits = mid;
+        } else {
+            too_long = mid;
+        }
+    }
+    let caption = with_name(fits)?;
+    if caption.chars().count() > MAX_CAPTION_CHARS {
+        return Err(anyhow!("Caption metadata exceeds Telegram's caption limit"));
+    }
+    Ok(caption)
+}
+
+fn render_caption(meta: &CaptionMetadata, key: Option<&[u8; 32]>) -> Result<String> {
+    let json = serde_json::to_vec(meta)?;
+    let payload_line = match key {
+        Some(master_key) => {
+            let caption_key = security::derive_subkey(master_key, CAPTION_KEY_CONTEXT);
+            let sealed = security::encrypt_bytes(&caption_key, &json, ENCRYPTED_PREFIX.as_bytes())?;
+            format!("{}{}", ENCRYPTED_PREFIX, B64URL.encode(sealed))
+        }
+        None => format!("{}{}", PLAIN_PREFIX, B64URL.encode(json)),
+    };
+    Ok(format!("{}\n{}", CAPTION_HEADLINE, payload_line))
+}
+
+/// Extract metadata from a message caption.
+///
+/// Returns `Ok(None)` for captions without a Wander(er) payload (manual uploads, old
+/// uploads), and an error when the payload is encrypted but no key was given or it
+/// does not decrypt.
+pub fn parse_caption(text: &str, key: Option<&[u8; 32]>) -> Result<Option<CaptionMetadata>> {
+    parse_caption_with_keys(text, key.copied().as_slice())
+        .map(|parsed| parsed.map(|(meta, _)| meta))
+}
+
+/// [`parse_caption`] with whichever of `keys` opens an encrypted payload, along with
+/// that key's index (`None` for a plaintext caption).
+pub fn parse_caption_with_keys(
+    text: &str,
+    keys: &[[u8; 32]],
+) -> Result<Option<(CaptionMetadata, Option<usize>)>> {
+    for line in text.lines().map(str::trim) {
+        if let Some(encoded) = line.strip_prefix(ENCRYPTED_PREFIX) {
+            if keys.is_empty() {
+                return Err(anyhow!("Caption is encrypted; unlock the vault to read it"));
+            }
+            let sealed = B64URL.decode(encoded)?;
+            let mut opened = Err(anyhow!("Caption does not decrypt"));
+            for (index, master_key) in keys.iter().enumerate() {
+                let caption_key = security::derive_subkey(master_key, CAPTION_KEY_CONTEXT);
+                opened =
+                    security::decrypt_bytes(&caption_key, &sealed, ENCRYPTED_PREFIX.as_bytes())
+                        .map(|json| (json, index));
+                if opened.is_ok() {
+                    break;
+                }
+            }
+            let (json, index) = opened?;
+            return Ok(Some((serde_json::from_slice(&json)?, Some(index))));
+        }
+        if let Some(encoded) = line.strip_prefix(PLAIN_PREFIX) {
+            let json = B64URL.decode(encoded)?;
+            return Ok(Some((serde_json::from_slice(&json)?, None)));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> CaptionMetadata {
+        CaptionMetadata::new(
+            "abc123",
+            "IMG_0001.jpg",
+            Some("2025-07-01 10:00:00".to_string()),
+            Some("image/jpeg".to_string()),
+            false,
+        )
+    }
+
+    #[test]
+    fn plain_caption_roundtrip() {
+        let caption = build_caption(&sample(), None).unwrap();
+        assert!(caption.starts_with(CAPTION_HEADLINE));
+        assert_eq!(parse_caption(&caption, None).unwrap(), Some(sample()));
+    }
+
+    #[test]
+    fn encrypted_caption_roundtrip() {
+        let key = [7u8; 32];
+        let meta = sample().for_part(1, 3);
+        let caption = build_caption(&meta, Some(&key)).unwrap();
+        assert!(!caption.contains("IMG_0001"));
+        assert_eq!(
+            parse_caption(&caption, Some(&key)).unwrap(),
+            Some(meta.clone())
+        );
+        assert!(parse_caption(&caption, None).is_err());
+        assert!(parse_caption(&caption, Some(&[8u8; 32])).is_err());
+        // A caption sealed before a key rotation opens with the retired key
+        assert_eq!(
+            parse_caption_with_keys(&caption, &[[8u8; 32], key]).unwrap(),
+            Some((meta, Some(1)))
+        );
+    }
+
+    #[test]
+    fn long_names_fit_the_caption_limit() {
+        let key = [7u8; 32];
+        let mut meta = sample();
+        meta.file_name = format!("{}.jpg", "写真".repeat(400));
+        for key in [None, Some(&key)] {
+            let caption = build_caption(&meta, key).expect("caption builds");
+            assert!(caption.chars().count() <= MAX_CAPTION_CHARS);
+            let parsed = parse_caption(&caption, key)
+                .expect("caption parses")
+                .expect("caption has metadata");
+            assert!(!parsed.file_name.is_empty());
+            assert!(meta.file_name.starts_with(&parsed.file_name));
+        }
+    }
+
+    #[test]
+    fn foreign_caption_is_ignored() {
+        assert_eq!(
+            parse_caption("Uploaded via Wander(er)", None).unwrap(),
+            None
+        );
+        assert_eq!(parse_caption("", None).unwrap(), None);
+    }
+}
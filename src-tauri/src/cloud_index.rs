@@ -0,0 +1,309 @@
+//! Server-side deduplication of uploads.
+//!
+//! Every upload's caption carries the blake3 hash of the original file (see
+//! [`crate::caption`]). The cloud index records those hashes as objects appear in the
+//! storage destination, so a file another device already stored is linked to the
+//! existing object instead of being uploaded again. Encrypted captions can only be read
+//! with the vault key, so encrypted libraries are indexed while the vault is unlocked.
+//! Message IDs are per chat, so each Telegram account's destination is indexed on its
+//! own; other backends are indexed as the primary account's.
+
+use crate::caption;
+use crate::database::{CloudIndexEntry, Database};
+use crate::security::CONTAINER_VERSION;
+use crate::storage::{self, ObjectId, StorageBackend, StoredObject};
+use crate::telegram::TelegramService;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio::time::{Duration, Instant};
+
+const DEDUP_ITEMS_KEY: &str = "cloud_dedup_items";
+const DEDUP_SAVED_BYTES_KEY: &str = "cloud_dedup_saved_bytes";
+
+/// Uploads starting within this long of the last refresh reuse the index as it is
+const REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Uploads skipped because the destination already held the file
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DedupStats {
+    pub items: i64,
+    pub saved_bytes: i64,
+}
+
+impl DedupStats {
+    pub fn load(db: &Database) -> Self {
+        let counter = |key: &str| {
+            db.get_config(key)
+                .ok()
+                .flatten()
+                .and_then(|v| v.parse::<i64>().ok())
+                .unwrap_or(0)
+        };
+        Self {
+            items: counter(DEDUP_ITEMS_KEY),
+            saved_bytes: counter(DEDUP_SAVED_BYTES_KEY),
+        }
+    }
+
+    /// Count one linked upload of `bytes`.
+    pub fn record(db: &Database, bytes: u64) -> Result<(), String> {
+        db.add_to_config_counters(&[(DEDUP_ITEMS_KEY, 1), (DEDUP_SAVED_BYTES_KEY, bytes as i64)])
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Forget the index, e.g. when the destination changes and object IDs no longer apply.
+pub fn reset(db: &Database) -> Result<(), String> {
+    db.clear_cloud_index()
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+#[derive(Default)]
+pub struct CloudIndex {
+    /// When the index last caught up with each account's destination
+    refreshed: Mutex<HashMap<i64, Instant>>,
+}
+
+impl CloudIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Find a complete copy of the file with `file_hash` in the storage destination of
+    /// `account_id`, whose service is `telegram`, stored encrypted or not as given.
+    /// Returns `(object_id, size)` per part in order.
+    ///
+    /// `key` must be the vault key in encrypted libraries: objects listed without it
+    /// would be passed over for good.
+    pub async fn find_copy(
+        &self,
+        db: &Database,
+        account_id: i64,
+        telegram: &Arc<TelegramService>,
+        file_hash: &str,
+        encrypted: bool,
+        key: Option<&[u8; 32]>,
+    ) -> Result<Option<Vec<(ObjectId, u64)>>, String> {
+        let backend = storage::active_backend(db, telegram)?;
+        self.refresh(db, account_id, backend.as_ref(), key).await?;
+
+        loop {
+            let entries = db
+                .get_cloud_index_entries(account_id, file_hash)
+                .map_err(|e| e.to_string())?;
+            let Some(parts) = complete_copy(&entries, encrypted) else {
+                return Ok(None);
+            };
+            if backend.kind() != "telegram" {
+                return Ok(Some(parts));
+            }
+
+            // Messages deleted in another Telegram client are still indexed
+            let ids: Vec<ObjectId> = parts.iter().map(|(id, _)| *id).collect();
+            let messages = telegram.get_messages_by_ids(&ids).await?;
+            let gone: Vec<ObjectId> = ids
+                .iter()
+                .zip(&messages)
+                .filter(|(_, message)| message.is_none())
+                .map(|(id, _)| *id)
+                .collect();
+            if gone.is_empty() {
+                return Ok(Some(parts));
+            }
+            db.remove_cloud_index_objects(account_id, &gone)
+                .map_err(|e| e.to_string())?;
+        }
+    }
+
+    /// Add objects stored since the last refresh, at most once per [`REFRESH_INTERVAL`].
+    async fn refresh(
+        &self,
+        db: &Database,
+        account_id: i64,
+        backend: &dyn StorageBackend,
+        key: Option<&[u8; 32]>,
+    ) -> Result<(), String> {
+        let mut refreshed = self.refreshed.lock().await;
+        if refreshed
+            .get(&account_id)
+            .is_some_and(|at| at.elapsed() < REFRESH_INTERVAL)
+        {
+            return Ok(());
+        }
+
+        let mut mark = db
+            .get_cloud_index_mark(account_id)
+            .map_err(|e| e.to_string())?
+            .unwrap_or(0);
+        let mut indexed = 0;
+        loop {
+            let objects = backend.list(mark, storage::LIST_PAGE_SIZE).await?;
+            let entries: Vec<CloudIndexEntry> = objects
+                .iter()
+                .filter_map(|object| index_entry(account_id, object, key))
+                .collect();
+            db.add_cloud_index_entries(&entries)
+                .map_err(|e| e.to_string())?;
+            indexed += entries.len();
+            if let Some(newest) = objects.last() {
+                mark = newest.id;
+                db.set_cloud_index_mark(account_id, mark)
+                    .map_err(|e| e.to_string())?;
+            }
+            if objects.len() < storage::LIST_PAGE_SIZE {
+                break;
+            }
+        }
+        if indexed > 0 {
+            log::debug!(
+                "Cloud index: {} new object(s) in {} storage of account {}",
+                indexed,
+                backend.kind(),
+                account_id
+            );
+        }
+        refreshed.insert(account_id, Instant::now());
+        Ok(())
+    }
+}
+
+/// Index entry for a library file, or `None` when the caption doesn't describe one.
+fn index_entry(
+    account_id: i64,
+    object: &StoredObject,
+    key: Option<&[u8; 32]>,
+) -> Option<CloudIndexEntry> {
+    let meta = caption::parse_caption(&object.caption, key)
+        .ok()
+        .flatten()?;
+    // Encrypted copies in the old container lack truncation protection; the encryption
+    // migration replaces them rather than new uploads linking to them
+    if meta.kind.is_some() || (meta.encrypted && meta.container_version() < CONTAINER_VERSION) {
+        return None;
+    }
+    Some(CloudIndexEntry {
+        account_id,
+        object_id: object.id,
+        file_hash: meta.file_hash,
+        size_bytes: object.size as i64,
+        encrypted: meta.encrypted,
+        part_index: meta.part_index.unwrap_or(0) as i64,
+        part_count: meta.part_count.unwrap_or(1).max(1) as i64,
+    })
+}
+
+/// The oldest upload among `entries` (one file's objects, ordered by ID) with every part
+/// present. The parts of one upload are sent one after another, so they must follow each
+/// other in ID order; encrypted parts of different uploads can't be mixed.
+fn complete_copy(entries: &[CloudIndexEntry], encrypted: bool) -> Option<Vec<(ObjectId, u64)>> {
+    let candidates: Vec<&CloudIndexEntry> = entries
+        .iter()
+        .filter(|e| e.encrypted == encrypted)
+        .collect();
+    for (start, first) in candidates.iter().enumerate() {
+        if first.part_index != 0 {
+            continue;
+        }
+        let mut parts = vec![*first];
+        let rest = candidates[start + 1..]
+            .iter()
+            .filter(|e| e.part_count == first.part_count);
+        for entry in rest {
+            if parts.len() as i64 == first.part_count || entry.part_index != parts.len() as i64 {
+                break;
+            }
+            parts.push(entry);
+        }
+        if parts.len() as i64 == first.part_count {
+            return Some(
+                parts
+                    .iter()
+                    .map(|e| (e.object_id, e.size_bytes.max(0) as u64))
+                    .collect(),
+            );
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::caption::CaptionMetadata;
+
+    fn entry(object_id: i32, part: i64, count: i64, encrypted: bool) -> CloudIndexEntry {
+        CloudIndexEntry {
+            account_id: 0,
+            object_id,
+            file_hash: "abc".to_string(),
+            size_bytes: 100,
+            encrypted,
+            part_index: part,
+            part_count: count,
+        }
+    }
+
+    fn object(id: i32, caption: String) -> StoredObject {
+        StoredObject {
+            id,
+            file_name: None,
+            mime_type: None,
+            size: 42,
+            caption,
+            uploaded_at: 0,
+        }
+    }
+
+    #[test]
+    fn test_index_entry_from_caption() {
+        let key = [9u8; 32];
+        let meta = CaptionMetadata::new("abc", "a.jpg", None, None, true).for_part(1, 3);
+        let caption = caption::build_caption(&meta, Some(&key)).unwrap();
+
+        let indexed = index_entry(2, &object(7, caption.clone()), Some(&key)).unwrap();
+        assert_eq!((indexed.account_id, indexed.object_id), (2, 7));
+        assert_eq!(indexed.file_hash, "abc");
+        assert_eq!((indexed.part_index, indexed.part_count), (1, 3));
+        assert_eq!(indexed.size_bytes, 42);
+        assert!(index_entry(2, &object(7, caption), None).is_none());
+
+        let mut old = CaptionMetadata::new("abc", "a.jpg", None, None, true);
+        old.container = None;
+        let caption = caption::build_caption(&old, Some(&key)).unwrap();
+        assert!(index_entry(2, &object(10, caption), Some(&key)).is_none());
+
+        let backup = CaptionMetadata::library_backup("def", "library.db", false);
+        let caption = caption::build_caption(&backup, None).unwrap();
+        assert!(index_entry(2, &object(8, caption), None).is_none());
+        assert!(index_entry(2, &object(9, "holiday".to_string()), None).is_none());
+    }
+
+    #[test]
+    fn test_complete_copy_single_file() {
+        let entries = vec![entry(5, 0, 1, false), entry(9, 0, 1, false)];
+        assert_eq!(complete_copy(&entries, false), Some(vec![(5, 100)]));
+        assert_eq!(complete_copy(&entries, true), None);
+    }
+
+    #[test]
+    fn test_complete_copy_needs_every_part() {
+        let missing = vec![entry(1, 0, 3, true), entry(2, 2, 3, true)];
+        assert_eq!(complete_copy(&missing, true), None);
+
+        let entries = vec![
+            entry(1, 0, 3, true),
+            entry(2, 2, 3, true),
+            entry(10, 0, 3, true),
+            entry(11, 1, 3, true),
+            entry(12, 2, 3, true),
+        ];
+        assert_eq!(
+            complete_copy(&entries, true),
+            Some(vec![(10, 100), (11, 100), (12, 100)])
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use crate::upload_schedule::UploadState;
 use img_hash::ImageHash;
 use rusqlite::{params, Connection, OptionalExtension, Result};
 use serde::{Deserialize, Serialize};
@@ -42,6 +43,81 @@ pub struct QueueItem {
     pub retries: i32,
     pub error_msg: Option<String>,
     pub added_at: i64,
+    /// Items with a higher priority upload first
+    pub priority: i64,
+}
+
+/// Progress of an interrupted upload, persisted so it can continue after a restart.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UploadCheckpoint {
+    /// Hash of the source file the progress belongs to
+    pub source_hash: String,
+    /// `(message_id, size)` of split parts already sent, in order
+    pub sent_parts: Vec<(i32, u64)>,
+    /// Random Telegram file ID of the part in progress; 0 when none was started
+    pub file_id: i64,
+    /// Chunks of `file_id` confirmed by Telegram
+    pub chunks_sent: i32,
+}
+
+impl UploadCheckpoint {
+    pub fn is_started(&self) -> bool {
+        !self.sent_parts.is_empty() || self.chunks_sent > 0
+    }
+}
+
+/// A library entry whose cloud copy was deleted outside the app.
+#[derive(Debug, Clone)]
+pub struct MissingCloudCopy {
+    pub media_id: i64,
+    pub file_path: String,
+    pub is_cloud_only: bool,
+    /// Message IDs of the item's other parts that were not deleted
+    pub surviving_ids: Vec<String>,
+}
+
+/// What the library records about an item's cloud copy, as checked by the cloud audit.
+#[derive(Debug, Clone)]
+pub struct CloudAuditRow {
+    pub media_id: i64,
+    pub file_path: String,
+    pub telegram_media_id: Option<String>,
+    pub size_bytes: Option<i64>,
+    pub uploaded: bool,
+    pub is_encrypted: bool,
+    pub is_cloud_only: bool,
+    pub is_deleted: bool,
+    /// `(telegram_message_id, size_bytes)` of split upload parts, in order
+    pub parts: Vec<(String, i64)>,
+}
+
+/// An extra Telegram account registered as a storage target. The account logged in
+/// during onboarding is implicit and always has ID 0.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TelegramAccountRow {
+    pub id: i64,
+    pub label: String,
+    pub created_at: i64,
+}
+
+/// One cloud copy of a media item: the account holding it and its message IDs in part
+/// order (a single ID unless the upload was split).
+#[derive(Debug, Clone, PartialEq)]
+pub struct CloudCopy {
+    pub account_id: i64,
+    pub message_ids: Vec<i32>,
+}
+
+/// A file (or one part of a split file) found in the storage destination by its caption.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CloudIndexEntry {
+    pub account_id: i64,
+    pub object_id: i32,
+    pub file_hash: String,
+    pub size_bytes: i64,
+    pub encrypted: bool,
+    pub part_index: i64,
+    pub part_count: i64,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -49,6 +125,10 @@ pub struct QueueCounts {
     pub pending: i64,
     pub uploading: i64,
     pub failed: i64,
+    pub paused: i64,
+    pub upload_state: UploadState,
+    /// Upload cap in KiB/s; 0 means unlimited
+    pub bandwidth_kbps: u64,
 }
 
 #[derive(Debug, Default, Serialize, Deserialize)]
@@ -62,12 +142,37 @@ pub struct SearchFilters {
     pub has_location: Option<bool>,
 }
 
+/// A cloud-only library entry rebuilt from the storage destination by disaster recovery.
+#[derive(Debug, Default)]
+pub struct RecoveredMedia {
+    pub file_path: String,
+    pub file_hash: String,
+    pub telegram_media_id: String,
+    pub mime_type: Option<String>,
+    pub size_bytes: Option<i64>,
+    pub created_at: i64,
+    pub uploaded_at: i64,
+    pub date_taken: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub camera_make: Option<String>,
+    pub camera_model: Option<String>,
+    pub is_favorite: bool,
+    pub rating: i32,
+    pub is_archived: bool,
+    pub is_encrypted: bool,
+    /// WBENC container version of the cloud copy when it is encrypted
+    pub container_version: Option<u8>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Album {
     pub id: i64,
     pub name: String,
     pub created_at: i64,
     pub cover_path: Option<String>,
+    /// Telegram account receiving the album's uploads in album placement mode
+    pub account_id: Option<i64>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -640,6 +745,237 @@ impl Database {
             version = 19;
         }
 
+        if version < 20 {
+            // Migration 20: Part layout for files uploaded in several messages.
+            conn.execute_batch(
+                "BEGIN;
+                 CREATE TABLE IF NOT EXISTS media_parts (
+                     media_id INTEGER NOT NULL,
+                     part_index INTEGER NOT NULL,
+                     telegram_message_id TEXT NOT NULL,
+                     size_bytes INTEGER NOT NULL,
+                     PRIMARY KEY (media_id, part_index),
+                     FOREIGN KEY(media_id) REFERENCES media(id) ON DELETE CASCADE
+                 );
+                 PRAGMA user_version = 20;
+                 COMMIT;",
+            )?;
+            version = 20;
+        }
+
+        if version < 21 {
+            // Migration 21: Checkpoints for resuming interrupted uploads.
+            conn.execute_batch(
+                "BEGIN;
+                 ALTER TABLE upload_queue ADD COLUMN upload_source_hash TEXT;
+                 ALTER TABLE upload_queue ADD COLUMN upload_sent_parts TEXT;
+                 ALTER TABLE upload_queue ADD COLUMN upload_file_id INTEGER;
+                 ALTER TABLE upload_queue ADD COLUMN upload_chunks_sent INTEGER DEFAULT 0;
+                 PRAGMA user_version = 21;
+                 COMMIT;",
+            )?;
+            version = 21;
+        }
+
+        if version < 22 {
+            // Migration 22: Flag media whose cloud copy was deleted outside the app.
+            conn.execute_batch(
+                "BEGIN;
+                 ALTER TABLE media ADD COLUMN cloud_missing INTEGER DEFAULT 0;
+                 PRAGMA user_version = 22;
+                 COMMIT;",
+            )?;
+            version = 22;
+        }
+
+        if version < 23 {
+            // Migration 23: Queue priority (higher uploads first).
+            conn.execute_batch(
+                "BEGIN;
+                 ALTER TABLE upload_queue ADD COLUMN priority INTEGER NOT NULL DEFAULT 0;
+                 PRAGMA user_version = 23;
+                 COMMIT;",
+            )?;
+            version = 23;
+        }
+
+        if version < 24 {
+            // Migration 24: Multiple Telegram accounts. `media.account_id` is the account
+            // holding `telegram_media_id` (0 = the onboarding account); mirror copies on
+            // other accounts live in media_copies.
+            conn.execute_batch(
+                "BEGIN;
+                 CREATE TABLE IF NOT EXISTS telegram_accounts (
+                     id INTEGER PRIMARY KEY AUTOINCREMENT,
+                     label TEXT NOT NULL,
+                     created_at INTEGER NOT NULL
+                 );
+                 CREATE TABLE IF NOT EXISTS media_copies (
+                     media_id INTEGER NOT NULL,
+                     account_id INTEGER NOT NULL,
+                     message_ids TEXT NOT NULL,
+                     uploaded_at INTEGER NOT NULL,
+                     PRIMARY KEY (media_id, account_id),
+                     FOREIGN KEY(media_id) REFERENCES media(id) ON DELETE CASCADE
+                 );
+                 ALTER TABLE media ADD COLUMN account_id INTEGER NOT NULL DEFAULT 0;
+                 ALTER TABLE albums ADD COLUMN account_id INTEGER;
+                 ALTER TABLE upload_queue ADD COLUMN account_id INTEGER;
+                 ALTER TABLE upload_queue ADD COLUMN mirror INTEGER NOT NULL DEFAULT 0;
+                 PRAGMA user_version = 24;
+                 COMMIT;",
+            )?;
+            version = 24;
+        }
+
+        if version < 25 {
+            // Migration 25: Index of files already in the storage destination, keyed by
+            // the hash in their caption, so uploads can link to another device's copy.
+            conn.execute_batch(
+                "BEGIN;
+                 CREATE TABLE IF NOT EXISTS cloud_index (
+                     object_id INTEGER PRIMARY KEY,
+                     file_hash TEXT NOT NULL,
+                     size_bytes INTEGER NOT NULL DEFAULT 0,
+                     encrypted INTEGER NOT NULL DEFAULT 0,
+                     part_index INTEGER NOT NULL DEFAULT 0,
+                     part_count INTEGER NOT NULL DEFAULT 1
+                 );
+                 CREATE INDEX IF NOT EXISTS idx_cloud_index_hash ON cloud_index(file_hash);
+                 PRAGMA user_version = 25;
+                 COMMIT;",
+            )?;
+            version = 25;
+        }
+
+        if version < 26 {
+            // Migration 26: WBENC container version of encrypted cloud copies. Everything
+            // encrypted so far used version 1, which the encryption migration upgrades.
+            conn.execute_batch(
+                "BEGIN;
+                 ALTER TABLE media ADD COLUMN container_version INTEGER;
+                 UPDATE media SET container_version = 1 WHERE is_encrypted = 1;
+                 PRAGMA user_version = 26;
+                 COMMIT;",
+            )?;
+            version = 26;
+        }
+
+        if version < 27 {
+            // Migration 27: master key generation of encrypted cloud copies, for key
+            // rotation. Copies found encrypted by recovery or an audit stay unknown (NULL).
+            conn.execute_batch(
+                "BEGIN;
+                 ALTER TABLE media ADD COLUMN key_generation INTEGER;
+                 UPDATE media SET key_generation = 1 WHERE is_encrypted = 1;
+                 PRAGMA user_version = 27;
+                 COMMIT;",
+            )?;
+            version = 27;
+        }
+
+        if version < 28 {
+            // Migration 28: libraries synced before the sync high-water mark existed start
+            // it at the newest object they already hold, so sync doesn't walk the whole
+            // history again.
+            conn.execute_batch(&format!(
+                "BEGIN;
+                 INSERT OR IGNORE INTO config (key, value, updated_at)
+                 SELECT '{}', CAST(MAX(CAST(telegram_media_id AS INTEGER)) AS TEXT),
+                        CAST(strftime('%s', 'now') AS INTEGER)
+                 FROM media
+                 WHERE telegram_media_id GLOB '[0-9]*'
+                 HAVING MAX(CAST(telegram_media_id AS INTEGER)) IS NOT NULL;
+                 PRAGMA user_version = 28;
+                 COMMIT;",
+                crate::sync_worker::SYNC_HIGH_WATER_MARK_KEY
+            ))?;
+            version = 28;
+        }
+
+        if version < 29 {
+            // Migration 29: mirror copy message IDs move from a comma-joined column to a
+            // row per part, so they can be looked up by message.
+            conn.execute_batch(
+                "PRAGMA foreign_keys = OFF;
+                 BEGIN;
+                 CREATE TABLE media_copies_new (
+                     media_id INTEGER NOT NULL,
+                     account_id INTEGER NOT NULL,
+                     uploaded_at INTEGER NOT NULL,
+                     PRIMARY KEY (media_id, account_id),
+                     FOREIGN KEY(media_id) REFERENCES media(id) ON DELETE CASCADE
+                 );
+                 INSERT INTO media_copies_new (media_id, account_id, uploaded_at)
+                 SELECT media_id, account_id, uploaded_at FROM media_copies;
+                 CREATE TABLE media_copy_messages (
+                     media_id INTEGER NOT NULL,
+                     account_id INTEGER NOT NULL,
+                     part_index INTEGER NOT NULL,
+                     message_id INTEGER NOT NULL,
+                     PRIMARY KEY (media_id, account_id, part_index),
+                     FOREIGN KEY(media_id, account_id)
+                         REFERENCES media_copies(media_id, account_id) ON DELETE CASCADE
+                 );
+                 WITH RECURSIVE split(media_id, account_id, part_index, id, rest) AS (
+                     SELECT media_id, account_id, -1, '', message_ids || ',' FROM media_copies
+                     UNION ALL
+                     SELECT media_id, account_id, part_index + 1,
+                            trim(substr(rest, 1, instr(rest, ',') - 1)),
+                            substr(rest, instr(rest, ',') + 1)
+                     FROM split WHERE rest != ''
+                 )
+                 INSERT INTO media_copy_messages (media_id, account_id, part_index, message_id)
+                 SELECT media_id, account_id, part_index, CAST(id AS INTEGER)
+                 FROM split WHERE part_index >= 0 AND id GLOB '[0-9]*';
+                 DROP TABLE media_copies;
+                 ALTER TABLE media_copies_new RENAME TO media_copies;
+                 CREATE INDEX IF NOT EXISTS idx_media_copy_messages_message
+                     ON media_copy_messages(account_id, message_id);
+                 PRAGMA user_version = 29;
+                 COMMIT;
+                 PRAGMA foreign_keys = ON;",
+            )?;
+            version = 29;
+        }
+
+        if version < 30 {
+            // Migration 30: the cloud index covers every Telegram account's destination.
+            // Message IDs are per chat, so entries and the indexed position are kept per
+            // account; what was indexed so far is the primary account's.
+            conn.execute_batch(
+                "BEGIN;
+                 CREATE TABLE cloud_index_new (
+                     account_id INTEGER NOT NULL DEFAULT 0,
+                     object_id INTEGER NOT NULL,
+                     file_hash TEXT NOT NULL,
+                     size_bytes INTEGER NOT NULL DEFAULT 0,
+                     encrypted INTEGER NOT NULL DEFAULT 0,
+                     part_index INTEGER NOT NULL DEFAULT 0,
+                     part_count INTEGER NOT NULL DEFAULT 1,
+                     PRIMARY KEY (account_id, object_id)
+                 );
+                 INSERT INTO cloud_index_new
+                     (account_id, object_id, file_hash, size_bytes, encrypted, part_index, part_count)
+                 SELECT 0, object_id, file_hash, size_bytes, encrypted, part_index, part_count
+                 FROM cloud_index;
+                 DROP TABLE cloud_index;
+                 ALTER TABLE cloud_index_new RENAME TO cloud_index;
+                 CREATE INDEX IF NOT EXISTS idx_cloud_index_hash ON cloud_index(file_hash);
+                 CREATE TABLE IF NOT EXISTS cloud_index_marks (
+                     account_id INTEGER PRIMARY KEY,
+                     object_id INTEGER NOT NULL
+                 );
+                 INSERT INTO cloud_index_marks (account_id, object_id)
+                 SELECT 0, CAST(value AS INTEGER) FROM config
+                 WHERE key = 'cloud_index_high_water_mark';
+                 DELETE FROM config WHERE key = 'cloud_index_high_water_mark';
+                 PRAGMA user_version = 30;
+                 COMMIT;",
+            )?;
+            version = 30;
+        }
+
         Ok(())
     }
 
@@ -1104,6 +1440,43 @@ impl Database {
         Ok(conn.last_insert_rowid())
     }
 
+    /// Insert a cloud-only row produced by disaster recovery. There is no local file or
+    /// thumbnail yet; `download_local_copy` materializes it on demand.
+    pub fn add_media_recovered(&self, media: &RecoveredMedia) -> Result<i64> {
+        let conn = self.get_conn()?;
+        conn.execute(
+            "INSERT INTO media (file_path, file_hash, telegram_media_id, mime_type, size_bytes, created_at, uploaded_at,
+                                date_taken, latitude, longitude, camera_make, camera_model, is_favorite, rating,
+                                is_archived, is_encrypted, container_version, is_cloud_only)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, 1)",
+            rusqlite::params![
+                media.file_path,
+                media.file_hash,
+                media.telegram_media_id,
+                media.mime_type,
+                media.size_bytes,
+                media.created_at,
+                media.uploaded_at,
+                media.date_taken,
+                media.latitude,
+                media.longitude,
+                media.camera_make,
+                media.camera_model,
+                media.is_favorite as i32,
+                media.rating,
+                media.is_archived as i32,
+                media.is_encrypted as i32,
+                media.container_version,
+            ],
+        )?;
+        let media_id = conn.last_insert_rowid();
+        let _ = conn.execute(
+            "INSERT INTO media_fts (file_path) VALUES (?1)",
+            [&media.file_path],
+        );
+        Ok(media_id)
+    }
+
     pub fn update_telegram_id(&self, file_hash: &str, telegram_id: &str) -> Result<usize> {
         let conn = self.get_conn()?;
         conn.execute(
@@ -1112,6 +1485,16 @@ impl Database {
         )
     }
 
+    /// Attach a Telegram ID to media matched by hash, leaving existing links untouched.
+    pub fn link_telegram_id_if_missing(&self, file_hash: &str, telegram_id: &str) -> Result<usize> {
+        let conn = self.get_conn()?;
+        conn.execute(
+            "UPDATE media SET telegram_media_id = ?1
+             WHERE file_hash = ?2 AND (telegram_media_id IS NULL OR telegram_media_id = '')",
+            (telegram_id, file_hash),
+        )
+    }
+
     /// Update Telegram ID by file path (used by UploadWorker after successful upload)
     pub fn update_telegram_id_by_path(&self, file_path: &str, telegram_id: &str) -> Result<usize> {
         let conn = self.get_conn()?;
@@ -1121,17 +1504,73 @@ impl Database {
         )
     }
 
-    pub fn mark_media_encrypted_by_path(&self, file_path: &str) -> Result<usize> {
+    /// Record the part layout of a split upload. Replaces any previous layout.
+    /// `parts` holds `(telegram_message_id, size_bytes)` in upload order.
+    pub fn set_media_parts_by_path(&self, file_path: &str, parts: &[(String, i64)]) -> Result<()> {
+        let mut conn = self.get_conn()?;
+        let tx = conn.transaction()?;
+        let media_id: Option<i64> = tx
+            .query_row(
+                "SELECT id FROM media WHERE file_path = ?1",
+                [file_path],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let Some(media_id) = media_id else {
+            return Ok(());
+        };
+
+        tx.execute("DELETE FROM media_parts WHERE media_id = ?1", [media_id])?;
+        for (idx, (msg_id, size)) in parts.iter().enumerate() {
+            tx.execute(
+                "INSERT INTO media_parts (media_id, part_index, telegram_message_id, size_bytes) VALUES (?1, ?2, ?3, ?4)",
+                params![media_id, idx as i64, msg_id, size],
+            )?;
+        }
+        tx.commit()
+    }
+
+    /// Get the message IDs of every part after the first one (the first part is
+    /// `media.telegram_media_id` itself). Used when deleting from Telegram.
+    pub fn get_extra_part_ids(&self, media_id: i64) -> Result<Vec<String>> {
+        let conn = self.get_conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT telegram_message_id FROM media_parts
+             WHERE media_id = ?1 AND part_index > 0
+             ORDER BY part_index ASC",
+        )?;
+        let rows = stmt.query_map([media_id], |row| row.get(0))?;
+        rows.collect()
+    }
+
+    /// Mark the cloud copy encrypted, stored in a `container_version` WBENC container
+    /// under the master key of `key_generation`.
+    pub fn mark_media_encrypted_by_path(
+        &self,
+        file_path: &str,
+        container_version: u8,
+        key_generation: u32,
+    ) -> Result<usize> {
         let conn = self.get_conn()?;
         conn.execute(
-            "UPDATE media SET is_encrypted = 1 WHERE file_path = ?1",
-            [file_path],
+            "UPDATE media SET is_encrypted = 1, container_version = ?2, key_generation = ?3
+             WHERE file_path = ?1",
+            params![file_path, container_version, key_generation],
         )
     }
 
-    pub fn mark_media_encrypted_by_id(&self, media_id: i64) -> Result<usize> {
+    pub fn mark_media_encrypted_by_id(
+        &self,
+        media_id: i64,
+        container_version: u8,
+        key_generation: u32,
+    ) -> Result<usize> {
         let conn = self.get_conn()?;
-        conn.execute("UPDATE media SET is_encrypted = 1 WHERE id = ?1", [media_id])
+        conn.execute(
+            "UPDATE media SET is_encrypted = 1, container_version = ?2, key_generation = ?3
+             WHERE id = ?1",
+            params![media_id, container_version, key_generation],
+        )
     }
 
     pub fn get_uploaded_unencrypted_media(
@@ -1166,6 +1605,70 @@ impl Database {
         Ok(out)
     }
 
+    /// Uploaded media whose encrypted cloud copy uses a container older than `version`.
+    /// Same row shape as [`Self::get_uploaded_unencrypted_media`].
+    pub fn get_media_with_old_container(
+        &self,
+        version: u8,
+        limit: i32,
+    ) -> Result<Vec<(i64, String, String, Option<String>)>> {
+        let conn = self.get_conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, file_path, telegram_media_id, thumbnail_path
+             FROM media
+             WHERE (is_deleted = 0 OR is_deleted IS NULL)
+               AND is_encrypted = 1
+               AND COALESCE(container_version, 1) < ?1
+               AND telegram_media_id IS NOT NULL
+               AND telegram_media_id != ''
+             ORDER BY id ASC
+             LIMIT ?2",
+        )?;
+        let rows = stmt.query_map(params![version, limit], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+        })?;
+        rows.collect()
+    }
+
+    /// Encrypted cloud copies under a master key older than `key_generation`, or under an
+    /// unknown one. Same row shape as [`Self::get_uploaded_unencrypted_media`].
+    pub fn get_media_with_old_key(
+        &self,
+        key_generation: u32,
+        limit: i32,
+    ) -> Result<Vec<(i64, String, String, Option<String>)>> {
+        let conn = self.get_conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, file_path, telegram_media_id, thumbnail_path
+             FROM media
+             WHERE (is_deleted = 0 OR is_deleted IS NULL)
+               AND is_encrypted = 1
+               AND COALESCE(key_generation, 0) < ?1
+               AND telegram_media_id IS NOT NULL
+               AND telegram_media_id != ''
+             ORDER BY id ASC
+             LIMIT ?2",
+        )?;
+        let rows = stmt.query_map(params![key_generation, limit], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+        })?;
+        rows.collect()
+    }
+
+    /// Thumbnails stored as WBENC containers (of any version).
+    pub fn get_encrypted_thumbnail_paths(&self, limit: i32) -> Result<Vec<(i64, String)>> {
+        let conn = self.get_conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, thumbnail_path
+             FROM media
+             WHERE thumbnail_path LIKE '%.wbenc'
+             ORDER BY id ASC
+             LIMIT ?1",
+        )?;
+        let rows = stmt.query_map([limit], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect()
+    }
+
     pub fn get_unencrypted_thumbnail_paths(&self, limit: i32) -> Result<Vec<(i64, String)>> {
         let conn = self.get_conn()?;
         let mut stmt = conn.prepare(
@@ -1663,6 +2166,16 @@ impl Database {
         Ok(media)
     }
 
+    pub fn media_exists_by_path(&self, file_path: &str) -> Result<bool> {
+        let conn = self.get_conn()?;
+        let count: i32 = conn.query_row(
+            "SELECT COUNT(*) FROM media WHERE file_path = ?1",
+            [file_path],
+            |row| row.get(0),
+        )?;
+        Ok(count > 0)
+    }
+
     pub fn media_exists_by_hash(&self, hash: &str) -> Result<bool> {
         let conn = self.get_conn()?;
         let count: i32 = conn.query_row(
@@ -1673,6 +2186,17 @@ impl Database {
         Ok(count > 0)
     }
 
+    /// Count media rows that reference a Telegram message.
+    pub fn count_uploaded_media(&self) -> Result<i64> {
+        let conn = self.get_conn()?;
+        conn.query_row(
+            "SELECT COUNT(*) FROM media
+             WHERE telegram_media_id IS NOT NULL AND telegram_media_id != ''",
+            [],
+            |row| row.get(0),
+        )
+    }
+
     pub fn is_media_uploaded(&self, hash: &str) -> Result<bool> {
         let conn = self.get_conn()?;
         let count: i32 = conn.query_row(
@@ -1688,9 +2212,9 @@ impl Database {
     pub fn add_to_queue(&self, file_path: &str) -> Result<()> {
         let conn = self.get_conn()?;
 
-        // Check if already in queue (pending or uploading)
+        // Check if already in queue (pending, uploading or paused)
         let count: i32 = conn.query_row(
-            "SELECT COUNT(*) FROM upload_queue WHERE file_path = ?1 AND status IN ('pending', 'uploading')",
+            "SELECT COUNT(*) FROM upload_queue WHERE file_path = ?1 AND mirror = 0 AND status IN ('pending', 'uploading', 'paused')",
             [file_path],
             |row| row.get(0),
         )?;
@@ -1708,14 +2232,20 @@ impl Database {
         Ok(())
     }
 
-    pub fn get_next_pending_item(&self) -> Result<Option<QueueItem>> {
+    /// Take the pending item with the highest priority (oldest first among equals) and
+    /// mark it `uploading` in one statement, so concurrent upload tasks never claim the
+    /// same file.
+    pub fn claim_next_pending_item(&self) -> Result<Option<QueueItem>> {
         let conn = self.get_conn()?;
         let mut stmt = conn.prepare(
-            "SELECT id, file_path, status, retries, error_msg, added_at 
-             FROM upload_queue 
-             WHERE status = 'pending' 
-             ORDER BY added_at ASC 
-             LIMIT 1",
+            "UPDATE upload_queue SET status = 'uploading'
+             WHERE id = (
+                 SELECT id FROM upload_queue
+                 WHERE status = 'pending'
+                 ORDER BY priority DESC, added_at ASC
+                 LIMIT 1
+             )
+             RETURNING id, file_path, status, retries, error_msg, added_at, priority",
         )?;
 
         stmt.query_row([], |row| {
@@ -1726,6 +2256,7 @@ impl Database {
                 retries: row.get(3)?,
                 error_msg: row.get(4)?,
                 added_at: row.get(5)?,
+                priority: row.get(6)?,
             })
         })
         .optional()
@@ -1734,9 +2265,9 @@ impl Database {
     pub fn get_queue_status(&self) -> Result<Vec<QueueItem>> {
         let conn = self.get_conn()?;
         let mut stmt = conn.prepare(
-            "SELECT id, file_path, status, retries, error_msg, added_at
+            "SELECT id, file_path, status, retries, error_msg, added_at, priority
              FROM upload_queue
-             ORDER BY added_at DESC
+             ORDER BY priority DESC, added_at DESC
              LIMIT 50",
         )?;
 
@@ -1748,6 +2279,7 @@ impl Database {
                 retries: row.get(3)?,
                 error_msg: row.get(4)?,
                 added_at: row.get(5)?,
+                priority: row.get(6)?,
             })
         })?;
 
@@ -1762,7 +2294,7 @@ impl Database {
         let conn = self.get_conn()?;
         let uploaded_at = OffsetDateTime::now_utc().unix_timestamp();
         conn.execute(
-            "UPDATE media SET uploaded_at = ?1 WHERE file_path = ?2",
+            "UPDATE media SET uploaded_at = ?1, cloud_missing = 0 WHERE file_path = ?2",
             (uploaded_at, path),
         )?;
         Ok(())
@@ -1782,6 +2314,78 @@ impl Database {
         Ok(())
     }
 
+    /// Put items left `uploading` or `rate_limited` by a previous run back in the queue.
+    pub fn reset_interrupted_uploads(&self) -> Result<usize> {
+        let conn = self.get_conn()?;
+        let count = conn.execute(
+            "UPDATE upload_queue SET status = 'pending'
+             WHERE status IN ('uploading', 'rate_limited')",
+            [],
+        )?;
+        Ok(count)
+    }
+
+    pub fn get_upload_checkpoint(&self, queue_id: i64) -> Result<Option<UploadCheckpoint>> {
+        let conn = self.get_conn()?;
+        let row: Option<(Option<String>, Option<String>, Option<i64>, Option<i32>)> = conn
+            .query_row(
+                "SELECT upload_source_hash, upload_sent_parts, upload_file_id, upload_chunks_sent
+                 FROM upload_queue WHERE id = ?1",
+                [queue_id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .optional()?;
+
+        Ok(row.and_then(|(hash, parts, file_id, chunks_sent)| {
+            Some(UploadCheckpoint {
+                source_hash: hash?,
+                sent_parts: parse_sent_parts(parts.as_deref().unwrap_or_default()),
+                file_id: file_id.unwrap_or(0),
+                chunks_sent: chunks_sent.unwrap_or(0),
+            })
+        }))
+    }
+
+    pub fn save_upload_checkpoint(
+        &self,
+        queue_id: i64,
+        checkpoint: &UploadCheckpoint,
+    ) -> Result<()> {
+        let conn = self.get_conn()?;
+        let sent_parts = checkpoint
+            .sent_parts
+            .iter()
+            .map(|(id, size)| format!("{}:{}", id, size))
+            .collect::<Vec<_>>()
+            .join(",");
+        conn.execute(
+            "UPDATE upload_queue
+             SET upload_source_hash = ?1, upload_sent_parts = ?2, upload_file_id = ?3,
+                 upload_chunks_sent = ?4
+             WHERE id = ?5",
+            params![
+                checkpoint.source_hash,
+                sent_parts,
+                checkpoint.file_id,
+                checkpoint.chunks_sent,
+                queue_id
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn clear_upload_checkpoint(&self, queue_id: i64) -> Result<()> {
+        let conn = self.get_conn()?;
+        conn.execute(
+            "UPDATE upload_queue
+             SET upload_source_hash = NULL, upload_sent_parts = NULL, upload_file_id = NULL,
+                 upload_chunks_sent = 0
+             WHERE id = ?1",
+            [queue_id],
+        )?;
+        Ok(())
+    }
+
     pub fn get_queue_counts(&self) -> Result<QueueCounts> {
         let conn = self.get_conn()?;
 
@@ -1803,10 +2407,19 @@ impl Database {
             |row| row.get(0),
         )?;
 
+        let paused: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM upload_queue WHERE status = 'paused'",
+            [],
+            |row| row.get(0),
+        )?;
+
         Ok(QueueCounts {
             pending,
             uploading,
             failed,
+            paused,
+            upload_state: UploadState::Running,
+            bandwidth_kbps: 0,
         })
     }
 
@@ -1819,6 +2432,111 @@ impl Database {
         Ok(())
     }
 
+    pub fn retry_all_failed_items(&self) -> Result<usize> {
+        let conn = self.get_conn()?;
+        conn.execute(
+            "UPDATE upload_queue SET status = 'pending', error_msg = NULL, retries = retries + 1 WHERE status = 'failed'",
+            [],
+        )
+    }
+
+    /// Move a waiting item ahead of everything else in the queue.
+    pub fn prioritize_queue_item(&self, id: i64) -> Result<usize> {
+        let conn = self.get_conn()?;
+        conn.execute(
+            "UPDATE upload_queue
+             SET priority = (SELECT COALESCE(MAX(priority), 0) + 1 FROM upload_queue)
+             WHERE id = ?1 AND status IN ('pending', 'paused')",
+            [id],
+        )
+    }
+
+    /// Change an item's status if it currently has one of `from`. Returns the previous
+    /// status, or `None` when the item is missing or in another state.
+    pub fn transition_queue_item(
+        &self,
+        id: i64,
+        from: &[&str],
+        to: &str,
+    ) -> Result<Option<String>> {
+        let conn = self.get_conn()?;
+        let current: Option<String> = conn
+            .query_row(
+                "SELECT status FROM upload_queue WHERE id = ?1",
+                [id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        match current {
+            Some(status) if from.contains(&status.as_str()) => {
+                conn.execute(
+                    "UPDATE upload_queue SET status = ?1 WHERE id = ?2",
+                    (to, id),
+                )?;
+                Ok(Some(status))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    pub fn get_queue_item_status(&self, id: i64) -> Result<Option<String>> {
+        let conn = self.get_conn()?;
+        conn.query_row(
+            "SELECT status FROM upload_queue WHERE id = ?1",
+            [id],
+            |row| row.get(0),
+        )
+        .optional()
+    }
+
+    /// Remove completed and cancelled items from the queue history.
+    pub fn clear_finished_queue_items(&self) -> Result<usize> {
+        let conn = self.get_conn()?;
+        conn.execute(
+            "DELETE FROM upload_queue WHERE status IN ('completed', 'cancelled')",
+            [],
+        )
+    }
+
+    /// Queue a mirror copy of an uploaded file for `account_id`, unless one is already
+    /// waiting.
+    pub fn add_mirror_to_queue(&self, file_path: &str, account_id: i64) -> Result<()> {
+        let conn = self.get_conn()?;
+        let added_at = OffsetDateTime::now_utc().unix_timestamp();
+        conn.execute(
+            "INSERT INTO upload_queue (file_path, status, added_at, account_id, mirror)
+             SELECT ?1, 'pending', ?2, ?3, 1
+             WHERE NOT EXISTS (
+                 SELECT 1 FROM upload_queue
+                 WHERE file_path = ?1 AND account_id = ?3 AND mirror = 1
+                   AND status IN ('pending', 'uploading', 'paused', 'rate_limited')
+             )",
+            params![file_path, added_at, account_id],
+        )?;
+        Ok(())
+    }
+
+    /// Target account of a queue item (`None` until the worker picks one) and whether
+    /// the item is a mirror copy.
+    pub fn get_queue_item_target(&self, id: i64) -> Result<(Option<i64>, bool)> {
+        let conn = self.get_conn()?;
+        conn.query_row(
+            "SELECT account_id, mirror FROM upload_queue WHERE id = ?1",
+            [id],
+            |row| Ok((row.get(0)?, row.get::<_, i64>(1)? != 0)),
+        )
+    }
+
+    /// Pin a queue item to an account, so a resumed upload continues on the account
+    /// holding its checkpointed parts.
+    pub fn set_queue_item_account(&self, id: i64, account_id: i64) -> Result<usize> {
+        let conn = self.get_conn()?;
+        conn.execute(
+            "UPDATE upload_queue SET account_id = ?1 WHERE id = ?2",
+            params![account_id, id],
+        )
+    }
+
     // --- Bulk Operations ---
 
     /// Set favorite status for multiple media items
@@ -1912,6 +2630,32 @@ impl Database {
         Ok(conn.last_insert_rowid())
     }
 
+    /// Route future uploads of the album's items to `account_id` (`None` = default
+    /// placement).
+    pub fn set_album_account(&self, album_id: i64, account_id: Option<i64>) -> Result<usize> {
+        let conn = self.get_conn()?;
+        conn.execute(
+            "UPDATE albums SET account_id = ?1 WHERE id = ?2",
+            params![account_id, album_id],
+        )
+    }
+
+    /// Account assigned to the oldest album containing the file, if any.
+    pub fn get_album_account_for_path(&self, file_path: &str) -> Result<Option<i64>> {
+        let conn = self.get_conn()?;
+        conn.query_row(
+            "SELECT a.account_id FROM albums a
+             JOIN album_media am ON am.album_id = a.id
+             JOIN media m ON m.id = am.media_id
+             WHERE m.file_path = ?1 AND a.account_id IS NOT NULL
+             ORDER BY a.created_at ASC
+             LIMIT 1",
+            [file_path],
+            |row| row.get(0),
+        )
+        .optional()
+    }
+
     pub fn get_albums(&self) -> Result<Vec<Album>> {
         let conn = self.get_conn()?;
         // Use a subquery to get the first non-archived, non-deleted media item for cover
@@ -1928,7 +2672,8 @@ impl Database {
                      WHERE am2.album_id = a.id
                        AND (m.is_deleted = 0 OR m.is_deleted IS NULL)
                        AND (m.is_archived = 0 OR m.is_archived IS NULL)
-                     ORDER BY am2.added_at DESC LIMIT 1) as cover_file_path
+                     ORDER BY am2.added_at DESC LIMIT 1) as cover_file_path,
+                    a.account_id
              FROM albums a
              ORDER BY a.created_at DESC",
         )?;
@@ -1943,6 +2688,7 @@ impl Database {
                 name: row.get(1)?,
                 created_at: row.get(2)?,
                 cover_path: cover,
+                account_id: row.get(5)?,
             })
         })?;
 
@@ -2310,6 +3056,17 @@ impl Database {
             // Delete media_albums for this media
             tx.execute("DELETE FROM album_media WHERE media_id = ?1", [id])?;
 
+            // Collect extra part messages of split uploads before the rows cascade away
+            {
+                let mut part_stmt = tx.prepare(
+                    "SELECT telegram_message_id FROM media_parts WHERE media_id = ?1 AND part_index > 0",
+                )?;
+                let part_ids = part_stmt.query_map([id], |row| row.get::<_, String>(0))?;
+                for part_id in part_ids {
+                    telegram_ids.push(part_id?);
+                }
+            }
+
             // Delete the media row
             tx.execute("DELETE FROM media WHERE id = ?1", [id])?;
             deleted_count += 1;
@@ -2414,6 +3171,19 @@ impl Database {
         Ok(())
     }
 
+    /// Media whose original is meant to be on this device, trashed items included.
+    pub fn get_local_original_paths(&self) -> Result<Vec<(i64, String)>> {
+        let conn = self.get_conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, file_path
+             FROM media
+             WHERE (is_cloud_only IS NULL OR is_cloud_only = 0)
+             ORDER BY id ASC",
+        )?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect()
+    }
+
     /// Reconcile cloud-only flags against filesystem state.
     /// If local file is missing but Telegram ID exists, mark as cloud-only.
     pub fn reconcile_cloud_only_flags(&self) -> Result<usize> {
@@ -2504,6 +3274,484 @@ impl Database {
         }
     }
 
+    /// Flag media backed by any of `message_ids` (first or later parts) as missing
+    /// from the cloud. Returns the newly flagged items; already flagged and trashed
+    /// items are skipped.
+    pub fn flag_cloud_missing(&self, message_ids: &[i32]) -> Result<Vec<MissingCloudCopy>> {
+        let mut conn = self.get_conn()?;
+        let tx = conn.transaction()?;
+        let mut flagged: Vec<MissingCloudCopy> = Vec::new();
+        {
+            let mut find = tx.prepare(
+                "SELECT id, file_path, is_cloud_only FROM media
+                 WHERE (cloud_missing IS NULL OR cloud_missing = 0)
+                   AND (is_deleted = 0 OR is_deleted IS NULL)
+                   AND account_id = 0
+                   AND (telegram_media_id = ?1
+                        OR id IN (SELECT media_id FROM media_parts WHERE telegram_message_id = ?1))",
+            )?;
+            let mut parts = tx.prepare(
+                "SELECT telegram_message_id FROM media_parts WHERE media_id = ?1
+                 UNION SELECT telegram_media_id FROM media WHERE id = ?1",
+            )?;
+            let deleted: Vec<String> = message_ids.iter().map(|id| id.to_string()).collect();
+
+            for message_id in &deleted {
+                let rows: Vec<(i64, String, Option<i32>)> = find
+                    .query_map([message_id], |row| {
+                        Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+                    })?
+                    .collect::<Result<_>>()?;
+                for (media_id, file_path, is_cloud_only) in rows {
+                    if flagged.iter().any(|m| m.media_id == media_id) {
+                        continue;
+                    }
+                    let surviving_ids = parts
+                        .query_map([media_id], |row| row.get::<_, Option<String>>(0))?
+                        .filter_map(|r| r.ok().flatten())
+                        .filter(|id| !id.is_empty() && !deleted.contains(id))
+                        .collect();
+                    tx.execute(
+                        "UPDATE media SET cloud_missing = 1 WHERE id = ?1",
+                        [media_id],
+                    )?;
+                    flagged.push(MissingCloudCopy {
+                        media_id,
+                        file_path,
+                        is_cloud_only: is_cloud_only.unwrap_or(0) != 0,
+                        surviving_ids,
+                    });
+                }
+            }
+        }
+        tx.commit()?;
+        Ok(flagged)
+    }
+
+    /// Forget the cloud copy of a media item and queue its local file for upload again.
+    /// `cloud_missing` is cleared once the new upload completes.
+    pub fn requeue_missing_upload(&self, media_id: i64, file_path: &str) -> Result<()> {
+        {
+            let mut conn = self.get_conn()?;
+            let tx = conn.transaction()?;
+            tx.execute(
+                "UPDATE media SET telegram_media_id = NULL, uploaded_at = NULL, account_id = 0 WHERE id = ?1",
+                [media_id],
+            )?;
+            tx.execute("DELETE FROM media_parts WHERE media_id = ?1", [media_id])?;
+            tx.commit()?;
+        }
+        self.add_to_queue(file_path)
+    }
+
+    /// Every media row that is uploaded to the primary account or linked to a cloud
+    /// object, with its parts.
+    pub fn get_cloud_audit_rows(&self) -> Result<Vec<CloudAuditRow>> {
+        let conn = self.get_conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, file_path, telegram_media_id, size_bytes, uploaded_at IS NOT NULL,
+                    is_encrypted, is_cloud_only, is_deleted
+             FROM media
+             WHERE account_id = 0
+               AND (uploaded_at IS NOT NULL
+                    OR (telegram_media_id IS NOT NULL AND telegram_media_id != ''))",
+        )?;
+        let mut rows: Vec<CloudAuditRow> = stmt
+            .query_map([], |row| {
+                Ok(CloudAuditRow {
+                    media_id: row.get(0)?,
+                    file_path: row.get(1)?,
+                    telegram_media_id: row.get::<_, Option<String>>(2)?.filter(|id| !id.is_empty()),
+                    size_bytes: row.get(3)?,
+                    uploaded: row.get(4)?,
+                    is_encrypted: row.get::<_, Option<i32>>(5)?.unwrap_or(0) != 0,
+                    is_cloud_only: row.get::<_, Option<i32>>(6)?.unwrap_or(0) != 0,
+                    is_deleted: row.get::<_, Option<i32>>(7)?.unwrap_or(0) != 0,
+                    parts: Vec::new(),
+                })
+            })?
+            .collect::<Result<_>>()?;
+
+        let mut parts = conn.prepare(
+            "SELECT telegram_message_id, size_bytes FROM media_parts
+             WHERE media_id = ?1
+             ORDER BY part_index ASC",
+        )?;
+        for row in rows.iter_mut() {
+            row.parts = parts
+                .query_map([row.media_id], |r| Ok((r.get(0)?, r.get(1)?)))?
+                .collect::<Result<_>>()?;
+        }
+        Ok(rows)
+    }
+
+    pub fn set_media_encrypted_flag(&self, media_id: i64, encrypted: bool) -> Result<usize> {
+        let conn = self.get_conn()?;
+        // The container of a newly found encrypted copy is unknown; assuming the old one
+        // lets the encryption migration bring it up to date.
+        conn.execute(
+            "UPDATE media
+             SET is_encrypted = ?1,
+                 container_version = CASE WHEN ?1 = 1 THEN COALESCE(container_version, 1) END,
+                 key_generation = CASE WHEN ?1 = 1 THEN key_generation END
+             WHERE id = ?2",
+            params![if encrypted { 1 } else { 0 }, media_id],
+        )
+    }
+
+    // --- Telegram Accounts ---
+
+    pub fn list_telegram_accounts(&self) -> Result<Vec<TelegramAccountRow>> {
+        let conn = self.get_conn()?;
+        let mut stmt =
+            conn.prepare("SELECT id, label, created_at FROM telegram_accounts ORDER BY id ASC")?;
+        let rows = stmt.query_map([], |row| {
+            Ok(TelegramAccountRow {
+                id: row.get(0)?,
+                label: row.get(1)?,
+                created_at: row.get(2)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    pub fn add_telegram_account(&self, label: &str) -> Result<i64> {
+        let conn = self.get_conn()?;
+        conn.execute(
+            "INSERT INTO telegram_accounts (label, created_at) VALUES (?1, ?2)",
+            params![label, OffsetDateTime::now_utc().unix_timestamp()],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// Remove an account along with album assignments and queued uploads targeting it.
+    pub fn remove_telegram_account(&self, account_id: i64) -> Result<()> {
+        let mut conn = self.get_conn()?;
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM telegram_accounts WHERE id = ?1", [account_id])?;
+        tx.execute(
+            "UPDATE albums SET account_id = NULL WHERE account_id = ?1",
+            [account_id],
+        )?;
+        tx.execute(
+            "DELETE FROM upload_queue WHERE account_id = ?1 AND mirror = 1",
+            [account_id],
+        )?;
+        tx.execute(
+            "UPDATE upload_queue SET account_id = NULL WHERE account_id = ?1",
+            [account_id],
+        )?;
+        tx.execute(
+            "DELETE FROM cloud_index WHERE account_id = ?1",
+            [account_id],
+        )?;
+        tx.execute(
+            "DELETE FROM cloud_index_marks WHERE account_id = ?1",
+            [account_id],
+        )?;
+        tx.commit()
+    }
+
+    /// Number of media items with a main or mirror copy on the account.
+    pub fn count_media_on_account(&self, account_id: i64) -> Result<i64> {
+        let conn = self.get_conn()?;
+        conn.query_row(
+            "SELECT COUNT(DISTINCT id) FROM (
+                 SELECT id FROM media
+                 WHERE account_id = ?1 AND telegram_media_id IS NOT NULL AND telegram_media_id != ''
+                 UNION ALL
+                 SELECT media_id FROM media_copies WHERE account_id = ?1
+             )",
+            [account_id],
+            |row| row.get(0),
+        )
+    }
+
+    /// Record the account that received the main upload of the file.
+    pub fn set_media_account_by_path(&self, file_path: &str, account_id: i64) -> Result<usize> {
+        let conn = self.get_conn()?;
+        conn.execute(
+            "UPDATE media SET account_id = ?1 WHERE file_path = ?2",
+            params![account_id, file_path],
+        )
+    }
+
+    /// Record a mirror copy of the file on `account_id`, replacing an earlier one.
+    pub fn add_media_copy_by_path(
+        &self,
+        file_path: &str,
+        account_id: i64,
+        message_ids: &[i32],
+    ) -> Result<usize> {
+        let mut conn = self.get_conn()?;
+        let tx = conn.transaction()?;
+        let media_ids: Vec<i64> = {
+            let mut stmt = tx.prepare("SELECT id FROM media WHERE file_path = ?1")?;
+            let rows = stmt.query_map([file_path], |row| row.get(0))?;
+            rows.collect::<Result<_>>()?
+        };
+        let uploaded_at = OffsetDateTime::now_utc().unix_timestamp();
+        for &media_id in &media_ids {
+            tx.execute(
+                "DELETE FROM media_copies WHERE media_id = ?1 AND account_id = ?2",
+                params![media_id, account_id],
+            )?;
+            tx.execute(
+                "INSERT INTO media_copies (media_id, account_id, uploaded_at) VALUES (?1, ?2, ?3)",
+                params![media_id, account_id, uploaded_at],
+            )?;
+            for (part_index, message_id) in message_ids.iter().enumerate() {
+                tx.execute(
+                    "INSERT INTO media_copy_messages (media_id, account_id, part_index, message_id)
+                     VALUES (?1, ?2, ?3, ?4)",
+                    params![media_id, account_id, part_index as i64, message_id],
+                )?;
+            }
+        }
+        tx.commit()?;
+        Ok(media_ids.len())
+    }
+
+    /// Forget the copy of a media item held by `account_id`.
+    pub fn remove_media_copy(&self, media_id: i64, account_id: i64) -> Result<usize> {
+        let conn = self.get_conn()?;
+        conn.execute(
+            "DELETE FROM media_copies WHERE media_id = ?1 AND account_id = ?2",
+            [media_id, account_id],
+        )
+    }
+
+    /// Every cloud copy of a media item, the main copy first.
+    pub fn get_cloud_copies(&self, media_id: i64) -> Result<Vec<CloudCopy>> {
+        self.query_cloud_copies("m.id = ?1", [media_id])
+    }
+
+    /// Cloud copies of every item in the trash.
+    pub fn get_trashed_cloud_copies(&self) -> Result<Vec<CloudCopy>> {
+        self.query_cloud_copies("m.is_deleted = 1", params![])
+    }
+
+    fn query_cloud_copies<P: rusqlite::Params + Copy>(
+        &self,
+        filter: &str,
+        params: P,
+    ) -> Result<Vec<CloudCopy>> {
+        let conn = self.get_conn()?;
+        let parse = |ids: &str| -> Vec<i32> {
+            ids.split(',')
+                .filter_map(|id| id.trim().parse::<i32>().ok())
+                .collect()
+        };
+
+        let mut main = conn.prepare(&format!(
+            "SELECT m.account_id, m.telegram_media_id,
+                    (SELECT group_concat(telegram_message_id, ',') FROM (
+                         SELECT telegram_message_id FROM media_parts
+                         WHERE media_id = m.id AND part_index > 0
+                         ORDER BY part_index ASC))
+             FROM media m
+             WHERE {} AND m.telegram_media_id IS NOT NULL AND m.telegram_media_id != ''",
+            filter
+        ))?;
+        let mut copies: Vec<CloudCopy> = main
+            .query_map(params, |row| {
+                let first: String = row.get(1)?;
+                let rest: Option<String> = row.get(2)?;
+                Ok(CloudCopy {
+                    account_id: row.get(0)?,
+                    message_ids: parse(&first)
+                        .into_iter()
+                        .chain(parse(rest.as_deref().unwrap_or_default()))
+                        .collect(),
+                })
+            })?
+            .collect::<Result<_>>()?;
+
+        let mut mirrors = conn.prepare(&format!(
+            "SELECT c.account_id,
+                    (SELECT group_concat(message_id, ',') FROM (
+                         SELECT message_id FROM media_copy_messages
+                         WHERE media_id = c.media_id AND account_id = c.account_id
+                         ORDER BY part_index ASC))
+             FROM media_copies c
+             JOIN media m ON m.id = c.media_id
+             WHERE {}
+             ORDER BY c.uploaded_at ASC",
+            filter
+        ))?;
+        let mirror_rows = mirrors.query_map(params, |row| {
+            let ids: Option<String> = row.get(1)?;
+            Ok(CloudCopy {
+                account_id: row.get(0)?,
+                message_ids: parse(ids.as_deref().unwrap_or_default()),
+            })
+        })?;
+        for copy in mirror_rows {
+            copies.push(copy?);
+        }
+        copies.retain(|copy| !copy.message_ids.is_empty());
+        Ok(copies)
+    }
+
+    /// The IDs among `object_ids` that no media row refers to any more, as main copy,
+    /// later part or mirror copy. Uploads linked to an existing object share it, so only
+    /// these may be deleted from the cloud. `account_id` limits the check to one
+    /// Telegram account; other backends pass `None`.
+    pub fn unreferenced_objects(
+        &self,
+        account_id: Option<i64>,
+        object_ids: &[i32],
+    ) -> Result<Vec<i32>> {
+        let conn = self.get_conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT EXISTS (
+                 SELECT 1 FROM media
+                 WHERE telegram_media_id = ?1 AND (?2 IS NULL OR account_id = ?2)
+                 UNION ALL
+                 SELECT 1 FROM media_parts p JOIN media m ON m.id = p.media_id
+                 WHERE p.telegram_message_id = ?1 AND (?2 IS NULL OR m.account_id = ?2)
+                 UNION ALL
+                 SELECT 1 FROM media_copy_messages
+                 WHERE message_id = ?3 AND (?2 IS NULL OR account_id = ?2)
+             )",
+        )?;
+        let mut unreferenced = Vec::new();
+        for &id in object_ids {
+            let referenced: bool =
+                stmt.query_row(params![id.to_string(), account_id, id], |row| row.get(0))?;
+            if !referenced && !unreferenced.contains(&id) {
+                unreferenced.push(id);
+            }
+        }
+        Ok(unreferenced)
+    }
+
+    /// `copies` narrowed to the messages no media row refers to any more, see
+    /// [`Self::unreferenced_objects`].
+    pub fn unreferenced_copies(&self, copies: &[CloudCopy]) -> Result<Vec<CloudCopy>> {
+        let mut unreferenced = Vec::new();
+        for copy in copies {
+            let message_ids =
+                self.unreferenced_objects(Some(copy.account_id), &copy.message_ids)?;
+            if !message_ids.is_empty() {
+                unreferenced.push(CloudCopy {
+                    account_id: copy.account_id,
+                    message_ids,
+                });
+            }
+        }
+        Ok(unreferenced)
+    }
+
+    // --- Cloud Index ---
+
+    pub fn add_cloud_index_entries(&self, entries: &[CloudIndexEntry]) -> Result<()> {
+        let mut conn = self.get_conn()?;
+        let tx = conn.transaction()?;
+        for entry in entries {
+            tx.execute(
+                "INSERT OR REPLACE INTO cloud_index
+                     (account_id, object_id, file_hash, size_bytes, encrypted, part_index,
+                      part_count)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    entry.account_id,
+                    entry.object_id,
+                    entry.file_hash,
+                    entry.size_bytes,
+                    entry.encrypted,
+                    entry.part_index,
+                    entry.part_count
+                ],
+            )?;
+        }
+        tx.commit()
+    }
+
+    /// Objects in the account's destination holding the file with `file_hash`, ordered
+    /// by object ID.
+    pub fn get_cloud_index_entries(
+        &self,
+        account_id: i64,
+        file_hash: &str,
+    ) -> Result<Vec<CloudIndexEntry>> {
+        let conn = self.get_conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT account_id, object_id, file_hash, size_bytes, encrypted, part_index,
+                    part_count
+             FROM cloud_index WHERE account_id = ?1 AND file_hash = ?2
+             ORDER BY object_id ASC",
+        )?;
+        let rows = stmt.query_map(params![account_id, file_hash], |row| {
+            Ok(CloudIndexEntry {
+                account_id: row.get(0)?,
+                object_id: row.get(1)?,
+                file_hash: row.get(2)?,
+                size_bytes: row.get(3)?,
+                encrypted: row.get(4)?,
+                part_index: row.get(5)?,
+                part_count: row.get(6)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    pub fn remove_cloud_index_objects(&self, account_id: i64, object_ids: &[i32]) -> Result<usize> {
+        let conn = self.get_conn()?;
+        let mut removed = 0;
+        for id in object_ids {
+            removed += conn.execute(
+                "DELETE FROM cloud_index WHERE account_id = ?1 AND object_id = ?2",
+                params![account_id, id],
+            )?;
+        }
+        Ok(removed)
+    }
+
+    /// Newest object ID indexed from the account's destination.
+    pub fn get_cloud_index_mark(&self, account_id: i64) -> Result<Option<i32>> {
+        let conn = self.get_conn()?;
+        conn.query_row(
+            "SELECT object_id FROM cloud_index_marks WHERE account_id = ?1",
+            [account_id],
+            |row| row.get(0),
+        )
+        .optional()
+    }
+
+    pub fn set_cloud_index_mark(&self, account_id: i64, object_id: i32) -> Result<()> {
+        let conn = self.get_conn()?;
+        conn.execute(
+            "INSERT OR REPLACE INTO cloud_index_marks (account_id, object_id) VALUES (?1, ?2)",
+            params![account_id, object_id],
+        )?;
+        Ok(())
+    }
+
+    /// Forget every account's index and indexed position.
+    pub fn clear_cloud_index(&self) -> Result<usize> {
+        let mut conn = self.get_conn()?;
+        let tx = conn.transaction()?;
+        let removed = tx.execute("DELETE FROM cloud_index", [])?;
+        tx.execute("DELETE FROM cloud_index_marks", [])?;
+        tx.commit()?;
+        Ok(removed)
+    }
+
+    /// Media whose cloud copy was deleted outside the app and could not be re-uploaded.
+    pub fn get_cloud_missing_media(&self) -> Result<Vec<MediaItem>> {
+        let conn = self.get_conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, file_path, file_hash, telegram_media_id, mime_type, width, height, duration, size_bytes, created_at, uploaded_at, thumbnail_path,
+                    date_taken, latitude, longitude, camera_make, camera_model, is_favorite, rating, is_deleted, deleted_at, is_archived, archived_at, is_cloud_only
+             FROM media
+             WHERE cloud_missing = 1 AND uploaded_at IS NOT NULL
+             ORDER BY COALESCE(date_taken, datetime(created_at, 'unixepoch')) DESC"
+        )?;
+        let media_iter = stmt.query_map([], Self::map_media_row)?;
+        media_iter.collect()
+    }
+
     /// Get all archived media items.
     pub fn get_archived_media(&self, limit: i32, offset: i32) -> Result<Vec<MediaItem>> {
         let limit = limit.max(0).min(1000);
@@ -2845,6 +4093,24 @@ impl Database {
         Ok(())
     }
 
+    /// Add to integer config values in one transaction; missing ones start at 0.
+    pub fn add_to_config_counters(&self, deltas: &[(&str, i64)]) -> Result<()> {
+        let mut conn = self.get_conn()?;
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let tx = conn.transaction()?;
+        for (key, delta) in deltas {
+            tx.execute(
+                "INSERT INTO config (key, value, updated_at) VALUES (?1, ?2, ?3)
+                 ON CONFLICT(key) DO UPDATE SET
+                    value = CAST(value AS INTEGER) + excluded.value,
+                    updated_at = excluded.updated_at",
+                (key, delta, now),
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
     /// Delete a config key
     pub fn remove_config(&self, key: &str) -> Result<()> {
         let conn = self.get_conn()?;
@@ -2939,7 +4205,8 @@ impl Database {
                      WHERE am2.album_id = a.id \
                        AND (m.is_deleted = 0 OR m.is_deleted IS NULL) \
                        AND (m.is_archived = 0 OR m.is_archived IS NULL) \
-                     ORDER BY am2.added_at DESC LIMIT 1) as cover_file_path \
+                     ORDER BY am2.added_at DESC LIMIT 1) as cover_file_path, \
+                    a.account_id \
              FROM albums a \
              INNER JOIN album_media am ON a.id = am.album_id \
              WHERE am.media_id = ?1",
@@ -2956,6 +4223,7 @@ impl Database {
                     name: row.get(1)?,
                     created_at: row.get(2)?,
                     cover_path: cover,
+                    account_id: row.get(5)?,
                 })
             })?
             .filter_map(|r| r.ok())
@@ -3019,7 +4287,7 @@ impl Database {
     pub fn get_album_by_name(&self, name: &str) -> Result<Option<Album>> {
         let conn = self.get_conn()?;
         let result = conn.query_row(
-            "SELECT id, name, created_at, NULL as cover_path FROM albums WHERE name = ?1",
+            "SELECT id, name, created_at, NULL as cover_path, account_id FROM albums WHERE name = ?1",
             [name],
             |row| {
                 Ok(Album {
@@ -3027,6 +4295,7 @@ impl Database {
                     name: row.get(1)?,
                     created_at: row.get(2)?,
                     cover_path: row.get(3)?,
+                    account_id: row.get(4)?,
                 })
             },
         );
@@ -3262,3 +4531,13 @@ impl Database {
         tags_iter.collect()
     }
 }
+
+/// Parse the `message_id:size` list stored in `upload_queue.upload_sent_parts`.
+fn parse_sent_parts(raw: &str) -> Vec<(i32, u64)> {
+    raw.split(',')
+        .filter_map(|entry| {
+            let (id, size) = entry.split_once(':')?;
+            Some((id.parse().ok()?, size.parse().ok()?))
+        })
+        .collect()
+}
@@ -0,0 +1,127 @@
+//! Splitting and reassembly of files that exceed Telegram's per-file size limit.
+//!
+//! Large files are uploaded as consecutive byte ranges ("parts"), each sent as its
+//! own message. The part layout is recorded in the `media_parts` table so downloads
+//! can stitch the parts back together transparently.
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Telegram rejects documents larger than 2000 MiB for regular accounts.
+pub const TELEGRAM_MAX_FILE_BYTES: u64 = 2000 * 1024 * 1024;
+
+/// Default size of each uploaded part, leaving headroom below the hard limit.
+pub const DEFAULT_PART_BYTES: u64 = 1900 * 1024 * 1024;
+
+/// A contiguous byte range of the source file that is uploaded as one message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PartRange {
+    pub index: u32,
+    pub offset: u64,
+    pub len: u64,
+}
+
+/// Compute the part layout for a file of `total_bytes`.
+///
+/// Files that fit in a single message produce exactly one part covering the whole file.
+pub fn plan_parts(total_bytes: u64, part_bytes: u64) -> Vec<PartRange> {
+    let part_bytes = part_bytes.clamp(1, TELEGRAM_MAX_FILE_BYTES);
+    if total_bytes <= part_bytes {
+        return vec![PartRange {
+            index: 0,
+            offset: 0,
+            len: total_bytes,
+        }];
+    }
+
+    let mut parts = Vec::new();
+    let mut offset = 0u64;
+    let mut index = 0u32;
+    while offset < total_bytes {
+        let len = part_bytes.min(total_bytes - offset);
+        parts.push(PartRange { index, offset, len });
+        offset += len;
+        index += 1;
+    }
+    parts
+}
+
+/// File name used for a part on the remote side, e.g. `clip.mp4.part002`.
+pub fn part_file_name(file_name: &str, index: u32) -> String {
+    format!("{}.part{:03}", file_name, index + 1)
+}
+
+/// Concatenate downloaded part files (in order) into `output`.
+///
+/// Returns the total number of bytes written.
+pub fn join_files(parts: &[PathBuf], output: &Path) -> std::io::Result<u64> {
+    if let Some(parent) = output.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut writer = BufWriter::new(File::create(output)?);
+    let mut written = 0u64;
+    for part in parts {
+        let mut reader = BufReader::new(File::open(part)?);
+        written += std::io::copy(&mut reader, &mut writer)?;
+    }
+    writer.flush()?;
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_parts_single() {
+        let parts = plan_parts(100, 1000);
+        assert_eq!(parts.len(), 1);
+        assert_eq!(
+            parts[0],
+            PartRange {
+                index: 0,
+                offset: 0,
+                len: 100
+            }
+        );
+    }
+
+    #[test]
+    fn test_plan_parts_split() {
+        let parts = plan_parts(25, 10);
+        assert_eq!(parts.len(), 3);
+        assert_eq!(
+            parts[1],
+            PartRange {
+                index: 1,
+                offset: 10,
+                len: 10
+            }
+        );
+        assert_eq!(
+            parts[2],
+            PartRange {
+                index: 2,
+                offset: 20,
+                len: 5
+            }
+        );
+    }
+
+    #[test]
+    fn test_join_files_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("wanderer-parts-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let a = dir.join("a");
+        let b = dir.join("b");
+        std::fs::write(&a, b"hello ").unwrap();
+        std::fs::write(&b, b"world").unwrap();
+
+        let out = dir.join("out");
+        let written = join_files(&[a, b], &out).unwrap();
+        assert_eq!(written, 11);
+        assert_eq!(std::fs::read(&out).unwrap(), b"hello world");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
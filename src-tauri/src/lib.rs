@@ -1,16 +1,30 @@
+mod accounts;
 mod ai;
+mod album_batch;
+mod audit;
 mod cache;
+mod caption;
 mod clip;
+mod cloud_index;
 mod database;
 mod errors;
+mod file_parts;
+mod local_originals;
+mod media_stream;
 mod media_utils;
 mod metadata;
 mod progress_stream;
+mod proxy;
+mod rate_limit;
 mod raw_support;
+mod recovery;
 mod security;
+mod session_vault;
+mod storage;
 mod sync_manifest;
 mod sync_worker;
 mod telegram;
+mod upload_schedule;
 mod upload_worker;
 mod view_cache;
 mod watcher;
@@ -25,22 +39,39 @@ use tokio::sync::Mutex;
 use tokio_util::sync::CancellationToken;
 
 struct AppState {
+    /// The onboarding account; also `accounts.primary()`
     telegram: Arc<TelegramService>,
+    /// Every registered Telegram account, for routing uploads and downloads
+    accounts: Arc<accounts::AccountPool>,
     db: Mutex<Option<Arc<Database>>>,
     watcher: Mutex<Option<watcher::FileWatcher>>,
     cache: cache::ThumbnailCache,
     security_runtime: Arc<Mutex<RuntimeState>>,
+    /// Uploads in flight, for pausing or cancelling single items
+    active_uploads: Arc<upload_worker::ActiveUploads>,
+    /// Local endpoint forwarding to the configured MTProxy, if one is in use
+    proxy_bridge: Mutex<Option<proxy::MtProxyBridge>>,
     /// Face detector is optional - AI features gracefully degrade if model fails to load
     face_detector: Option<Arc<Mutex<ai::FaceDetector>>>,
 }
 
 const APP_DATA_FALLBACK_DIR_NAME: &str = "com.wanderer.desktop";
+/// Temp directory holding decrypted copies of encrypted media being viewed
+const VIEW_MATERIALIZED_DIR_NAME: &str = "wanderer-view-cache-materialized";
+/// How long locking waits for tasks to let go of decrypted originals
+const PLAIN_COPY_RELEASE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
 const SECURITY_BUNDLE_KEY: &str = "security_bundle_v1";
 const SECURITY_MODE_KEY: &str = "security_mode";
 const SECURITY_ONBOARDING_COMPLETE_KEY: &str = "security_onboarding_complete";
+/// DPAPI-protected credentials saved by earlier versions, moved to the secret store
 const TELEGRAM_CREDS_KEY: &str = "security_telegram_credentials";
+const TELEGRAM_CREDS_SECRET: &str = "telegram_api_credentials";
 const SECURITY_MIGRATION_STATUS_KEY: &str = "security_migration_status";
 const SECURITY_MIGRATION_PENDING_PREFIX: &str = "security_migration_pending_new_msg_";
+const SECURITY_ROTATION_STATUS_KEY: &str = "security_rotation_status";
+const SECURITY_ROTATION_PENDING_PREFIX: &str = "security_rotation_pending_new_msg_";
+const TELEGRAM_DESTINATION_KEY: &str = "telegram_destination_peer";
+const TELEGRAM_DESTINATION_NAME_KEY: &str = "telegram_destination_name";
 
 fn fallback_app_data_dir() -> Result<std::path::PathBuf, String> {
     let base = dirs::data_local_dir()
@@ -75,6 +106,15 @@ struct SecurityStatusResponse {
     encryption_configured: bool,
     encryption_locked: bool,
     telegram_credentials_configured: bool,
+    /// Where secrets are kept, if the store is open
+    secret_store_kind: Option<String>,
+    /// The passphrase-protected secrets file needs unlocking
+    secret_store_locked: bool,
+    /// Whether the secrets file exists, i.e. unlocking asks for its passphrase
+    /// rather than a new one
+    secret_store_created: bool,
+    /// Local originals are kept encrypted
+    local_encryption: bool,
     migration: MigrationStatus,
 }
 
@@ -90,6 +130,15 @@ struct RegenerateRecoveryResponse {
     recovery_key: String,
 }
 
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct KeyRotationStatusResponse {
+    key_generation: u32,
+    /// Whether the retired key is still kept, i.e. the rotation hasn't finished
+    pending: bool,
+    progress: MigrationStatus,
+}
+
 fn load_security_bundle(db: &Database) -> Result<Option<SecurityBundle>, String> {
     let raw = db
         .get_config(SECURITY_BUNDLE_KEY)
@@ -115,18 +164,22 @@ fn save_security_bundle(db: &Database, bundle: &SecurityBundle) -> Result<(), St
     Ok(())
 }
 
-fn load_migration_status(db: &Database) -> MigrationStatus {
-    db.get_config(SECURITY_MIGRATION_STATUS_KEY)
+/// Progress of the background job whose status is saved under `status_key`.
+fn load_migration_status(db: &Database, status_key: &str) -> MigrationStatus {
+    db.get_config(status_key)
         .ok()
         .flatten()
         .and_then(|json| serde_json::from_str::<MigrationStatus>(&json).ok())
         .unwrap_or_default()
 }
 
-fn save_migration_status(db: &Database, status: &MigrationStatus) -> Result<(), String> {
+fn save_migration_status(
+    db: &Database,
+    status_key: &str,
+    status: &MigrationStatus,
+) -> Result<(), String> {
     let json = serde_json::to_string(status).map_err(|e| e.to_string())?;
-    db.set_config(SECURITY_MIGRATION_STATUS_KEY, &json)
-        .map_err(|e| e.to_string())
+    db.set_config(status_key, &json).map_err(|e| e.to_string())
 }
 
 fn ensure_thumbnail_encrypted(
@@ -163,7 +216,11 @@ async fn materialize_thumbnail_path_for_response(
         return Some(thumbnail_path);
     }
 
-    let key = state.security_runtime.lock().await.master_key?;
+    // Thumbnails not re-encrypted yet by a key rotation open with the retired key
+    let keys = state.security_runtime.lock().await.read_keys();
+    if keys.is_empty() {
+        return None;
+    }
     let cache_dir = std::env::temp_dir().join("wanderer-thumb-cache");
     if std::fs::create_dir_all(&cache_dir).is_err() {
         return None;
@@ -183,13 +240,46 @@ async fn materialize_thumbnail_path_for_response(
         true
     };
 
-    if needs_refresh && security::decrypt_file(&src, &output, &key).is_err() {
+    if needs_refresh && security::decrypt_file_with_keys(&src, &output, &keys).is_err() {
         return None;
     }
 
     Some(output.to_string_lossy().to_string())
 }
 
+/// Handler of the `wbstream:` protocol; see [`media_stream`].
+fn handle_stream_request(
+    ctx: tauri::UriSchemeContext<'_, tauri::Wry>,
+    request: tauri::http::Request<Vec<u8>>,
+    responder: tauri::UriSchemeResponder,
+) {
+    let app = ctx.app_handle().clone();
+    tauri::async_runtime::spawn(async move {
+        let state = app.state::<AppState>();
+        let db = state.db.lock().await.clone();
+        let keys = state.security_runtime.lock().await.read_keys();
+        let unavailable = || {
+            tauri::http::Response::builder()
+                .status(tauri::http::StatusCode::SERVICE_UNAVAILABLE)
+                .body(Vec::new())
+                .unwrap_or_default()
+        };
+        let response = match (db, resolve_app_data_dir(&app)) {
+            // Reading and decrypting the range is blocking file I/O
+            (Some(db), Ok(app_dir)) => tokio::task::spawn_blocking(move || {
+                media_stream::respond(&db, &app_dir.join("view_cache"), &keys, &request)
+            })
+            .await
+            .unwrap_or_else(|e| {
+                log::warn!("Media stream request failed: {}", e);
+                unavailable()
+            }),
+            _ => unavailable(),
+        };
+        responder.respond(response);
+    });
+}
+
 async fn materialize_media_items_for_response(
     mut items: Vec<database::MediaItem>,
     state: &State<'_, AppState>,
@@ -201,12 +291,348 @@ async fn materialize_media_items_for_response(
     items
 }
 
+/// Encrypt `source` into `temp_path` for re-upload and build its caption.
+fn encrypt_for_migration(
+    db: &Database,
+    file_path: &str,
+    source: &std::path::Path,
+    temp_path: &std::path::Path,
+    key: &[u8; 32],
+) -> Result<String, String> {
+    let file_hash = media_utils::hash_file_streaming(source).map_err(|e| e.to_string())?;
+    let caption_meta = caption::metadata_for_file(db, file_path, &file_hash, true);
+    let caption_text =
+        caption::build_caption(&caption_meta, Some(key)).map_err(|e| e.to_string())?;
+    let header = security::FileHeader::new(
+        &caption_meta.file_name,
+        caption_meta.mime_type.clone(),
+        &file_hash,
+    );
+    security::encrypt_file_with_header(source, temp_path, key, &header)
+        .map_err(|e| e.to_string())?;
+    Ok(caption_text)
+}
+
 async fn get_active_master_key(state: &State<'_, AppState>) -> Option<[u8; 32]> {
     state.security_runtime.lock().await.master_key
 }
 
+/// Keep the unlocked master key, and the key it replaced while a key rotation is
+/// unfinished, and pick up the originals skipped while the vault was locked.
+async fn set_unlocked_keys(
+    state: &State<'_, AppState>,
+    bundle: &SecurityBundle,
+    master_key: [u8; 32],
+) -> Result<(), String> {
+    let retired_key = bundle
+        .retired_master_key(&master_key)
+        .map_err(|e| e.to_string())?;
+    {
+        let mut runtime = state.security_runtime.lock().await;
+        runtime.master_key = Some(master_key);
+        runtime.key_generation = bundle.key_generation;
+        runtime.retired_key = retired_key;
+    }
+    // Originals the watcher saw while the vault was locked can be read now
+    if let Some(watcher) = state.watcher.lock().await.as_ref() {
+        watcher.rescan_skipped();
+    }
+    Ok(())
+}
+
+/// Whether the library is encrypted and its vault is locked, which keeps the Telegram
+/// sessions sealed.
+async fn is_vault_locked(state: &State<'_, AppState>) -> bool {
+    let encrypted = {
+        let db_guard = state.db.lock().await;
+        db_guard
+            .as_ref()
+            .and_then(|db| load_security_bundle(db).ok().flatten())
+            .map(|bundle| bundle.mode == EncryptionMode::Encrypted)
+            .unwrap_or(false)
+    };
+    encrypted && get_active_master_key(state).await.is_none()
+}
+
+/// Config keys whose values earlier versions kept in plain config. They move to the
+/// secret store under the same name once it is open.
+const PLAIN_CONFIG_SECRETS: &[&str] = &[
+    storage::STORAGE_S3_SECRET_KEY_KEY,
+    proxy::PROXY_PASSWORD_KEY,
+    proxy::PROXY_SECRET_KEY,
+];
+
+fn migrate_plain_config_secrets(store: &dyn security::secret_store::SecretStore, db: &Database) {
+    for &key in PLAIN_CONFIG_SECRETS {
+        let value = match db.get_config(key) {
+            Ok(Some(value)) => value,
+            Ok(None) => continue,
+            Err(e) => {
+                log::warn!("Failed to read {} from config: {}", key, e);
+                continue;
+            }
+        };
+        let moved = if value.is_empty() {
+            Ok(())
+        } else {
+            security::secret_store::save_value(store, key, &value)
+        };
+        match moved.and_then(|()| db.remove_config(key).map_err(Into::into)) {
+            Ok(()) => log::info!("Moved {} to {}", key, store.kind()),
+            Err(e) => log::warn!("Failed to move {} to {}: {}", key, store.kind(), e),
+        }
+    }
+}
+
+/// Load the Telegram API credentials from the secret store, first moving over the
+/// DPAPI-protected copy and other secrets earlier versions kept in the config table.
+async fn load_telegram_credentials(state: &State<'_, AppState>, db: &Database) {
+    let Some(store) = security::secret_store::opened() else {
+        log::info!("Secret store is locked; Telegram API credentials load after unlock");
+        return;
+    };
+    migrate_plain_config_secrets(store.as_ref(), db);
+
+    match db.get_config(TELEGRAM_CREDS_KEY) {
+        Ok(Some(blob)) => {
+            match security::secret_store::migrate_protected_blob(
+                store.as_ref(),
+                TELEGRAM_CREDS_SECRET,
+                &blob,
+            ) {
+                Ok(()) => {
+                    if let Err(e) = db.remove_config(TELEGRAM_CREDS_KEY) {
+                        log::warn!("Failed to remove migrated Telegram credentials: {}", e);
+                    }
+                    log::info!("Moved Telegram API credentials to {}", store.kind());
+                }
+                Err(e) => log::warn!("Failed to migrate stored Telegram credentials: {}", e),
+            }
+        }
+        Ok(None) => {}
+        Err(e) => log::warn!("Failed to read Telegram credentials from config: {}", e),
+    }
+
+    match security::secret_store::load_value::<TelegramApiCredentials>(
+        store.as_ref(),
+        TELEGRAM_CREDS_SECRET,
+    ) {
+        Ok(Some(creds)) => {
+            state
+                .telegram
+                .set_credentials(creds.api_id, creds.api_hash.clone())
+                .await;
+            state
+                .accounts
+                .set_credentials(creds.api_id, &creds.api_hash)
+                .await;
+            log::info!("Loaded Telegram API credentials from {}", store.kind());
+        }
+        Ok(None) => {}
+        Err(e) => log::warn!("Failed to load stored Telegram credentials: {}", e),
+    }
+}
+
+/// Restore the sealed Telegram sessions and reconnect every account after the vault
+/// is unlocked.
+async fn unlock_sessions(
+    state: &State<'_, AppState>,
+    app: &tauri::AppHandle,
+    master_key: &[u8; 32],
+) -> Result<(), String> {
+    let db = {
+        let db_guard = state.db.lock().await;
+        db_guard.as_ref().ok_or("Database not initialized")?.clone()
+    };
+    let app_dir = resolve_app_data_dir(app)?;
+    let key = session_vault::session_key(master_key);
+    let retired_key = state.security_runtime.lock().await.retired_key;
+    for dir in accounts::session_dirs(&db, &app_dir) {
+        let mut result = session_vault::unseal(&dir, &key);
+        // A key rotation interrupted before resealing leaves sessions under the old key
+        if let (Err(_), Some(retired)) = (&result, retired_key) {
+            result = session_vault::unseal(&dir, &session_vault::session_key(&retired))
+                .and_then(|_| session_vault::snapshot(&dir, &key).map(|_| ()));
+        }
+        if let Err(e) = result {
+            log::warn!("Failed to unseal Telegram session in {:?}: {}", dir, e);
+        }
+    }
+
+    if state.telegram.has_credentials().await && !state.telegram.is_connected().await {
+        if let Err(e) = state.telegram.connect(app_dir.clone()).await {
+            log::warn!("Failed to connect to Telegram: {}", e);
+        }
+    }
+    state.accounts.open_saved(&db, &app_dir).await;
+    Ok(())
+}
+
+/// Disconnect every account and seal its session, leaving no usable login on disk.
+async fn lock_sessions(
+    state: &State<'_, AppState>,
+    app: &tauri::AppHandle,
+    master_key: &[u8; 32],
+) -> Result<(), String> {
+    let db = {
+        let db_guard = state.db.lock().await;
+        db_guard.as_ref().ok_or("Database not initialized")?.clone()
+    };
+    let app_dir = resolve_app_data_dir(app)?;
+    state.accounts.disconnect_all().await;
+    let key = session_vault::session_key(master_key);
+    for dir in accounts::session_dirs(&db, &app_dir) {
+        session_vault::seal(&dir, &key).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Update the sealed copy of an account's session after a login, so a crash before
+/// the next lock doesn't lose it.
+async fn snapshot_session(
+    state: &State<'_, AppState>,
+    app: &tauri::AppHandle,
+    account_id: Option<i64>,
+) {
+    let Some(master_key) = get_active_master_key(state).await else {
+        return;
+    };
+    let Ok(app_dir) = resolve_app_data_dir(app) else {
+        return;
+    };
+    let dir = accounts::session_dir(&app_dir, account_id.unwrap_or(accounts::PRIMARY_ACCOUNT));
+    if let Err(e) = session_vault::snapshot(&dir, &session_vault::session_key(&master_key)) {
+        log::warn!("Failed to seal Telegram session in {:?}: {}", dir, e);
+    }
+}
+
+/// Download the raw blob of a media item's cloud copy, reassembling split uploads from
+/// their parts.
+async fn download_media_blob(
+    state: &State<'_, AppState>,
+    media_id: i64,
+    msg_id: i32,
+    path: &str,
+) -> Result<(), String> {
+    let db = state.db.lock().await.clone();
+    download_cloud_blob(db.as_deref(), &state.accounts, media_id, msg_id, path).await
+}
+
+/// Download a cloud copy into the view cache in the background, kept encrypted under
+/// `key`. The stream protocol serves it from the staging file while it arrives.
+fn start_view_download(
+    app: &tauri::AppHandle,
+    media_id: i64,
+    msg_id: i32,
+    cache_blob_path: &std::path::Path,
+    key: [u8; 32],
+) -> Result<Arc<media_stream::PartialBlob>, String> {
+    let staging_dir = std::env::temp_dir().join("wanderer-view-cache-staging");
+    std::fs::create_dir_all(&staging_dir).map_err(|e| e.to_string())?;
+    let raw_download_path = staging_dir.join(format!(
+        "view_{}_{}.bin",
+        media_id,
+        time::OffsetDateTime::now_utc().unix_timestamp_nanos()
+    ));
+    let partial = media_stream::begin_download(media_id, raw_download_path.clone());
+
+    let app = app.clone();
+    let cache_blob_path = cache_blob_path.to_path_buf();
+    let download = partial.clone();
+    tauri::async_runtime::spawn(async move {
+        let state = app.state::<AppState>();
+        let raw_download_str = raw_download_path.to_string_lossy().to_string();
+        let outcome = match download_media_blob(&state, media_id, msg_id, &raw_download_str).await {
+            Ok(()) => seal_view_download(&raw_download_path, &cache_blob_path, &key),
+            Err(e) => Err(format!("Failed to download from Telegram: {}", e)),
+        };
+        let _ = std::fs::remove_file(&raw_download_path);
+        if let Err(e) = &outcome {
+            log::warn!("View download of media {} failed: {}", media_id, e);
+        }
+        media_stream::finish_download(media_id, &download, outcome);
+    });
+    Ok(partial)
+}
+
+/// Move a finished view download into the cache, encrypting it first if it was stored
+/// in plaintext.
+fn seal_view_download(
+    raw_download_path: &std::path::Path,
+    cache_blob_path: &std::path::Path,
+    key: &[u8; 32],
+) -> Result<(), String> {
+    let downloaded_is_encrypted =
+        security::is_encrypted_file(raw_download_path).map_err(|e| e.to_string())?;
+    if !downloaded_is_encrypted {
+        return security::encrypt_file(raw_download_path, cache_blob_path, key)
+            .map_err(|e| e.to_string());
+    }
+    if std::fs::rename(raw_download_path, cache_blob_path).is_err() {
+        std::fs::copy(raw_download_path, cache_blob_path).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// `download_media_blob` for background tasks that hold the services directly.
+/// The blob is written as stored, still encrypted if it was. Telegram copies are tried
+/// in turn, so a mirror stands in when the account holding the main copy is offline.
+async fn download_cloud_blob(
+    db: Option<&Database>,
+    accounts: &accounts::AccountPool,
+    media_id: i64,
+    msg_id: i32,
+    path: &str,
+) -> Result<(), String> {
+    if let Some(backend) = db.map(storage::open_external).transpose()?.flatten() {
+        return backend.download(msg_id, std::path::Path::new(path)).await;
+    }
+
+    let copies = match db {
+        Some(db) => db.get_cloud_copies(media_id).map_err(|e| e.to_string())?,
+        None => Vec::new(),
+    };
+    if copies.is_empty() {
+        return accounts
+            .primary()
+            .download_by_message_id(msg_id, path)
+            .await;
+    }
+
+    let mut last_error = String::new();
+    for copy in copies {
+        let Some(telegram) = accounts.get(copy.account_id).await else {
+            last_error = format!("Telegram account {} is not connected", copy.account_id);
+            continue;
+        };
+        let result = if copy.message_ids.len() > 1 {
+            telegram
+                .download_message_parts(&copy.message_ids, path)
+                .await
+        } else {
+            telegram
+                .download_by_message_id(copy.message_ids[0], path)
+                .await
+        };
+        match result {
+            Ok(()) => return Ok(()),
+            Err(e) => {
+                log::warn!(
+                    "Download of media {} from account {} failed: {}",
+                    media_id,
+                    copy.account_id,
+                    e
+                );
+                last_error = e;
+            }
+        }
+    }
+    Err(last_error)
+}
+
 async fn download_and_materialize_media(
     state: &State<'_, AppState>,
+    media_id: i64,
     msg_id: i32,
     final_path: &std::path::Path,
 ) -> Result<(), String> {
@@ -223,14 +649,12 @@ async fn download_and_materialize_media(
     ));
     let temp_path_str = temp_path.to_string_lossy().to_string();
 
-    state
-        .telegram
-        .download_by_message_id(msg_id, &temp_path_str)
+    download_media_blob(state, media_id, msg_id, &temp_path_str)
         .await
         .map_err(|e| format!("Failed to download from Telegram: {}", e))?;
 
-    let maybe_key = get_active_master_key(state).await;
-    let result = security::decrypt_file_if_needed(&temp_path, final_path, maybe_key.as_ref())
+    let keys = state.security_runtime.lock().await.read_keys();
+    let result = security::decrypt_file_with_keys_if_needed(&temp_path, final_path, &keys)
         .map_err(|e| e.to_string());
 
     let _ = std::fs::remove_file(&temp_path);
@@ -239,6 +663,7 @@ async fn download_and_materialize_media(
 
 async fn get_security_status_inner(
     state: &State<'_, AppState>,
+    app: &tauri::AppHandle,
 ) -> Result<SecurityStatusResponse, String> {
     let db_guard = state.db.lock().await;
     let db = db_guard.as_ref().ok_or("Database not initialized")?;
@@ -265,10 +690,22 @@ async fn get_security_status_inner(
         false
     };
 
-    let telegram_credentials_configured = db
-        .get_config(TELEGRAM_CREDS_KEY)
-        .map_err(|e| e.to_string())?
-        .is_some();
+    let secrets = security::secret_store::opened();
+    let stored_credentials = match &secrets {
+        Some(store) => store
+            .get(TELEGRAM_CREDS_SECRET)
+            .map_err(|e| e.to_string())?
+            .is_some(),
+        None => false,
+    };
+    let telegram_credentials_configured = stored_credentials
+        || db
+            .get_config(TELEGRAM_CREDS_KEY)
+            .map_err(|e| e.to_string())?
+            .is_some();
+    let secret_store_created = resolve_app_data_dir(app)?
+        .join(security::secret_store::SECRETS_FILE)
+        .exists();
 
     let runtime_migration = state.security_runtime.lock().await.migration.clone();
     let migration = if runtime_migration.total == 0
@@ -276,7 +713,7 @@ async fn get_security_status_inner(
         && runtime_migration.succeeded == 0
         && runtime_migration.failed == 0
     {
-        load_migration_status(db)
+        load_migration_status(db, SECURITY_MIGRATION_STATUS_KEY)
     } else {
         runtime_migration
     };
@@ -287,13 +724,20 @@ async fn get_security_status_inner(
         encryption_configured,
         encryption_locked,
         telegram_credentials_configured,
+        secret_store_kind: secrets.as_ref().map(|store| store.kind().to_string()),
+        secret_store_locked: secrets.is_none(),
+        secret_store_created,
+        local_encryption: local_originals::is_enabled(db),
         migration,
     })
 }
 
 #[tauri::command]
-async fn get_security_status(state: State<'_, AppState>) -> Result<SecurityStatusResponse, String> {
-    get_security_status_inner(&state).await
+async fn get_security_status(
+    state: State<'_, AppState>,
+    app: tauri::AppHandle,
+) -> Result<SecurityStatusResponse, String> {
+    get_security_status_inner(&state, &app).await
 }
 
 #[tauri::command]
@@ -317,6 +761,7 @@ async fn initialize_unencrypted_mode(state: State<'_, AppState>) -> Result<(), S
 async fn initialize_encryption(
     passphrase: String,
     state: State<'_, AppState>,
+    app: tauri::AppHandle,
 ) -> Result<InitializeEncryptionResponse, String> {
     {
         let db_guard = state.db.lock().await;
@@ -334,14 +779,21 @@ async fn initialize_encryption(
     let db_guard = state.db.lock().await;
     let db = db_guard.as_ref().ok_or("Database not initialized")?;
     save_security_bundle(db, &bundle)?;
+    drop(db_guard);
 
-    state.security_runtime.lock().await.master_key = Some(master_key);
+    set_unlocked_keys(&state, &bundle, master_key).await?;
+    // Seals the existing plaintext sessions
+    unlock_sessions(&state, &app, &master_key).await?;
 
     Ok(InitializeEncryptionResponse { recovery_key })
 }
 
 #[tauri::command]
-async fn unlock_encryption(passphrase: String, state: State<'_, AppState>) -> Result<(), String> {
+async fn unlock_encryption(
+    passphrase: String,
+    state: State<'_, AppState>,
+    app: tauri::AppHandle,
+) -> Result<(), String> {
     let db_guard = state.db.lock().await;
     let db = db_guard.as_ref().ok_or("Database not initialized")?;
     let bundle = load_security_bundle(db)?
@@ -353,13 +805,32 @@ async fn unlock_encryption(passphrase: String, state: State<'_, AppState>) -> Re
         .unlock_with_passphrase(&passphrase)
         .map_err(|e| e.to_string())?;
     drop(db_guard);
-    state.security_runtime.lock().await.master_key = Some(key);
-    Ok(())
+    set_unlocked_keys(&state, &bundle, key).await?;
+    unlock_sessions(&state, &app, &key).await
 }
 
 #[tauri::command]
-async fn lock_encryption(state: State<'_, AppState>) -> Result<(), String> {
-    state.security_runtime.lock().await.master_key = None;
+async fn lock_encryption(state: State<'_, AppState>, app: tauri::AppHandle) -> Result<(), String> {
+    // Seal the sessions while the key is still there; if that fails the vault stays
+    // unlocked rather than leaving sessions that can't be sealed any more
+    let key = state.security_runtime.lock().await.master_key;
+    if let Some(key) = key {
+        lock_sessions(&state, &app, &key).await?;
+    }
+    {
+        let mut runtime = state.security_runtime.lock().await;
+        runtime.retired_key = None;
+        runtime.master_key = None;
+    }
+    // No new copies can be decrypted now. Stop the uploads reading one (they go back to
+    // the queue) and give the AI scan time to finish its item before deleting them.
+    state.active_uploads.interrupt_all();
+    let deadline = tokio::time::Instant::now() + PLAIN_COPY_RELEASE_TIMEOUT;
+    while local_originals::held_copies() > 0 && tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    local_originals::clear_plain_copies();
+    let _ = std::fs::remove_dir_all(std::env::temp_dir().join(VIEW_MATERIALIZED_DIR_NAME));
     Ok(())
 }
 
@@ -368,6 +839,7 @@ async fn recover_encryption(
     recovery_key: String,
     new_passphrase: String,
     state: State<'_, AppState>,
+    app: tauri::AppHandle,
 ) -> Result<(), String> {
     let db_guard = state.db.lock().await;
     let db = db_guard.as_ref().ok_or("Database not initialized")?;
@@ -378,14 +850,15 @@ async fn recover_encryption(
         .map_err(|e| e.to_string())?;
     save_security_bundle(db, &next_bundle)?;
     drop(db_guard);
-    state.security_runtime.lock().await.master_key = Some(key);
-    Ok(())
+    set_unlocked_keys(&state, &next_bundle, key).await?;
+    unlock_sessions(&state, &app, &key).await
 }
 
 #[tauri::command]
 async fn regenerate_recovery_key(
     passphrase: String,
     state: State<'_, AppState>,
+    app: tauri::AppHandle,
 ) -> Result<RegenerateRecoveryResponse, String> {
     let db_guard = state.db.lock().await;
     let db = db_guard.as_ref().ok_or("Database not initialized")?;
@@ -396,7 +869,8 @@ async fn regenerate_recovery_key(
         .map_err(|e| e.to_string())?;
     save_security_bundle(db, &next_bundle)?;
     drop(db_guard);
-    state.security_runtime.lock().await.master_key = Some(key);
+    set_unlocked_keys(&state, &next_bundle, key).await?;
+    unlock_sessions(&state, &app, &key).await?;
     Ok(RegenerateRecoveryResponse { recovery_key })
 }
 
@@ -426,12 +900,14 @@ async fn set_telegram_api_credentials(
         api_hash: api_hash.trim().to_string(),
     };
 
-    let protected_blob = security::serialize_and_protect(&creds, "wanderer-telegram-credentials")
+    let store = security::secret_store::opened()
+        .ok_or("Unlock the secret store before saving credentials")?;
+    security::secret_store::save_value(store.as_ref(), TELEGRAM_CREDS_SECRET, &creds)
         .map_err(|e| e.to_string())?;
 
     let db_guard = state.db.lock().await;
     let db = db_guard.as_ref().ok_or("Database not initialized")?;
-    db.set_config(TELEGRAM_CREDS_KEY, &protected_blob)
+    db.remove_config(TELEGRAM_CREDS_KEY)
         .map_err(|e| e.to_string())?;
     drop(db_guard);
 
@@ -439,6 +915,10 @@ async fn set_telegram_api_credentials(
         .telegram
         .set_credentials(creds.api_id, creds.api_hash.clone())
         .await;
+    state
+        .accounts
+        .set_credentials(creds.api_id, &creds.api_hash)
+        .await;
     Ok(())
 }
 
@@ -447,6 +927,11 @@ async fn clear_telegram_api_credentials(
     state: State<'_, AppState>,
     app: tauri::AppHandle,
 ) -> Result<(), String> {
+    if let Some(store) = security::secret_store::opened() {
+        store
+            .delete(TELEGRAM_CREDS_SECRET)
+            .map_err(|e| e.to_string())?;
+    }
     {
         let db_guard = state.db.lock().await;
         let db = db_guard.as_ref().ok_or("Database not initialized")?;
@@ -454,11 +939,56 @@ async fn clear_telegram_api_credentials(
             .map_err(|e| e.to_string())?;
     }
     let app_dir = resolve_app_data_dir(&app)?;
-    let _ = state.telegram.logout(app_dir).await;
+    let _ = state.telegram.logout(app_dir.clone()).await;
+    let _ = session_vault::remove_sealed(&app_dir);
     state.telegram.clear_credentials().await;
     Ok(())
 }
 
+/// Open the passphrase-protected secrets file, creating it on first use, on machines
+/// without a platform secret store.
+#[tauri::command]
+async fn unlock_secret_store(
+    passphrase: String,
+    state: State<'_, AppState>,
+    app: tauri::AppHandle,
+) -> Result<(), String> {
+    let app_dir = resolve_app_data_dir(&app)?;
+    let path = app_dir.join(security::secret_store::SECRETS_FILE);
+    if !path.exists() && passphrase.chars().count() < 8 {
+        return Err("Passphrase must be at least 8 characters".to_string());
+    }
+    let store =
+        security::secret_store::FileStore::open(&path, &passphrase).map_err(|e| e.to_string())?;
+    security::secret_store::set_opened(Arc::new(store));
+
+    let db = {
+        let db_guard = state.db.lock().await;
+        db_guard.as_ref().ok_or("Database not initialized")?.clone()
+    };
+    load_telegram_credentials(&state, &db).await;
+
+    // The proxy password and secret are kept in the store too, so startup applied the
+    // proxy settings without them
+    if !state.telegram.is_connected().await {
+        if let Err(e) = apply_proxy_settings(&state, &proxy::ProxySettings::load(&db)).await {
+            log::warn!("Failed to apply proxy settings: {}", e);
+        }
+    }
+
+    // Startup skipped connecting without credentials; a locked vault connects on unlock
+    if !is_vault_locked(&state).await
+        && state.telegram.has_credentials().await
+        && !state.telegram.is_connected().await
+    {
+        if let Err(e) = state.telegram.connect(app_dir.clone()).await {
+            log::warn!("Failed to connect to Telegram: {}", e);
+        }
+        state.accounts.open_saved(&db, &app_dir).await;
+    }
+    Ok(())
+}
+
 #[tauri::command]
 async fn get_encryption_migration_status(
     state: State<'_, AppState>,
@@ -471,12 +1001,33 @@ async fn get_encryption_migration_status(
     {
         let db_guard = state.db.lock().await;
         let db = db_guard.as_ref().ok_or("Database not initialized")?;
-        Ok(load_migration_status(db))
+        Ok(load_migration_status(db, SECURITY_MIGRATION_STATUS_KEY))
     } else {
         Ok(runtime_status)
     }
 }
 
+/// Turn encryption of local originals on or off. The encryption migration rewrites the
+/// originals already in the library.
+#[tauri::command]
+async fn set_local_encryption(enabled: bool, state: State<'_, AppState>) -> Result<(), String> {
+    let db = {
+        let db_guard = state.db.lock().await;
+        db_guard.as_ref().ok_or("Database not initialized")?.clone()
+    };
+
+    let bundle = load_security_bundle(&db)?;
+    if bundle.map(|b| b.mode) != Some(EncryptionMode::Encrypted) {
+        return Err("Enable encryption before encrypting local originals".to_string());
+    }
+    if is_vault_locked(&state).await {
+        return Err("Unlock encryption before changing local encryption".to_string());
+    }
+
+    local_originals::set_enabled(&db, enabled)?;
+    start_encryption_migration(state).await
+}
+
 #[tauri::command]
 async fn start_encryption_migration(state: State<'_, AppState>) -> Result<(), String> {
     let db = {
@@ -493,19 +1044,55 @@ async fn start_encryption_migration(state: State<'_, AppState>) -> Result<(), St
         return Err("Encryption mode is not enabled".to_string());
     }
 
-    let key = state
-        .security_runtime
-        .lock()
-        .await
-        .master_key
-        .ok_or_else(|| "Unlock encryption before starting migration".to_string())?;
+    let (key, key_generation, read_keys) = {
+        let runtime = state.security_runtime.lock().await;
+        let key = runtime
+            .master_key
+            .ok_or_else(|| "Unlock encryption before starting migration".to_string())?;
+        if runtime.rotation_worker_active {
+            return Err("Wait for the key rotation to finish".to_string());
+        }
+        (key, runtime.key_generation, runtime.read_keys())
+    };
 
-    let cloud_items = db
+    // Copies in the v1 container are re-encrypted like plaintext ones
+    let mut cloud_items = db
         .get_uploaded_unencrypted_media(1_000_000)
         .map_err(|e| e.to_string())?;
+    cloud_items.extend(
+        db.get_media_with_old_container(security::CONTAINER_VERSION, 1_000_000)
+            .map_err(|e| e.to_string())?,
+    );
     let thumb_items = db
         .get_unencrypted_thumbnail_paths(1_000_000)
         .map_err(|e| e.to_string())?;
+    let old_thumbs: Vec<(i64, String)> = db
+        .get_encrypted_thumbnail_paths(1_000_000)
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .filter(|(_, path)| {
+            security::container_version(std::path::Path::new(path))
+                .ok()
+                .flatten()
+                .is_some_and(|version| version < security::CONTAINER_VERSION)
+        })
+        .collect();
+    // Originals still to be encrypted with local encryption on, or decrypted after
+    // turning it off
+    let local_setting = local_originals::setting(&db);
+    let local_items: Vec<String> = match local_setting {
+        Some(encrypt) => db
+            .get_local_original_paths()
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .map(|(_, path)| path)
+            .filter(|path| {
+                security::is_encrypted_file(std::path::Path::new(path))
+                    .is_ok_and(|encrypted| encrypted != encrypt)
+            })
+            .collect(),
+        None => Vec::new(),
+    };
 
     {
         let mut runtime = state.security_runtime.lock().await;
@@ -515,18 +1102,26 @@ async fn start_encryption_migration(state: State<'_, AppState>) -> Result<(), St
         runtime.migration_worker_active = true;
         runtime.migration = MigrationStatus {
             running: true,
-            total: (cloud_items.len() + thumb_items.len()) as i64,
+            total: (cloud_items.len() + thumb_items.len() + old_thumbs.len() + local_items.len())
+                as i64,
             processed: 0,
             succeeded: 0,
             failed: 0,
             last_error: None,
         };
-        let _ = save_migration_status(&db, &runtime.migration);
+        let _ = save_migration_status(&db, SECURITY_MIGRATION_STATUS_KEY, &runtime.migration);
     }
 
     let runtime = state.security_runtime.clone();
-    let telegram = state.telegram.clone();
-    let pending_prefix = SECURITY_MIGRATION_PENDING_PREFIX.to_string();
+    let job = ReencryptJob {
+        db: db.clone(),
+        accounts: state.accounts.clone(),
+        external: storage::open_external(&db)?,
+        key,
+        key_generation,
+        read_keys,
+        pending_prefix: SECURITY_MIGRATION_PENDING_PREFIX,
+    };
 
     tokio::spawn(async move {
         for (media_id, thumb_path) in thumb_items {
@@ -554,127 +1149,1009 @@ async fn start_encryption_migration(state: State<'_, AppState>) -> Result<(), St
                     state_guard.migration.last_error = Some(err);
                 }
             }
-            let _ = save_migration_status(&db, &state_guard.migration);
+            let _ =
+                save_migration_status(&db, SECURITY_MIGRATION_STATUS_KEY, &state_guard.migration);
+        }
+
+        for (_, thumb_path) in old_thumbs {
+            let result = security::upgrade_container(std::path::Path::new(&thumb_path), &key)
+                .map_err(|e| e.to_string());
+
+            let mut state_guard = runtime.lock().await;
+            state_guard.migration.processed += 1;
+            match result {
+                Ok(_) => state_guard.migration.succeeded += 1,
+                Err(err) => {
+                    state_guard.migration.failed += 1;
+                    state_guard.migration.last_error = Some(err);
+                }
+            }
+            let _ =
+                save_migration_status(&db, SECURITY_MIGRATION_STATUS_KEY, &state_guard.migration);
+        }
+
+        let mut local_failed = false;
+        for file_path in local_items {
+            let path = std::path::Path::new(&file_path);
+            let result = if local_setting == Some(true) {
+                local_originals::encrypt_in_place(path, &key)
+            } else {
+                local_originals::decrypt_in_place(path, &job.read_keys)
+            };
+            local_failed |= result.is_err();
+
+            let mut state_guard = runtime.lock().await;
+            state_guard.migration.processed += 1;
+            match result {
+                Ok(_) => state_guard.migration.succeeded += 1,
+                Err(err) => {
+                    state_guard.migration.failed += 1;
+                    state_guard.migration.last_error = Some(err);
+                }
+            }
+            let _ =
+                save_migration_status(&db, SECURITY_MIGRATION_STATUS_KEY, &state_guard.migration);
+        }
+        if local_setting == Some(false) && !local_failed {
+            if let Err(e) = local_originals::finish_disabling(&db) {
+                log::warn!("Failed to finish turning off local encryption: {}", e);
+            }
+        }
+
+        for (media_id, file_path, previous_tg_id, thumbnail_path) in cloud_items {
+            let result = job
+                .replace_cloud_copy(
+                    media_id,
+                    &file_path,
+                    &previous_tg_id,
+                    thumbnail_path.as_deref(),
+                )
+                .await;
+
+            let mut state_guard = runtime.lock().await;
+            state_guard.migration.processed += 1;
+            match result {
+                Ok(_) => state_guard.migration.succeeded += 1,
+                Err(err) => {
+                    state_guard.migration.failed += 1;
+                    state_guard.migration.last_error = Some(err);
+                }
+            }
+            let _ =
+                save_migration_status(&db, SECURITY_MIGRATION_STATUS_KEY, &state_guard.migration);
+        }
+
+        let mut state_guard = runtime.lock().await;
+        state_guard.migration.running = false;
+        state_guard.migration_worker_active = false;
+        let _ = save_migration_status(&db, SECURITY_MIGRATION_STATUS_KEY, &state_guard.migration);
+    });
+
+    Ok(())
+}
+
+/// Replaces cloud copies with uploads in the current container, encrypted with the
+/// current master key. Shared by the encryption migration and key rotation.
+struct ReencryptJob {
+    db: Arc<Database>,
+    accounts: Arc<accounts::AccountPool>,
+    external: Option<Arc<dyn storage::StorageBackend>>,
+    key: [u8; 32],
+    key_generation: u32,
+    /// Keys that may open the existing copies
+    read_keys: Vec<[u8; 32]>,
+    /// Config key prefix remembering a new upload until the old copy is replaced
+    pending_prefix: &'static str,
+}
+
+impl ReencryptJob {
+    async fn replace_cloud_copy(
+        &self,
+        media_id: i64,
+        file_path: &str,
+        previous_tg_id: &str,
+        thumbnail_path: Option<&str>,
+    ) -> Result<(), String> {
+        let db = &self.db;
+        let key = &self.key;
+        let pending_key = format!("{}{}", self.pending_prefix, media_id);
+
+        let copies = db.get_cloud_copies(media_id).map_err(|e| e.to_string())?;
+        let backend: Arc<dyn storage::StorageBackend> = match &self.external {
+            Some(backend) => backend.clone(),
+            None => {
+                // The re-encrypted upload stays on the account holding the main copy
+                let account_id = copies
+                    .first()
+                    .map(|copy| copy.account_id)
+                    .unwrap_or(accounts::PRIMARY_ACCOUNT);
+                let service =
+                    self.accounts.get(account_id).await.ok_or_else(|| {
+                        format!("Telegram account {} is not connected", account_id)
+                    })?;
+                service as Arc<dyn storage::StorageBackend>
+            }
+        };
+
+        if let Some(thumb_path) = thumbnail_path {
+            if let Some(new_thumb) = ensure_thumbnail_encrypted(thumb_path, key)? {
+                let new_thumb_str = new_thumb.to_string_lossy().to_string();
+                if new_thumb_str != thumb_path {
+                    db.update_thumbnail_path(media_id, &new_thumb_str)
+                        .map_err(|e| e.to_string())?;
+                }
+            }
+        }
+
+        let maybe_pending = db
+            .get_config(&pending_key)
+            .map_err(|e| e.to_string())?
+            .and_then(|v| v.parse::<i32>().ok());
+
+        // The re-encrypted payload and its caption, kept to replace the mirror copies
+        let mut payload = None;
+        let new_msg_id = if let Some(id) = maybe_pending {
+            id
+        } else {
+            let temp_dir = std::env::temp_dir().join("wanderer-migration");
+            std::fs::create_dir_all(&temp_dir).map_err(|e| e.to_string())?;
+
+            // Cloud-only items have no local file; fetch the blob by ID. Copies that
+            // come back encrypted (old container or retired key) are opened first, and
+            // so are encrypted local originals.
+            let mut fetched_source = None;
+            let local = std::path::Path::new(file_path);
+            let plain_local = if local.exists() {
+                Some(local_originals::PlainOriginal::open(
+                    local,
+                    &self.read_keys,
+                )?)
+            } else {
+                None
+            };
+            let source = if let Some(plain) = &plain_local {
+                plain.path().to_path_buf()
+            } else {
+                let blob_path = temp_dir.join(format!("media_{}_blob.bin", media_id));
+                let plain_path = temp_dir.join(format!("media_{}_plain.bin", media_id));
+                let download = match previous_tg_id.parse::<i32>() {
+                    Ok(msg_id) => {
+                        download_cloud_blob(
+                            Some(db.as_ref()),
+                            &self.accounts,
+                            media_id,
+                            msg_id,
+                            &blob_path.to_string_lossy(),
+                        )
+                        .await
+                    }
+                    Err(_) => Err("Invalid Telegram message ID".to_string()),
+                };
+                let opened = download.and_then(|_| {
+                    security::decrypt_file_with_keys_if_needed(
+                        &blob_path,
+                        &plain_path,
+                        &self.read_keys,
+                    )
+                    .map_err(|e| e.to_string())
+                });
+                let _ = std::fs::remove_file(&blob_path);
+                if let Err(e) = opened {
+                    let _ = std::fs::remove_file(&plain_path);
+                    return Err(format!(
+                        "Local file is missing and cloud fetch failed: {}",
+                        e
+                    ));
+                }
+                fetched_source = Some(plain_path.clone());
+                plain_path
+            };
+
+            let temp_path = temp_dir.join(format!("media_{}_enc.wbenc", media_id));
+            let prepared = encrypt_for_migration(db, file_path, &source, &temp_path, key);
+            drop(plain_local);
+            if let Some(plain_path) = fetched_source {
+                let _ = std::fs::remove_file(plain_path);
+            }
+            let caption_text = prepared?;
+
+            let pacing = self.accounts.primary().upload_pacing();
+            let upload_res = backend
+                .upload(&temp_path, caption_text.clone(), &pacing)
+                .await;
+            let uploaded_id = match upload_res {
+                Ok(id) => id,
+                Err(e) => {
+                    let _ = std::fs::remove_file(&temp_path);
+                    return Err(e);
+                }
+            };
+            payload = Some((temp_path, caption_text));
+            db.set_config(&pending_key, &uploaded_id.to_string())
+                .map_err(|e| e.to_string())?;
+            uploaded_id
+        };
+
+        // The re-upload is a single message; drop the old split layout with it.
+        let old_part_ids: Vec<i32> = db
+            .get_extra_part_ids(media_id)
+            .map_err(|e| e.to_string())?
+            .iter()
+            .filter_map(|id| id.parse::<i32>().ok())
+            .collect();
+        db.update_telegram_id_by_path(file_path, &new_msg_id.to_string())
+            .map_err(|e| e.to_string())?;
+        db.set_media_parts_by_path(file_path, &[])
+            .map_err(|e| e.to_string())?;
+
+        if let Ok(old_id) = previous_tg_id.parse::<i32>() {
+            if old_id != new_msg_id {
+                let mut stale = vec![old_id];
+                stale.extend(old_part_ids);
+                // Items linked to the same upload still point at the old objects
+                let account_id = self.external.is_none().then(|| {
+                    copies
+                        .first()
+                        .map_or(accounts::PRIMARY_ACCOUNT, |copy| copy.account_id)
+                });
+                let stale = db
+                    .unreferenced_objects(account_id, &stale)
+                    .map_err(|e| e.to_string())?;
+                if !stale.is_empty() {
+                    let _ = backend.delete(&stale).await;
+                }
+            }
+        }
+
+        // Mirror copies still hold the old bytes. The item stays on the old key until
+        // they are replaced, so a failure here is retried on the next run.
+        let mirrors = copies.get(1..).unwrap_or_default();
+        let mut replaced = Ok(());
+        if self.external.is_none() {
+            let payload = payload
+                .as_ref()
+                .map(|(path, caption)| (path.as_path(), caption.as_str()));
+            for mirror in mirrors {
+                replaced = self
+                    .replace_mirror(media_id, file_path, mirror, payload)
+                    .await;
+                if replaced.is_err() {
+                    break;
+                }
+            }
+        }
+        if let Some((temp_path, _)) = &payload {
+            let _ = std::fs::remove_file(temp_path);
+        }
+        replaced?;
+
+        db.mark_media_encrypted_by_id(media_id, security::CONTAINER_VERSION, self.key_generation)
+            .map_err(|e| e.to_string())?;
+        let _ = db.remove_config(&pending_key);
+        Ok(())
+    }
+
+    /// Replace a mirror copy encrypted under the old key. `payload` (the re-encrypted
+    /// file and its caption) is uploaded to the mirror's account; without it, when
+    /// resuming a half-finished item, the mirror is re-queued from the local file. The
+    /// old copy is only deleted once its replacement is uploaded or queued.
+    async fn replace_mirror(
+        &self,
+        media_id: i64,
+        file_path: &str,
+        mirror: &database::CloudCopy,
+        payload: Option<(&std::path::Path, &str)>,
+    ) -> Result<(), String> {
+        let db = &self.db;
+        if let Some((path, caption)) = payload {
+            let service = self.accounts.get(mirror.account_id).await.ok_or_else(|| {
+                format!("Telegram account {} is not connected", mirror.account_id)
+            })?;
+            let pacing = service.upload_pacing();
+            let uploaded_id = storage::StorageBackend::upload(
+                service.as_ref(),
+                path,
+                caption.to_string(),
+                &pacing,
+            )
+            .await?;
+            db.add_media_copy_by_path(file_path, mirror.account_id, &[uploaded_id])
+                .map_err(|e| e.to_string())?;
+        } else if std::path::Path::new(file_path).exists() {
+            db.remove_media_copy(media_id, mirror.account_id)
+                .map_err(|e| e.to_string())?;
+            db.add_mirror_to_queue(file_path, mirror.account_id)
+                .map_err(|e| e.to_string())?;
+        } else {
+            return Err(format!(
+                "Mirror copy on account {} can't be replaced without the local file",
+                mirror.account_id
+            ));
+        }
+        let stale = db
+            .unreferenced_copies(std::slice::from_ref(mirror))
+            .map_err(|e| e.to_string())?;
+        self.accounts.delete_copies(&stale).await;
+        Ok(())
+    }
+}
+
+/// Replace the master key and start re-encrypting everything under the new one. The
+/// previous key stays in the bundle until the background job has finished.
+#[tauri::command]
+async fn start_key_rotation(
+    passphrase: String,
+    state: State<'_, AppState>,
+    app: tauri::AppHandle,
+) -> Result<RegenerateRecoveryResponse, String> {
+    let db = {
+        let db_guard = state.db.lock().await;
+        db_guard.as_ref().ok_or("Database not initialized")?.clone()
+    };
+    let bundle = load_security_bundle(&db)?
+        .ok_or_else(|| "Encryption is not initialized for this library".to_string())?;
+    if bundle.mode != EncryptionMode::Encrypted {
+        return Err("Encryption mode is not enabled".to_string());
+    }
+    {
+        let runtime = state.security_runtime.lock().await;
+        if runtime.master_key.is_none() {
+            return Err("Unlock encryption before rotating the master key".to_string());
+        }
+        if runtime.migration_worker_active {
+            return Err("Wait for the encryption migration to finish".to_string());
+        }
+    }
+
+    let (next_bundle, recovery_key, key, _) = bundle
+        .rotate_master_key(&passphrase)
+        .map_err(|e| e.to_string())?;
+    save_security_bundle(&db, &next_bundle)?;
+    set_unlocked_keys(&state, &next_bundle, key).await?;
+
+    let app_dir = resolve_app_data_dir(&app)?;
+    let session_key = session_vault::session_key(&key);
+    for dir in accounts::session_dirs(&db, &app_dir) {
+        if let Err(e) = session_vault::snapshot(&dir, &session_key) {
+            log::warn!("Failed to reseal Telegram session in {:?}: {}", dir, e);
+        }
+    }
+    // Indexed copies are under the old key, so new uploads mustn't link to them
+    cloud_index::reset(&db)?;
+    if let Err(e) = view_cache::remove_encrypted_blobs(&app_dir.join("view_cache")) {
+        log::warn!("Failed to clear the view cache: {}", e);
+    }
+
+    spawn_key_rotation(&state, db).await?;
+    Ok(RegenerateRecoveryResponse { recovery_key })
+}
+
+/// Continue a key rotation that was interrupted or had failures.
+#[tauri::command]
+async fn resume_key_rotation(state: State<'_, AppState>) -> Result<(), String> {
+    let db = {
+        let db_guard = state.db.lock().await;
+        db_guard.as_ref().ok_or("Database not initialized")?.clone()
+    };
+    spawn_key_rotation(&state, db).await
+}
+
+#[tauri::command]
+async fn get_key_rotation_status(
+    state: State<'_, AppState>,
+) -> Result<KeyRotationStatusResponse, String> {
+    let db_guard = state.db.lock().await;
+    let db = db_guard.as_ref().ok_or("Database not initialized")?;
+    let bundle = load_security_bundle(db)?;
+    let runtime = state.security_runtime.lock().await;
+    let mut progress = runtime.rotation.clone();
+    // A job cut short by quitting is saved as running
+    progress.running = runtime.rotation_worker_active;
+    Ok(KeyRotationStatusResponse {
+        key_generation: bundle.as_ref().map_or(0, |b| b.key_generation),
+        pending: bundle.is_some_and(|b| b.retired_key.is_some()),
+        progress,
+    })
+}
+
+/// Run the background job moving thumbnails, local originals and cloud copies from the
+/// retired key to the current one. Does nothing when it is already running.
+async fn spawn_key_rotation(state: &State<'_, AppState>, db: Arc<Database>) -> Result<(), String> {
+    let (key, key_generation, retired_key) = {
+        let runtime = state.security_runtime.lock().await;
+        let key = runtime
+            .master_key
+            .ok_or_else(|| "Unlock encryption before rotating the master key".to_string())?;
+        (key, runtime.key_generation, runtime.retired_key)
+    };
+    let retired_key = retired_key.ok_or_else(|| "No key rotation is in progress".to_string())?;
+
+    let thumbs = db
+        .get_encrypted_thumbnail_paths(1_000_000)
+        .map_err(|e| e.to_string())?;
+    let originals: Vec<String> = db
+        .get_local_original_paths()
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .map(|(_, path)| path)
+        .filter(|path| security::is_encrypted_file(std::path::Path::new(path)).unwrap_or(false))
+        .collect();
+    let remaining = db
+        .get_media_with_old_key(key_generation, 1_000_000)
+        .map_err(|e| e.to_string())?;
+    let job = ReencryptJob {
+        db: db.clone(),
+        accounts: state.accounts.clone(),
+        external: storage::open_external(&db)?,
+        key,
+        key_generation,
+        read_keys: vec![key, retired_key],
+        pending_prefix: SECURITY_ROTATION_PENDING_PREFIX,
+    };
+
+    {
+        let mut runtime = state.security_runtime.lock().await;
+        if runtime.rotation_worker_active {
+            return Ok(());
+        }
+        runtime.rotation_worker_active = true;
+        runtime.rotation = MigrationStatus {
+            running: true,
+            total: (thumbs.len() + originals.len() + remaining.len()) as i64,
+            processed: 0,
+            succeeded: 0,
+            failed: 0,
+            last_error: None,
+        };
+        let _ = save_migration_status(&db, SECURITY_ROTATION_STATUS_KEY, &runtime.rotation);
+    }
+
+    let runtime = state.security_runtime.clone();
+    let active_uploads = state.active_uploads.clone();
+    tokio::spawn(async move {
+        for (_, thumb_path) in thumbs {
+            let path = std::path::Path::new(&thumb_path);
+            let result = if path.exists() && security::is_encrypted_file(path).unwrap_or(false) {
+                security::reencrypt_container(path, &retired_key, &key)
+                    .map(|_| ())
+                    .map_err(|e| e.to_string())
+            } else {
+                Ok(())
+            };
+            record_rotation_step(&runtime, &db, result).await;
+        }
+
+        for file_path in originals {
+            let result = local_originals::reencrypt_in_place(
+                std::path::Path::new(&file_path),
+                &[retired_key],
+                &key,
+            )
+            .map(|_| ());
+            record_rotation_step(&runtime, &db, result).await;
+        }
+
+        let mut attempted = std::collections::HashSet::new();
+        loop {
+            let remaining: Vec<_> = match db.get_media_with_old_key(key_generation, 1_000_000) {
+                Ok(rows) => rows
+                    .into_iter()
+                    .filter(|(media_id, ..)| !attempted.contains(media_id))
+                    .collect(),
+                Err(e) => {
+                    record_rotation_step(&runtime, &db, Err(e.to_string())).await;
+                    break;
+                }
+            };
+            if remaining.is_empty() {
+                // Uploads that took the old key before the switch mark their rows when
+                // they finish
+                if active_uploads.is_idle() {
+                    break;
+                }
+                tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+                continue;
+            }
+            {
+                let mut state_guard = runtime.lock().await;
+                state_guard.rotation.total =
+                    state_guard.rotation.processed + remaining.len() as i64;
+            }
+
+            for (media_id, file_path, previous_tg_id, thumbnail_path) in remaining {
+                attempted.insert(media_id);
+                let result = job
+                    .replace_cloud_copy(
+                        media_id,
+                        &file_path,
+                        &previous_tg_id,
+                        thumbnail_path.as_deref(),
+                    )
+                    .await;
+                record_rotation_step(&runtime, &db, result).await;
+            }
+        }
+
+        let mut state_guard = runtime.lock().await;
+        if state_guard.rotation.failed == 0 {
+            // Nothing is encrypted with the retired key anymore
+            let finished = load_security_bundle(&db).and_then(|bundle| match bundle {
+                Some(bundle) => save_security_bundle(&db, &bundle.finish_rotation()),
+                None => Ok(()),
+            });
+            match finished {
+                Ok(()) => state_guard.retired_key = None,
+                Err(err) => state_guard.rotation.last_error = Some(err),
+            }
+        }
+        state_guard.rotation.running = false;
+        state_guard.rotation_worker_active = false;
+        let _ = save_migration_status(&db, SECURITY_ROTATION_STATUS_KEY, &state_guard.rotation);
+    });
+
+    Ok(())
+}
+
+async fn record_rotation_step(
+    runtime: &Mutex<RuntimeState>,
+    db: &Database,
+    result: Result<(), String>,
+) {
+    let mut state_guard = runtime.lock().await;
+    state_guard.rotation.processed += 1;
+    match result {
+        Ok(_) => state_guard.rotation.succeeded += 1,
+        Err(err) => {
+            state_guard.rotation.failed += 1;
+            state_guard.rotation.last_error = Some(err);
+        }
+    }
+    let _ = save_migration_status(db, SECURITY_ROTATION_STATUS_KEY, &state_guard.rotation);
+}
+
+/// The service logging in `account_id`, the primary account when `None`.
+async fn login_service(
+    state: &State<'_, AppState>,
+    account_id: Option<i64>,
+) -> Result<Arc<TelegramService>, telegram::LoginError> {
+    if is_vault_locked(state).await {
+        return Err(telegram::LoginError::new(
+            telegram::LoginErrorKind::Other,
+            "Unlock the vault before signing in to Telegram",
+        ));
+    }
+    let account_id = account_id.unwrap_or(accounts::PRIMARY_ACCOUNT);
+    state.accounts.get(account_id).await.ok_or_else(|| {
+        telegram::LoginError::new(
+            telegram::LoginErrorKind::Other,
+            format!("Telegram account {} is not registered", account_id),
+        )
+    })
+}
+
+#[tauri::command]
+async fn login_request_code(
+    phone: String,
+    account_id: Option<i64>,
+    state: State<'_, AppState>,
+    app: tauri::AppHandle,
+) -> Result<(), String> {
+    let telegram = login_service(&state, account_id)
+        .await
+        .map_err(|e| e.to_string())?;
+    if !telegram.has_credentials().await {
+        return Err(
+            "Telegram API credentials are not configured. Complete onboarding first.".to_string(),
+        );
+    }
+    let app_dir = resolve_app_data_dir(&app)?;
+    let session_dir =
+        accounts::session_dir(&app_dir, account_id.unwrap_or(accounts::PRIMARY_ACCOUNT));
+
+    match telegram.request_code(&phone, session_dir).await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[tauri::command]
+async fn login_sign_in(
+    code: String,
+    account_id: Option<i64>,
+    state: State<'_, AppState>,
+    app: tauri::AppHandle,
+) -> Result<telegram::SignInOutcome, telegram::LoginError> {
+    let outcome = login_service(&state, account_id)
+        .await?
+        .sign_in(&code)
+        .await?;
+    if matches!(outcome, telegram::SignInOutcome::SignedIn { .. }) {
+        snapshot_session(&state, &app, account_id).await;
+    }
+    Ok(outcome)
+}
+
+/// Begin a QR code login; the returned code is scanned from a logged-in phone.
+#[tauri::command]
+async fn login_qr_start(
+    account_id: Option<i64>,
+    state: State<'_, AppState>,
+    app: tauri::AppHandle,
+) -> Result<telegram::QrLoginStep, telegram::LoginError> {
+    let telegram = login_service(&state, account_id).await?;
+    if !telegram.has_credentials().await {
+        return Err(telegram::LoginError::new(
+            telegram::LoginErrorKind::NotConnected,
+            "Telegram API credentials are not configured. Complete onboarding first.",
+        ));
+    }
+    let app_dir = resolve_app_data_dir(&app)
+        .map_err(|e| telegram::LoginError::new(telegram::LoginErrorKind::Other, e))?;
+    let session_dir =
+        accounts::session_dir(&app_dir, account_id.unwrap_or(accounts::PRIMARY_ACCOUNT));
+    telegram.start_qr_login(session_dir).await
+}
+
+/// Resolve once the QR code is accepted or replaced with a fresh one.
+#[tauri::command]
+async fn login_qr_wait(
+    account_id: Option<i64>,
+    state: State<'_, AppState>,
+    app: tauri::AppHandle,
+) -> Result<telegram::QrLoginStep, telegram::LoginError> {
+    let step = login_service(&state, account_id)
+        .await?
+        .wait_qr_login()
+        .await?;
+    if matches!(step, telegram::QrLoginStep::SignedIn { .. }) {
+        snapshot_session(&state, &app, account_id).await;
+    }
+    Ok(step)
+}
+
+/// Second login step for accounts with a cloud password.
+#[tauri::command]
+async fn login_check_password(
+    password: String,
+    account_id: Option<i64>,
+    state: State<'_, AppState>,
+    app: tauri::AppHandle,
+) -> Result<String, telegram::LoginError> {
+    let name = login_service(&state, account_id)
+        .await?
+        .check_password(&password)
+        .await?;
+    snapshot_session(&state, &app, account_id).await;
+    Ok(name)
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct UploadDestinationResponse {
+    /// `None` means Saved Messages
+    peer_id: Option<i64>,
+    name: Option<String>,
+}
+
+#[tauri::command]
+async fn list_destination_peers(
+    state: State<'_, AppState>,
+) -> Result<Vec<telegram::DestinationPeer>, String> {
+    state.telegram.list_destination_peers().await
+}
+
+#[tauri::command]
+async fn get_upload_destination(
+    state: State<'_, AppState>,
+) -> Result<UploadDestinationResponse, String> {
+    let db_guard = state.db.lock().await;
+    let db = db_guard.as_ref().ok_or("Database not initialized")?;
+    let peer_id = db
+        .get_config(TELEGRAM_DESTINATION_KEY)
+        .map_err(|e| e.to_string())?
+        .and_then(|v| v.parse::<i64>().ok());
+    let name = match peer_id {
+        Some(_) => db
+            .get_config(TELEGRAM_DESTINATION_NAME_KEY)
+            .map_err(|e| e.to_string())?,
+        None => None,
+    };
+    Ok(UploadDestinationResponse { peer_id, name })
+}
+
+/// Change where uploads are stored. Existing uploads stay in the old peer and would no
+/// longer be reachable, so switching with uploaded media requires `force`.
+#[tauri::command]
+async fn set_upload_destination(
+    peer_id: Option<i64>,
+    force: bool,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    let name = match peer_id {
+        Some(id) => {
+            let peers = state.telegram.list_destination_peers().await?;
+            let peer = peers
+                .into_iter()
+                .find(|p| p.id == id)
+                .ok_or_else(|| "Selected channel or group was not found".to_string())?;
+            Some(peer.name)
+        }
+        None => None,
+    };
+
+    let db_guard = state.db.lock().await;
+    let db = db_guard.as_ref().ok_or("Database not initialized")?;
+
+    let current = db
+        .get_config(TELEGRAM_DESTINATION_KEY)
+        .map_err(|e| e.to_string())?
+        .and_then(|v| v.parse::<i64>().ok());
+    if current == peer_id {
+        return Ok(());
+    }
+
+    let uploaded = db.count_uploaded_media().map_err(|e| e.to_string())?;
+    if uploaded > 0 && !force {
+        return Err(format!(
+            "{} item(s) are already stored in the current destination. Switching would make them unreachable.",
+            uploaded
+        ));
+    }
+
+    match (peer_id, name) {
+        (Some(id), Some(name)) => {
+            db.set_config(TELEGRAM_DESTINATION_KEY, &id.to_string())
+                .map_err(|e| e.to_string())?;
+            db.set_config(TELEGRAM_DESTINATION_NAME_KEY, &name)
+                .map_err(|e| e.to_string())?;
+        }
+        _ => {
+            db.remove_config(TELEGRAM_DESTINATION_KEY)
+                .map_err(|e| e.to_string())?;
+            db.remove_config(TELEGRAM_DESTINATION_NAME_KEY)
+                .map_err(|e| e.to_string())?;
         }
+    }
+    // Message IDs are per chat, so the sync position and cloud index don't carry over.
+    db.remove_config(sync_worker::SYNC_HIGH_WATER_MARK_KEY)
+        .map_err(|e| e.to_string())?;
+    cloud_index::reset(db)?;
+    drop(db_guard);
 
-        for (media_id, file_path, previous_tg_id, thumbnail_path) in cloud_items {
-            let pending_key = format!("{}{}", pending_prefix, media_id);
-
-            let result: Result<(), String> = async {
-                if let Some(thumb_path) = thumbnail_path.as_deref() {
-                    if let Some(new_thumb) = ensure_thumbnail_encrypted(thumb_path, &key)? {
-                        let new_thumb_str = new_thumb.to_string_lossy().to_string();
-                        if new_thumb_str != thumb_path {
-                            db.update_thumbnail_path(media_id, &new_thumb_str)
-                                .map_err(|e| e.to_string())?;
-                        }
-                    }
-                }
+    state.telegram.set_destination(peer_id).await;
+    log::info!("Upload destination changed to {:?}", peer_id);
+    Ok(())
+}
 
-                let maybe_pending = db
-                    .get_config(&pending_key)
-                    .map_err(|e| e.to_string())?
-                    .and_then(|v| v.parse::<i32>().ok());
+#[tauri::command]
+async fn get_storage_settings(
+    state: State<'_, AppState>,
+) -> Result<storage::StorageSettings, String> {
+    let db_guard = state.db.lock().await;
+    let db = db_guard.as_ref().ok_or("Database not initialized")?;
+    Ok(storage::StorageSettings::load(db).redacted())
+}
 
-                let new_msg_id = if let Some(id) = maybe_pending {
-                    id
-                } else {
-                    let source = std::path::Path::new(&file_path);
-                    if !source.exists() {
-                        return Err("Local file is missing; cannot migrate cloud blob".to_string());
-                    }
+/// Select the backend uploads go to. The new backend is checked by listing it first.
+/// Like `set_upload_destination`, switching with uploaded media requires `force`.
+#[tauri::command]
+async fn set_storage_settings(
+    settings: storage::StorageSettings,
+    force: bool,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    let db_guard = state.db.lock().await;
+    let db = db_guard.as_ref().ok_or("Database not initialized")?.clone();
+    drop(db_guard);
 
-                    let temp_dir = std::env::temp_dir().join("wanderer-migration");
-                    std::fs::create_dir_all(&temp_dir).map_err(|e| e.to_string())?;
-                    let temp_path = temp_dir.join(format!("media_{}_enc.wbenc", media_id));
-                    security::encrypt_file(source, &temp_path, &key).map_err(|e| e.to_string())?;
+    let current = storage::StorageSettings::load(&db);
+    let mut settings = settings;
+    if settings
+        .s3_secret_key
+        .as_deref()
+        .unwrap_or_default()
+        .is_empty()
+    {
+        settings.s3_secret_key = current.s3_secret_key.clone();
+    }
 
-                    let temp_path_str = temp_path.to_string_lossy().to_string();
-                    let upload_res = telegram
-                        .upload_file_with_progress(&temp_path_str, |_bytes, _total, _speed| {})
-                        .await;
-                    let _ = std::fs::remove_file(&temp_path);
+    let changed = settings.backend != current.backend
+        || settings.local_dir != current.local_dir
+        || settings.s3_endpoint != current.s3_endpoint
+        || settings.s3_bucket != current.s3_bucket
+        || settings.s3_prefix != current.s3_prefix;
+    if changed {
+        let uploaded = db.count_uploaded_media().map_err(|e| e.to_string())?;
+        if uploaded > 0 && !force {
+            return Err(format!(
+                "{} item(s) are already stored in the current backend. Switching would make them unreachable.",
+                uploaded
+            ));
+        }
+    }
 
-                    let uploaded_id = upload_res.map_err(|e| e.to_string())?;
-                    db.set_config(&pending_key, &uploaded_id.to_string())
-                        .map_err(|e| e.to_string())?;
-                    uploaded_id
-                };
+    if let Some(backend) = settings.open_external()? {
+        backend
+            .list(storage::ObjectId::MAX, 1)
+            .await
+            .map_err(|e| format!("Storage backend is not reachable: {}", e))?;
+    }
 
-                db.update_telegram_id_by_path(&file_path, &new_msg_id.to_string())
-                    .map_err(|e| e.to_string())?;
-                db.mark_media_encrypted_by_id(media_id)
-                    .map_err(|e| e.to_string())?;
+    settings.save(&db)?;
+    if changed {
+        // Object IDs are per backend, so the sync position and cloud index don't carry over.
+        db.remove_config(sync_worker::SYNC_HIGH_WATER_MARK_KEY)
+            .map_err(|e| e.to_string())?;
+        cloud_index::reset(&db)?;
+    }
+    log::info!("Storage backend set to {}", settings.backend);
+    Ok(())
+}
 
-                if let Ok(old_id) = previous_tg_id.parse::<i32>() {
-                    if old_id != new_msg_id {
-                        let _ = telegram.delete_messages(&[old_id]).await;
-                    }
-                }
+#[tauri::command]
+async fn get_me(state: State<'_, AppState>) -> Result<String, String> {
+    if !state.telegram.has_credentials().await {
+        return Err("Telegram API credentials are not configured".to_string());
+    }
+    state.telegram.get_me().await
+}
 
-                let _ = db.remove_config(&pending_key);
-                Ok(())
-            }
-            .await;
+#[tauri::command]
+async fn logout(state: State<'_, AppState>, app: tauri::AppHandle) -> Result<(), String> {
+    let app_dir = resolve_app_data_dir(&app)?;
 
-            let mut state_guard = runtime.lock().await;
-            state_guard.migration.processed += 1;
-            match result {
-                Ok(_) => state_guard.migration.succeeded += 1,
-                Err(err) => {
-                    state_guard.migration.failed += 1;
-                    state_guard.migration.last_error = Some(err);
-                }
-            }
-            let _ = save_migration_status(&db, &state_guard.migration);
-        }
+    state.telegram.logout(app_dir.clone()).await?;
+    session_vault::remove_sealed(&app_dir).map_err(|e| e.to_string())
+}
 
-        let mut state_guard = runtime.lock().await;
-        state_guard.migration.running = false;
-        state_guard.migration_worker_active = false;
-        let _ = save_migration_status(&db, &state_guard.migration);
-    });
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TelegramAccountResponse {
+    id: i64,
+    label: String,
+    /// Display name of the signed-in user; `None` until the account is logged in
+    name: Option<String>,
+    is_primary: bool,
+    /// Items with a main or mirror copy on this account
+    media_count: i64,
+}
 
-    Ok(())
+/// The onboarding account followed by every extra storage account.
+#[tauri::command]
+async fn list_telegram_accounts(
+    state: State<'_, AppState>,
+) -> Result<Vec<TelegramAccountResponse>, String> {
+    let (rows, counts) = {
+        let db_guard = state.db.lock().await;
+        let db = db_guard.as_ref().ok_or("Database not initialized")?;
+        let rows = db.list_telegram_accounts().map_err(|e| e.to_string())?;
+        let counts = std::iter::once(accounts::PRIMARY_ACCOUNT)
+            .chain(rows.iter().map(|row| row.id))
+            .map(|id| db.count_media_on_account(id).map_err(|e| e.to_string()))
+            .collect::<Result<Vec<_>, _>>()?;
+        (rows, counts)
+    };
+
+    let entries = std::iter::once((accounts::PRIMARY_ACCOUNT, "Primary".to_string()))
+        .chain(rows.into_iter().map(|row| (row.id, row.label)));
+    let mut response = Vec::new();
+    for ((id, label), media_count) in entries.zip(counts) {
+        let name = match state.accounts.get(id).await {
+            Some(service) if service.is_authorized().await => service.get_me().await.ok(),
+            _ => None,
+        };
+        response.push(TelegramAccountResponse {
+            id,
+            label,
+            name,
+            is_primary: id == accounts::PRIMARY_ACCOUNT,
+            media_count,
+        });
+    }
+    Ok(response)
 }
 
+/// Register an extra storage account. It is logged in afterwards with the login
+/// commands and its `account_id`.
 #[tauri::command]
-async fn login_request_code(
-    phone: String,
+async fn add_telegram_account(
+    label: String,
     state: State<'_, AppState>,
     app: tauri::AppHandle,
-) -> Result<(), String> {
-    if !state.telegram.has_credentials().await {
-        return Err(
-            "Telegram API credentials are not configured. Complete onboarding first.".to_string(),
-        );
+) -> Result<i64, String> {
+    let label = label.trim();
+    if label.is_empty() {
+        return Err("Account label cannot be empty".to_string());
     }
     let app_dir = resolve_app_data_dir(&app)?;
-
-    match state.telegram.request_code(&phone, app_dir).await {
-        Ok(_) => Ok(()),
-        Err(e) => Err(e.to_string()),
+    let id = {
+        let db_guard = state.db.lock().await;
+        let db = db_guard.as_ref().ok_or("Database not initialized")?;
+        db.add_telegram_account(label).map_err(|e| e.to_string())?
+    };
+    if let Err(e) = state.accounts.open(id, &app_dir).await {
+        // An account that never connected can't have logged in, so nothing is lost
+        state.accounts.close(id).await;
+        let _ = std::fs::remove_dir_all(accounts::session_dir(&app_dir, id));
+        let db_guard = state.db.lock().await;
+        if let Some(db) = db_guard.as_ref() {
+            if let Err(remove_error) = db.remove_telegram_account(id) {
+                log::warn!("Failed to remove account {}: {}", id, remove_error);
+            }
+        }
+        return Err(e);
     }
+    Ok(id)
 }
 
+/// Sign out and forget an extra account. Accounts still holding copies are refused
+/// so their items stay downloadable.
 #[tauri::command]
-async fn login_sign_in(code: String, state: State<'_, AppState>) -> Result<String, String> {
-    state.telegram.sign_in(&code).await
+async fn remove_telegram_account(
+    account_id: i64,
+    state: State<'_, AppState>,
+    app: tauri::AppHandle,
+) -> Result<(), String> {
+    if account_id == accounts::PRIMARY_ACCOUNT {
+        return Err("The primary account can't be removed; log out instead".to_string());
+    }
+    {
+        let db_guard = state.db.lock().await;
+        let db = db_guard.as_ref().ok_or("Database not initialized")?;
+        let count = db
+            .count_media_on_account(account_id)
+            .map_err(|e| e.to_string())?;
+        if count > 0 {
+            return Err(format!(
+                "{} item(s) are stored on this account. Move or delete them first.",
+                count
+            ));
+        }
+        db.remove_telegram_account(account_id)
+            .map_err(|e| e.to_string())?;
+    }
+
+    let dir = accounts::session_dir(&resolve_app_data_dir(&app)?, account_id);
+    if let Some(service) = state.accounts.close(account_id).await {
+        service.logout(dir.clone()).await?;
+    }
+    let _ = std::fs::remove_dir_all(dir);
+    Ok(())
 }
 
 #[tauri::command]
-async fn get_me(state: State<'_, AppState>) -> Result<String, String> {
-    if !state.telegram.has_credentials().await {
-        return Err("Telegram API credentials are not configured".to_string());
-    }
-    state.telegram.get_me().await
+async fn get_account_placement(
+    state: State<'_, AppState>,
+) -> Result<accounts::PlacementSettings, String> {
+    let db_guard = state.db.lock().await;
+    let db = db_guard.as_ref().ok_or("Database not initialized")?;
+    Ok(accounts::PlacementSettings::load(db))
 }
 
+/// Choose how new uploads are spread over the accounts. Items already uploaded stay
+/// where they are.
 #[tauri::command]
-async fn logout(state: State<'_, AppState>, app: tauri::AppHandle) -> Result<(), String> {
-    let app_dir = resolve_app_data_dir(&app)?;
+async fn set_account_placement(
+    settings: accounts::PlacementSettings,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    let db_guard = state.db.lock().await;
+    let db = db_guard.as_ref().ok_or("Database not initialized")?;
+    settings.save(db)
+}
 
-    state.telegram.logout(app_dir).await
+/// Send future uploads of an album's items to one account (album placement mode).
+#[tauri::command]
+async fn set_album_account(
+    album_id: i64,
+    account_id: Option<i64>,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    let db_guard = state.db.lock().await;
+    let db = db_guard.as_ref().ok_or("Database not initialized")?;
+    db.set_album_account(album_id, account_id)
+        .map_err(|e| e.to_string())?;
+    Ok(())
 }
 
 #[tauri::command]
@@ -835,6 +2312,8 @@ async fn debug_reset_faces(state: State<'_, AppState>) -> Result<usize, String>
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    // Before the runtime starts any thread
+    upload_schedule::init_local_offset();
     // TODO: Load from config/env
     // Load .env file if it exists
     dotenvy::dotenv().ok();
@@ -861,12 +2340,16 @@ pub fn run() {
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_mcp_bridge::init())
+        .register_asynchronous_uri_scheme_protocol(media_stream::SCHEME, handle_stream_request)
         .manage(AppState {
-            telegram: telegram_service,
+            telegram: telegram_service.clone(),
+            accounts: Arc::new(accounts::AccountPool::new(telegram_service)),
             db: Mutex::new(None),
             watcher: Mutex::new(None),
             cache: thumbnail_cache.clone(),
             security_runtime,
+            active_uploads: Arc::new(upload_worker::ActiveUploads::new()),
+            proxy_bridge: Mutex::new(None),
             face_detector: face_detector,
         })
         .setup(move |app| {
@@ -926,12 +2409,31 @@ pub fn run() {
                     }
                 };
 
+                // Sessions of an encrypted library stay sealed until the vault is unlocked
+                let mut sessions_sealed = false;
                 if let Some(db) = db_arc {
+                    // Decrypted originals a crash may have left behind
+                    local_originals::clear_plain_copies();
+
                     // Load persisted security mode/bundle.
                     match load_security_bundle(&db) {
                         Ok(Some(bundle)) if bundle.mode == EncryptionMode::Encrypted => {
                             state.security_runtime.lock().await.master_key = None;
                             log::info!("Encryption enabled for this library (vault locked)");
+                            sessions_sealed = true;
+                            for dir in accounts::session_dirs(&db, &app_dir) {
+                                match session_vault::discard_working_copy(&dir) {
+                                    Ok(true) => log::info!(
+                                        "Removed plaintext Telegram session left in {:?}",
+                                        dir
+                                    ),
+                                    Ok(false) => {}
+                                    Err(e) => log::warn!(
+                                        "Failed to remove plaintext Telegram session: {}",
+                                        e
+                                    ),
+                                }
+                            }
                         }
                         Ok(Some(_)) | Ok(None) => {
                             state.security_runtime.lock().await.master_key = None;
@@ -940,31 +2442,38 @@ pub fn run() {
                             log::warn!("Failed to load security bundle: {}", e);
                         }
                     }
-                    state.security_runtime.lock().await.migration = load_migration_status(&db);
-
-                    // Load BYOK Telegram API credentials from DPAPI-protected config.
-                    match db.get_config(TELEGRAM_CREDS_KEY) {
-                        Ok(Some(blob)) => {
-                            match security::unprotect_and_deserialize::<TelegramApiCredentials>(&blob)
-                            {
-                                Ok(creds) => {
-                                    state
-                                        .telegram
-                                        .set_credentials(creds.api_id, creds.api_hash)
-                                        .await;
-                                    log::info!("Loaded Telegram API credentials from secure storage");
-                                }
-                                Err(e) => {
-                                    log::warn!(
-                                        "Failed to decode stored Telegram credentials: {}",
-                                        e
-                                    );
-                                }
+                    state.security_runtime.lock().await.migration =
+                        load_migration_status(&db, SECURITY_MIGRATION_STATUS_KEY);
+                    state.security_runtime.lock().await.rotation =
+                        load_migration_status(&db, SECURITY_ROTATION_STATUS_KEY);
+
+                    // Load BYOK Telegram API credentials from the secret store. Without a
+                    // platform store they wait for the secrets file to be unlocked.
+                    if let Some(store) = security::secret_store::PlatformStore::open() {
+                        security::secret_store::set_opened(Arc::new(store));
+                    }
+                    load_telegram_credentials(&state, &db).await;
+
+                    // Proxy settings must be applied before the first connection.
+                    if let Err(e) =
+                        apply_proxy_settings(&state, &proxy::ProxySettings::load(&db)).await
+                    {
+                        log::warn!("Failed to apply proxy settings: {}", e);
+                    }
+
+                    // Storage destination must be known before the first Telegram call.
+                    match db.get_config(TELEGRAM_DESTINATION_KEY) {
+                        Ok(Some(value)) => match value.parse::<i64>() {
+                            Ok(peer_id) => {
+                                state.telegram.set_destination(Some(peer_id)).await;
                             }
-                        }
+                            Err(_) => {
+                                log::warn!("Ignoring invalid storage destination '{}'", value);
+                            }
+                        },
                         Ok(None) => {}
                         Err(e) => {
-                            log::warn!("Failed to read Telegram credentials from config: {}", e);
+                            log::warn!("Failed to read storage destination from config: {}", e);
                         }
                     }
 
@@ -1004,8 +2513,12 @@ pub fn run() {
 
                     // Start AI Worker
                     let models_dir = app_dir.join("models");
-                    let ai_worker =
-                        ai::worker::AiWorker::new(db.clone(), state.face_detector.clone(), models_dir);
+                    let ai_worker = ai::worker::AiWorker::new(
+                        db.clone(),
+                        state.face_detector.clone(),
+                        models_dir,
+                        state.security_runtime.clone(),
+                    );
 
                     let worker_cancel = tokio_util::sync::CancellationToken::new();
                     let worker_cancel_clone = worker_cancel.clone();
@@ -1017,17 +2530,26 @@ pub fn run() {
                     // Create cancellation token for graceful shutdown
                     let cancel_token = CancellationToken::new();
 
-                    // Start Upload Worker
-                    let telegram_for_worker = state.telegram.clone();
+                    // Start Upload Worker once the extra accounts are connected (or timed
+                    // out), so items pinned to them don't fail on startup
+                    let accounts_for_worker = state.accounts.clone();
+                    let app_dir_for_worker = app_dir.clone();
                     let db_for_worker = db.clone();
                     let app_handle_for_worker = app_handle.clone();
                     let security_for_worker = state.security_runtime.clone();
+                    let active_for_worker = state.active_uploads.clone();
                     let cancel_for_upload = cancel_token.clone();
                     tauri::async_runtime::spawn(async move {
+                        if !sessions_sealed {
+                            accounts_for_worker
+                                .open_saved(&db_for_worker, &app_dir_for_worker)
+                                .await;
+                        }
                         upload_worker::run_upload_worker(
                             db_for_worker,
-                            telegram_for_worker,
+                            accounts_for_worker,
                             security_for_worker,
+                            active_for_worker,
                             app_handle_for_worker,
                             cancel_for_upload,
                         )
@@ -1049,6 +2571,33 @@ pub fn run() {
                         sync_worker.run(cancel_for_sync).await;
                     });
 
+                    // Keep the sealed Telegram sessions current while the vault is unlocked
+                    let db_for_reseal = db.clone();
+                    let security_for_reseal = state.security_runtime.clone();
+                    let app_dir_for_reseal = app_dir.clone();
+                    tauri::async_runtime::spawn(async move {
+                        let mut ticker = tokio::time::interval(session_vault::RESEAL_INTERVAL);
+                        loop {
+                            ticker.tick().await;
+                            // Held throughout, so a key rotation can't swap the key midway
+                            let runtime = security_for_reseal.lock().await;
+                            let Some(master_key) = runtime.master_key else {
+                                continue;
+                            };
+                            let key = session_vault::session_key(&master_key);
+                            let dirs = accounts::session_dirs(&db_for_reseal, &app_dir_for_reseal);
+                            for dir in dirs {
+                                if let Err(e) = session_vault::reseal_if_changed(&dir, &key) {
+                                    log::warn!(
+                                        "Failed to seal Telegram session in {:?}: {}",
+                                        dir,
+                                        e
+                                    );
+                                }
+                            }
+                        }
+                    });
+
                     // Start View Cache Cleanup Task
                     let db_for_cleanup = db.clone();
                     let app_handle_for_cleanup = app_handle.clone();
@@ -1091,7 +2640,9 @@ pub fn run() {
                 }
 
                 // Connect Telegram only when BYOK credentials are configured.
-                if state.telegram.has_credentials().await {
+                if sessions_sealed {
+                    log::info!("Telegram session is sealed; connecting after unlock");
+                } else if state.telegram.has_credentials().await {
                     if let Err(e) = state.telegram.connect(app_dir.clone()).await {
                         eprintln!("Failed to connect to Telegram: {}", e);
                     }
@@ -1112,10 +2663,32 @@ pub fn run() {
             complete_onboarding,
             set_telegram_api_credentials,
             clear_telegram_api_credentials,
+            unlock_secret_store,
             get_encryption_migration_status,
             start_encryption_migration,
+            set_local_encryption,
+            start_key_rotation,
+            resume_key_rotation,
+            get_key_rotation_status,
             login_request_code,
             login_sign_in,
+            login_check_password,
+            list_telegram_accounts,
+            add_telegram_account,
+            remove_telegram_account,
+            get_account_placement,
+            set_account_placement,
+            set_album_account,
+            get_proxy_settings,
+            set_proxy_settings,
+            test_telegram_connection,
+            login_qr_start,
+            login_qr_wait,
+            list_destination_peers,
+            get_upload_destination,
+            set_upload_destination,
+            get_storage_settings,
+            set_storage_settings,
             get_me,
             logout,
             get_media,
@@ -1141,7 +2714,17 @@ pub fn run() {
             // Phase 3: Upload Queue
             get_upload_queue,
             get_queue_counts,
+            get_dedup_stats,
+            get_upload_controls,
+            set_upload_controls,
+            set_uploads_paused,
             retry_upload,
+            retry_all_failed_uploads,
+            clear_finished_uploads,
+            prioritize_upload,
+            pause_upload,
+            resume_upload,
+            cancel_upload,
             // Phase 5: Bulk Operations
             bulk_set_favorite,
             bulk_delete,
@@ -1171,12 +2754,16 @@ pub fn run() {
             archive_media,
             unarchive_media,
             get_archived_media,
+            get_cloud_missing_media,
             // Permanent Delete
             permanent_delete_media,
             empty_trash,
             // Backup
             get_backup_path,
             backup_database,
+            recover_library_from_telegram,
+            run_cloud_audit,
+            repair_cloud_audit_issue,
             // Cloud-Only Mode
             remove_local_copy,
             download_local_copy,
@@ -1193,8 +2780,22 @@ pub fn run() {
             semantic_search,
             index_pending_clip,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app_handle, event| {
+            // Seal the Telegram sessions of an unlocked vault on the way out
+            if let tauri::RunEvent::Exit = event {
+                let state: State<AppState> = app_handle.state();
+                tauri::async_runtime::block_on(async {
+                    let key = state.security_runtime.lock().await.master_key.take();
+                    if let Some(key) = key {
+                        if let Err(e) = lock_sessions(&state, app_handle, &key).await {
+                            log::warn!("Failed to seal Telegram sessions on exit: {}", e);
+                        }
+                    }
+                });
+            }
+        });
 }
 
 #[tauri::command]
@@ -1250,87 +2851,298 @@ async fn import_files(files: Vec<String>, app: tauri::AppHandle) -> Result<usize
 // --- Phase 2: Favorites & Ratings Commands ---
 
 #[tauri::command]
-async fn toggle_favorite(media_id: i64, state: State<'_, AppState>) -> Result<bool, String> {
+async fn toggle_favorite(media_id: i64, state: State<'_, AppState>) -> Result<bool, String> {
+    let db_guard = state.db.lock().await;
+    let db = db_guard.as_ref().ok_or("Database not initialized")?;
+    db.toggle_favorite(media_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn set_rating(media_id: i64, rating: i32, state: State<'_, AppState>) -> Result<(), String> {
+    let db_guard = state.db.lock().await;
+    let db = db_guard.as_ref().ok_or("Database not initialized")?;
+    db.set_rating(media_id, rating).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_favorites(
+    limit: i32,
+    offset: i32,
+    state: State<'_, AppState>,
+) -> Result<Vec<database::MediaItem>, String> {
+    let db_guard = state.db.lock().await;
+    let db = db_guard.as_ref().ok_or("Database not initialized")?;
+    let items = db.get_favorites(limit, offset).map_err(|e| e.to_string())?;
+    drop(db_guard);
+    Ok(materialize_media_items_for_response(items, &state).await)
+}
+
+// --- Phase 2: Trash Commands ---
+
+#[tauri::command]
+async fn soft_delete_media(media_id: i64, state: State<'_, AppState>) -> Result<(), String> {
+    println!(">>> soft_delete_media CALLED for id={}", media_id);
+    let db_guard = state.db.lock().await;
+    let db = db_guard.as_ref().ok_or("Database not initialized")?;
+    db.soft_delete(media_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn restore_from_trash(media_id: i64, state: State<'_, AppState>) -> Result<(), String> {
+    let db_guard = state.db.lock().await;
+    let db = db_guard.as_ref().ok_or("Database not initialized")?;
+    db.restore_from_trash(media_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_trash(
+    limit: i32,
+    offset: i32,
+    state: State<'_, AppState>,
+) -> Result<Vec<database::MediaItem>, String> {
+    println!(
+        ">>> get_trash CALLED with limit={}, offset={}",
+        limit, offset
+    );
+    let db_guard = state.db.lock().await;
+    let db = db_guard.as_ref().ok_or("Database not initialized")?;
+    let items = db.get_trash(limit, offset).map_err(|e| e.to_string())?;
+    drop(db_guard);
+    Ok(materialize_media_items_for_response(items, &state).await)
+}
+
+// --- Phase 3: Upload Queue Commands ---
+
+#[tauri::command]
+async fn get_upload_queue(state: State<'_, AppState>) -> Result<Vec<database::QueueItem>, String> {
+    let db_guard = state.db.lock().await;
+    let db = db_guard.as_ref().ok_or("Database not initialized")?;
+    db.get_queue_status().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_queue_counts(state: State<'_, AppState>) -> Result<database::QueueCounts, String> {
+    let db_guard = state.db.lock().await;
+    let db = db_guard.as_ref().ok_or("Database not initialized")?;
+    let mut counts = db.get_queue_counts().map_err(|e| e.to_string())?;
+    let controls = upload_schedule::UploadControls::load(db);
+    counts.upload_state = controls.state(upload_schedule::local_minute_of_day());
+    counts.bandwidth_kbps = controls.bandwidth_kbps;
+    Ok(counts)
+}
+
+/// Uploads linked to a copy already in storage instead of being sent again.
+#[tauri::command]
+async fn get_dedup_stats(state: State<'_, AppState>) -> Result<cloud_index::DedupStats, String> {
+    let db_guard = state.db.lock().await;
+    let db = db_guard.as_ref().ok_or("Database not initialized")?;
+    Ok(cloud_index::DedupStats::load(db))
+}
+
+#[tauri::command]
+async fn get_upload_controls(
+    state: State<'_, AppState>,
+) -> Result<upload_schedule::UploadControls, String> {
+    let db_guard = state.db.lock().await;
+    let db = db_guard.as_ref().ok_or("Database not initialized")?;
+    Ok(upload_schedule::UploadControls::load(db))
+}
+
+/// Save the upload pause switch, bandwidth cap and schedule. They apply to uploads
+/// in flight immediately.
+#[tauri::command]
+async fn set_upload_controls(
+    controls: upload_schedule::UploadControls,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
     let db_guard = state.db.lock().await;
     let db = db_guard.as_ref().ok_or("Database not initialized")?;
-    db.toggle_favorite(media_id).map_err(|e| e.to_string())
+    controls.save(db)?;
+    apply_upload_controls(&state.telegram, &controls);
+    Ok(())
 }
 
+/// Pause or resume the upload worker without changing the other controls.
 #[tauri::command]
-async fn set_rating(media_id: i64, rating: i32, state: State<'_, AppState>) -> Result<(), String> {
+async fn set_uploads_paused(paused: bool, state: State<'_, AppState>) -> Result<(), String> {
     let db_guard = state.db.lock().await;
     let db = db_guard.as_ref().ok_or("Database not initialized")?;
-    db.set_rating(media_id, rating).map_err(|e| e.to_string())
+    let controls = upload_schedule::UploadControls {
+        paused,
+        ..upload_schedule::UploadControls::load(db)
+    };
+    controls.save(db)?;
+    apply_upload_controls(&state.telegram, &controls);
+    Ok(())
+}
+
+/// Route every account through the proxy in `settings`. Only connections opened
+/// afterwards use it.
+async fn apply_proxy_settings(
+    state: &State<'_, AppState>,
+    settings: &proxy::ProxySettings,
+) -> Result<(), String> {
+    let (url, bridge) = settings.endpoint()?.client_url().await?;
+    state.telegram.set_proxy_url(url.clone()).await;
+    state.accounts.set_proxy_url(url).await;
+    // Dropping the previous bridge stops it
+    *state.proxy_bridge.lock().await = bridge;
+    Ok(())
 }
 
 #[tauri::command]
-async fn get_favorites(
-    limit: i32,
-    offset: i32,
-    state: State<'_, AppState>,
-) -> Result<Vec<database::MediaItem>, String> {
+async fn get_proxy_settings(state: State<'_, AppState>) -> Result<proxy::ProxySettings, String> {
     let db_guard = state.db.lock().await;
     let db = db_guard.as_ref().ok_or("Database not initialized")?;
-    let items = db.get_favorites(limit, offset).map_err(|e| e.to_string())?;
-    drop(db_guard);
-    Ok(materialize_media_items_for_response(items, &state).await)
+    Ok(proxy::ProxySettings::load(db).redacted())
 }
 
-// --- Phase 2: Trash Commands ---
+/// Save the proxy settings and reconnect every connected account through them.
+#[tauri::command]
+async fn set_proxy_settings(
+    settings: proxy::ProxySettings,
+    state: State<'_, AppState>,
+    app: tauri::AppHandle,
+) -> Result<(), String> {
+    let settings = {
+        let db_guard = state.db.lock().await;
+        let db = db_guard.as_ref().ok_or("Database not initialized")?;
+        let settings = settings.with_stored_secrets(db);
+        settings.save(db)?;
+        settings
+    };
+    apply_proxy_settings(&state, &settings).await?;
+
+    let app_dir = resolve_app_data_dir(&app)?;
+    for account_id in state.accounts.ids().await {
+        let Some(service) = state.accounts.get(account_id).await else {
+            continue;
+        };
+        if service.is_connected().await {
+            service
+                .connect(accounts::session_dir(&app_dir, account_id))
+                .await
+                .map_err(|e| format!("Proxy saved, but reconnecting failed: {}", e))?;
+        }
+    }
+    log::info!("Proxy set to {:?}", settings.kind);
+    Ok(())
+}
 
+/// Check that Telegram is reachable with `settings`, or the saved settings when
+/// omitted. Empty password and secret fields fall back to the saved ones.
 #[tauri::command]
-async fn soft_delete_media(media_id: i64, state: State<'_, AppState>) -> Result<(), String> {
-    println!(">>> soft_delete_media CALLED for id={}", media_id);
+async fn test_telegram_connection(
+    settings: Option<proxy::ProxySettings>,
+    state: State<'_, AppState>,
+) -> Result<proxy::ConnectionTestResult, String> {
+    let settings = {
+        let db_guard = state.db.lock().await;
+        let db = db_guard.as_ref().ok_or("Database not initialized")?;
+        match settings {
+            Some(settings) => settings.with_stored_secrets(db),
+            None => proxy::ProxySettings::load(db),
+        }
+    };
+    Ok(proxy::test_connection(&settings).await)
+}
+
+fn apply_upload_controls(telegram: &TelegramService, controls: &upload_schedule::UploadControls) {
+    telegram.bandwidth().set_rate(controls.bytes_per_sec());
+    let state = controls.state(upload_schedule::local_minute_of_day());
+    telegram
+        .upload_gate()
+        .set_open(state == upload_schedule::UploadState::Running);
+}
+
+#[tauri::command]
+async fn retry_upload(id: i64, state: State<'_, AppState>) -> Result<(), String> {
     let db_guard = state.db.lock().await;
     let db = db_guard.as_ref().ok_or("Database not initialized")?;
-    db.soft_delete(media_id).map_err(|e| e.to_string())
+    db.retry_failed_item(id).map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-async fn restore_from_trash(media_id: i64, state: State<'_, AppState>) -> Result<(), String> {
+async fn retry_all_failed_uploads(state: State<'_, AppState>) -> Result<usize, String> {
     let db_guard = state.db.lock().await;
     let db = db_guard.as_ref().ok_or("Database not initialized")?;
-    db.restore_from_trash(media_id).map_err(|e| e.to_string())
+    db.retry_all_failed_items().map_err(|e| e.to_string())
 }
 
+/// Remove completed and cancelled items from the queue list.
 #[tauri::command]
-async fn get_trash(
-    limit: i32,
-    offset: i32,
-    state: State<'_, AppState>,
-) -> Result<Vec<database::MediaItem>, String> {
-    println!(
-        ">>> get_trash CALLED with limit={}, offset={}",
-        limit, offset
-    );
+async fn clear_finished_uploads(state: State<'_, AppState>) -> Result<usize, String> {
     let db_guard = state.db.lock().await;
     let db = db_guard.as_ref().ok_or("Database not initialized")?;
-    let items = db.get_trash(limit, offset).map_err(|e| e.to_string())?;
-    drop(db_guard);
-    Ok(materialize_media_items_for_response(items, &state).await)
+    db.clear_finished_queue_items().map_err(|e| e.to_string())
 }
 
-// --- Phase 3: Upload Queue Commands ---
-
+/// Move a waiting upload to the front of the queue.
 #[tauri::command]
-async fn get_upload_queue(state: State<'_, AppState>) -> Result<Vec<database::QueueItem>, String> {
+async fn prioritize_upload(id: i64, state: State<'_, AppState>) -> Result<(), String> {
     let db_guard = state.db.lock().await;
     let db = db_guard.as_ref().ok_or("Database not initialized")?;
-    db.get_queue_status().map_err(|e| e.to_string())
+    match db.prioritize_queue_item(id).map_err(|e| e.to_string())? {
+        0 => Err("Only waiting uploads can be moved to the front".to_string()),
+        _ => Ok(()),
+    }
 }
 
+/// Hold a single upload. An upload in flight stops after its current chunk and keeps
+/// its progress.
 #[tauri::command]
-async fn get_queue_counts(state: State<'_, AppState>) -> Result<database::QueueCounts, String> {
+async fn pause_upload(id: i64, state: State<'_, AppState>) -> Result<(), String> {
     let db_guard = state.db.lock().await;
     let db = db_guard.as_ref().ok_or("Database not initialized")?;
-    db.get_queue_counts().map_err(|e| e.to_string())
+    let previous = db
+        .transition_queue_item(id, &["pending", "uploading", "rate_limited"], "paused")
+        .map_err(|e| e.to_string())?
+        .ok_or("Only waiting or running uploads can be paused")?;
+    if previous != "pending" {
+        state.active_uploads.interrupt(id);
+    }
+    Ok(())
 }
 
 #[tauri::command]
-async fn retry_upload(id: i64, state: State<'_, AppState>) -> Result<(), String> {
+async fn resume_upload(id: i64, state: State<'_, AppState>) -> Result<(), String> {
     let db_guard = state.db.lock().await;
     let db = db_guard.as_ref().ok_or("Database not initialized")?;
-    db.retry_failed_item(id).map_err(|e| e.to_string())
+    db.transition_queue_item(id, &["paused"], "pending")
+        .map_err(|e| e.to_string())?
+        .ok_or("Upload is not paused")?;
+    Ok(())
+}
+
+/// Cancel an upload, stopping it if it is in flight and deleting any parts already sent.
+#[tauri::command]
+async fn cancel_upload(id: i64, state: State<'_, AppState>) -> Result<(), String> {
+    let db = {
+        let db_guard = state.db.lock().await;
+        db_guard.as_ref().ok_or("Database not initialized")?.clone()
+    };
+    let previous = db
+        .transition_queue_item(
+            id,
+            &["pending", "paused", "uploading", "rate_limited", "failed"],
+            "cancelled",
+        )
+        .map_err(|e| e.to_string())?
+        .ok_or("Upload has already finished")?;
+
+    // The worker cleans up after an upload in flight; otherwise do it here
+    let in_flight = matches!(previous.as_str(), "uploading" | "rate_limited");
+    if !(in_flight && state.active_uploads.interrupt(id)) {
+        let (account_id, _) = db.get_queue_item_target(id).map_err(|e| e.to_string())?;
+        let telegram = state
+            .accounts
+            .get(account_id.unwrap_or(accounts::PRIMARY_ACCOUNT))
+            .await
+            .unwrap_or_else(|| state.telegram.clone());
+        upload_worker::discard_queue_progress(&db, &telegram, id).await;
+        let _ = std::fs::remove_file(upload_worker::staged_upload_path(id));
+    }
+    Ok(())
 }
 
 // --- Phase 5: Bulk Operations Commands ---
@@ -1381,6 +3193,7 @@ async fn export_media(
     let db = db_guard.as_ref().ok_or("Database not initialized")?;
     let items = db.get_media_by_ids(&media_ids).map_err(|e| e.to_string())?;
     drop(db_guard);
+    let keys = state.security_runtime.lock().await.read_keys();
 
     let dest_path = Path::new(&destination);
     if !dest_path.exists() {
@@ -1442,7 +3255,9 @@ async fn export_media(
         };
 
         if source.exists() {
-            std::fs::copy(source, &final_dest).map_err(|e| e.to_string())?;
+            // Encrypted local originals are exported decrypted
+            security::decrypt_file_with_keys_if_needed(source, &final_dest, &keys)
+                .map_err(|e| e.to_string())?;
             exported += 1;
             continue;
         }
@@ -1469,7 +3284,7 @@ async fn export_media(
             }
         };
 
-        match download_and_materialize_media(&state, msg_id, &final_dest).await {
+        match download_and_materialize_media(&state, item.id, msg_id, &final_dest).await {
             Ok(_) => {
                 exported += 1;
             }
@@ -1502,9 +3317,13 @@ async fn find_duplicates(
     };
 
     if !items_to_scan.is_empty() {
+        let keys = state.security_runtime.lock().await.read_keys();
         for (media_id, file_path) in items_to_scan {
             let path = std::path::Path::new(&file_path);
-            if let Some(phash) = media_utils::generate_phash(path) {
+            let Ok(plain) = local_originals::PlainOriginal::open(path, &keys) else {
+                continue;
+            };
+            if let Some(phash) = media_utils::generate_phash(plain.path()) {
                 let db_guard = state.db.lock().await;
                 if let Some(db) = db_guard.as_ref() {
                     let _ = db.update_phash(media_id, &phash);
@@ -1558,12 +3377,17 @@ async fn scan_duplicates(
     let _ = app.emit("scan-duplicates-started", total);
 
     let mut success_count = 0;
+    let keys = state.security_runtime.lock().await.read_keys();
 
     for (idx, (media_id, file_path)) in items_to_scan.into_iter().enumerate() {
         let path = std::path::Path::new(&file_path);
+        let plain = local_originals::PlainOriginal::open(path, &keys).ok();
 
         // Compute phash
-        if let Some(phash) = media_utils::generate_phash(path) {
+        if let Some(phash) = plain
+            .as_ref()
+            .and_then(|plain| media_utils::generate_phash(plain.path()))
+        {
             // Update database
             let db_guard = state.db.lock().await;
             if let Some(db) = db_guard.as_ref() {
@@ -1772,26 +3596,102 @@ async fn get_archived_media(
     Ok(materialize_media_items_for_response(items, &state).await)
 }
 
+/// Items whose cloud copy was deleted outside the app and could not be re-uploaded.
+#[tauri::command]
+async fn get_cloud_missing_media(
+    state: State<'_, AppState>,
+) -> Result<Vec<database::MediaItem>, String> {
+    let db_guard = state.db.lock().await;
+    let db = db_guard.as_ref().ok_or("Database not initialized")?;
+    let items = db.get_cloud_missing_media().map_err(|e| e.to_string())?;
+    drop(db_guard);
+    Ok(materialize_media_items_for_response(items, &state).await)
+}
+
+/// Delete the cloud objects of removed media rows that nothing in the library refers to
+/// any more, and drop them from the cloud index. Uploads linked to an existing object
+/// share it, so objects other items still use stay. Returns how many objects went.
+async fn delete_unreferenced_objects(
+    state: &State<'_, AppState>,
+    db: &Database,
+    external: Option<Arc<dyn storage::StorageBackend>>,
+    copies: &[database::CloudCopy],
+    object_ids: &[String],
+) -> usize {
+    // Telegram copies may be spread over several accounts
+    let Some(backend) = external else {
+        let copies = match db.unreferenced_copies(copies) {
+            Ok(copies) => copies,
+            Err(e) => {
+                log::warn!("Failed to check cloud copies before deleting: {}", e);
+                return 0;
+            }
+        };
+        let deleted = state.accounts.delete_copies(&copies).await;
+        for copy in &deleted {
+            if let Err(e) = db.remove_cloud_index_objects(copy.account_id, &copy.message_ids) {
+                log::warn!("Failed to update the cloud index: {}", e);
+            }
+        }
+        return deleted.iter().map(|copy| copy.message_ids.len()).sum();
+    };
+
+    let ids: Vec<i32> = object_ids
+        .iter()
+        .filter_map(|id| id.parse::<i32>().ok())
+        .collect();
+    let ids = match db.unreferenced_objects(None, &ids) {
+        Ok(ids) => ids,
+        Err(e) => {
+            log::warn!("Failed to check cloud objects before deleting: {}", e);
+            return 0;
+        }
+    };
+    if ids.is_empty() {
+        return 0;
+    }
+    match backend.delete(&ids).await {
+        Ok(deleted) => {
+            if let Err(e) = db.remove_cloud_index_objects(accounts::PRIMARY_ACCOUNT, &ids) {
+                log::warn!("Failed to update the cloud index: {}", e);
+            }
+            deleted
+        }
+        Err(e) => {
+            log::warn!("Failed to delete from {} storage: {}", backend.kind(), e);
+            0
+        }
+    }
+}
+
 #[tauri::command]
 async fn permanent_delete_media(
     media_id: i64,
     delete_from_telegram: bool,
     state: State<'_, AppState>,
 ) -> Result<(), String> {
-    let db_guard = state.db.lock().await;
-    let db = db_guard.as_ref().ok_or("Database not initialized")?;
+    let db = {
+        let db_guard = state.db.lock().await;
+        db_guard.as_ref().ok_or("Database not initialized")?.clone()
+    };
+
+    // Split uploads keep their extra parts in media_parts, which cascades away on delete.
+    let extra_part_ids = db.get_extra_part_ids(media_id).map_err(|e| e.to_string())?;
+
+    let external = storage::open_external(&db)?;
+    // Copies have to be read before the row and its media_copies cascade away
+    let copies = db.get_cloud_copies(media_id).map_err(|e| e.to_string())?;
 
     // Delete from local + DB, get telegram_media_id
     let telegram_media_id = db.permanent_delete(media_id).map_err(|e| e.to_string())?;
 
-    // Optionally delete from Telegram
+    // Optionally delete the cloud copies, unless other items were linked to them
     if delete_from_telegram {
-        if let Some(tg_id_str) = telegram_media_id {
-            if let Ok(tg_id) = tg_id_str.parse::<i32>() {
-                drop(db_guard); // Release DB lock before async operation
-                let _ = state.telegram.delete_messages(&[tg_id]).await;
-            }
-        }
+        let object_ids: Vec<String> = telegram_media_id
+            .into_iter()
+            .chain(extra_part_ids)
+            .collect();
+        delete_unreferenced_objects(&state, &db, external, &copies, &object_ids).await;
     }
 
     Ok(())
@@ -1807,8 +3707,17 @@ async fn empty_trash(
         delete_from_telegram
     );
 
-    let db_guard = state.db.lock().await;
-    let db = db_guard.as_ref().ok_or("Database not initialized")?;
+    let db = {
+        let db_guard = state.db.lock().await;
+        db_guard.as_ref().ok_or("Database not initialized")?.clone()
+    };
+
+    let external = storage::open_external(&db)?;
+    let copies = if delete_from_telegram && external.is_none() {
+        db.get_trashed_cloud_copies().map_err(|e| e.to_string())?
+    } else {
+        Vec::new()
+    };
 
     // Delete all trashed items from local + DB
     let (deleted_count, telegram_ids) = db.empty_trash().map_err(|e| e.to_string())?;
@@ -1820,45 +3729,39 @@ async fn empty_trash(
         delete_from_telegram
     );
 
-    // Optionally delete from Telegram
-    if delete_from_telegram && !telegram_ids.is_empty() {
-        drop(db_guard); // Release DB lock before async operation
-
-        let msg_ids: Vec<i32> = telegram_ids
-            .iter()
-            .filter_map(|id| {
-                let parsed = id.parse::<i32>().ok();
-                if parsed.is_none() {
-                    println!("empty_trash: Failed to parse telegram_id '{}' as i32", id);
-                }
-                parsed
-            })
-            .collect();
-
+    // Optionally delete the cloud copies, unless other items were linked to them
+    if delete_from_telegram {
+        let deleted =
+            delete_unreferenced_objects(&state, &db, external, &copies, &telegram_ids).await;
         println!(
-            "empty_trash: Parsed {} message IDs for Telegram deletion: {:?}",
-            msg_ids.len(),
-            msg_ids
+            "empty_trash: Successfully deleted {} cloud objects",
+            deleted
         );
-
-        if !msg_ids.is_empty() {
-            match state.telegram.delete_messages(&msg_ids).await {
-                Ok(deleted) => {
-                    println!(
-                        "empty_trash: Successfully deleted {} messages from Telegram",
-                        deleted
-                    );
-                }
-                Err(e) => {
-                    println!("empty_trash: Failed to delete from Telegram: {}", e);
-                }
-            }
-        }
     }
 
     Ok(deleted_count)
 }
 
+/// Caption for an uploaded `library.db` backup.
+async fn backup_caption(
+    state: &State<'_, AppState>,
+    path: &std::path::Path,
+    encrypted: bool,
+) -> Result<String, String> {
+    let file_hash = media_utils::hash_file_streaming(path).map_err(|e| e.to_string())?;
+    let file_name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("library_backup.db");
+    let meta = caption::CaptionMetadata::library_backup(&file_hash, file_name, encrypted);
+    let key = if encrypted {
+        get_active_master_key(state).await
+    } else {
+        None
+    };
+    caption::build_caption(&meta, key.as_ref()).map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 async fn backup_database(
     destination: Option<String>,
@@ -1923,9 +3826,19 @@ async fn backup_database(
 
     let backup_path_str = final_backup_path.to_string_lossy().to_string();
 
-    // Optionally upload to Telegram
+    // Optionally upload to Telegram, tagged so disaster recovery can find the newest backup
     if upload_to_telegram {
-        match state.telegram.upload_file(&backup_path_str).await {
+        let encrypted = security_mode == "encrypted";
+        let upload_result = match backup_caption(&state, &final_backup_path, encrypted).await {
+            Ok(caption_text) => {
+                state
+                    .telegram
+                    .upload_file(&backup_path_str, caption_text)
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+        match upload_result {
             Ok(_) => {
                 log::info!("Database backup uploaded to Telegram");
             }
@@ -1939,6 +3852,124 @@ async fn backup_database(
     Ok(backup_path_str)
 }
 
+/// Disaster recovery: rebuild cloud-only library entries from the storage destination.
+/// `backup_path` selects a local `library.db` backup to restore metadata from instead of
+/// the newest one found in the chat.
+#[tauri::command]
+async fn recover_library_from_telegram(
+    backup_path: Option<String>,
+    identify_unknown: Option<bool>,
+    state: State<'_, AppState>,
+    app: tauri::AppHandle,
+) -> Result<recovery::RecoveryReport, String> {
+    let db = {
+        let db_guard = state.db.lock().await;
+        db_guard.as_ref().ok_or("Database not initialized")?.clone()
+    };
+    if !state.telegram.is_authorized().await {
+        return Err("Telegram is not connected".to_string());
+    }
+
+    let encrypted = db
+        .get_config(SECURITY_MODE_KEY)
+        .map_err(|e| e.to_string())?
+        .map(|v| v == "encrypted")
+        .unwrap_or(false);
+    let keys = state.security_runtime.lock().await.read_keys();
+    if encrypted && keys.is_empty() {
+        return Err("Unlock encryption before recovering the library".to_string());
+    }
+
+    let app_dir = resolve_app_data_dir(&app)?;
+    recovery::rebuild_library(
+        db,
+        state.telegram.clone(),
+        keys,
+        app_dir.join("backup"),
+        std::env::temp_dir().join("wanderer-recovery"),
+        backup_path.map(std::path::PathBuf::from),
+        identify_unknown.unwrap_or(false),
+        app,
+    )
+    .await
+}
+
+/// Compare the library with the storage backend and report what doesn't match.
+/// With `verify_headers`, uploads without caption metadata are downloaded once to
+/// check whether they are encrypted. Only the primary Telegram account is audited.
+#[tauri::command]
+async fn run_cloud_audit(
+    verify_headers: Option<bool>,
+    state: State<'_, AppState>,
+) -> Result<audit::AuditReport, String> {
+    let db = {
+        let db_guard = state.db.lock().await;
+        db_guard.as_ref().ok_or("Database not initialized")?.clone()
+    };
+    let backend = storage::active_backend(&db, &state.telegram)?;
+    if backend.kind() == "telegram" && !state.telegram.is_authorized().await {
+        return Err("Telegram is not connected".to_string());
+    }
+    let read_keys = audit_read_keys(&db, &state).await?;
+
+    audit::run_audit(
+        &db,
+        backend.as_ref(),
+        read_keys,
+        verify_headers.unwrap_or(false),
+        std::env::temp_dir().join("wanderer-audit"),
+    )
+    .await
+}
+
+/// Apply one repair action from a cloud audit report.
+#[tauri::command]
+async fn repair_cloud_audit_issue(
+    repair: audit::AuditRepair,
+    state: State<'_, AppState>,
+    app: tauri::AppHandle,
+) -> Result<(), String> {
+    let db = {
+        let db_guard = state.db.lock().await;
+        db_guard.as_ref().ok_or("Database not initialized")?.clone()
+    };
+    let backend = storage::active_backend(&db, &state.telegram)?;
+    let read_keys = audit_read_keys(&db, &state).await?;
+    let imported = matches!(repair, audit::AuditRepair::Import { .. });
+
+    let app_dir = resolve_app_data_dir(&app)?;
+    audit::repair(
+        &db,
+        backend.as_ref(),
+        repair,
+        read_keys,
+        &app_dir.join("backup"),
+    )
+    .await?;
+    if imported {
+        let _ = app.emit("media-added", ());
+    }
+    Ok(())
+}
+
+/// The keys for reading encrypted captions, including the one an unfinished key
+/// rotation retired. Required when encryption is on.
+async fn audit_read_keys(
+    db: &Database,
+    state: &State<'_, AppState>,
+) -> Result<Vec<[u8; 32]>, String> {
+    let encrypted = db
+        .get_config(SECURITY_MODE_KEY)
+        .map_err(|e| e.to_string())?
+        .map(|v| v == "encrypted")
+        .unwrap_or(false);
+    let read_keys = state.security_runtime.lock().await.read_keys();
+    if encrypted && read_keys.is_empty() {
+        return Err("Unlock encryption before auditing the cloud library".to_string());
+    }
+    Ok(read_keys)
+}
+
 #[tauri::command]
 async fn remove_local_copy(media_id: i64, state: State<'_, AppState>) -> Result<(), String> {
     // Get the media item to find the file path
@@ -2032,7 +4063,8 @@ async fn download_local_copy(
     ));
 
     // Download from Telegram and decrypt transparently when needed.
-    let download_result = download_and_materialize_media(&state, msg_id, &staged_path).await;
+    let download_result =
+        download_and_materialize_media(&state, media_id, msg_id, &staged_path).await;
     if let Err(e) = download_result {
         let _ = std::fs::remove_file(&staged_path);
         return Err(e);
@@ -2053,6 +4085,15 @@ async fn download_local_copy(
     let db_guard = state.db.lock().await;
     let db = db_guard.as_ref().ok_or("Database not initialized")?;
 
+    let key = get_active_master_key(&state).await;
+    if let Err(e) = local_originals::protect_new(db, &download_path, key.as_ref()) {
+        log::warn!(
+            "Failed to encrypt restored original {:?}: {}",
+            download_path,
+            e
+        );
+    }
+
     // Mark as not cloud-only
     db.set_cloud_only(media_id, false)
         .map_err(|e| e.to_string())?;
@@ -2080,8 +4121,12 @@ async fn download_for_view(
         .map_err(|e| e.to_string())?
         .ok_or_else(|| "Media not found".to_string())?;
 
+    // Encrypted local originals are viewed like encrypted cloud copies
+    let local_sealed = !media.is_cloud_only
+        && security::is_encrypted_file(std::path::Path::new(&media.file_path)).unwrap_or(false);
+
     // Check if it's cloud-only
-    if !media.is_cloud_only {
+    if !media.is_cloud_only && !local_sealed {
         // If not cloud-only, return existing path if it exists
         // Or if it doesn't exist (deleted manually?), simple return file_path
         // expecting frontend to handle it, OR we could try to download it?
@@ -2089,16 +4134,15 @@ async fn download_for_view(
         return Ok(media.file_path);
     }
 
-    // Get the telegram_media_id
-    let telegram_id = media
-        .telegram_media_id
-        .clone()
-        .ok_or_else(|| "No Telegram ID found".to_string())?;
-
     // Parse the telegram_media_id to get the message ID
-    let msg_id: i32 = telegram_id
-        .parse()
-        .map_err(|_| "Invalid Telegram message ID".to_string())?;
+    let msg_id = || -> Result<i32, String> {
+        media
+            .telegram_media_id
+            .as_deref()
+            .ok_or_else(|| "No Telegram ID found".to_string())?
+            .parse()
+            .map_err(|_| "Invalid Telegram message ID".to_string())
+    };
 
     // Drop db guard
     drop(db_guard);
@@ -2122,60 +4166,73 @@ async fn download_for_view(
         .and_then(|n| n.to_str())
         .unwrap_or("cache_file");
 
-    if encrypted_mode {
+    if encrypted_mode || local_sealed {
         let key = get_active_master_key(&state)
             .await
             .ok_or_else(|| "Encryption vault is locked. Unlock to view cloud media.".to_string())?;
+        let keys = state.security_runtime.lock().await.read_keys();
 
         // In encrypted mode, keep cache encrypted-at-rest and materialize plaintext
         // only in temp for active viewing.
-        let cache_blob_path = cache_dir.join(format!("{}_{}.wbenc", media_id, filename));
+        let cache_blob_path =
+            view_cache::encrypted_blob_path(&cache_dir, media_id, &media.file_path);
+        let is_video = media
+            .mime_type
+            .as_deref()
+            .is_some_and(|m| m.starts_with("video/"));
+
+        // A sealed original already is a container and is read in place
+        let source_path = if local_sealed {
+            std::path::PathBuf::from(&media.file_path)
+        } else {
+            cache_blob_path.clone()
+        };
 
-        if !cache_blob_path.exists() {
-            let staging_dir = std::env::temp_dir().join("wanderer-view-cache-staging");
-            std::fs::create_dir_all(&staging_dir).map_err(|e| e.to_string())?;
-            let raw_download_path = staging_dir.join(format!(
-                "view_{}_{}.bin",
+        let download = match media_stream::download_in_progress(media_id) {
+            Some(partial) => Some(partial),
+            None if local_sealed || cache_blob_path.exists() => None,
+            None => Some(start_view_download(
+                &app,
                 media_id,
-                time::OffsetDateTime::now_utc().unix_timestamp_nanos()
-            ));
-            let raw_download_str = raw_download_path.to_string_lossy().to_string();
-
-            state
-                .telegram
-                .download_by_message_id(msg_id, &raw_download_str)
-                .await
-                .map_err(|e| format!("Failed to download from Telegram: {}", e))?;
-
-            let downloaded_is_encrypted =
-                security::is_encrypted_file(&raw_download_path).map_err(|e| e.to_string())?;
-
-            let write_result = if downloaded_is_encrypted {
-                match std::fs::rename(&raw_download_path, &cache_blob_path) {
-                    Ok(_) => Ok(()),
-                    Err(_) => {
-                        std::fs::copy(&raw_download_path, &cache_blob_path)
-                            .map_err(|e| e.to_string())?;
-                        let _ = std::fs::remove_file(&raw_download_path);
-                        Ok(())
-                    }
+                msg_id()?,
+                &cache_blob_path,
+                key,
+            )?),
+        };
+        if let Some(partial) = download {
+            // Videos start playing once the container header is in; the stream
+            // protocol waits for the chunks each request covers
+            loop {
+                if let Some(outcome) = partial.outcome() {
+                    outcome?;
+                    break;
                 }
-            } else {
-                security::encrypt_file(&raw_download_path, &cache_blob_path, &key)
-                    .map_err(|e| e.to_string())?;
-                let _ = std::fs::remove_file(&raw_download_path);
-                Ok(())
-            };
+                if is_video && partial.streamable(&keys) {
+                    return Ok(cache_blob_path.to_string_lossy().to_string());
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            }
+        }
 
-            if let Err(e) = write_result {
-                let _ = std::fs::remove_file(&raw_download_path);
-                return Err(e);
+        if !local_sealed {
+            // Copies not re-encrypted yet by a key rotation are cached under the current key
+            let retired_key = state.security_runtime.lock().await.retired_key;
+            if let Some(retired_key) = retired_key {
+                if security::read_file_header(&cache_blob_path, &key).is_err() {
+                    security::reencrypt_container(&cache_blob_path, &retired_key, &key)
+                        .map_err(|e| e.to_string())?;
+                }
             }
+            let _ = filetime::set_file_mtime(&cache_blob_path, filetime::FileTime::now());
         }
 
-        let _ = filetime::set_file_mtime(&cache_blob_path, filetime::FileTime::now());
+        // Videos play through the stream protocol, decrypted as they play. The blob
+        // path names the stream even for a sealed original, which is never copied.
+        if is_video {
+            return Ok(cache_blob_path.to_string_lossy().to_string());
+        }
 
-        let materialized_dir = std::env::temp_dir().join("wanderer-view-cache-materialized");
+        let materialized_dir = std::env::temp_dir().join(VIEW_MATERIALIZED_DIR_NAME);
         std::fs::create_dir_all(&materialized_dir).map_err(|e| e.to_string())?;
         let cache_key = blake3::hash(cache_blob_path.to_string_lossy().as_bytes())
             .to_hex()
@@ -2187,7 +4244,7 @@ async fn download_for_view(
         let materialized_path = materialized_dir.join(format!("{}_{}.{}", media_id, cache_key, ext));
 
         let needs_refresh = if materialized_path.exists() {
-            let src_m = std::fs::metadata(&cache_blob_path).and_then(|m| m.modified());
+            let src_m = std::fs::metadata(&source_path).and_then(|m| m.modified());
             let out_m = std::fs::metadata(&materialized_path).and_then(|m| m.modified());
             match (src_m, out_m) {
                 (Ok(s), Ok(o)) => s > o,
@@ -2198,7 +4255,7 @@ async fn download_for_view(
         };
 
         if needs_refresh {
-            security::decrypt_file_if_needed(&cache_blob_path, &materialized_path, Some(&key))
+            security::decrypt_file_with_keys_if_needed(&source_path, &materialized_path, &keys)
                 .map_err(|e| e.to_string())?;
         }
         let _ = filetime::set_file_mtime(&materialized_path, filetime::FileTime::now());
@@ -2213,7 +4270,7 @@ async fn download_for_view(
         return Ok(cache_path_str);
     }
 
-    download_and_materialize_media(&state, msg_id, &cache_path).await?;
+    download_and_materialize_media(&state, media_id, msg_id()?, &cache_path).await?;
 
     log::info!(
         "Downloaded view cache for media {} to {}",
@@ -2246,15 +4303,10 @@ async fn generate_share_link(media_id: i64, state: State<'_, AppState>) -> Resul
         .parse()
         .map_err(|_| "Invalid telegram_media_id format")?;
 
-    // Generate Saved Messages deep link
-    // tg://resolve?domain=me works for Saved Messages
-    // For direct message link: https://t.me/c/{chat_id}/{msg_id} but Saved Messages is special
-    // Using the "me" domain which represents Saved Messages
-    let share_link = format!("tg://openmessage?user_id=me&message_id={}", msg_id);
+    drop(db_guard);
 
-    // Alternative formats that also work:
-    // - https://t.me/c/0/{msg_id} (Saved Messages as chat_id 0)
-    // - tg://privatepost?channel=0&post={msg_id}
+    // Saved Messages get a tg://openmessage deep link, channels a https://t.me/c/ link
+    let share_link = state.telegram.share_link(msg_id).await;
 
     log::info!(
         "Generated share link for media {}: {}",
@@ -2516,6 +4568,7 @@ async fn index_pending_clip(
     let pending = db
         .get_pending_clip_items(limit)
         .map_err(|e| e.to_string())?;
+    let keys = state.security_runtime.lock().await.read_keys();
     let mut count = 0;
 
     for (id, path_str) in pending {
@@ -2524,9 +4577,19 @@ async fn index_pending_clip(
             let _ = db.mark_clip_failed(id);
             continue;
         }
+        // Encrypted originals wait for the vault to unlock
+        let plain = match local_originals::PlainOriginal::open(path, &keys) {
+            Ok(plain) => plain,
+            Err(_) if keys.is_empty() => continue,
+            Err(e) => {
+                log::error!("Failed to read {}: {}", path_str, e);
+                let _ = db.mark_clip_failed(id);
+                continue;
+            }
+        };
 
         // Encode
-        match clip::encode_image(path) {
+        match clip::encode_image(plain.path()) {
             Ok(embedding) => {
                 if let Err(e) = db.store_clip_embedding(id, &embedding) {
                     log::error!("Failed to store embedding for {}: {}", path_str, e);
@@ -0,0 +1,317 @@
+//! Local originals kept encrypted at rest.
+//!
+//! With local encryption on, the originals of an encrypted library are rewritten in place
+//! as WBENC containers under the master key. They keep their paths, so the media table,
+//! upload queue and sync manifests still refer to them unchanged. Everything that needs
+//! the plaintext (thumbnails, AI indexing, uploads, export) reads it through
+//! [`PlainOriginal`].
+
+use crate::database::Database;
+use crate::media_utils;
+use crate::security::{self, FileHeader};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// Config key: `true` while originals are kept encrypted, `false` after turning it off
+/// until the encryption migration has decrypted them all again
+pub const LOCAL_ENCRYPTION_KEY: &str = "security_local_originals_encrypted";
+
+static NEXT_COPY: AtomicU64 = AtomicU64::new(0);
+/// Copies handed out by [`PlainOriginal`] and not dropped yet
+static HELD_COPIES: AtomicUsize = AtomicUsize::new(0);
+/// Held while an original is rewritten, so the watcher and a restore racing on the same
+/// new file can't both encrypt it
+static REWRITE: Mutex<()> = Mutex::new(());
+
+fn rewrite_lock() -> std::sync::MutexGuard<'static, ()> {
+    REWRITE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// `Some(true)` with local encryption on, `Some(false)` while originals are still being
+/// decrypted after turning it off, `None` otherwise.
+pub fn setting(db: &Database) -> Option<bool> {
+    db.get_config(LOCAL_ENCRYPTION_KEY)
+        .ok()
+        .flatten()
+        .map(|v| v.eq_ignore_ascii_case("true"))
+}
+
+pub fn is_enabled(db: &Database) -> bool {
+    setting(db) == Some(true)
+}
+
+pub fn set_enabled(db: &Database, enabled: bool) -> Result<(), String> {
+    if !enabled && setting(db).is_none() {
+        return Ok(());
+    }
+    db.set_config(LOCAL_ENCRYPTION_KEY, if enabled { "true" } else { "false" })
+        .map_err(|e| e.to_string())
+}
+
+/// Forget the setting once every original is plain again.
+pub fn finish_disabling(db: &Database) -> Result<(), String> {
+    db.remove_config(LOCAL_ENCRYPTION_KEY)
+        .map_err(|e| e.to_string())
+}
+
+fn plain_copies_dir() -> PathBuf {
+    std::env::temp_dir().join("wanderer-plain-originals")
+}
+
+/// Delete every decrypted copy still around: at startup, in case a crash left some
+/// behind, and when the vault locks once their readers have let go.
+pub fn clear_plain_copies() {
+    let _ = std::fs::remove_dir_all(plain_copies_dir());
+}
+
+/// How many copies in the temp directory are still in use.
+pub fn held_copies() -> usize {
+    HELD_COPIES.load(Ordering::SeqCst)
+}
+
+/// A fresh path in the temp directory for a copy of `path`.
+fn new_copy_path(path: &Path) -> Result<PathBuf, String> {
+    let dir = plain_copies_dir();
+    std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    // Decoders pick the format by extension, so the copy keeps the original's name
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    Ok(dir.join(format!(
+        "{}_{}_{}",
+        std::process::id(),
+        NEXT_COPY.fetch_add(1, Ordering::Relaxed),
+        name
+    )))
+}
+
+/// The plaintext of an original: the file itself, or a decrypted copy in the temp
+/// directory that is deleted when this is dropped.
+pub struct PlainOriginal {
+    path: PathBuf,
+    temporary: bool,
+}
+
+impl PlainOriginal {
+    /// `keys` are tried in turn on an encrypted original; see
+    /// [`security::RuntimeState::read_keys`].
+    pub fn open(path: &Path, keys: &[[u8; 32]]) -> Result<Self, String> {
+        if !security::is_encrypted_file(path).map_err(|e| e.to_string())? {
+            return Ok(Self {
+                path: path.to_path_buf(),
+                temporary: false,
+            });
+        }
+        if keys.is_empty() {
+            return Err("Unlock encryption to read encrypted originals".to_string());
+        }
+
+        let copy = new_copy_path(path)?;
+        if let Err(e) = security::decrypt_file_with_keys(path, &copy, keys) {
+            let _ = std::fs::remove_file(&copy);
+            return Err(e.to_string());
+        }
+        Ok(Self::temporary(copy))
+    }
+
+    /// [`Self::open`] for readers that go back to the file several times, like the
+    /// upload worker hashing, encrypting and sending it. With local encryption on, a
+    /// plain original may be encrypted in place meanwhile, so it is pinned instead of
+    /// read by path.
+    pub fn open_pinned(db: &Database, path: &Path, keys: &[[u8; 32]]) -> Result<Self, String> {
+        let _rewrite = rewrite_lock();
+        if is_enabled(db) && !security::is_encrypted_file(path).map_err(|e| e.to_string())? {
+            return Self::pin(path);
+        }
+        Self::open(path, keys)
+    }
+
+    /// Link (or, across file systems, copy) a plain original into the temp directory.
+    /// Rewrites replace the original's file, so the link keeps the current bytes.
+    fn pin(path: &Path) -> Result<Self, String> {
+        let copy = new_copy_path(path)?;
+        if std::fs::hard_link(path, &copy).is_err() {
+            if let Err(e) = std::fs::copy(path, &copy) {
+                let _ = std::fs::remove_file(&copy);
+                return Err(e.to_string());
+            }
+        }
+        Ok(Self::temporary(copy))
+    }
+
+    fn temporary(path: PathBuf) -> Self {
+        HELD_COPIES.fetch_add(1, Ordering::SeqCst);
+        Self {
+            path,
+            temporary: true,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for PlainOriginal {
+    fn drop(&mut self) {
+        if self.temporary {
+            let _ = std::fs::remove_file(&self.path);
+            HELD_COPIES.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+}
+
+/// blake3 hash of an original's plaintext. Encrypted originals name it in their header.
+pub fn content_hash(path: &Path, keys: &[[u8; 32]]) -> Result<String, String> {
+    if security::is_encrypted_file(path).map_err(|e| e.to_string())? {
+        let sealed_hash = keys
+            .iter()
+            .find_map(|key| security::read_file_header(path, key).ok().flatten())
+            .and_then(|header| header.blake3)
+            .filter(|hash| !hash.is_empty());
+        if let Some(hash) = sealed_hash {
+            return Ok(hash);
+        }
+    }
+    let plain = PlainOriginal::open(path, keys)?;
+    media_utils::hash_file_streaming(plain.path()).map_err(|e| e.to_string())
+}
+
+/// Encrypt an original in place. Returns `false` when it already is encrypted.
+pub fn encrypt_in_place(path: &Path, key: &[u8; 32]) -> Result<bool, String> {
+    let _rewrite = rewrite_lock();
+    if security::is_encrypted_file(path).map_err(|e| e.to_string())? {
+        return Ok(false);
+    }
+    seal(path, path, key)?;
+    Ok(true)
+}
+
+/// Move an encrypted original to `key` from one of `old_keys`. Returns `false` when it
+/// already is under `key`.
+pub fn reencrypt_in_place(
+    path: &Path,
+    old_keys: &[[u8; 32]],
+    key: &[u8; 32],
+) -> Result<bool, String> {
+    let _rewrite = rewrite_lock();
+    if security::read_file_header(path, key).is_ok() {
+        return Ok(false);
+    }
+    // Decrypted outside the library folder, so the watcher never sees the plaintext
+    let plain = PlainOriginal::open(path, old_keys)?;
+    seal(path, plain.path(), key)?;
+    Ok(true)
+}
+
+/// Replace `path` with `source` encrypted under `key`.
+fn seal(path: &Path, source: &Path, key: &[u8; 32]) -> Result<(), String> {
+    let file_hash = media_utils::hash_file_streaming(source).map_err(|e| e.to_string())?;
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let mime_type = mime_guess::from_path(path).first().map(|m| m.to_string());
+    let header = FileHeader::new(&file_name, mime_type, &file_hash);
+    replace_file(path, "wbenc.tmp", |temp| {
+        security::encrypt_file_with_header(source, temp, key, &header).map_err(|e| e.to_string())
+    })
+}
+
+/// Decrypt an original in place. Returns `false` when it isn't encrypted.
+pub fn decrypt_in_place(path: &Path, keys: &[[u8; 32]]) -> Result<bool, String> {
+    let _rewrite = rewrite_lock();
+    if !security::is_encrypted_file(path).map_err(|e| e.to_string())? {
+        return Ok(false);
+    }
+    replace_file(path, "plain.tmp", |temp| {
+        security::decrypt_file_with_keys(path, temp, keys)
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    })?;
+    Ok(true)
+}
+
+/// Encrypt an original just added to the library when local encryption is on. Without
+/// the key it stays plain until the encryption migration next runs.
+pub fn protect_new(db: &Database, path: &Path, key: Option<&[u8; 32]>) -> Result<(), String> {
+    match key {
+        Some(key) if is_enabled(db) => encrypt_in_place(path, key).map(|_| ()),
+        _ => Ok(()),
+    }
+}
+
+/// Write the new contents of `path` next to it, then move them over it keeping its
+/// modification time. The watcher ignores the `.tmp` file in between.
+fn replace_file(
+    path: &Path,
+    suffix: &str,
+    write: impl FnOnce(&Path) -> Result<(), String>,
+) -> Result<(), String> {
+    let mut temp = OsString::from(path.as_os_str());
+    temp.push(".");
+    temp.push(suffix);
+    let temp = PathBuf::from(temp);
+    let modified = std::fs::metadata(path).and_then(|m| m.modified()).ok();
+
+    let result = write(&temp).and_then(|_| {
+        if let Some(modified) = modified {
+            let _ = filetime::set_file_mtime(&temp, filetime::FileTime::from_system_time(modified));
+        }
+        std::fs::rename(&temp, path).map_err(|e| e.to_string())
+    });
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encrypt_and_decrypt_in_place() {
+        let dir = std::env::temp_dir().join(format!("wanderer-originals-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("photo.jpg");
+        std::fs::write(&path, b"jpeg bytes").unwrap();
+        let plain_hash = media_utils::hash_file_streaming(&path).unwrap();
+        let key = [7u8; 32];
+
+        assert!(encrypt_in_place(&path, &key).unwrap());
+        assert!(!encrypt_in_place(&path, &key).unwrap());
+        assert!(security::is_encrypted_file(&path).unwrap());
+        assert_eq!(content_hash(&path, &[key]).unwrap(), plain_hash);
+        assert!(PlainOriginal::open(&path, &[]).is_err());
+
+        let new_key = [8u8; 32];
+        assert!(reencrypt_in_place(&path, &[key], &new_key).unwrap());
+        assert!(!reencrypt_in_place(&path, &[key], &new_key).unwrap());
+        assert_eq!(content_hash(&path, &[new_key]).unwrap(), plain_hash);
+        let key = new_key;
+
+        let copy_path = {
+            let plain = PlainOriginal::open(&path, &[[1u8; 32], key]).unwrap();
+            assert_eq!(plain.path().extension().unwrap(), "jpg");
+            assert_eq!(std::fs::read(plain.path()).unwrap(), b"jpeg bytes");
+            plain.path().to_path_buf()
+        };
+        assert!(!copy_path.exists());
+
+        assert!(decrypt_in_place(&path, &[key]).unwrap());
+        assert_eq!(std::fs::read(&path).unwrap(), b"jpeg bytes");
+        assert_eq!(PlainOriginal::open(&path, &[]).unwrap().path(), path);
+
+        // A pinned original keeps its bytes while it is encrypted in place
+        let pinned = PlainOriginal::pin(&path).unwrap();
+        assert!(encrypt_in_place(&path, &key).unwrap());
+        assert_eq!(std::fs::read(pinned.path()).unwrap(), b"jpeg bytes");
+        drop(pinned);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,352 @@
+//! `wbstream:` URI protocol for playing encrypted videos.
+//!
+//! The viewer points its `<video>` at `wbstream://localhost/<media id>`. Requests are
+//! answered with HTTP Range responses decrypted straight from the container, so only
+//! the chunks around the playback position are ever decrypted and no plaintext copy
+//! is written to disk. Sealed local originals are read in place; cloud videos are read
+//! from their view-cache blob, or from the partial download while it still arrives.
+
+use crate::database::Database;
+use crate::security::{self, DecryptingReader};
+use crate::view_cache;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::http::{header, Request, Response, StatusCode};
+
+pub const SCHEME: &str = "wbstream";
+/// Most bytes returned for one request; players ask again for the rest
+const MAX_RESPONSE_BYTES: u64 = 4 * 1024 * 1024;
+/// How long a request waits for the chunks it covers to be downloaded
+const ARRIVAL_TIMEOUT: Duration = Duration::from_secs(30);
+const ARRIVAL_POLL: Duration = Duration::from_millis(100);
+
+/// Cloud downloads into the view cache still running, by media ID
+static DOWNLOADS: Mutex<BTreeMap<i64, Arc<PartialBlob>>> = Mutex::new(BTreeMap::new());
+
+/// A cloud copy being downloaded into the view cache.
+pub struct PartialBlob {
+    path: PathBuf,
+    /// Set once the download has ended and the blob is in the view cache
+    outcome: Mutex<Option<Result<(), String>>>,
+}
+
+impl PartialBlob {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn outcome(&self) -> Option<Result<(), String>> {
+        self.outcome
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Whether the bytes so far open as a container that records its length, so
+    /// playback can start before the rest arrives.
+    pub fn streamable(&self, keys: &[[u8; 32]]) -> bool {
+        DecryptingReader::open_with_keys(&self.path, keys)
+            .is_ok_and(|reader| reader.container_version() == 2)
+    }
+}
+
+fn downloads() -> std::sync::MutexGuard<'static, BTreeMap<i64, Arc<PartialBlob>>> {
+    DOWNLOADS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// The download of `media_id` already running, if any.
+pub fn download_in_progress(media_id: i64) -> Option<Arc<PartialBlob>> {
+    downloads().get(&media_id).cloned()
+}
+
+/// Serve `media_id` from `path` while it is downloaded there.
+pub fn begin_download(media_id: i64, path: PathBuf) -> Arc<PartialBlob> {
+    let partial = Arc::new(PartialBlob {
+        path,
+        outcome: Mutex::new(None),
+    });
+    downloads().insert(media_id, partial.clone());
+    partial
+}
+
+/// Record how the download ended, once its blob has been moved into the view cache.
+pub fn finish_download(media_id: i64, partial: &Arc<PartialBlob>, outcome: Result<(), String>) {
+    *partial.outcome.lock().unwrap_or_else(|e| e.into_inner()) = Some(outcome);
+    let mut downloads = downloads();
+    if downloads
+        .get(&media_id)
+        .is_some_and(|current| Arc::ptr_eq(current, partial))
+    {
+        downloads.remove(&media_id);
+    }
+}
+
+/// Answer a `wbstream:` request. `keys` are the current and retired master keys, and
+/// empty while the vault is locked.
+pub fn respond(
+    db: &Database,
+    cache_dir: &Path,
+    keys: &[[u8; 32]],
+    request: &Request<Vec<u8>>,
+) -> Response<Vec<u8>> {
+    serve(db, cache_dir, keys, request).unwrap_or_else(|(status, message)| {
+        log::warn!("Stream request {} failed: {}", request.uri(), message);
+        Response::builder()
+            .status(status)
+            .header(header::CONTENT_TYPE, "text/plain")
+            .body(message.into_bytes())
+            .unwrap_or_default()
+    })
+}
+
+type Source = (DecryptingReader<BufReader<File>>, Option<Arc<PartialBlob>>);
+
+/// Open the container holding `media_id`'s content: the partial download along with
+/// its progress, the sealed local original, or the view-cache blob.
+fn open_source(
+    media_id: i64,
+    original: Option<&Path>,
+    blob: &Path,
+    keys: &[[u8; 32]],
+) -> Result<Source, String> {
+    let deadline = Instant::now() + ARRIVAL_TIMEOUT;
+    while let Some(partial) = download_in_progress(media_id) {
+        if let Ok(reader) = DecryptingReader::open_with_keys(partial.path(), keys) {
+            if reader.container_version() == 2 {
+                return Ok((reader, Some(partial)));
+            }
+        }
+        // The header hasn't arrived, or the finished download was just moved
+        if partial.outcome().is_some() || Instant::now() >= deadline {
+            break;
+        }
+        std::thread::sleep(ARRIVAL_POLL);
+    }
+
+    let source = original
+        .filter(|path| security::is_encrypted_file(path).unwrap_or(false))
+        .unwrap_or(blob);
+    DecryptingReader::open_with_keys(source, keys)
+        .map(|reader| (reader, None))
+        .map_err(|e| e.to_string())
+}
+
+/// Wait until a download has written `len` bytes of the file `reader` is open on.
+fn wait_for_arrival(
+    reader: &DecryptingReader<BufReader<File>>,
+    partial: &PartialBlob,
+    len: u64,
+) -> Result<(), (StatusCode, String)> {
+    let deadline = Instant::now() + ARRIVAL_TIMEOUT;
+    loop {
+        // The open handle follows the file when the finished download is moved
+        let arrived = reader
+            .get_ref()
+            .get_ref()
+            .metadata()
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+            .len();
+        if arrived >= len {
+            return Ok(());
+        }
+        match partial.outcome() {
+            Some(Err(e)) => return Err((StatusCode::SERVICE_UNAVAILABLE, e)),
+            Some(Ok(())) => {
+                return Err((
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Downloaded file is truncated".to_string(),
+                ))
+            }
+            None if Instant::now() >= deadline => {
+                return Err((
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "Download has not reached the requested range yet".to_string(),
+                ))
+            }
+            None => std::thread::sleep(ARRIVAL_POLL),
+        }
+    }
+}
+
+fn serve(
+    db: &Database,
+    cache_dir: &Path,
+    keys: &[[u8; 32]],
+    request: &Request<Vec<u8>>,
+) -> Result<Response<Vec<u8>>, (StatusCode, String)> {
+    let media_id: i64 = request
+        .uri()
+        .path()
+        .trim_start_matches('/')
+        .parse()
+        .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid media ID".to_string()))?;
+    if keys.is_empty() {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "Encryption vault is locked".to_string(),
+        ));
+    }
+    let media = db
+        .get_media_by_id(media_id)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "Media not found".to_string()))?;
+
+    let blob = view_cache::encrypted_blob_path(cache_dir, media_id, &media.file_path);
+    let original = (!media.is_cloud_only).then(|| Path::new(&media.file_path));
+    let (mut reader, partial) =
+        open_source(media_id, original, &blob, keys).map_err(|e| (StatusCode::NOT_FOUND, e))?;
+    let len = reader.plain_len();
+    let mime_type = media
+        .mime_type
+        .clone()
+        .or_else(|| {
+            mime_guess::from_path(&media.file_path)
+                .first()
+                .map(|m| m.to_string())
+        })
+        .unwrap_or_else(|| "application/octet-stream".to_string());
+    let response = Response::builder()
+        .header(header::CONTENT_TYPE, mime_type)
+        .header(header::ACCEPT_RANGES, "bytes");
+
+    let requested = request
+        .headers()
+        .get(header::RANGE)
+        .and_then(|v| v.to_str().ok());
+    let (start, end) = match requested {
+        Some(value) => match parse_range(value, len) {
+            Some(range) => range,
+            None => {
+                return response
+                    .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                    .header(header::CONTENT_RANGE, format!("bytes */{}", len))
+                    .body(Vec::new())
+                    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()));
+            }
+        },
+        None if len == 0 => {
+            return response
+                .status(StatusCode::OK)
+                .body(Vec::new())
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()));
+        }
+        None => (0, len - 1),
+    };
+    let end = end.min(start + MAX_RESPONSE_BYTES - 1);
+    if let Some(partial) = &partial {
+        wait_for_arrival(&reader, partial, reader.sealed_len_through(end))?;
+    }
+
+    let mut body = vec![0u8; (end - start + 1) as usize];
+    reader
+        .seek(SeekFrom::Start(start))
+        .and_then(|_| reader.read_exact(&mut body))
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    // A request without a range gets a partial answer too when the file is too large
+    // for one response
+    let status = if requested.is_some() || end + 1 < len {
+        StatusCode::PARTIAL_CONTENT
+    } else {
+        StatusCode::OK
+    };
+    response
+        .status(status)
+        .header(header::CONTENT_LENGTH, body.len())
+        .header(
+            header::CONTENT_RANGE,
+            format!("bytes {}-{}/{}", start, end, len),
+        )
+        .body(body)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+/// First range of a `Range: bytes=...` header as inclusive offsets into a body of
+/// `len` bytes, or `None` when it can't be satisfied.
+fn parse_range(value: &str, len: u64) -> Option<(u64, u64)> {
+    let spec = value
+        .trim()
+        .strip_prefix("bytes=")?
+        .split(',')
+        .next()?
+        .trim();
+    let (start, end) = spec.split_once('-')?;
+    let last = len.checked_sub(1)?;
+    let (start, end) = if start.is_empty() {
+        // "-N" asks for the last N bytes
+        let suffix: u64 = end.parse().ok()?;
+        if suffix == 0 {
+            return None;
+        }
+        (len.saturating_sub(suffix), last)
+    } else {
+        let start: u64 = start.parse().ok()?;
+        let end = if end.is_empty() {
+            last
+        } else {
+            end.parse::<u64>().ok()?.min(last)
+        };
+        (start, end)
+    };
+    (start <= end).then_some((start, end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_range_forms() {
+        assert_eq!(parse_range("bytes=0-", 100), Some((0, 99)));
+        assert_eq!(parse_range("bytes=10-19", 100), Some((10, 19)));
+        assert_eq!(parse_range("bytes=90-200", 100), Some((90, 99)));
+        assert_eq!(parse_range("bytes=-30", 100), Some((70, 99)));
+        assert_eq!(parse_range("bytes=-300", 100), Some((0, 99)));
+        assert_eq!(parse_range("bytes=5-9, 20-29", 100), Some((5, 9)));
+    }
+
+    #[test]
+    fn test_partial_download_serves_arrived_chunks() {
+        let dir = std::env::temp_dir().join(format!("wanderer-stream-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("create dir");
+        let (plain, sealed, partial_path) =
+            (dir.join("plain"), dir.join("sealed"), dir.join("partial"));
+        let key = [3u8; 32];
+        std::fs::write(&plain, vec![7u8; 3 * 1024 * 1024]).expect("write plain");
+        security::encrypt_file(&plain, &sealed, &key).expect("encrypt");
+        let raw = std::fs::read(&sealed).expect("read sealed");
+
+        let media_id = -1;
+        let partial = begin_download(media_id, partial_path.clone());
+        std::fs::write(&partial_path, &raw[..raw.len() / 2]).expect("write half");
+        let (mut reader, serving) =
+            open_source(media_id, None, &sealed, &[[9u8; 32], key]).expect("open partial");
+        assert!(serving.is_some_and(|p| Arc::ptr_eq(&p, &partial)));
+        assert!(wait_for_arrival(&reader, &partial, reader.sealed_len_through(10)).is_ok());
+        let mut head = [0u8; 10];
+        reader.read_exact(&mut head).expect("read head");
+        assert_eq!(head, [7u8; 10]);
+
+        // A failed download ends the wait for chunks that never arrived
+        let last = reader.plain_len() - 1;
+        finish_download(media_id, &partial, Err("offline".to_string()));
+        let (status, _) = wait_for_arrival(&reader, &partial, reader.sealed_len_through(last))
+            .expect_err("tail never arrived");
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert!(download_in_progress(media_id).is_none());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_parse_range_unsatisfiable() {
+        assert_eq!(parse_range("bytes=100-", 100), None);
+        assert_eq!(parse_range("bytes=20-10", 100), None);
+        assert_eq!(parse_range("bytes=-0", 100), None);
+        assert_eq!(parse_range("bytes=0-", 0), None);
+        assert_eq!(parse_range("items=0-5", 100), None);
+    }
+}
@@ -30,6 +30,17 @@ pub fn hash_file_streaming(path: &Path) -> std::io::Result<String> {
     Ok(hasher.finalize().to_hex().to_string())
 }
 
+/// Reduce a file name taken from remote metadata to its final path component, so it
+/// can't point outside the directory it is joined onto.
+pub fn safe_file_name(original: &str) -> String {
+    Path::new(original)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .filter(|n| !n.is_empty())
+        .unwrap_or("file")
+        .to_string()
+}
+
 /// Generate a perceptual hash for an image file.
 ///
 /// Perceptual hashes are similar for visually similar images,
@@ -1,3 +1,5 @@
+use crate::rate_limit::BandwidthLimiter;
+use std::future::Future;
 use std::io;
 use std::pin::Pin;
 use std::sync::Arc;
@@ -5,6 +7,7 @@ use std::task::{Context, Poll};
 use std::time::Instant;
 use tokio::io::AsyncRead;
 use tokio::sync::Mutex;
+use tokio::time::Sleep;
 
 /// Progress callback type - receives (bytes_uploaded, total_bytes, speed_bps)
 pub type ProgressCallback = Arc<dyn Fn(u64, u64, f64) + Send + Sync>;
@@ -18,6 +21,9 @@ pub struct ProgressStream<R> {
     callback: ProgressCallback,
     last_callback_bytes: u64,
     callback_threshold: u64, // Only call callback every N bytes
+    limiter: Option<Arc<BandwidthLimiter>>,
+    /// Pause imposed by the limiter before the next read
+    delay: Option<Pin<Box<Sleep>>>,
 }
 
 impl<R> ProgressStream<R> {
@@ -33,9 +39,17 @@ impl<R> ProgressStream<R> {
             callback,
             last_callback_bytes: 0,
             callback_threshold,
+            limiter: None,
+            delay: None,
         }
     }
 
+    /// Throttle reads to the rate allowed by `limiter`.
+    pub fn with_limiter(mut self, limiter: Arc<BandwidthLimiter>) -> Self {
+        self.limiter = Some(limiter);
+        self
+    }
+
     fn calculate_speed(&self, bytes: u64) -> f64 {
         let elapsed = self.start_time.elapsed().as_secs_f64();
         if elapsed > 0.0 {
@@ -52,6 +66,13 @@ impl<R: AsyncRead + Unpin> AsyncRead for ProgressStream<R> {
         cx: &mut Context<'_>,
         buf: &mut tokio::io::ReadBuf<'_>,
     ) -> Poll<io::Result<()>> {
+        if let Some(delay) = self.delay.as_mut() {
+            if delay.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+            self.delay = None;
+        }
+
         let before_len = buf.filled().len();
 
         // Poll the inner reader
@@ -68,6 +89,13 @@ impl<R: AsyncRead + Unpin> AsyncRead for ProgressStream<R> {
                     *guard
                 };
 
+                if let Some(limiter) = &self.limiter {
+                    let wait = limiter.consume(bytes_just_read as u64);
+                    if !wait.is_zero() {
+                        self.delay = Some(Box::pin(tokio::time::sleep(wait)));
+                    }
+                }
+
                 // Only invoke callback periodically to avoid overhead
                 if current_bytes - self.last_callback_bytes >= self.callback_threshold
                     || current_bytes >= self.total_bytes
@@ -108,4 +136,20 @@ mod tests {
         assert_eq!(output.len(), 1024);
         assert_eq!(progress_bytes.load(Ordering::SeqCst), 1024);
     }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_progress_stream_respects_limiter() {
+        let limiter = Arc::new(BandwidthLimiter::new());
+        limiter.set_rate(1024);
+        let callback: ProgressCallback = Arc::new(|_, _, _| {});
+        let mut stream = ProgressStream::new(std::io::Cursor::new(vec![0u8; 4096]), 4096, callback)
+            .with_limiter(limiter);
+
+        let started = tokio::time::Instant::now();
+        let mut output = Vec::new();
+        stream.read_to_end(&mut output).await.unwrap();
+
+        assert_eq!(output.len(), 4096);
+        assert!(started.elapsed() >= std::time::Duration::from_secs(3));
+    }
 }
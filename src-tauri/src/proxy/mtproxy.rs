@@ -0,0 +1,521 @@
+//! MTProxy support through a local SOCKS5 bridge.
+//!
+//! The Telegram client can only dial through SOCKS5, so [`MtProxyBridge`] listens on
+//! localhost as a SOCKS5 proxy. Each CONNECT names a Telegram DC address; the bridge maps
+//! it to a DC number, opens an obfuscated2 connection to the MTProxy with that DC in the
+//! handshake, and re-frames packets between the client's transport and the padded or
+//! plain intermediate transport MTProxy expects.
+//!
+//! Plain (16 byte) and `dd` secrets are supported. Fake-TLS `ee` secrets are not.
+
+use super::socks5;
+use aes::cipher::{KeyIvInit, StreamCipher};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+pub type Aes256Ctr = ctr::Ctr128BE<aes::Aes256>;
+
+const TAG_ABRIDGED: u8 = 0xef;
+const TAG_INTERMEDIATE: [u8; 4] = [0xee; 4];
+const TAG_PADDED: [u8; 4] = [0xdd; 4];
+/// Largest frame accepted from either side
+const MAX_FRAME_BYTES: usize = 4 * 1024 * 1024;
+
+/// Parsed MTProxy secret.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MtProxySecret {
+    key: [u8; 16],
+    /// `dd` secrets ask for the padded intermediate transport
+    padded: bool,
+}
+
+impl MtProxySecret {
+    /// Parse a secret as shown in proxy links: 32 hex digits, optionally prefixed by `dd`.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let bytes = hex::decode(text.trim())
+            .map_err(|_| "The MTProxy secret must be hexadecimal".to_string())?;
+        let (padded, key) = match bytes.as_slice() {
+            [0xee, ..] => {
+                return Err("Fake-TLS (ee) MTProxy secrets are not supported".to_string());
+            }
+            [0xdd, key @ ..] if key.len() == 16 => (true, key),
+            key if key.len() == 16 => (false, key),
+            _ => return Err("The MTProxy secret must be 16 bytes (32 hex digits)".to_string()),
+        };
+        Ok(Self {
+            key: key.try_into().expect("length checked"),
+            padded,
+        })
+    }
+
+    fn tag(&self) -> [u8; 4] {
+        if self.padded {
+            TAG_PADDED
+        } else {
+            TAG_INTERMEDIATE
+        }
+    }
+}
+
+/// DC number of a Telegram server address, from the ranges Telegram publishes.
+///
+/// Media-only servers, which file downloads are sent to, get the negated DC number:
+/// MTProxy forwards those to the DC's media servers.
+pub fn dc_for_address(ip: IpAddr) -> Option<i16> {
+    match ip {
+        IpAddr::V4(ip) => match ip.octets() {
+            [149, 154, 175, 50..=59] => Some(1),
+            [149, 154, 175, 100..=119] => Some(3),
+            [149, 154, 167, 35..=59] => Some(2),
+            [149, 154, 167, 90..=99] => Some(4),
+            [91, 108, 56, _] => Some(5),
+            [149, 154, 167, 128..=255] => Some(-2),
+            [149, 154, 164..=166, _] => Some(-4),
+            _ => None,
+        },
+        // 2001:b28:f23d:f001::a, 2001:67c:4e8:f002::a, ... carry the DC in the 4th group;
+        // media servers end in ::b
+        IpAddr::V6(ip) => match ip.segments() {
+            [0x2001, 0x0b28 | 0x067c, _, group, _, _, _, host] if group & 0xfff0 == 0xf000 => {
+                let dc = Some((group & 0x000f) as i16).filter(|dc| (1..=5).contains(dc))?;
+                Some(if host == 0x000b { -dc } else { dc })
+            }
+            _ => None,
+        },
+    }
+}
+
+/// Cipher pair of an obfuscated2 connection.
+pub struct Obfuscation {
+    pub encrypt: Aes256Ctr,
+    pub decrypt: Aes256Ctr,
+}
+
+/// Build the 64 byte obfuscated2 handshake for `dc_id`, returning it with the ciphers
+/// for the rest of the connection.
+pub fn handshake(secret: &MtProxySecret, dc_id: i16) -> ([u8; 64], Obfuscation) {
+    let mut init = [0u8; 64];
+    loop {
+        rand::thread_rng().fill_bytes(&mut init);
+        let first = [init[0], init[1], init[2], init[3]];
+        // Avoid prefixes a server could mistake for another protocol
+        let reserved = init[0] == TAG_ABRIDGED
+            || [b"HEAD", b"POST", b"GET ", b"OPTI"].contains(&&first)
+            || first == TAG_INTERMEDIATE
+            || first == TAG_PADDED
+            || first == [0x16, 0x03, 0x01, 0x02]
+            || init[4..8] == [0; 4];
+        if !reserved {
+            break;
+        }
+    }
+    init[56..60].copy_from_slice(&secret.tag());
+    init[60..62].copy_from_slice(&dc_id.to_le_bytes());
+
+    let reversed: Vec<u8> = init[8..56].iter().rev().copied().collect();
+    let derive = |key: &[u8], iv: &[u8]| {
+        let key = Sha256::new()
+            .chain_update(key)
+            .chain_update(secret.key)
+            .finalize();
+        Aes256Ctr::new(&key, iv.into())
+    };
+    let mut encrypt = derive(&init[8..40], &init[40..56]);
+    let decrypt = derive(&reversed[0..32], &reversed[32..48]);
+
+    let mut encrypted = init;
+    encrypt.apply_keystream(&mut encrypted);
+    init[56..64].copy_from_slice(&encrypted[56..64]);
+    (init, Obfuscation { encrypt, decrypt })
+}
+
+/// Write an intermediate frame, encrypting it when a cipher is given.
+pub async fn write_intermediate<W>(
+    writer: &mut W,
+    cipher: Option<&mut Aes256Ctr>,
+    payload: &[u8],
+) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let mut frame = Vec::with_capacity(payload.len() + 4);
+    frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    frame.extend_from_slice(payload);
+    if let Some(cipher) = cipher {
+        cipher.apply_keystream(&mut frame);
+    }
+    writer.write_all(&frame).await
+}
+
+/// Read an intermediate frame, decrypting it when a cipher is given. Quick acks come
+/// back as `None`.
+pub async fn read_intermediate<R>(
+    reader: &mut R,
+    mut cipher: Option<&mut Aes256Ctr>,
+) -> io::Result<Option<Vec<u8>>>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut len = [0u8; 4];
+    reader.read_exact(&mut len).await?;
+    if let Some(cipher) = cipher.as_deref_mut() {
+        cipher.apply_keystream(&mut len);
+    }
+    let len = u32::from_le_bytes(len);
+    if len & 0x8000_0000 != 0 {
+        return Ok(None);
+    }
+    let mut payload = read_payload(reader, len as usize).await?;
+    if let Some(cipher) = cipher {
+        cipher.apply_keystream(&mut payload);
+    }
+    Ok(Some(payload))
+}
+
+async fn read_payload<R>(reader: &mut R, len: usize) -> io::Result<Vec<u8>>
+where
+    R: AsyncRead + Unpin,
+{
+    if len > MAX_FRAME_BYTES {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Frame of {} bytes is too large", len),
+        ));
+    }
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload).await?;
+    Ok(payload)
+}
+
+/// Drop the random padding of a padded intermediate frame. MTProto packets carry
+/// enough structure to find their real end: plain messages state their length and
+/// encrypted ones are a 24 byte header followed by 16 byte blocks.
+fn strip_padding(frame: &mut Vec<u8>) {
+    if frame.len() < 24 {
+        // Transport error codes are 4 bytes
+        frame.truncate(4);
+    } else if frame[0..8] == [0; 8] {
+        let data_len = u32::from_le_bytes([frame[16], frame[17], frame[18], frame[19]]);
+        frame.truncate(20usize.saturating_add(data_len as usize));
+    } else {
+        let blocks = (frame.len() - 24) / 16;
+        frame.truncate(24 + blocks * 16);
+    }
+}
+
+/// Transport framing spoken by the Telegram client on the local side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Framing {
+    Abridged,
+    Intermediate,
+    Full,
+}
+
+fn encode_frame(framing: Framing, seq: &mut u32, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(payload.len() + 12);
+    match framing {
+        Framing::Abridged => {
+            let words = payload.len() / 4;
+            if words < 0x7f {
+                frame.push(words as u8);
+            } else {
+                frame.push(0x7f);
+                frame.extend_from_slice(&(words as u32).to_le_bytes()[..3]);
+            }
+            frame.extend_from_slice(payload);
+        }
+        Framing::Intermediate => {
+            frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+            frame.extend_from_slice(payload);
+        }
+        Framing::Full => {
+            frame.extend_from_slice(&(payload.len() as u32 + 12).to_le_bytes());
+            frame.extend_from_slice(&seq.to_le_bytes());
+            frame.extend_from_slice(payload);
+            let crc = crc32fast::hash(&frame);
+            frame.extend_from_slice(&crc.to_le_bytes());
+            *seq += 1;
+        }
+    }
+    frame
+}
+
+/// Reads packets from the local client, whatever transport it picked.
+struct ClientReader {
+    half: OwnedReadHalf,
+    framing: Framing,
+    /// Length word of the first Full frame, read while detecting the transport
+    carried_len: Option<[u8; 4]>,
+}
+
+impl ClientReader {
+    async fn detect(mut half: OwnedReadHalf) -> io::Result<Self> {
+        let mut head = [0u8; 4];
+        half.read_exact(&mut head[..1]).await?;
+        if head[0] == TAG_ABRIDGED {
+            return Ok(Self {
+                half,
+                framing: Framing::Abridged,
+                carried_len: None,
+            });
+        }
+        half.read_exact(&mut head[1..]).await?;
+        let (framing, carried_len) = if head == TAG_INTERMEDIATE || head == TAG_PADDED {
+            (Framing::Intermediate, None)
+        } else {
+            (Framing::Full, Some(head))
+        };
+        Ok(Self {
+            half,
+            framing,
+            carried_len,
+        })
+    }
+
+    async fn next(&mut self) -> io::Result<Vec<u8>> {
+        match self.framing {
+            Framing::Abridged => {
+                let first = self.half.read_u8().await? & 0x7f;
+                let words = if first == 0x7f {
+                    let mut len = [0u8; 4];
+                    self.half.read_exact(&mut len[..3]).await?;
+                    u32::from_le_bytes(len) as usize
+                } else {
+                    first as usize
+                };
+                read_payload(&mut self.half, words * 4).await
+            }
+            Framing::Intermediate => {
+                let len = self.half.read_u32_le().await? as usize;
+                read_payload(&mut self.half, len).await
+            }
+            Framing::Full => {
+                let len = match self.carried_len.take() {
+                    Some(len) => u32::from_le_bytes(len),
+                    None => self.half.read_u32_le().await?,
+                } as usize;
+                if len < 12 {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "Full transport frame is too short",
+                    ));
+                }
+                // Sequence number, payload, CRC; the local hop needs no checking
+                let rest = read_payload(&mut self.half, len - 4).await?;
+                Ok(rest[4..rest.len() - 4].to_vec())
+            }
+        }
+    }
+}
+
+/// A local SOCKS5 endpoint that forwards Telegram connections through an MTProxy.
+/// Stops accepting connections when dropped.
+pub struct MtProxyBridge {
+    local_addr: SocketAddr,
+    handle: JoinHandle<()>,
+}
+
+impl MtProxyBridge {
+    /// Listen on a free localhost port, forwarding to the MTProxy at `proxy_addr`.
+    pub async fn start(proxy_addr: String, secret: MtProxySecret) -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let local_addr = listener.local_addr()?;
+        let handle = tokio::spawn(async move {
+            loop {
+                let (client, _) = match listener.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        log::warn!("MTProxy bridge stopped accepting: {}", e);
+                        return;
+                    }
+                };
+                let proxy_addr = proxy_addr.clone();
+                let secret = secret.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = relay(client, &proxy_addr, &secret).await {
+                        log::debug!("MTProxy connection closed: {}", e);
+                    }
+                });
+            }
+        });
+        log::info!("MTProxy bridge listening on {}", local_addr);
+        Ok(Self { local_addr, handle })
+    }
+
+    /// SOCKS5 URL for the Telegram client.
+    pub fn url(&self) -> String {
+        format!("socks5://{}", self.local_addr)
+    }
+}
+
+impl Drop for MtProxyBridge {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+/// Open an obfuscated2 connection to the MTProxy for `dc_id`.
+pub async fn open(
+    proxy_addr: &str,
+    secret: &MtProxySecret,
+    dc_id: i16,
+) -> io::Result<(TcpStream, Obfuscation)> {
+    let mut stream = TcpStream::connect(proxy_addr).await?;
+    stream.set_nodelay(true)?;
+    let (init, cipher) = handshake(secret, dc_id);
+    stream.write_all(&init).await?;
+    Ok((stream, cipher))
+}
+
+async fn relay(mut client: TcpStream, proxy_addr: &str, secret: &MtProxySecret) -> io::Result<()> {
+    let target = socks5::accept(&mut client).await?;
+    let Some(dc_id) = dc_for_address(target.ip()) else {
+        socks5::reply(&mut client, socks5::REPLY_NOT_ALLOWED).await?;
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("{} is not a known Telegram address", target),
+        ));
+    };
+    let (server, cipher) = match open(proxy_addr, secret, dc_id).await {
+        Ok(opened) => opened,
+        Err(e) => {
+            socks5::reply(&mut client, socks5::REPLY_HOST_UNREACHABLE).await?;
+            return Err(e);
+        }
+    };
+    socks5::reply(&mut client, socks5::REPLY_SUCCEEDED).await?;
+
+    let (client_read, client_write) = client.into_split();
+    let (server_read, server_write) = server.into_split();
+    let reader = ClientReader::detect(client_read).await?;
+    let framing = reader.framing;
+
+    // Whichever side closes first ends the connection
+    tokio::select! {
+        result = pump_upstream(reader, server_write, cipher.encrypt) => result,
+        result = pump_downstream(server_read, client_write, cipher.decrypt, framing, secret.padded) => result,
+    }
+}
+
+async fn pump_upstream(
+    mut reader: ClientReader,
+    mut server: OwnedWriteHalf,
+    mut encrypt: Aes256Ctr,
+) -> io::Result<()> {
+    loop {
+        let packet = reader.next().await?;
+        write_intermediate(&mut server, Some(&mut encrypt), &packet).await?;
+    }
+}
+
+async fn pump_downstream(
+    mut server: OwnedReadHalf,
+    mut client: OwnedWriteHalf,
+    mut decrypt: Aes256Ctr,
+    framing: Framing,
+    padded: bool,
+) -> io::Result<()> {
+    let mut seq = 0;
+    loop {
+        let Some(mut packet) = read_intermediate(&mut server, Some(&mut decrypt)).await? else {
+            continue;
+        };
+        if padded {
+            strip_padding(&mut packet);
+        }
+        client
+            .write_all(&encode_frame(framing, &mut seq, &packet))
+            .await?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_secret() {
+        let plain = MtProxySecret::parse("0123456789abcdef0123456789abcdef").unwrap();
+        assert!(!plain.padded);
+        let padded = MtProxySecret::parse("dd0123456789abcdef0123456789abcdef").unwrap();
+        assert!(padded.padded);
+        assert_eq!(plain.key, padded.key);
+        assert!(MtProxySecret::parse("ee0123456789abcdef0123456789abcdef")
+            .unwrap_err()
+            .contains("Fake-TLS"));
+        assert!(MtProxySecret::parse("0123").is_err());
+        assert!(MtProxySecret::parse("not hex").is_err());
+    }
+
+    #[test]
+    fn test_dc_for_address() {
+        let dc = |ip: &str| dc_for_address(ip.parse().unwrap());
+        assert_eq!(dc("149.154.175.53"), Some(1));
+        assert_eq!(dc("149.154.167.51"), Some(2));
+        assert_eq!(dc("149.154.175.100"), Some(3));
+        assert_eq!(dc("149.154.167.92"), Some(4));
+        assert_eq!(dc("91.108.56.190"), Some(5));
+        assert_eq!(dc("2001:67c:4e8:f002::a"), Some(2));
+        assert_eq!(dc("2001:b28:f23f:f005::a"), Some(5));
+        assert_eq!(dc("149.154.167.151"), Some(-2));
+        assert_eq!(dc("149.154.166.120"), Some(-4));
+        assert_eq!(dc("2001:67c:4e8:f002::b"), Some(-2));
+        assert_eq!(dc("8.8.8.8"), None);
+    }
+
+    #[test]
+    fn test_handshake_is_readable_by_proxy() {
+        let secret = MtProxySecret::parse("dd0123456789abcdef0123456789abcdef").unwrap();
+        let (init, _) = handshake(&secret, 4);
+
+        // The proxy derives its read key from the same bytes and secret
+        let key = Sha256::new()
+            .chain_update(&init[8..40])
+            .chain_update(secret.key)
+            .finalize();
+        let mut decrypt = Aes256Ctr::new(&key, init[40..56].into());
+        let mut plain = init;
+        decrypt.apply_keystream(&mut plain);
+        assert_eq!(plain[56..60], TAG_PADDED);
+        assert_eq!(i16::from_le_bytes([plain[60], plain[61]]), 4);
+        assert_ne!(init[0], TAG_ABRIDGED);
+    }
+
+    #[test]
+    fn test_strip_padding() {
+        let mut plain = vec![0u8; 40];
+        plain[16..20].copy_from_slice(&20u32.to_le_bytes());
+        plain.extend_from_slice(&[7; 9]);
+        strip_padding(&mut plain);
+        assert_eq!(plain.len(), 40);
+
+        let mut encrypted = vec![1u8; 24 + 32 + 5];
+        strip_padding(&mut encrypted);
+        assert_eq!(encrypted.len(), 56);
+
+        let mut error = vec![0x6c, 0xfe, 0xff, 0xff, 3, 3];
+        strip_padding(&mut error);
+        assert_eq!(error, [0x6c, 0xfe, 0xff, 0xff]);
+    }
+
+    #[test]
+    fn test_encode_frame() {
+        let mut seq = 0;
+        assert_eq!(encode_frame(Framing::Abridged, &mut seq, &[1; 8])[0], 2);
+        let long = encode_frame(Framing::Abridged, &mut seq, &[1; 0x7f * 4]);
+        assert_eq!(long[..4], [0x7f, 0x7f, 0, 0]);
+
+        let full = encode_frame(Framing::Full, &mut seq, &[1; 8]);
+        assert_eq!(full.len(), 20);
+        assert_eq!(full[..4], 20u32.to_le_bytes());
+        assert_eq!(seq, 1);
+        let crc = crc32fast::hash(&full[..16]);
+        assert_eq!(full[16..], crc.to_le_bytes());
+    }
+}
@@ -0,0 +1,271 @@
+//! The parts of SOCKS5 (RFC 1928/1929) needed here: a client CONNECT with optional
+//! username/password auth for connection tests, and a no-auth CONNECT server for the
+//! local MTProxy bridge.
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+const VERSION: u8 = 0x05;
+const AUTH_NONE: u8 = 0x00;
+const AUTH_PASSWORD: u8 = 0x02;
+const AUTH_UNACCEPTABLE: u8 = 0xff;
+const CMD_CONNECT: u8 = 0x01;
+const ATYP_IPV4: u8 = 0x01;
+const ATYP_DOMAIN: u8 = 0x03;
+const ATYP_IPV6: u8 = 0x04;
+
+pub const REPLY_SUCCEEDED: u8 = 0x00;
+pub const REPLY_NOT_ALLOWED: u8 = 0x02;
+pub const REPLY_HOST_UNREACHABLE: u8 = 0x04;
+pub const REPLY_COMMAND_NOT_SUPPORTED: u8 = 0x07;
+pub const REPLY_ADDRESS_NOT_SUPPORTED: u8 = 0x08;
+
+#[derive(Debug)]
+pub enum Socks5Error {
+    Io(io::Error),
+    /// The proxy wants credentials and none were given, or it refused all methods
+    NoAcceptableAuth,
+    AuthFailed,
+    /// The proxy couldn't open the connection; carries the reply code
+    Rejected(u8),
+    Protocol(&'static str),
+}
+
+impl std::fmt::Display for Socks5Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{}", e),
+            Self::NoAcceptableAuth => write!(f, "The proxy requires a username and password"),
+            Self::AuthFailed => write!(f, "The proxy rejected the username or password"),
+            Self::Rejected(code) => write!(
+                f,
+                "The proxy refused the connection ({})",
+                reply_text(*code)
+            ),
+            Self::Protocol(what) => write!(f, "Not a SOCKS5 proxy: {}", what),
+        }
+    }
+}
+
+impl From<io::Error> for Socks5Error {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+fn reply_text(code: u8) -> &'static str {
+    match code {
+        0x01 => "general failure",
+        0x02 => "not allowed by ruleset",
+        0x03 => "network unreachable",
+        0x04 => "host unreachable",
+        0x05 => "connection refused",
+        0x06 => "TTL expired",
+        0x07 => "command not supported",
+        0x08 => "address type not supported",
+        _ => "unknown error",
+    }
+}
+
+/// Whether a reply code means the proxy works but couldn't reach the target.
+pub fn is_target_unreachable(code: u8) -> bool {
+    matches!(code, 0x03..=0x06)
+}
+
+/// Ask the proxy on `stream` to connect to `target`.
+pub async fn connect<S>(
+    stream: &mut S,
+    target: SocketAddr,
+    auth: Option<(&str, &str)>,
+) -> Result<(), Socks5Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let methods: &[u8] = if auth.is_some() {
+        &[AUTH_NONE, AUTH_PASSWORD]
+    } else {
+        &[AUTH_NONE]
+    };
+    let mut greeting = vec![VERSION, methods.len() as u8];
+    greeting.extend_from_slice(methods);
+    stream.write_all(&greeting).await?;
+
+    let mut choice = [0u8; 2];
+    stream.read_exact(&mut choice).await?;
+    if choice[0] != VERSION {
+        return Err(Socks5Error::Protocol("unexpected greeting reply"));
+    }
+    match (choice[1], auth) {
+        (AUTH_NONE, _) => {}
+        (AUTH_PASSWORD, Some((username, password))) => {
+            if username.len() > 255 || password.len() > 255 {
+                return Err(Socks5Error::Protocol("username or password too long"));
+            }
+            let mut request = vec![0x01, username.len() as u8];
+            request.extend_from_slice(username.as_bytes());
+            request.push(password.len() as u8);
+            request.extend_from_slice(password.as_bytes());
+            stream.write_all(&request).await?;
+
+            let mut status = [0u8; 2];
+            stream.read_exact(&mut status).await?;
+            if status[1] != 0x00 {
+                return Err(Socks5Error::AuthFailed);
+            }
+        }
+        _ => return Err(Socks5Error::NoAcceptableAuth),
+    }
+
+    let mut request = vec![VERSION, CMD_CONNECT, 0x00];
+    match target.ip() {
+        IpAddr::V4(ip) => {
+            request.push(ATYP_IPV4);
+            request.extend_from_slice(&ip.octets());
+        }
+        IpAddr::V6(ip) => {
+            request.push(ATYP_IPV6);
+            request.extend_from_slice(&ip.octets());
+        }
+    }
+    request.extend_from_slice(&target.port().to_be_bytes());
+    stream.write_all(&request).await?;
+
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply).await?;
+    if reply[0] != VERSION {
+        return Err(Socks5Error::Protocol("unexpected connect reply"));
+    }
+    if reply[1] != REPLY_SUCCEEDED {
+        return Err(Socks5Error::Rejected(reply[1]));
+    }
+    // Skip the bound address
+    let skip = match reply[3] {
+        ATYP_IPV4 => 4,
+        ATYP_IPV6 => 16,
+        ATYP_DOMAIN => stream.read_u8().await? as usize,
+        _ => return Err(Socks5Error::Protocol("unknown bound address type")),
+    };
+    let mut bound = vec![0u8; skip + 2];
+    stream.read_exact(&mut bound).await?;
+    Ok(())
+}
+
+/// Serve the handshake of a local client, returning the requested IP address.
+/// Requests the bridge can't serve are answered here and come back as errors.
+pub async fn accept<S>(stream: &mut S) -> io::Result<SocketAddr>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut head = [0u8; 2];
+    stream.read_exact(&mut head).await?;
+    let mut methods = vec![0u8; head[1] as usize];
+    stream.read_exact(&mut methods).await?;
+    if head[0] != VERSION || !methods.contains(&AUTH_NONE) {
+        stream.write_all(&[VERSION, AUTH_UNACCEPTABLE]).await?;
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "SOCKS5 client without no-auth support",
+        ));
+    }
+    stream.write_all(&[VERSION, AUTH_NONE]).await?;
+
+    let mut request = [0u8; 4];
+    stream.read_exact(&mut request).await?;
+    let ip = match request[3] {
+        ATYP_IPV4 => {
+            let mut octets = [0u8; 4];
+            stream.read_exact(&mut octets).await?;
+            Some(IpAddr::V4(Ipv4Addr::from(octets)))
+        }
+        ATYP_IPV6 => {
+            let mut octets = [0u8; 16];
+            stream.read_exact(&mut octets).await?;
+            Some(IpAddr::V6(Ipv6Addr::from(octets)))
+        }
+        ATYP_DOMAIN => {
+            let len = stream.read_u8().await? as usize;
+            let mut name = vec![0u8; len];
+            stream.read_exact(&mut name).await?;
+            None
+        }
+        _ => None,
+    };
+    let port = stream.read_u16().await?;
+
+    if request[1] != CMD_CONNECT {
+        reply(stream, REPLY_COMMAND_NOT_SUPPORTED).await?;
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "SOCKS5 command other than CONNECT",
+        ));
+    }
+    match ip {
+        Some(ip) => Ok(SocketAddr::new(ip, port)),
+        None => {
+            reply(stream, REPLY_ADDRESS_NOT_SUPPORTED).await?;
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "SOCKS5 target is not an IP address",
+            ))
+        }
+    }
+}
+
+/// Send the CONNECT reply to a local client.
+pub async fn reply<S>(stream: &mut S, code: u8) -> io::Result<()>
+where
+    S: AsyncWrite + Unpin,
+{
+    stream
+        .write_all(&[VERSION, code, 0x00, ATYP_IPV4, 0, 0, 0, 0, 0, 0])
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_connect_with_password() {
+        let (mut client, mut server) = tokio::io::duplex(256);
+        let target: SocketAddr = "149.154.167.51:443".parse().unwrap();
+
+        let proxy = tokio::spawn(async move {
+            let mut greeting = [0u8; 4];
+            server.read_exact(&mut greeting).await.unwrap();
+            assert_eq!(greeting, [5, 2, AUTH_NONE, AUTH_PASSWORD]);
+            server.write_all(&[5, AUTH_PASSWORD]).await.unwrap();
+
+            let mut auth = [0u8; 10];
+            server.read_exact(&mut auth).await.unwrap();
+            assert_eq!(&auth, b"\x01\x03bob\x04pass");
+            server.write_all(&[1, 0]).await.unwrap();
+
+            let mut request = [0u8; 10];
+            server.read_exact(&mut request).await.unwrap();
+            assert_eq!(request, [5, 1, 0, 1, 149, 154, 167, 51, 1, 187]);
+            reply(&mut server, REPLY_SUCCEEDED).await.unwrap();
+        });
+
+        connect(&mut client, target, Some(("bob", "pass")))
+            .await
+            .unwrap();
+        proxy.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_accept_reads_target() {
+        let (mut client, mut server) = tokio::io::duplex(256);
+        let target: SocketAddr = "91.108.56.190:443".parse().unwrap();
+
+        let bridge = tokio::spawn(async move {
+            let requested = accept(&mut server).await.unwrap();
+            reply(&mut server, REPLY_SUCCEEDED).await.unwrap();
+            requested
+        });
+
+        connect(&mut client, target, None).await.unwrap();
+        assert_eq!(bridge.await.unwrap(), target);
+    }
+}
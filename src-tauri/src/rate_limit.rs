@@ -0,0 +1,211 @@
+//! Rate control shared by every Telegram caller.
+//!
+//! A FLOOD_WAIT reply throttles the whole account, not just the request that hit it.
+//! [`FloodGovernor`] remembers when traffic may resume so uploads, sync and downloads
+//! back off together instead of each one running into the limit separately.
+//! [`BandwidthLimiter`] caps the combined upload rate of parallel uploads, and
+//! [`UploadGate`] holds uploads between chunks while they are paused or out of schedule.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+#[derive(Debug, Default)]
+pub struct FloodGovernor {
+    resume_at: Mutex<Option<Instant>>,
+}
+
+impl FloodGovernor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pause all traffic for `secs` seconds. An existing longer pause is kept.
+    pub fn pause_for(&self, secs: u64) {
+        let until = Instant::now() + Duration::from_secs(secs);
+        let mut resume_at = self.resume_at.lock().unwrap_or_else(|e| e.into_inner());
+        match *resume_at {
+            Some(current) if current >= until => {}
+            _ => *resume_at = Some(until),
+        }
+    }
+
+    /// Time left until traffic may resume, or `None` when not paused.
+    pub fn remaining(&self) -> Option<Duration> {
+        let resume_at = *self.resume_at.lock().unwrap_or_else(|e| e.into_inner());
+        resume_at
+            .and_then(|t| t.checked_duration_since(Instant::now()))
+            .filter(|d| !d.is_zero())
+    }
+
+    /// Wait until any active pause has elapsed. Re-checks after sleeping because
+    /// another caller may have extended the pause in the meantime.
+    pub async fn wait(&self) {
+        while let Some(left) = self.remaining() {
+            tokio::time::sleep(left).await;
+        }
+    }
+}
+
+/// Token bucket shared by all uploads. A rate of 0 means unlimited.
+#[derive(Debug)]
+pub struct BandwidthLimiter {
+    bucket: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    rate_bps: u64,
+    /// Bytes that may be sent right now; negative while in debt
+    available: f64,
+    refilled_at: Instant,
+}
+
+impl Default for BandwidthLimiter {
+    fn default() -> Self {
+        Self {
+            bucket: Mutex::new(Bucket {
+                rate_bps: 0,
+                available: 0.0,
+                refilled_at: Instant::now(),
+            }),
+        }
+    }
+}
+
+impl BandwidthLimiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the cap in bytes per second; 0 removes it.
+    pub fn set_rate(&self, bytes_per_sec: u64) {
+        let mut bucket = self.bucket.lock().unwrap_or_else(|e| e.into_inner());
+        if bucket.rate_bps != bytes_per_sec {
+            bucket.rate_bps = bytes_per_sec;
+            bucket.available = 0.0;
+            bucket.refilled_at = Instant::now();
+        }
+    }
+
+    pub fn rate(&self) -> u64 {
+        self.bucket
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .rate_bps
+    }
+
+    /// Record `bytes` as sent and return how long the caller should wait before
+    /// sending more. The bucket holds at most one second of traffic.
+    pub fn consume(&self, bytes: u64) -> Duration {
+        let mut bucket = self.bucket.lock().unwrap_or_else(|e| e.into_inner());
+        if bucket.rate_bps == 0 {
+            return Duration::ZERO;
+        }
+        let now = Instant::now();
+        let rate = bucket.rate_bps as f64;
+        let refill = now.duration_since(bucket.refilled_at).as_secs_f64() * rate;
+        bucket.available = (bucket.available + refill).min(rate) - bytes as f64;
+        bucket.refilled_at = now;
+        if bucket.available < 0.0 {
+            Duration::from_secs_f64(-bucket.available / rate)
+        } else {
+            Duration::ZERO
+        }
+    }
+}
+
+/// Open/closed switch that uploads wait on between chunks.
+#[derive(Debug)]
+pub struct UploadGate {
+    open: watch::Sender<bool>,
+}
+
+impl Default for UploadGate {
+    fn default() -> Self {
+        Self {
+            open: watch::Sender::new(true),
+        }
+    }
+}
+
+impl UploadGate {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_open(&self, open: bool) {
+        self.open.send_if_modified(|current| {
+            let changed = *current != open;
+            *current = open;
+            changed
+        });
+    }
+
+    pub fn is_open(&self) -> bool {
+        *self.open.borrow()
+    }
+
+    /// Wait until the gate is open.
+    pub async fn wait_open(&self) {
+        let mut open = self.open.subscribe();
+        // The sender lives as long as `self`, so this can't fail while we wait
+        let _ = open.wait_for(|open| *open).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_not_paused_by_default() {
+        assert!(FloodGovernor::new().remaining().is_none());
+    }
+
+    #[test]
+    fn test_pause_is_never_shortened() {
+        let governor = FloodGovernor::new();
+        governor.pause_for(60);
+        governor.pause_for(1);
+        assert!(governor.remaining().unwrap() > Duration::from_secs(30));
+    }
+
+    #[test]
+    fn test_zero_pause_does_not_block() {
+        let governor = FloodGovernor::new();
+        governor.pause_for(0);
+        assert!(governor.remaining().is_none());
+    }
+
+    #[test]
+    fn test_unlimited_bandwidth_never_waits() {
+        let limiter = BandwidthLimiter::new();
+        assert_eq!(limiter.consume(u64::MAX / 2), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_bandwidth_debt_becomes_wait() {
+        let limiter = BandwidthLimiter::new();
+        limiter.set_rate(1000);
+        // The bucket starts empty, so 2000 bytes at 1000 B/s is about two seconds of debt
+        let wait = limiter.consume(2000);
+        assert!(wait > Duration::from_millis(1900) && wait <= Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn test_gate_releases_waiters_when_opened() {
+        let gate = std::sync::Arc::new(UploadGate::new());
+        gate.set_open(false);
+        assert!(!gate.is_open());
+        let waiter = tokio::spawn({
+            let gate = gate.clone();
+            async move { gate.wait_open().await }
+        });
+        gate.set_open(true);
+        tokio::time::timeout(Duration::from_secs(1), waiter)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+}
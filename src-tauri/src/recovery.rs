@@ -0,0 +1,684 @@
+//! Disaster recovery: rebuild the library from the storage destination's history.
+//!
+//! Used when `library.db` is lost. The full message history is paged through, each
+//! media message is identified by its caption metadata (or, for older uploads, by the
+//! newest `library.db` backup found in the chat) and registered as a cloud-only entry.
+//! Favorites, ratings, albums and EXIF fields are restored from that backup when present.
+
+use crate::caption::{self, CaptionMetadata};
+use crate::database::{Database, MediaItem, RecoveredMedia};
+use crate::media_utils;
+use crate::security;
+use crate::storage::{ObjectId, StoredObject};
+use crate::telegram::TelegramService;
+use grammers_client::media::Media;
+use grammers_client::message::Message;
+use log::{info, warn};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter};
+
+/// Messages requested per history page
+const HISTORY_PAGE_SIZE: usize = 100;
+
+static RECOVERY_RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Event payload for `recovery-progress`
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecoveryProgressEvent {
+    /// One of "scanning", "restoring_backup", "rebuilding", "done"
+    pub phase: String,
+    pub scanned_messages: usize,
+    pub processed: usize,
+    pub total: usize,
+}
+
+/// Summary returned once recovery finishes
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecoveryReport {
+    pub scanned_messages: usize,
+    /// New cloud-only rows inserted
+    pub restored: usize,
+    /// Items already in the library that were linked to their message
+    pub linked: usize,
+    /// Messages without caption metadata or a backup entry (skipped)
+    pub unidentified: usize,
+    /// Split uploads with missing parts (skipped)
+    pub incomplete: usize,
+    pub failed: usize,
+    /// Message ID of the library backup metadata was restored from
+    pub backup_message_id: Option<i32>,
+    pub last_error: Option<String>,
+}
+
+/// A media message from the storage destination, reduced to what recovery needs.
+struct CloudEntry {
+    msg_id: i32,
+    meta: Option<CaptionMetadata>,
+    mime_type: Option<String>,
+    file_name: Option<String>,
+    date: i64,
+}
+
+/// One logical library item: a single message or all parts of a split upload.
+struct CloudItem {
+    first: CloudEntry,
+    /// Message IDs of all parts, in order (empty for single messages)
+    parts: Vec<i32>,
+}
+
+/// Library rows from the newest backup, indexed for lookup.
+#[derive(Default)]
+struct BackupIndex {
+    by_hash: HashMap<String, (MediaItem, Vec<String>)>,
+    hash_by_telegram_id: HashMap<String, String>,
+}
+
+/// Clears the running flag when recovery ends, including on early returns.
+struct RunningGuard;
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        RECOVERY_RUNNING.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Rebuild library rows for every media message in the storage destination.
+///
+/// `keys` are the master keys that may have sealed uploads, current first (see
+/// `RuntimeState::read_keys`), so uploads from before an unfinished key rotation open too.
+/// `restore_dir` is where recovered items would live locally (the app's `backup`
+/// folder); `work_dir` holds temporary downloads. `backup_db` overrides the backup
+/// that metadata is restored from; otherwise the newest one in the chat is used.
+/// With `identify_unknown`, messages that carry no metadata at all are downloaded
+/// once to compute their hash instead of being skipped.
+#[allow(clippy::too_many_arguments)]
+pub async fn rebuild_library(
+    db: Arc<Database>,
+    telegram: Arc<TelegramService>,
+    keys: Vec<[u8; 32]>,
+    restore_dir: PathBuf,
+    work_dir: PathBuf,
+    backup_db: Option<PathBuf>,
+    identify_unknown: bool,
+    app_handle: AppHandle,
+) -> Result<RecoveryReport, String> {
+    if RECOVERY_RUNNING.swap(true, Ordering::SeqCst) {
+        return Err("Recovery is already running".to_string());
+    }
+    let _running = RunningGuard;
+    std::fs::create_dir_all(&work_dir).map_err(|e| e.to_string())?;
+
+    let mut report = RecoveryReport::default();
+
+    // 1. Page through the complete history, newest first.
+    let mut entries = Vec::new();
+    let mut offset_id = 0;
+    loop {
+        let page = telegram.get_history(offset_id, HISTORY_PAGE_SIZE).await?;
+        let page_len = page.len();
+        for msg in page {
+            offset_id = msg.id();
+            report.scanned_messages += 1;
+            if let Some(entry) = cloud_entry(&msg, &keys) {
+                entries.push(entry);
+            }
+        }
+        emit_progress(&app_handle, "scanning", &report, 0, 0);
+        if page_len < HISTORY_PAGE_SIZE {
+            break;
+        }
+    }
+    info!(
+        "Recovery: scanned {} messages, {} with media",
+        report.scanned_messages,
+        entries.len()
+    );
+
+    // 2. Load the newest library backup for metadata and pre-caption uploads.
+    emit_progress(&app_handle, "restoring_backup", &report, 0, 0);
+    let backup_source = match backup_db {
+        // Work on a copy: opening a backup runs schema migrations on it.
+        Some(path) => {
+            let copy = work_dir.join("library_backup_selected.db");
+            match security::decrypt_file_with_keys_if_needed(&path, &copy, &keys) {
+                Ok(_) => Some(copy),
+                Err(e) => return Err(format!("Failed to read selected backup: {}", e)),
+            }
+        }
+        None => {
+            let newest = entries
+                .iter()
+                .find(|e| is_library_backup(e))
+                .map(|e| e.msg_id);
+            match newest {
+                Some(msg_id) => match download_backup(&telegram, msg_id, &keys, &work_dir).await {
+                    Ok(path) => {
+                        report.backup_message_id = Some(msg_id);
+                        Some(path)
+                    }
+                    Err(e) => {
+                        warn!("Recovery: could not restore library backup: {}", e);
+                        report.last_error = Some(e);
+                        None
+                    }
+                },
+                None => None,
+            }
+        }
+    };
+    let backup = match backup_source.as_deref() {
+        Some(path) => load_backup_index(path).unwrap_or_else(|e| {
+            warn!("Recovery: could not read library backup {:?}: {}", path, e);
+            report.last_error = Some(e);
+            BackupIndex::default()
+        }),
+        None => BackupIndex::default(),
+    };
+
+    // 3. Group split uploads and rebuild rows, oldest first.
+    entries.retain(|e| !is_library_backup(e));
+    let (items, incomplete) = group_items(entries);
+    report.incomplete = incomplete;
+
+    let total = items.len();
+    let mut used_paths = HashSet::new();
+    for (processed, item) in items.into_iter().enumerate() {
+        let mut outcome = restore_item(&db, &backup, &item, None, &restore_dir, &mut used_paths);
+        if identify_unknown && matches!(outcome, Ok(RestoreOutcome::Unidentified)) {
+            outcome = match hash_by_download(&telegram, &item, &keys, &work_dir).await {
+                Ok(hash) => restore_item(
+                    &db,
+                    &backup,
+                    &item,
+                    Some(hash),
+                    &restore_dir,
+                    &mut used_paths,
+                ),
+                Err(e) => Err(e),
+            };
+        }
+        match outcome {
+            Ok(RestoreOutcome::Restored) => report.restored += 1,
+            Ok(RestoreOutcome::Linked) => report.linked += 1,
+            Ok(RestoreOutcome::Unidentified) => report.unidentified += 1,
+            Err(e) => {
+                warn!(
+                    "Recovery: failed to restore message {}: {}",
+                    item.first.msg_id, e
+                );
+                report.failed += 1;
+                report.last_error = Some(e);
+            }
+        }
+        if processed % 25 == 0 || processed + 1 == total {
+            emit_progress(&app_handle, "rebuilding", &report, processed + 1, total);
+        }
+    }
+
+    if let Some(path) = backup_source {
+        let _ = std::fs::remove_file(path);
+    }
+
+    emit_progress(&app_handle, "done", &report, total, total);
+    let _ = app_handle.emit("media-added", ());
+    info!(
+        "Recovery finished: {} restored, {} linked, {} unidentified, {} incomplete, {} failed",
+        report.restored, report.linked, report.unidentified, report.incomplete, report.failed
+    );
+    Ok(report)
+}
+
+/// Register an orphaned upload found by the cloud audit as a cloud-only library item.
+/// `parts` lists every part of a split upload in order (empty for a single object).
+/// Returns false when the content was already in the library and was only linked.
+pub fn import_object(
+    db: &Database,
+    object: &StoredObject,
+    meta: CaptionMetadata,
+    parts: Vec<ObjectId>,
+    restore_dir: &Path,
+) -> Result<bool, String> {
+    let item = CloudItem {
+        first: CloudEntry {
+            msg_id: object.id,
+            meta: Some(meta),
+            mime_type: object.mime_type.clone(),
+            file_name: object.file_name.clone(),
+            date: object.uploaded_at,
+        },
+        parts,
+    };
+    let outcome = restore_item(
+        db,
+        &BackupIndex::default(),
+        &item,
+        None,
+        restore_dir,
+        &mut HashSet::new(),
+    )?;
+    match outcome {
+        RestoreOutcome::Restored => Ok(true),
+        RestoreOutcome::Linked => Ok(false),
+        RestoreOutcome::Unidentified => Err("Upload carries no library metadata".to_string()),
+    }
+}
+
+fn emit_progress(
+    app_handle: &AppHandle,
+    phase: &str,
+    report: &RecoveryReport,
+    processed: usize,
+    total: usize,
+) {
+    let _ = app_handle.emit(
+        "recovery-progress",
+        RecoveryProgressEvent {
+            phase: phase.to_string(),
+            scanned_messages: report.scanned_messages,
+            processed,
+            total,
+        },
+    );
+}
+
+fn cloud_entry(msg: &Message, keys: &[[u8; 32]]) -> Option<CloudEntry> {
+    let media = msg.media()?;
+    let (mime_type, file_name) = match &media {
+        Media::Photo(_) => (Some("image/jpeg".to_string()), None),
+        Media::Document(doc) => (
+            doc.mime_type().map(str::to_string),
+            doc.name().filter(|n| !n.is_empty()).map(str::to_string),
+        ),
+        _ => (None, None),
+    };
+    let meta = caption::parse_caption_with_keys(msg.text(), keys)
+        .unwrap_or_else(|e| {
+            warn!(
+                "Recovery: unreadable caption on message {}: {}",
+                msg.id(),
+                e
+            );
+            None
+        })
+        .map(|(meta, _)| meta);
+    Some(CloudEntry {
+        msg_id: msg.id(),
+        meta,
+        mime_type,
+        file_name,
+        date: msg.date().timestamp(),
+    })
+}
+
+fn is_library_backup(entry: &CloudEntry) -> bool {
+    match &entry.meta {
+        Some(meta) => meta.is_library_backup(),
+        // Backups uploaded before captions carried metadata
+        None => entry
+            .file_name
+            .as_deref()
+            .map(|n| n.starts_with("library_backup_"))
+            .unwrap_or(false),
+    }
+}
+
+/// Merge parts of split uploads into one item each. Returns the items in ascending
+/// message order plus the number of split uploads missing parts.
+fn group_items(entries: Vec<CloudEntry>) -> (Vec<CloudItem>, usize) {
+    let mut items = Vec::new();
+    let mut splits: HashMap<String, Vec<CloudEntry>> = HashMap::new();
+    for entry in entries {
+        match entry.meta.as_ref().filter(|m| m.is_split()) {
+            Some(meta) => splits
+                .entry(meta.file_hash.clone())
+                .or_default()
+                .push(entry),
+            None => items.push(CloudItem {
+                first: entry,
+                parts: Vec::new(),
+            }),
+        }
+    }
+
+    let mut incomplete = 0;
+    for (_, group) in splits {
+        let expected = group
+            .first()
+            .and_then(|e| e.meta.as_ref())
+            .and_then(|m| m.part_count)
+            .unwrap_or(0);
+        // Keep the newest message per part index in case a part was re-sent.
+        let mut by_index: BTreeMap<u32, CloudEntry> = BTreeMap::new();
+        for entry in group {
+            let index = entry.meta.as_ref().and_then(|m| m.part_index).unwrap_or(0);
+            let newer = by_index
+                .get(&index)
+                .map(|existing| existing.msg_id < entry.msg_id)
+                .unwrap_or(true);
+            if newer {
+                by_index.insert(index, entry);
+            }
+        }
+        if by_index.len() as u32 != expected || !(0..expected).all(|i| by_index.contains_key(&i)) {
+            incomplete += 1;
+            continue;
+        }
+        let parts = by_index.values().map(|e| e.msg_id).collect();
+        if let Some(first) = by_index.into_values().next() {
+            items.push(CloudItem { first, parts });
+        }
+    }
+
+    items.sort_by_key(|item| item.first.msg_id);
+    (items, incomplete)
+}
+
+async fn download_backup(
+    telegram: &TelegramService,
+    msg_id: i32,
+    keys: &[[u8; 32]],
+    work_dir: &Path,
+) -> Result<PathBuf, String> {
+    let download_path = work_dir.join(format!("library_backup_{}.download", msg_id));
+    let restored_path = work_dir.join(format!("library_backup_{}.db", msg_id));
+    telegram
+        .download_by_message_id(msg_id, &download_path.to_string_lossy())
+        .await?;
+    let result = security::decrypt_file_with_keys_if_needed(&download_path, &restored_path, keys)
+        .map_err(|e| e.to_string());
+    let _ = std::fs::remove_file(&download_path);
+    result.map(|_| restored_path)
+}
+
+fn load_backup_index(path: &Path) -> Result<BackupIndex, String> {
+    let backup_db = Database::new(path).map_err(|e| e.to_string())?;
+    let mut index = BackupIndex::default();
+    for item in backup_db
+        .get_all_media_for_sync()
+        .map_err(|e| e.to_string())?
+    {
+        let Some(hash) = item.file_hash.clone() else {
+            continue;
+        };
+        let albums = backup_db
+            .get_albums_for_media(item.id)
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .map(|a| a.name)
+            .collect();
+        if let Some(tg_id) = item.telegram_media_id.clone().filter(|id| !id.is_empty()) {
+            index.hash_by_telegram_id.insert(tg_id, hash.clone());
+        }
+        index.by_hash.insert(hash, (item, albums));
+    }
+    info!(
+        "Recovery: loaded {} entries from library backup",
+        index.by_hash.len()
+    );
+    Ok(index)
+}
+
+enum RestoreOutcome {
+    Restored,
+    Linked,
+    Unidentified,
+}
+
+/// Download an item without metadata and return the blake3 hash of its plaintext.
+async fn hash_by_download(
+    telegram: &TelegramService,
+    item: &CloudItem,
+    keys: &[[u8; 32]],
+    work_dir: &Path,
+) -> Result<String, String> {
+    let msg_id = item.first.msg_id;
+    let download_path = work_dir.join(format!("identify_{}.download", msg_id));
+    let plain_path = work_dir.join(format!("identify_{}.bin", msg_id));
+    let result = async {
+        telegram
+            .download_by_message_id(msg_id, &download_path.to_string_lossy())
+            .await?;
+        // v2 containers name the hash in their header, sparing the decrypt
+        let sealed_hash = keys
+            .iter()
+            .find_map(|key| {
+                security::read_file_header(&download_path, key)
+                    .ok()
+                    .flatten()
+            })
+            .and_then(|header| header.blake3)
+            .filter(|hash| !hash.is_empty());
+        if let Some(hash) = sealed_hash {
+            return Ok(hash);
+        }
+        security::decrypt_file_with_keys_if_needed(&download_path, &plain_path, keys)
+            .map_err(|e| e.to_string())?;
+        media_utils::hash_file_streaming(&plain_path).map_err(|e| e.to_string())
+    }
+    .await;
+    let _ = std::fs::remove_file(&download_path);
+    let _ = std::fs::remove_file(&plain_path);
+    result
+}
+
+fn restore_item(
+    db: &Database,
+    backup: &BackupIndex,
+    item: &CloudItem,
+    known_hash: Option<String>,
+    restore_dir: &Path,
+    used_paths: &mut HashSet<PathBuf>,
+) -> Result<RestoreOutcome, String> {
+    let entry = &item.first;
+    let msg_id_str = entry.msg_id.to_string();
+    let file_hash = match (entry.meta.as_ref(), known_hash) {
+        (Some(meta), _) => meta.file_hash.clone(),
+        (None, Some(hash)) => hash,
+        (None, None) => match backup.hash_by_telegram_id.get(&msg_id_str) {
+            Some(hash) => hash.clone(),
+            None => return Ok(RestoreOutcome::Unidentified),
+        },
+    };
+
+    if db
+        .media_exists_by_hash(&file_hash)
+        .map_err(|e| e.to_string())?
+    {
+        db.link_telegram_id_if_missing(&file_hash, &msg_id_str)
+            .map_err(|e| e.to_string())?;
+        return Ok(RestoreOutcome::Linked);
+    }
+
+    let backed_up = backup.by_hash.get(&file_hash);
+    let original_name = entry
+        .meta
+        .as_ref()
+        .map(|m| m.file_name.clone())
+        .or_else(|| backed_up.and_then(|(m, _)| file_name_of(&m.file_path)))
+        .or_else(|| entry.file_name.clone())
+        .unwrap_or_else(|| format!("tg_{}", entry.msg_id));
+    let file_path = unique_restore_path(db, restore_dir, entry.msg_id, &original_name, used_paths)?;
+    let file_path_str = file_path.to_string_lossy().to_string();
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    let mut media = RecoveredMedia {
+        file_path: file_path_str.clone(),
+        file_hash: file_hash.clone(),
+        telegram_media_id: msg_id_str,
+        mime_type: entry
+            .meta
+            .as_ref()
+            .and_then(|m| m.mime_type.clone())
+            .or_else(|| entry.mime_type.clone()),
+        created_at: now,
+        uploaded_at: entry.date,
+        date_taken: entry.meta.as_ref().and_then(|m| m.date_taken.clone()),
+        is_encrypted: entry.meta.as_ref().map(|m| m.encrypted).unwrap_or(false),
+        container_version: entry
+            .meta
+            .as_ref()
+            .filter(|m| m.encrypted)
+            .map(|m| m.container_version()),
+        ..Default::default()
+    };
+    if let Some((prev, _)) = backed_up {
+        media.mime_type = media.mime_type.or_else(|| prev.mime_type.clone());
+        media.size_bytes = prev.size_bytes;
+        media.created_at = prev.created_at;
+        media.date_taken = media.date_taken.or_else(|| prev.date_taken.clone());
+        media.latitude = prev.latitude;
+        media.longitude = prev.longitude;
+        media.camera_make = prev.camera_make.clone();
+        media.camera_model = prev.camera_model.clone();
+        media.is_favorite = prev.is_favorite;
+        media.rating = prev.rating;
+        media.is_archived = prev.is_archived;
+    }
+
+    let media_id = db.add_media_recovered(&media).map_err(|e| e.to_string())?;
+
+    if item.parts.len() > 1 {
+        // Part sizes aren't known without downloading; only the order matters for reassembly.
+        let parts: Vec<(String, i64)> = item.parts.iter().map(|id| (id.to_string(), 0)).collect();
+        db.set_media_parts_by_path(&file_path_str, &parts)
+            .map_err(|e| e.to_string())?;
+    }
+
+    if let Some((_, albums)) = backed_up {
+        for name in albums {
+            let album_id = match db.get_album_by_name(name).map_err(|e| e.to_string())? {
+                Some(album) => album.id,
+                None => db.create_album(name).map_err(|e| e.to_string())?,
+            };
+            db.add_media_to_album(album_id, media_id)
+                .map_err(|e| e.to_string())?;
+        }
+    }
+
+    Ok(RestoreOutcome::Restored)
+}
+
+fn file_name_of(path: &str) -> Option<String> {
+    Path::new(path)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .map(str::to_string)
+}
+
+/// Local path a recovered item will be restored to. Falls back to a message-ID prefix
+/// when the original name is already used on disk, in the library or in this run.
+fn unique_restore_path(
+    db: &Database,
+    restore_dir: &Path,
+    msg_id: i32,
+    original_name: &str,
+    used_paths: &mut HashSet<PathBuf>,
+) -> Result<PathBuf, String> {
+    let name = media_utils::safe_file_name(original_name);
+    let mut path = restore_dir.join(&name);
+    let taken = |p: &PathBuf, used: &HashSet<PathBuf>| -> Result<bool, String> {
+        Ok(p.exists()
+            || used.contains(p)
+            || db
+                .media_exists_by_path(&p.to_string_lossy())
+                .map_err(|e| e.to_string())?)
+    };
+    if taken(&path, used_paths)? {
+        path = restore_dir.join(format!("tg_{}_{}", msg_id, name));
+    }
+    used_paths.insert(path.clone());
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(msg_id: i32, meta: Option<CaptionMetadata>) -> CloudEntry {
+        CloudEntry {
+            msg_id,
+            meta,
+            mime_type: Some("image/jpeg".to_string()),
+            file_name: None,
+            date: 0,
+        }
+    }
+
+    #[test]
+    fn test_group_items_merges_split_parts() {
+        let video = CaptionMetadata::new("video", "clip.mp4", None, None, false);
+        let broken = CaptionMetadata::new("broken", "long.mp4", None, None, false);
+        let photo = CaptionMetadata::new("photo", "a.jpg", None, None, false);
+        let entries = vec![
+            entry(1, None),
+            entry(2, Some(video.for_part(1, 2))),
+            entry(3, Some(video.for_part(0, 2))),
+            entry(4, Some(broken.for_part(0, 2))),
+            // Part 0 sent again after an interrupted upload
+            entry(5, Some(video.for_part(0, 2))),
+            entry(6, Some(photo)),
+        ];
+
+        let (items, incomplete) = group_items(entries);
+        assert_eq!(incomplete, 1);
+        let grouped: Vec<_> = items
+            .iter()
+            .map(|item| (item.first.msg_id, item.parts.clone()))
+            .collect();
+        assert_eq!(
+            grouped,
+            vec![(1, Vec::new()), (5, vec![5, 2]), (6, Vec::new())]
+        );
+    }
+
+    #[test]
+    fn test_unique_restore_path_avoids_collisions() {
+        let dir = std::env::temp_dir().join(format!("wanderer-recovery-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let restore_dir = dir.join("backup");
+        std::fs::create_dir_all(&restore_dir).unwrap();
+        let db = Database::new(dir.join("library.db")).unwrap();
+        let mut used = HashSet::new();
+
+        // Free names are kept, and only the first item of a run gets them
+        let first = unique_restore_path(&db, &restore_dir, 7, "b.jpg", &mut used).unwrap();
+        assert_eq!(first, restore_dir.join("b.jpg"));
+        let second = unique_restore_path(&db, &restore_dir, 8, "b.jpg", &mut used).unwrap();
+        assert_eq!(second, restore_dir.join("tg_8_b.jpg"));
+
+        // Names taken on disk or in the library
+        std::fs::write(restore_dir.join("a.jpg"), b"local").unwrap();
+        let on_disk = unique_restore_path(&db, &restore_dir, 9, "a.jpg", &mut used).unwrap();
+        assert_eq!(on_disk, restore_dir.join("tg_9_a.jpg"));
+        let library_path = restore_dir.join("c.jpg");
+        db.add_media(
+            &library_path.to_string_lossy(),
+            None,
+            None,
+            0,
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+        let in_library = unique_restore_path(&db, &restore_dir, 10, "c.jpg", &mut used).unwrap();
+        assert_eq!(in_library, restore_dir.join("tg_10_c.jpg"));
+
+        // Only the file name of the original path is used
+        let nested = unique_restore_path(&db, &restore_dir, 11, "../x/d.jpg", &mut used).unwrap();
+        assert_eq!(nested, restore_dir.join("d.jpg"));
+
+        drop(db);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
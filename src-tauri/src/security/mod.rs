@@ -1,3 +1,5 @@
+pub mod secret_store;
+
 use aes_gcm::aead::{Aead, KeyInit, Payload};
 use aes_gcm::{Aes256Gcm, Key, Nonce};
 use anyhow::{anyhow, Context, Result};
@@ -11,13 +13,51 @@ use base64::{engine::general_purpose::STANDARD as B64, Engine as _};
 use rand::RngCore;
 use serde::{Deserialize, Serialize};
 use std::fs::File;
-use std::io::{BufReader, BufWriter, Read, Write};
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
 use std::path::Path;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-const FILE_MAGIC: &[u8; 6] = b"WBENC1";
-const FILE_VERSION: u8 = 1;
+const FILE_MAGIC_V1: &[u8; 6] = b"WBENC1";
+const FILE_MAGIC_V2: &[u8; 6] = b"WBENC2";
+/// Container version written by [`encrypt_file`]
+pub const CONTAINER_VERSION: u8 = 2;
 const DEFAULT_CHUNK_SIZE: u32 = 1024 * 1024; // 1MB
+/// Magic, version, chunk size, base nonce, file ID and plaintext length of a v2 file.
+/// Every v2 chunk and the metadata header are authenticated against these bytes.
+const V2_PREAMBLE_LEN: usize = 6 + 1 + 4 + 12 + 16 + 8;
+/// The sealed metadata header is padded to a fixed size, so its length reveals nothing
+/// about the file name.
+const V2_HEADER_LEN: usize = 1024;
+const MAX_HEADER_FIELD_CHARS: usize = 200;
+const RETIRED_KEY_AAD: &[u8] = b"wanderer retired master key";
+
+/// Original file details sealed into the header of a v2 container.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FileHeader {
+    #[serde(rename = "n", default, skip_serializing_if = "Option::is_none")]
+    pub file_name: Option<String>,
+    #[serde(rename = "m", default, skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+    /// blake3 hash of the plaintext, hex encoded
+    #[serde(rename = "h", default, skip_serializing_if = "Option::is_none")]
+    pub blake3: Option<String>,
+}
+
+impl FileHeader {
+    pub fn new(file_name: &str, mime_type: Option<String>, blake3: &str) -> Self {
+        let clip = |value: &str| {
+            value
+                .chars()
+                .take(MAX_HEADER_FIELD_CHARS)
+                .collect::<String>()
+        };
+        Self {
+            file_name: Some(clip(file_name)),
+            mime_type: mime_type.map(|m| clip(&m)),
+            blake3: Some(clip(blake3)),
+        }
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
@@ -46,6 +86,23 @@ pub struct SecurityBundle {
     pub created_at: i64,
     pub passphrase_wrap: Option<WrappedMasterKey>,
     pub recovery: Option<RecoveryData>,
+    /// Incremented by every key rotation; libraries from before rotation are at 1
+    #[serde(default = "first_key_generation")]
+    pub key_generation: u32,
+    /// The previous master key while a rotation re-encrypts the data it protects
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retired_key: Option<RetiredKey>,
+}
+
+/// A replaced master key, sealed with the master key that replaced it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetiredKey {
+    pub key_generation: u32,
+    pub sealed_b64: String,
+}
+
+fn first_key_generation() -> u32 {
+    1
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -81,8 +138,33 @@ impl Default for MigrationStatus {
 #[derive(Debug, Default)]
 pub struct RuntimeState {
     pub master_key: Option<[u8; 32]>,
+    /// Generation of `master_key`
+    pub key_generation: u32,
+    /// Previous master key, kept until a key rotation has re-encrypted everything
+    pub retired_key: Option<[u8; 32]>,
     pub migration: MigrationStatus,
     pub migration_worker_active: bool,
+    pub rotation: MigrationStatus,
+    pub rotation_worker_active: bool,
+}
+
+impl RuntimeState {
+    /// Keys that may have encrypted existing data, current first. Empty while locked.
+    pub fn read_keys(&self) -> Vec<[u8; 32]> {
+        self.master_key
+            .into_iter()
+            .chain(self.retired_key)
+            .collect()
+    }
+
+    /// Generation of the key at `index` in [`Self::read_keys`].
+    pub fn read_key_generation(&self, index: usize) -> u32 {
+        if index == 0 {
+            self.key_generation
+        } else {
+            self.key_generation.saturating_sub(1)
+        }
+    }
 }
 
 impl SecurityBundle {
@@ -93,6 +175,8 @@ impl SecurityBundle {
             created_at: unix_ts(),
             passphrase_wrap: None,
             recovery: None,
+            key_generation: first_key_generation(),
+            retired_key: None,
         }
     }
 
@@ -123,6 +207,8 @@ impl SecurityBundle {
                     verifier_phc,
                     wrap: recovery_wrap,
                 }),
+                key_generation: first_key_generation(),
+                retired_key: None,
             },
             recovery_key,
             master_key,
@@ -177,6 +263,65 @@ impl SecurityBundle {
         next.recovery = Some(RecoveryData { verifier_phc, wrap });
         Ok((next, new_recovery_key, master_key))
     }
+
+    /// Replace the master key with a new random one. The passphrase and a new recovery
+    /// key wrap the new key, and the old one stays sealed in the bundle until
+    /// [`Self::finish_rotation`]. Returns the new bundle, recovery key, master key and the
+    /// key it replaces.
+    pub fn rotate_master_key(
+        &self,
+        passphrase: &str,
+    ) -> Result<(Self, String, [u8; 32], [u8; 32])> {
+        if self.retired_key.is_some() {
+            return Err(anyhow!("A key rotation is still in progress"));
+        }
+        let old_key = self.unlock_with_passphrase(passphrase)?;
+
+        let mut new_key = [0u8; 32];
+        rand::rngs::OsRng.fill_bytes(&mut new_key);
+        let recovery_key = generate_recovery_key();
+        let recovery_wrap = wrap_master_key_with_secret(recovery_key.as_bytes(), &new_key)?;
+        let verifier_phc = hash_recovery_key(&recovery_key)?;
+        let mut key_id_bytes = [0u8; 16];
+        rand::rngs::OsRng.fill_bytes(&mut key_id_bytes);
+        let passphrase_wrap = wrap_master_key_with_secret(passphrase.as_bytes(), &new_key)?;
+
+        let mut next = self.clone();
+        next.key_id = B64.encode(key_id_bytes);
+        next.passphrase_wrap = Some(passphrase_wrap);
+        next.recovery = Some(RecoveryData {
+            verifier_phc,
+            wrap: recovery_wrap,
+        });
+        next.key_generation = self.key_generation + 1;
+        next.retired_key = Some(RetiredKey {
+            key_generation: self.key_generation,
+            sealed_b64: B64.encode(encrypt_bytes(&new_key, &old_key, RETIRED_KEY_AAD)?),
+        });
+        Ok((next, recovery_key, new_key, old_key))
+    }
+
+    /// The master key a running rotation replaces, opened with the current master key.
+    pub fn retired_master_key(&self, master_key: &[u8; 32]) -> Result<Option<[u8; 32]>> {
+        let Some(retired) = &self.retired_key else {
+            return Ok(None);
+        };
+        let sealed = B64
+            .decode(&retired.sealed_b64)
+            .context("Invalid retired key encoding")?;
+        let key = decrypt_bytes(master_key, &sealed, RETIRED_KEY_AAD)?;
+        let key: [u8; 32] = key
+            .try_into()
+            .map_err(|_| anyhow!("Invalid retired key length"))?;
+        Ok(Some(key))
+    }
+
+    /// Forget the retired key once nothing is encrypted with it anymore.
+    pub fn finish_rotation(&self) -> Self {
+        let mut next = self.clone();
+        next.retired_key = None;
+        next
+    }
 }
 
 fn unix_ts() -> i64 {
@@ -292,24 +437,128 @@ fn derive_chunk_nonce(base_nonce: &[u8; 12], chunk_idx: u32) -> [u8; 12] {
     nonce
 }
 
+/// Derive an independent purpose-specific key from the master key.
+pub fn derive_subkey(master_key: &[u8; 32], context: &str) -> [u8; 32] {
+    blake3::derive_key(context, master_key)
+}
+
+/// Seal a small in-memory payload. Output is `nonce || ciphertext+tag`.
+pub fn encrypt_bytes(key: &[u8; 32], plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    let mut nonce = [0u8; 12];
+    rand::rngs::OsRng.fill_bytes(&mut nonce);
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    let ciphertext = cipher
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: plaintext,
+                aad,
+            },
+        )
+        .map_err(|_| anyhow!("Failed to encrypt payload"))?;
+
+    let mut out = Vec::with_capacity(nonce.len() + ciphertext.len());
+    out.extend_from_slice(&nonce);
+    out.extend_from_slice(&ciphertext);
+    Ok(out)
+}
+
+/// Open a payload produced by [`encrypt_bytes`].
+pub fn decrypt_bytes(key: &[u8; 32], sealed: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    if sealed.len() < 12 + 16 {
+        return Err(anyhow!("Encrypted payload is truncated"));
+    }
+    let (nonce, ciphertext) = sealed.split_at(12);
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    cipher
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad,
+            },
+        )
+        .map_err(|_| anyhow!("Failed to decrypt payload. Key may be invalid"))
+}
+
 pub fn is_encrypted_file(path: &Path) -> Result<bool> {
+    Ok(container_version(path)?.is_some())
+}
+
+/// WBENC container version of the file, or `None` when it isn't one.
+pub fn container_version(path: &Path) -> Result<Option<u8>> {
     let mut file = File::open(path)?;
     let mut magic = [0u8; 6];
     let read = file.read(&mut magic)?;
     if read != 6 {
-        return Ok(false);
+        return Ok(None);
     }
-    Ok(&magic == FILE_MAGIC)
+    Ok(match &magic {
+        m if m == FILE_MAGIC_V1 => Some(1),
+        m if m == FILE_MAGIC_V2 => Some(2),
+        _ => None,
+    })
+}
+
+/// Size of the WBENC container of `version` for `plain_len` input bytes: the header,
+/// then a length prefix and GCM tag around every chunk. Version 2 always writes at
+/// least one (final) chunk.
+pub fn encrypted_file_size(version: u8, plain_len: u64) -> u64 {
+    let chunks = plain_len.div_ceil(DEFAULT_CHUNK_SIZE as u64);
+    let (header, chunks) = if version == 1 {
+        ((FILE_MAGIC_V1.len() + 1 + 4 + 12) as u64, chunks)
+    } else {
+        (
+            (V2_PREAMBLE_LEN + 12 + V2_HEADER_LEN + 16) as u64,
+            chunks.max(1),
+        )
+    };
+    header + plain_len + chunks * (4 + 16)
+}
+
+/// AAD of v2 chunk `chunk_idx`, binding it to the file and marking the last chunk.
+fn chunk_aad_v2(preamble: &[u8], chunk_idx: u32, is_final: bool) -> Vec<u8> {
+    let mut aad = Vec::with_capacity(preamble.len() + 5);
+    aad.extend_from_slice(preamble);
+    aad.extend_from_slice(&chunk_idx.to_le_bytes());
+    aad.push(is_final as u8);
+    aad
 }
 
 pub fn encrypt_file(input_path: &Path, output_path: &Path, key: &[u8; 32]) -> Result<()> {
+    encrypt_file_with_header(input_path, output_path, key, &FileHeader::default())
+}
+
+/// Encrypt a file into a v2 container whose sealed header carries `header`.
+pub fn encrypt_file_with_header(
+    input_path: &Path,
+    output_path: &Path,
+    key: &[u8; 32],
+    header: &FileHeader,
+) -> Result<()> {
     let input = File::open(input_path).with_context(|| {
         format!(
             "Failed to open input file for encryption: {}",
             input_path.display()
         )
     })?;
-    let mut reader = BufReader::new(input);
+    let plain_len = input.metadata()?.len();
+    encrypt_reader(BufReader::new(input), plain_len, output_path, key, header)
+}
+
+/// Encrypt the `plain_len` bytes `reader` yields into a v2 container.
+fn encrypt_reader<R: Read>(
+    mut reader: R,
+    plain_len: u64,
+    output_path: &Path,
+    key: &[u8; 32],
+    header: &FileHeader,
+) -> Result<()> {
+    let mut header_plain = serde_json::to_vec(header)?;
+    if header_plain.len() > V2_HEADER_LEN {
+        return Err(anyhow!("Encrypted file header is too large"));
+    }
+    header_plain.resize(V2_HEADER_LEN, 0);
 
     if let Some(parent) = output_path.parent() {
         std::fs::create_dir_all(parent)?;
@@ -324,45 +573,61 @@ pub fn encrypt_file(input_path: &Path, output_path: &Path, key: &[u8; 32]) -> Re
 
     let mut base_nonce = [0u8; 12];
     rand::rngs::OsRng.fill_bytes(&mut base_nonce);
-
-    writer.write_all(FILE_MAGIC)?;
-    writer.write_all(&[FILE_VERSION])?;
-    writer.write_all(&DEFAULT_CHUNK_SIZE.to_le_bytes())?;
-    writer.write_all(&base_nonce)?;
+    let mut file_id = [0u8; 16];
+    rand::rngs::OsRng.fill_bytes(&mut file_id);
+
+    let mut preamble = Vec::with_capacity(V2_PREAMBLE_LEN);
+    preamble.extend_from_slice(FILE_MAGIC_V2);
+    preamble.push(CONTAINER_VERSION);
+    preamble.extend_from_slice(&DEFAULT_CHUNK_SIZE.to_le_bytes());
+    preamble.extend_from_slice(&base_nonce);
+    preamble.extend_from_slice(&file_id);
+    preamble.extend_from_slice(&plain_len.to_le_bytes());
+    writer.write_all(&preamble)?;
+    writer.write_all(&encrypt_bytes(key, &header_plain, &preamble)?)?;
 
     let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    let chunk_count = plain_len.div_ceil(DEFAULT_CHUNK_SIZE as u64).max(1);
+    let chunk_count =
+        u32::try_from(chunk_count).map_err(|_| anyhow!("File is too large to encrypt"))?;
     let mut chunk_buf = vec![0u8; DEFAULT_CHUNK_SIZE as usize];
-    let mut chunk_idx: u32 = 0;
+    let mut written: u64 = 0;
 
-    loop {
-        let n = reader.read(&mut chunk_buf)?;
-        if n == 0 {
-            break;
-        }
+    for chunk_idx in 0..chunk_count {
+        let is_final = chunk_idx + 1 == chunk_count;
+        let expected = (plain_len - written).min(DEFAULT_CHUNK_SIZE as u64) as usize;
+        reader
+            .read_exact(&mut chunk_buf[..expected])
+            .context("Input file shrank while it was being encrypted")?;
 
         let nonce = derive_chunk_nonce(&base_nonce, chunk_idx);
-        let aad = chunk_idx.to_le_bytes();
+        let aad = chunk_aad_v2(&preamble, chunk_idx, is_final);
         let payload = Payload {
-            msg: &chunk_buf[..n],
+            msg: &chunk_buf[..expected],
             aad: &aad,
         };
         let ciphertext = cipher
             .encrypt(Nonce::from_slice(&nonce), payload)
             .map_err(|_| anyhow!("Chunk encryption failed at chunk {}", chunk_idx))?;
 
-        let len = ciphertext.len() as u32;
-        writer.write_all(&len.to_le_bytes())?;
+        writer.write_all(&(ciphertext.len() as u32).to_le_bytes())?;
         writer.write_all(&ciphertext)?;
-        chunk_idx = chunk_idx
-            .checked_add(1)
-            .ok_or_else(|| anyhow!("Chunk counter overflow"))?;
+        written += expected as u64;
+    }
+    if reader.read(&mut chunk_buf[..1])? != 0 {
+        return Err(anyhow!("Input file grew while it was being encrypted"));
     }
 
     writer.flush()?;
     Ok(())
 }
 
-pub fn decrypt_file(input_path: &Path, output_path: &Path, key: &[u8; 32]) -> Result<()> {
+/// Decrypt a v1 or v2 container. Returns the sealed header of a v2 file.
+pub fn decrypt_file(
+    input_path: &Path,
+    output_path: &Path,
+    key: &[u8; 32],
+) -> Result<Option<FileHeader>> {
     let input = File::open(input_path).with_context(|| {
         format!(
             "Failed to open encrypted input file: {}",
@@ -370,16 +635,57 @@ pub fn decrypt_file(input_path: &Path, output_path: &Path, key: &[u8; 32]) -> Re
         )
     })?;
     let mut reader = BufReader::new(input);
+    let container = read_container_header(&mut reader, key)?;
+
+    if let Some(parent) = output_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let output = File::create(output_path)
+        .with_context(|| format!("Failed to create output file: {}", output_path.display()))?;
+    let mut writer = BufWriter::new(output);
 
+    match &container.v2 {
+        None => decrypt_chunks_v1(&mut reader, &mut writer, key, &container)?,
+        Some(v2) => decrypt_chunks_v2(&mut reader, &mut writer, key, &container, v2)?,
+    }
+
+    writer.flush()?;
+    Ok(container.v2.map(|v2| v2.header))
+}
+
+/// Read the sealed header of a v2 container without decrypting its contents. Returns
+/// `None` for v1 files, which have no header.
+pub fn read_file_header(path: &Path, key: &[u8; 32]) -> Result<Option<FileHeader>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    Ok(read_container_header(&mut reader, key)?
+        .v2
+        .map(|v2| v2.header))
+}
+
+struct ContainerHeader {
+    chunk_size: u32,
+    base_nonce: [u8; 12],
+    v2: Option<V2Header>,
+}
+
+struct V2Header {
+    preamble: Vec<u8>,
+    plain_len: u64,
+    header: FileHeader,
+}
+
+fn read_container_header<R: Read>(reader: &mut R, key: &[u8; 32]) -> Result<ContainerHeader> {
     let mut magic = [0u8; 6];
     reader.read_exact(&mut magic)?;
-    if &magic != FILE_MAGIC {
-        return Err(anyhow!("Input is not a Wander(er) encrypted file"));
-    }
+    let expected_version = match &magic {
+        m if m == FILE_MAGIC_V1 => 1,
+        m if m == FILE_MAGIC_V2 => 2,
+        _ => return Err(anyhow!("Input is not a Wander(er) encrypted file")),
+    };
 
     let mut version = [0u8; 1];
     reader.read_exact(&mut version)?;
-    if version[0] != FILE_VERSION {
+    if version[0] != expected_version {
         return Err(anyhow!(
             "Unsupported encrypted file version: {}",
             version[0]
@@ -395,34 +701,83 @@ pub fn decrypt_file(input_path: &Path, output_path: &Path, key: &[u8; 32]) -> Re
 
     let mut base_nonce = [0u8; 12];
     reader.read_exact(&mut base_nonce)?;
+    if expected_version == 1 {
+        return Ok(ContainerHeader {
+            chunk_size,
+            base_nonce,
+            v2: None,
+        });
+    }
 
-    if let Some(parent) = output_path.parent() {
-        std::fs::create_dir_all(parent)?;
+    let mut file_id = [0u8; 16];
+    reader.read_exact(&mut file_id)?;
+    let mut plain_len_bytes = [0u8; 8];
+    reader.read_exact(&mut plain_len_bytes)?;
+
+    let mut preamble = Vec::with_capacity(V2_PREAMBLE_LEN);
+    preamble.extend_from_slice(&magic);
+    preamble.push(version[0]);
+    preamble.extend_from_slice(&chunk_size_bytes);
+    preamble.extend_from_slice(&base_nonce);
+    preamble.extend_from_slice(&file_id);
+    preamble.extend_from_slice(&plain_len_bytes);
+
+    let mut sealed = vec![0u8; 12 + V2_HEADER_LEN + 16];
+    reader.read_exact(&mut sealed)?;
+    let mut header_plain = decrypt_bytes(key, &sealed, &preamble)?;
+    let used = header_plain
+        .iter()
+        .rposition(|b| *b != 0)
+        .map_or(0, |i| i + 1);
+    header_plain.truncate(used);
+    let header = if header_plain.is_empty() {
+        FileHeader::default()
+    } else {
+        serde_json::from_slice(&header_plain)?
+    };
+
+    Ok(ContainerHeader {
+        chunk_size,
+        base_nonce,
+        v2: Some(V2Header {
+            preamble,
+            plain_len: u64::from_le_bytes(plain_len_bytes),
+            header,
+        }),
+    })
+}
+
+/// Read a chunk's length prefix, or `None` at the end of the file.
+fn read_chunk_len<R: Read>(reader: &mut R) -> Result<Option<usize>> {
+    let mut len_buf = [0u8; 4];
+    match reader.read_exact(&mut len_buf) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
     }
-    let output = File::create(output_path)
-        .with_context(|| format!("Failed to create output file: {}", output_path.display()))?;
-    let mut writer = BufWriter::new(output);
+    let ct_len = u32::from_le_bytes(len_buf) as usize;
+    if ct_len < 16 {
+        return Err(anyhow!("Invalid encrypted chunk length"));
+    }
+    Ok(Some(ct_len))
+}
 
+/// Version 1 chunks carry only their index as AAD, so a file cut at a chunk boundary
+/// decrypts without error.
+fn decrypt_chunks_v1<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    key: &[u8; 32],
+    container: &ContainerHeader,
+) -> Result<()> {
     let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
     let mut chunk_idx: u32 = 0;
-    let mut len_buf = [0u8; 4];
-
-    loop {
-        match reader.read_exact(&mut len_buf) {
-            Ok(()) => {}
-            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
-            Err(e) => return Err(e.into()),
-        }
-
-        let ct_len = u32::from_le_bytes(len_buf) as usize;
-        if ct_len < 16 {
-            return Err(anyhow!("Invalid encrypted chunk length"));
-        }
 
+    while let Some(ct_len) = read_chunk_len(reader)? {
         let mut ciphertext = vec![0u8; ct_len];
         reader.read_exact(&mut ciphertext)?;
 
-        let nonce = derive_chunk_nonce(&base_nonce, chunk_idx);
+        let nonce = derive_chunk_nonce(&container.base_nonce, chunk_idx);
         let aad = chunk_idx.to_le_bytes();
         let payload = Payload {
             msg: ciphertext.as_ref(),
@@ -432,7 +787,7 @@ pub fn decrypt_file(input_path: &Path, output_path: &Path, key: &[u8; 32]) -> Re
             .decrypt(Nonce::from_slice(&nonce), payload)
             .map_err(|_| anyhow!("Chunk decryption failed at chunk {}", chunk_idx))?;
 
-        if plaintext.len() > chunk_size as usize {
+        if plaintext.len() > container.chunk_size as usize {
             return Err(anyhow!("Invalid plaintext chunk length"));
         }
 
@@ -441,65 +796,328 @@ pub fn decrypt_file(input_path: &Path, output_path: &Path, key: &[u8; 32]) -> Re
             .checked_add(1)
             .ok_or_else(|| anyhow!("Chunk counter overflow"))?;
     }
+    Ok(())
+}
+
+fn decrypt_chunks_v2<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    key: &[u8; 32],
+    container: &ContainerHeader,
+    v2: &V2Header,
+) -> Result<()> {
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    let chunk_count = v2.plain_len.div_ceil(container.chunk_size as u64).max(1);
+    let chunk_count =
+        u32::try_from(chunk_count).map_err(|_| anyhow!("Invalid encrypted file length"))?;
+    let mut remaining = v2.plain_len;
+
+    for chunk_idx in 0..chunk_count {
+        let is_final = chunk_idx + 1 == chunk_count;
+        let expected = remaining.min(container.chunk_size as u64) as usize;
+        let ct_len = read_chunk_len(reader)?
+            .ok_or_else(|| anyhow!("Encrypted file is truncated at chunk {}", chunk_idx))?;
+        if ct_len != expected + 16 {
+            return Err(anyhow!("Invalid encrypted chunk length"));
+        }
+
+        let mut ciphertext = vec![0u8; ct_len];
+        reader
+            .read_exact(&mut ciphertext)
+            .with_context(|| format!("Encrypted file is truncated at chunk {}", chunk_idx))?;
+
+        let nonce = derive_chunk_nonce(&container.base_nonce, chunk_idx);
+        let aad = chunk_aad_v2(&v2.preamble, chunk_idx, is_final);
+        let payload = Payload {
+            msg: ciphertext.as_ref(),
+            aad: &aad,
+        };
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(&nonce), payload)
+            .map_err(|_| anyhow!("Chunk decryption failed at chunk {}", chunk_idx))?;
+
+        writer.write_all(&plaintext)?;
+        remaining -= plaintext.len() as u64;
+    }
 
-    writer.flush()?;
+    let mut trailing = [0u8; 1];
+    if reader.read(&mut trailing)? != 0 {
+        return Err(anyhow!("Encrypted file has data after its final chunk"));
+    }
     Ok(())
 }
 
-pub fn decrypt_file_if_needed(
+/// Rewrite a container in the current format, keeping its sealed header.
+pub fn upgrade_container(path: &Path, key: &[u8; 32]) -> Result<()> {
+    reencrypt_container(path, key, key).map(|_| ())
+}
+
+/// Rewrite a container under `new_key` in the current format, keeping its sealed header.
+/// The container may be encrypted with either key. Returns `false` when it already is
+/// current and under `new_key`.
+pub fn reencrypt_container(path: &Path, old_key: &[u8; 32], new_key: &[u8; 32]) -> Result<bool> {
+    if container_version(path)? == Some(CONTAINER_VERSION)
+        && read_file_header(path, new_key).is_ok()
+    {
+        return Ok(false);
+    }
+    let sealed = path.with_extension("upgrade.tmp");
+    // Chunks go from one container to the other, so no plaintext reaches the disk
+    let result = DecryptingReader::open_with_keys(path, &[*old_key, *new_key]).and_then(|reader| {
+        let header = reader.header().cloned().unwrap_or_default();
+        let plain_len = reader.plain_len();
+        encrypt_reader(reader, plain_len, &sealed, new_key, &header)
+    });
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(&sealed);
+        return Err(e);
+    }
+    std::fs::rename(&sealed, path)?;
+    Ok(true)
+}
+
+/// Decrypt a container with whichever of `keys` opens it. Returns the sealed header of
+/// a v2 file.
+pub fn decrypt_file_with_keys(
     input_path: &Path,
     output_path: &Path,
-    key: Option<&[u8; 32]>,
-) -> Result<bool> {
-    if !is_encrypted_file(input_path)? {
-        if let Some(parent) = output_path.parent() {
-            std::fs::create_dir_all(parent)?;
+    keys: &[[u8; 32]],
+) -> Result<Option<FileHeader>> {
+    decrypt_file_with_key_index(input_path, output_path, keys).map(|(header, _)| header)
+}
+
+/// [`decrypt_file_with_keys`], also returning the index of the key that opened it.
+fn decrypt_file_with_key_index(
+    input_path: &Path,
+    output_path: &Path,
+    keys: &[[u8; 32]],
+) -> Result<(Option<FileHeader>, usize)> {
+    let mut last_error = anyhow!("Encrypted file requires unlocked encryption key");
+    for (index, key) in keys.iter().enumerate() {
+        match decrypt_file(input_path, output_path, key) {
+            Ok(header) => return Ok((header, index)),
+            Err(e) => last_error = e,
         }
-        std::fs::copy(input_path, output_path)?;
-        return Ok(false);
     }
+    Err(last_error)
+}
 
-    let key = key.ok_or_else(|| anyhow!("Encrypted file requires unlocked encryption key"))?;
-    decrypt_file(input_path, output_path, key)?;
-    Ok(true)
+/// Seekable reader over the plaintext of a v1 or v2 container. Every chunk but the last
+/// holds exactly `chunk_size` bytes and has its own derived nonce, so any byte range is
+/// read by decrypting only the chunks it covers.
+pub struct DecryptingReader<R> {
+    inner: R,
+    cipher: Aes256Gcm,
+    container: ContainerHeader,
+    /// Offset of the first chunk's length prefix
+    data_start: u64,
+    plain_len: u64,
+    chunk_count: u32,
+    position: u64,
+    /// Index and plaintext of the chunk read last
+    current: Option<(u32, Vec<u8>)>,
 }
 
-#[cfg(target_os = "windows")]
-pub fn dpapi_protect(data: &[u8], description: &str) -> Result<Vec<u8>> {
-    use windows_sys::Win32::Security::Cryptography::{
-        CryptProtectData, CRYPTPROTECT_UI_FORBIDDEN, CRYPT_INTEGER_BLOB,
-    };
-    use windows_sys::Win32::Foundation::LocalFree;
+impl DecryptingReader<BufReader<File>> {
+    pub fn open(path: &Path, key: &[u8; 32]) -> Result<Self> {
+        let input = File::open(path)
+            .with_context(|| format!("Failed to open encrypted input file: {}", path.display()))?;
+        Self::new(BufReader::new(input), key)
+    }
 
-    let mut in_blob = CRYPT_INTEGER_BLOB {
-        cbData: data.len() as u32,
-        pbData: data.as_ptr() as *mut u8,
-    };
-    let mut out_blob = CRYPT_INTEGER_BLOB::default();
-    let description_utf16: Vec<u16> = description.encode_utf16().chain(Some(0)).collect();
+    /// Open a container with whichever of `keys` sealed it.
+    pub fn open_with_keys(path: &Path, keys: &[[u8; 32]]) -> Result<Self> {
+        let mut last_error = anyhow!("Encrypted file requires unlocked encryption key");
+        for key in keys {
+            match Self::open(path, key) {
+                Ok(reader) => return Ok(reader),
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    }
+}
 
-    let ok = unsafe {
-        CryptProtectData(
-            &mut in_blob,
-            description_utf16.as_ptr(),
-            std::ptr::null(),
-            std::ptr::null_mut(),
-            std::ptr::null(),
-            CRYPTPROTECT_UI_FORBIDDEN,
-            &mut out_blob,
-        )
-    };
+impl<R: Read + Seek> DecryptingReader<R> {
+    pub fn new(mut inner: R, key: &[u8; 32]) -> Result<Self> {
+        inner.seek(SeekFrom::Start(0))?;
+        let container = read_container_header(&mut inner, key)?;
+        let data_start = inner.stream_position()?;
+        let chunk_size = container.chunk_size as u64;
+        let plain_len = match &container.v2 {
+            Some(v2) => v2.plain_len,
+            // v1 doesn't record the length; it follows from the size of the chunks
+            None => {
+                let data_len = inner.seek(SeekFrom::End(0))? - data_start;
+                let stride = 4 + chunk_size + 16;
+                let last = data_len % stride;
+                if last != 0 && last < 4 + 16 {
+                    return Err(anyhow!("Invalid encrypted chunk length"));
+                }
+                data_len / stride * chunk_size + last.saturating_sub(4 + 16)
+            }
+        };
+        let chunk_count = plain_len.div_ceil(chunk_size);
+        let chunk_count = if container.v2.is_some() {
+            chunk_count.max(1)
+        } else {
+            chunk_count
+        };
+        let chunk_count =
+            u32::try_from(chunk_count).map_err(|_| anyhow!("Invalid encrypted file length"))?;
+
+        Ok(Self {
+            inner,
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key)),
+            container,
+            data_start,
+            plain_len,
+            chunk_count,
+            position: 0,
+            current: None,
+        })
+    }
 
-    if ok == 0 {
-        return Err(anyhow!("CryptProtectData failed"));
+    pub fn plain_len(&self) -> u64 {
+        self.plain_len
     }
 
-    let bytes = unsafe { std::slice::from_raw_parts(out_blob.pbData, out_blob.cbData as usize) }
-        .to_vec();
-    unsafe {
-        let _ = LocalFree(out_blob.pbData as _);
+    /// 2 when the header records the plaintext length, so a container still being
+    /// written can be read up to the chunks that have arrived.
+    pub fn container_version(&self) -> u8 {
+        if self.container.v2.is_some() {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// Size the container must have reached for the plaintext byte at `plain_offset`
+    /// to be readable.
+    pub fn sealed_len_through(&self, plain_offset: u64) -> u64 {
+        let chunk_size = self.container.chunk_size as u64;
+        let chunk_idx = plain_offset.min(self.plain_len.saturating_sub(1)) / chunk_size;
+        let chunk_len = (self.plain_len - chunk_idx * chunk_size).min(chunk_size);
+        self.data_start + chunk_idx * (4 + chunk_size + 16) + 4 + chunk_len + 16
+    }
+
+    /// Sealed header of a v2 container.
+    pub fn header(&self) -> Option<&FileHeader> {
+        self.container.v2.as_ref().map(|v2| &v2.header)
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    fn load_chunk(&mut self, chunk_idx: u32) -> Result<()> {
+        if self
+            .current
+            .as_ref()
+            .is_some_and(|(idx, _)| *idx == chunk_idx)
+        {
+            return Ok(());
+        }
+        let chunk_size = self.container.chunk_size as u64;
+        let offset = chunk_idx as u64 * chunk_size;
+        let expected = (self.plain_len - offset).min(chunk_size) as usize;
+
+        self.inner.seek(SeekFrom::Start(
+            self.data_start + chunk_idx as u64 * (4 + chunk_size + 16),
+        ))?;
+        let ct_len = read_chunk_len(&mut self.inner)?
+            .ok_or_else(|| anyhow!("Encrypted file is truncated at chunk {}", chunk_idx))?;
+        if ct_len != expected + 16 {
+            return Err(anyhow!("Invalid encrypted chunk length"));
+        }
+        let mut ciphertext = vec![0u8; ct_len];
+        self.inner
+            .read_exact(&mut ciphertext)
+            .with_context(|| format!("Encrypted file is truncated at chunk {}", chunk_idx))?;
+
+        let nonce = derive_chunk_nonce(&self.container.base_nonce, chunk_idx);
+        let aad = match &self.container.v2 {
+            Some(v2) => chunk_aad_v2(&v2.preamble, chunk_idx, chunk_idx + 1 == self.chunk_count),
+            None => chunk_idx.to_le_bytes().to_vec(),
+        };
+        let payload = Payload {
+            msg: ciphertext.as_ref(),
+            aad: &aad,
+        };
+        let plaintext = self
+            .cipher
+            .decrypt(Nonce::from_slice(&nonce), payload)
+            .map_err(|_| anyhow!("Chunk decryption failed at chunk {}", chunk_idx))?;
+        self.current = Some((chunk_idx, plaintext));
+        Ok(())
     }
-    Ok(bytes)
+}
+
+impl<R: Read + Seek> Read for DecryptingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if buf.is_empty() || self.position >= self.plain_len {
+            return Ok(0);
+        }
+        let chunk_size = self.container.chunk_size as u64;
+        let chunk_idx = (self.position / chunk_size) as u32;
+        self.load_chunk(chunk_idx)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+
+        let chunk = self
+            .current
+            .as_ref()
+            .map(|(_, c)| c.as_slice())
+            .unwrap_or_default();
+        let start = (self.position % chunk_size) as usize;
+        let count = buf.len().min(chunk.len() - start);
+        buf[..count].copy_from_slice(&chunk[start..start + count]);
+        self.position += count as u64;
+        Ok(count)
+    }
+}
+
+impl<R: Read + Seek> Seek for DecryptingReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => self.plain_len.checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+        };
+        self.position = target.ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Seek before the start of the file",
+            )
+        })?;
+        Ok(self.position)
+    }
+}
+
+pub fn decrypt_file_if_needed(
+    input_path: &Path,
+    output_path: &Path,
+    key: Option<&[u8; 32]>,
+) -> Result<bool> {
+    decrypt_file_with_keys_if_needed(input_path, output_path, key.copied().as_slice())
+        .map(|opened_with| opened_with.is_some())
+}
+
+/// Copy a plaintext file, or decrypt a container with whichever of `keys` opens it.
+/// Returns the index of that key, or `None` when the file wasn't encrypted.
+pub fn decrypt_file_with_keys_if_needed(
+    input_path: &Path,
+    output_path: &Path,
+    keys: &[[u8; 32]],
+) -> Result<Option<usize>> {
+    if !is_encrypted_file(input_path)? {
+        if let Some(parent) = output_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::copy(input_path, output_path)?;
+        return Ok(None);
+    }
+
+    decrypt_file_with_key_index(input_path, output_path, keys).map(|(_, index)| Some(index))
 }
 
 #[cfg(target_os = "windows")]
@@ -544,13 +1162,6 @@ pub fn dpapi_unprotect(data: &[u8]) -> Result<Vec<u8>> {
     Ok(bytes)
 }
 
-#[cfg(not(target_os = "windows"))]
-pub fn dpapi_protect(_data: &[u8], _description: &str) -> Result<Vec<u8>> {
-    Err(anyhow!(
-        "DPAPI secure storage is only supported on Windows in this build"
-    ))
-}
-
 #[cfg(not(target_os = "windows"))]
 pub fn dpapi_unprotect(_data: &[u8]) -> Result<Vec<u8>> {
     Err(anyhow!(
@@ -558,18 +1169,6 @@ pub fn dpapi_unprotect(_data: &[u8]) -> Result<Vec<u8>> {
     ))
 }
 
-pub fn serialize_and_protect<T: Serialize>(value: &T, description: &str) -> Result<String> {
-    let bytes = serde_json::to_vec(value)?;
-    let protected = dpapi_protect(&bytes, description)?;
-    Ok(B64.encode(protected))
-}
-
-pub fn unprotect_and_deserialize<T: for<'de> Deserialize<'de>>(blob_b64: &str) -> Result<T> {
-    let protected = B64.decode(blob_b64)?;
-    let bytes = dpapi_unprotect(&protected)?;
-    Ok(serde_json::from_slice::<T>(&bytes)?)
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -592,4 +1191,275 @@ mod tests {
         assert_eq!(key.len(), 32);
         assert!(bundle.unlock_with_passphrase("bad passphrase").is_err());
     }
+
+    #[test]
+    fn key_rotation_keeps_retired_key_until_finished() {
+        let (bundle, _, old_key) =
+            SecurityBundle::new_encrypted("correct horse battery staple").expect("bundle");
+        assert_eq!(bundle.retired_master_key(&old_key).expect("retired"), None);
+
+        let (rotated, recovery_key, new_key, replaced) = bundle
+            .rotate_master_key("correct horse battery staple")
+            .expect("rotate");
+        assert_eq!(replaced, old_key);
+        assert_ne!(new_key, old_key);
+        assert_eq!(rotated.key_generation, 2);
+        assert_eq!(
+            rotated
+                .unlock_with_passphrase("correct horse battery staple")
+                .expect("unlock"),
+            new_key
+        );
+        assert_eq!(
+            rotated.retired_master_key(&new_key).expect("retired"),
+            Some(old_key)
+        );
+        assert!(rotated.retired_master_key(&old_key).is_err());
+        assert!(rotated
+            .rotate_master_key("correct horse battery staple")
+            .is_err());
+        let (_, recovered) = rotated
+            .recover_and_rewrap(&recovery_key, "another passphrase")
+            .expect("recover");
+        assert_eq!(recovered, new_key);
+
+        let finished = rotated.finish_rotation();
+        assert_eq!(
+            finished.retired_master_key(&new_key).expect("retired"),
+            None
+        );
+        assert_eq!(finished.key_generation, 2);
+    }
+
+    #[test]
+    fn encrypted_file_size_matches_output() {
+        let dir = std::env::temp_dir();
+        let input = dir.join(format!("wanderer-size-in-{}", std::process::id()));
+        let output = dir.join(format!("wanderer-size-out-{}", std::process::id()));
+        let key = [7u8; 32];
+        for len in [0usize, 5, DEFAULT_CHUNK_SIZE as usize + 3] {
+            std::fs::write(&input, vec![1u8; len]).expect("write input");
+            encrypt_file(&input, &output, &key).expect("encrypt");
+            let actual = std::fs::metadata(&output).expect("metadata").len();
+            assert_eq!(encrypted_file_size(CONTAINER_VERSION, len as u64), actual);
+            encrypt_file_v1(&input, &output, &key);
+            let actual = std::fs::metadata(&output).expect("metadata").len();
+            assert_eq!(encrypted_file_size(1, len as u64), actual);
+        }
+        let _ = std::fs::remove_file(&input);
+        let _ = std::fs::remove_file(&output);
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("wanderer-{}-{}", name, std::process::id()))
+    }
+
+    /// The v1 writer, kept to check that old files still decrypt and upgrade.
+    fn encrypt_file_v1(input: &Path, output: &Path, key: &[u8; 32]) {
+        let plain = std::fs::read(input).expect("read input");
+        let base_nonce = [5u8; 12];
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+        let mut out = Vec::new();
+        out.extend_from_slice(FILE_MAGIC_V1);
+        out.push(1);
+        out.extend_from_slice(&DEFAULT_CHUNK_SIZE.to_le_bytes());
+        out.extend_from_slice(&base_nonce);
+        for (idx, chunk) in plain.chunks(DEFAULT_CHUNK_SIZE as usize).enumerate() {
+            let idx = idx as u32;
+            let aad = idx.to_le_bytes();
+            let ciphertext = cipher
+                .encrypt(
+                    Nonce::from_slice(&derive_chunk_nonce(&base_nonce, idx)),
+                    Payload {
+                        msg: chunk,
+                        aad: &aad,
+                    },
+                )
+                .expect("encrypt chunk");
+            out.extend_from_slice(&(ciphertext.len() as u32).to_le_bytes());
+            out.extend_from_slice(&ciphertext);
+        }
+        std::fs::write(output, out).expect("write output");
+    }
+
+    #[test]
+    fn v2_roundtrip_carries_header() {
+        let input = temp_path("v2-in");
+        let sealed = temp_path("v2-sealed");
+        let output = temp_path("v2-out");
+        let key = [8u8; 32];
+        let data: Vec<u8> = (0..DEFAULT_CHUNK_SIZE as usize * 2 + 9)
+            .map(|i| i as u8)
+            .collect();
+        std::fs::write(&input, &data).expect("write input");
+        let header = FileHeader::new("beach.jpg", Some("image/jpeg".to_string()), "abc123");
+
+        encrypt_file_with_header(&input, &sealed, &key, &header).expect("encrypt");
+        assert_eq!(container_version(&sealed).expect("version"), Some(2));
+        assert_eq!(
+            read_file_header(&sealed, &key).expect("header"),
+            Some(header.clone())
+        );
+        let raw = std::fs::read(&sealed).expect("read sealed");
+        assert!(!raw.windows(9).any(|w| w == b"beach.jpg"));
+
+        let restored = decrypt_file(&sealed, &output, &key).expect("decrypt");
+        assert_eq!(restored, Some(header));
+        assert_eq!(std::fs::read(&output).expect("read output"), data);
+        assert!(decrypt_file(&sealed, &output, &[9u8; 32]).is_err());
+        for path in [&input, &sealed, &output] {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    #[test]
+    fn v2_detects_truncation() {
+        let input = temp_path("trunc-in");
+        let sealed = temp_path("trunc-sealed");
+        let output = temp_path("trunc-out");
+        let key = [10u8; 32];
+        for len in [0usize, DEFAULT_CHUNK_SIZE as usize + 1] {
+            std::fs::write(&input, vec![3u8; len]).expect("write input");
+            encrypt_file(&input, &sealed, &key).expect("encrypt");
+            let raw = std::fs::read(&sealed).expect("read sealed");
+
+            // Cut after the first chunk, or drop the only (empty) chunk
+            let first_chunk_end = if len == 0 {
+                raw.len() - 4 - 16
+            } else {
+                raw.len() - 4 - 16 - 1
+            };
+            std::fs::write(&sealed, &raw[..first_chunk_end]).expect("truncate");
+            assert!(decrypt_file(&sealed, &output, &key).is_err());
+
+            let mut extended = raw.clone();
+            extended.push(0);
+            std::fs::write(&sealed, &extended).expect("extend");
+            assert!(decrypt_file(&sealed, &output, &key).is_err());
+        }
+        for path in [&input, &sealed, &output] {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    #[test]
+    fn v1_files_decrypt_and_upgrade() {
+        let input = temp_path("v1-in");
+        let sealed = temp_path("v1-sealed.wbenc");
+        let output = temp_path("v1-out");
+        let key = [11u8; 32];
+        std::fs::write(&input, b"old thumbnail").expect("write input");
+        encrypt_file_v1(&input, &sealed, &key);
+
+        assert_eq!(container_version(&sealed).expect("version"), Some(1));
+        assert_eq!(decrypt_file(&sealed, &output, &key).expect("decrypt"), None);
+        assert_eq!(std::fs::read(&output).expect("read"), b"old thumbnail");
+
+        upgrade_container(&sealed, &key).expect("upgrade");
+        assert_eq!(container_version(&sealed).expect("version"), Some(2));
+        decrypt_file(&sealed, &output, &key).expect("decrypt upgraded");
+        assert_eq!(std::fs::read(&output).expect("read"), b"old thumbnail");
+        for path in [&input, &sealed, &output] {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    #[test]
+    fn reencrypt_container_switches_keys() {
+        let input = temp_path("rekey-in");
+        let sealed = temp_path("rekey-sealed.wbenc");
+        let output = temp_path("rekey-out");
+        let (old_key, new_key) = ([13u8; 32], [14u8; 32]);
+        std::fs::write(&input, b"thumbnail").expect("write input");
+        let header = FileHeader::new("a.jpg", None, "abc");
+        encrypt_file_with_header(&input, &sealed, &old_key, &header).expect("encrypt");
+
+        assert!(reencrypt_container(&sealed, &old_key, &new_key).expect("rekey"));
+        assert!(decrypt_file(&sealed, &output, &old_key).is_err());
+        assert_eq!(
+            decrypt_file(&sealed, &output, &new_key).expect("decrypt"),
+            Some(header)
+        );
+        assert_eq!(std::fs::read(&output).expect("read"), b"thumbnail");
+        // Already done, e.g. when an interrupted rotation runs again
+        assert!(!reencrypt_container(&sealed, &old_key, &new_key).expect("rekey again"));
+
+        let both = [old_key, new_key];
+        assert_eq!(
+            decrypt_file_with_keys_if_needed(&sealed, &output, &both).expect("open"),
+            Some(1)
+        );
+        assert!(decrypt_file_with_keys_if_needed(&sealed, &output, &[]).is_err());
+        for path in [&input, &sealed, &output] {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    #[test]
+    fn decrypting_reader_reads_any_range() {
+        let input = temp_path("reader-in");
+        let sealed = temp_path("reader-sealed");
+        let key = [12u8; 32];
+        let plain: Vec<u8> = (0..DEFAULT_CHUNK_SIZE as usize * 2 + 100)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        std::fs::write(&input, &plain).expect("write input");
+
+        for v1 in [false, true] {
+            if v1 {
+                encrypt_file_v1(&input, &sealed, &key);
+            } else {
+                encrypt_file(&input, &sealed, &key).expect("encrypt");
+            }
+            let mut reader = DecryptingReader::open(&sealed, &key).expect("open");
+            assert_eq!(reader.plain_len(), plain.len() as u64);
+
+            // Spans the first chunk boundary
+            let start = DEFAULT_CHUNK_SIZE as usize - 10;
+            let mut range = vec![0u8; 30];
+            reader.seek(SeekFrom::Start(start as u64)).expect("seek");
+            reader.read_exact(&mut range).expect("read range");
+            assert_eq!(range, plain[start..start + 30]);
+
+            let mut tail = Vec::new();
+            reader.seek(SeekFrom::End(-50)).expect("seek end");
+            reader.read_to_end(&mut tail).expect("read tail");
+            assert_eq!(tail, plain[plain.len() - 50..]);
+            let before_start = -(plain.len() as i64) - 1;
+            assert!(reader.seek(SeekFrom::End(before_start)).is_err());
+        }
+
+        // A v2 file cut short fails once the read reaches the missing data
+        encrypt_file(&input, &sealed, &key).expect("encrypt");
+        let raw = std::fs::read(&sealed).expect("read sealed");
+        std::fs::write(&sealed, &raw[..raw.len() - 10]).expect("truncate");
+        let mut reader = DecryptingReader::open(&sealed, &key).expect("open truncated");
+        let mut head = vec![0u8; 10];
+        reader.read_exact(&mut head).expect("read head");
+        let mut rest = Vec::new();
+        assert!(reader.read_to_end(&mut rest).is_err());
+
+        // A v2 file still arriving serves every byte up to its sealed length
+        let reader = DecryptingReader::open(&sealed, &key).expect("open");
+        assert_eq!(reader.container_version(), 2);
+        let second_chunk = DEFAULT_CHUNK_SIZE as u64 + 5;
+        let needed = reader.sealed_len_through(second_chunk) as usize;
+        assert_eq!(
+            reader.sealed_len_through(plain.len() as u64 - 1),
+            raw.len() as u64
+        );
+        std::fs::write(&sealed, &raw[..needed]).expect("cut at chunk");
+        let mut reader = DecryptingReader::open(&sealed, &key).expect("open partial");
+        let mut range = vec![0u8; 6];
+        reader
+            .seek(SeekFrom::Start(second_chunk - 5))
+            .expect("seek");
+        reader.read_exact(&mut range).expect("read arrived chunk");
+        assert_eq!(range, plain[DEFAULT_CHUNK_SIZE as usize..][..6]);
+        reader.seek(SeekFrom::End(-1)).expect("seek end");
+        assert!(reader.read_exact(&mut range[..1]).is_err());
+        for path in [&input, &sealed] {
+            let _ = std::fs::remove_file(path);
+        }
+    }
 }
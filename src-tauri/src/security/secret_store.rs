@@ -0,0 +1,278 @@
+//! Storage for secrets kept out of the library database, such as the Telegram API
+//! credentials.
+//!
+//! The platform store holds them where one is available: the Windows Credential
+//! Manager, the macOS Keychain or the Secret Service on Linux. Without one, e.g. on a
+//! Linux machine with no keyring daemon, they go to a file sealed with a key derived
+//! from a passphrase the user enters.
+
+use super::{decrypt_bytes, derive_secret_key, dpapi_unprotect, encrypt_bytes};
+use anyhow::{anyhow, Context, Result};
+use base64::{engine::general_purpose::STANDARD as B64, Engine as _};
+use rand::RngCore;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+
+/// Service the platform store files entries under
+const SERVICE: &str = "com.wanderer.desktop";
+/// Fallback file in the app data directory
+pub const SECRETS_FILE: &str = "secrets.wbsec";
+/// Looked up to tell whether the platform store answers at all
+const PROBE_NAME: &str = "probe";
+const CHECK_AAD: &[u8] = b"wanderer secrets file check";
+const CHECK_VALUE: &[u8] = b"wanderer";
+
+pub trait SecretStore: Send + Sync {
+    /// Where the secrets are kept, shown in settings
+    fn kind(&self) -> &'static str;
+    fn get(&self, name: &str) -> Result<Option<Vec<u8>>>;
+    fn set(&self, name: &str, value: &[u8]) -> Result<()>;
+    fn delete(&self, name: &str) -> Result<()>;
+}
+
+/// The store secrets are read from this session, once the platform store answered or
+/// the secrets file was unlocked. Settings loaded outside commands (e.g. by the upload
+/// worker) read their secrets from it too.
+static OPENED: RwLock<Option<Arc<dyn SecretStore>>> = RwLock::new(None);
+
+pub fn opened() -> Option<Arc<dyn SecretStore>> {
+    OPENED.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+pub fn set_opened(store: Arc<dyn SecretStore>) {
+    *OPENED.write().unwrap_or_else(|e| e.into_inner()) = Some(store);
+}
+
+/// A text secret from the opened store; `None` while the store is locked or has none.
+pub fn opened_string(name: &str) -> Option<String> {
+    let store = opened()?;
+    match load_value(store.as_ref(), name) {
+        Ok(value) => value,
+        Err(e) => {
+            log::warn!("Failed to read {} from {}: {}", name, store.kind(), e);
+            None
+        }
+    }
+}
+
+/// Save or, with `None`, delete a text secret in the opened store.
+pub fn save_opened_string(name: &str, value: Option<&str>) -> Result<()> {
+    let store = opened().ok_or_else(|| anyhow!("Unlock the secret store to save {}", name))?;
+    match value {
+        Some(value) => save_value(store.as_ref(), name, &value),
+        None => store.delete(name),
+    }
+}
+
+/// Read a JSON value saved with [`save_value`].
+pub fn load_value<T: DeserializeOwned>(store: &dyn SecretStore, name: &str) -> Result<Option<T>> {
+    match store.get(name)? {
+        Some(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+        None => Ok(None),
+    }
+}
+
+pub fn save_value<T: Serialize>(store: &dyn SecretStore, name: &str, value: &T) -> Result<()> {
+    store.set(name, &serde_json::to_vec(value)?)
+}
+
+/// Move a value that earlier versions kept DPAPI-protected in the config table into
+/// `store`. Such blobs only exist, and only open, on Windows.
+pub fn migrate_protected_blob(store: &dyn SecretStore, name: &str, blob_b64: &str) -> Result<()> {
+    let protected = B64
+        .decode(blob_b64)
+        .context("Invalid protected blob encoding")?;
+    store.set(name, &dpapi_unprotect(&protected)?)
+}
+
+/// Secrets in the operating system's credential store.
+pub struct PlatformStore;
+
+impl PlatformStore {
+    /// The platform store, if it answers. Linux sessions without a Secret Service
+    /// provider have none.
+    pub fn open() -> Option<Self> {
+        match Self.get(PROBE_NAME) {
+            Ok(_) => Some(Self),
+            Err(e) => {
+                log::info!("No platform secret store available: {}", e);
+                None
+            }
+        }
+    }
+
+    fn entry(name: &str) -> Result<keyring::Entry> {
+        keyring::Entry::new(SERVICE, name).map_err(|e| anyhow!("Invalid secret name: {}", e))
+    }
+}
+
+impl SecretStore for PlatformStore {
+    fn kind(&self) -> &'static str {
+        if cfg!(target_os = "windows") {
+            "Windows Credential Manager"
+        } else if cfg!(target_os = "macos") {
+            "macOS Keychain"
+        } else {
+            "Secret Service"
+        }
+    }
+
+    fn get(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        match Self::entry(name)?.get_secret() {
+            Ok(secret) => Ok(Some(secret)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(anyhow!(
+                "Failed to read {} from {}: {}",
+                name,
+                self.kind(),
+                e
+            )),
+        }
+    }
+
+    fn set(&self, name: &str, value: &[u8]) -> Result<()> {
+        Self::entry(name)?
+            .set_secret(value)
+            .map_err(|e| anyhow!("Failed to save {} to {}: {}", name, self.kind(), e))
+    }
+
+    fn delete(&self, name: &str) -> Result<()> {
+        match Self::entry(name)?.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(anyhow!(
+                "Failed to delete {} from {}: {}",
+                name,
+                self.kind(),
+                e
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SecretsFile {
+    salt_b64: String,
+    /// Sealed known value, so a wrong passphrase fails on opening
+    check_b64: String,
+    /// Sealed values by name; each is authenticated against its name
+    entries: BTreeMap<String, String>,
+}
+
+/// Secrets in a file sealed with a key derived from the user's passphrase.
+pub struct FileStore {
+    path: PathBuf,
+    key: [u8; 32],
+    file: Mutex<SecretsFile>,
+}
+
+impl FileStore {
+    /// Open the file at `path` with `passphrase`, creating it when it doesn't exist.
+    pub fn open(path: &Path, passphrase: &str) -> Result<Self> {
+        if !path.exists() {
+            let mut salt = [0u8; 16];
+            rand::rngs::OsRng.fill_bytes(&mut salt);
+            let key = derive_secret_key(passphrase.as_bytes(), &salt)?;
+            let file = SecretsFile {
+                salt_b64: B64.encode(salt),
+                check_b64: B64.encode(encrypt_bytes(&key, CHECK_VALUE, CHECK_AAD)?),
+                entries: BTreeMap::new(),
+            };
+            write_file(path, &file)?;
+            return Ok(Self {
+                path: path.to_path_buf(),
+                key,
+                file: Mutex::new(file),
+            });
+        }
+
+        let file: SecretsFile =
+            serde_json::from_slice(&std::fs::read(path)?).context("Invalid secrets file")?;
+        let salt: [u8; 16] = B64
+            .decode(&file.salt_b64)
+            .context("Invalid secrets file salt encoding")?
+            .try_into()
+            .map_err(|_| anyhow!("Invalid secrets file salt length"))?;
+        let key = derive_secret_key(passphrase.as_bytes(), &salt)?;
+        let check = B64
+            .decode(&file.check_b64)
+            .context("Invalid secrets file check encoding")?;
+        decrypt_bytes(&key, &check, CHECK_AAD)
+            .map_err(|_| anyhow!("Wrong passphrase for the secrets file"))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            key,
+            file: Mutex::new(file),
+        })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, SecretsFile> {
+        self.file.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl SecretStore for FileStore {
+    fn kind(&self) -> &'static str {
+        "Passphrase-protected file"
+    }
+
+    fn get(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        let Some(sealed_b64) = self.lock().entries.get(name).cloned() else {
+            return Ok(None);
+        };
+        let sealed = B64.decode(sealed_b64).context("Invalid secret encoding")?;
+        decrypt_bytes(&self.key, &sealed, name.as_bytes()).map(Some)
+    }
+
+    fn set(&self, name: &str, value: &[u8]) -> Result<()> {
+        let sealed = encrypt_bytes(&self.key, value, name.as_bytes())?;
+        let mut file = self.lock();
+        file.entries.insert(name.to_string(), B64.encode(sealed));
+        write_file(&self.path, &file)
+    }
+
+    fn delete(&self, name: &str) -> Result<()> {
+        let mut file = self.lock();
+        if file.entries.remove(name).is_some() {
+            write_file(&self.path, &file)?;
+        }
+        Ok(())
+    }
+}
+
+/// Replace the file atomically, so a crash mid-write keeps the previous secrets.
+fn write_file(path: &Path, file: &SecretsFile) -> Result<()> {
+    let temp = path.with_extension("tmp");
+    std::fs::write(&temp, serde_json::to_vec_pretty(file)?)?;
+    std::fs::rename(&temp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_store_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("wanderer-secrets-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).expect("create temp dir");
+        let path = dir.join(SECRETS_FILE);
+
+        let store = FileStore::open(&path, "correct horse").expect("create secrets file");
+        save_value(&store, "creds", &(42, "hash")).expect("save value");
+        store.set("other", b"value").expect("set entry");
+        store.delete("other").expect("delete entry");
+        let written = std::fs::read_to_string(&path).expect("read secrets file");
+        assert!(!written.contains("hash"));
+
+        let reopened = FileStore::open(&path, "correct horse").expect("reopen secrets file");
+        let creds: Option<(i32, String)> = load_value(&reopened, "creds").expect("load value");
+        assert_eq!(creds, Some((42, "hash".to_string())));
+        assert_eq!(reopened.get("other").expect("get entry"), None);
+        assert!(FileStore::open(&path, "wrong horse").is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
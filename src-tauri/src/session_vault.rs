@@ -0,0 +1,202 @@
+//! At-rest protection for Telegram session databases in encrypted libraries.
+//!
+//! A session directory holds the sealed `session.db.wbenc` and, only while the vault
+//! is unlocked, the plaintext working copy `session.db` that the client opens. Locking
+//! the vault (or quitting) writes the working copy back to the sealed file and deletes
+//! it, so a copied app data dir doesn't carry a usable login.
+//!
+//! The client keeps writing its session (auth keys, update state) while connected, so
+//! the sealed file is refreshed every [`RESEAL_INTERVAL`] after such writes. A working
+//! copy a crash leaves behind is then no newer than that and is discarded at startup.
+
+use crate::security;
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Working copy opened by the Telegram client
+pub const SESSION_FILE: &str = "session.db";
+const SEALED_FILE: &str = "session.db.wbenc";
+/// SQLite files that sit next to the database while it is open
+const SIDECAR_SUFFIXES: [&str; 3] = ["-journal", "-wal", "-shm"];
+const KEY_CONTEXT: &str = "wanderer 2025-06 telegram session database";
+/// How often working copies written by the client are sealed again
+pub const RESEAL_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Held while a sealed file is written, since locking and the periodic reseal share the
+/// temp file
+static SEALING: Mutex<()> = Mutex::new(());
+
+/// Key sealing session databases, derived from the vault master key.
+pub fn session_key(master_key: &[u8; 32]) -> [u8; 32] {
+    security::derive_subkey(master_key, KEY_CONTEXT)
+}
+
+fn working_path(dir: &Path) -> PathBuf {
+    dir.join(SESSION_FILE)
+}
+
+fn sealed_path(dir: &Path) -> PathBuf {
+    dir.join(SEALED_FILE)
+}
+
+pub fn is_sealed(dir: &Path) -> bool {
+    sealed_path(dir).exists()
+}
+
+/// Restore the working copy from the sealed file. A working copy that is already
+/// present is the one the client has open (e.g. after unlocking with the recovery key
+/// while connected) and is kept; crash leftovers are gone by now, see
+/// [`discard_working_copy`]. Sessions from before encryption have no sealed file yet
+/// and are sealed right away.
+pub fn unseal(dir: &Path, key: &[u8; 32]) -> Result<()> {
+    let working = working_path(dir);
+    if working.exists() {
+        if !is_sealed(dir) {
+            log::info!("Sealing plaintext Telegram session in {:?}", dir);
+            snapshot(dir, key)?;
+        }
+        return Ok(());
+    }
+    if is_sealed(dir) {
+        // A wrong key fails midway, which mustn't leave a truncated working copy
+        let temp = dir.join(format!("{}.tmp", SESSION_FILE));
+        if let Err(e) = security::decrypt_file(&sealed_path(dir), &temp, key) {
+            let _ = std::fs::remove_file(&temp);
+            return Err(e);
+        }
+        std::fs::rename(&temp, &working)?;
+    }
+    Ok(())
+}
+
+/// Write the working copy to the sealed file without closing it. Returns `false` when
+/// there is no working copy.
+pub fn snapshot(dir: &Path, key: &[u8; 32]) -> Result<bool> {
+    let working = working_path(dir);
+    let _sealing = SEALING.lock().unwrap_or_else(|e| e.into_inner());
+    if !working.exists() {
+        return Ok(false);
+    }
+    // Replace the sealed file atomically so a crash mid-write keeps the old one
+    let temp = dir.join(format!("{}.tmp", SEALED_FILE));
+    security::encrypt_file(&working, &temp, key)?;
+    std::fs::rename(&temp, sealed_path(dir))?;
+    Ok(true)
+}
+
+/// Seal the working copy again if the client wrote it since it was last sealed.
+pub fn reseal_if_changed(dir: &Path, key: &[u8; 32]) -> Result<bool> {
+    let modified = |path: PathBuf| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    let Some(sealed_at) = modified(sealed_path(dir)) else {
+        return snapshot(dir, key);
+    };
+    // Equal times count as written: coarse timestamps can't order a write and a seal
+    // within the same tick
+    let written = std::iter::once(SESSION_FILE.to_string())
+        .chain(SIDECAR_SUFFIXES.map(|suffix| format!("{}{}", SESSION_FILE, suffix)))
+        .filter_map(|name| modified(dir.join(name)))
+        .any(|at| at >= sealed_at);
+    if !written {
+        return Ok(false);
+    }
+    snapshot(dir, key)
+}
+
+/// Seal the working copy and delete it. The client using it must be disconnected.
+pub fn seal(dir: &Path, key: &[u8; 32]) -> Result<()> {
+    if snapshot(dir, key)? {
+        remove_working_copy(dir)?;
+    }
+    Ok(())
+}
+
+/// Delete a working copy left behind by a crash while the vault is locked. Nothing is
+/// deleted unless a sealed copy exists to restore from; it holds everything the client
+/// wrote up to [`RESEAL_INTERVAL`] before the crash.
+pub fn discard_working_copy(dir: &Path) -> Result<bool> {
+    if !is_sealed(dir) || !working_path(dir).exists() {
+        return Ok(false);
+    }
+    remove_working_copy(dir)?;
+    Ok(true)
+}
+
+/// Delete the sealed session, e.g. after logging out.
+pub fn remove_sealed(dir: &Path) -> Result<()> {
+    match std::fs::remove_file(sealed_path(dir)) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+fn remove_working_copy(dir: &Path) -> Result<()> {
+    std::fs::remove_file(working_path(dir))?;
+    for suffix in SIDECAR_SUFFIXES {
+        let _ = std::fs::remove_file(dir.join(format!("{}{}", SESSION_FILE, suffix)));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("wanderer-session-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_seal_unseal_roundtrip() {
+        let dir = temp_dir("roundtrip");
+        let key = session_key(&[3u8; 32]);
+        std::fs::write(dir.join(SESSION_FILE), b"auth key").unwrap();
+        std::fs::write(dir.join("session.db-journal"), b"").unwrap();
+
+        seal(&dir, &key).unwrap();
+        assert!(is_sealed(&dir));
+        assert!(!dir.join(SESSION_FILE).exists());
+        assert!(!dir.join("session.db-journal").exists());
+        let sealed = std::fs::read(sealed_path(&dir)).unwrap();
+        assert!(!sealed.windows(8).any(|w| w == b"auth key"));
+
+        unseal(&dir, &key).unwrap();
+        assert_eq!(std::fs::read(dir.join(SESSION_FILE)).unwrap(), b"auth key");
+        assert!(unseal(&temp_dir("empty"), &key).is_ok());
+
+        let wrong = session_key(&[4u8; 32]);
+        std::fs::remove_file(dir.join(SESSION_FILE)).unwrap();
+        assert!(unseal(&dir, &wrong).is_err());
+        assert!(!dir.join(SESSION_FILE).exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_plaintext_session_migrates() {
+        let dir = temp_dir("migrate");
+        let key = session_key(&[5u8; 32]);
+        std::fs::write(dir.join(SESSION_FILE), b"legacy").unwrap();
+
+        // Locked: nothing to restore from, so the legacy session stays
+        assert!(!discard_working_copy(&dir).unwrap());
+        unseal(&dir, &key).unwrap();
+        assert!(is_sealed(&dir));
+        assert!(dir.join(SESSION_FILE).exists());
+
+        // Sealed again only after the client writes the working copy
+        assert!(!reseal_if_changed(&dir, &key).unwrap());
+        std::thread::sleep(Duration::from_millis(20));
+        std::fs::write(dir.join(SESSION_FILE), b"updated").unwrap();
+        assert!(reseal_if_changed(&dir, &key).unwrap());
+
+        // A crash leftover goes once a sealed copy exists
+        assert!(discard_working_copy(&dir).unwrap());
+        assert!(!dir.join(SESSION_FILE).exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
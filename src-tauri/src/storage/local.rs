@@ -0,0 +1,289 @@
+//! [`StorageBackend`] that keeps objects in a local directory, such as a mounted NAS share.
+//!
+//! Each object is stored as `<id>.bin` with a `<id>.json` [`ObjectRecord`] next to it,
+//! named by [`object_name`]. The data is written first, as `<id>.bin.partial` until it
+//! is complete, and the record last.
+
+use super::{
+    object_name, parse_object_name, ObjectId, ObjectRecord, StorageBackend, StoredObject,
+    UploadPacing, CLAIM_TIMEOUT,
+};
+use async_trait::async_trait;
+use futures_util::TryStreamExt;
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+/// File holding the last claimed object ID
+const LAST_ID_FILE: &str = "last_id";
+
+pub struct LocalDirBackend {
+    root: PathBuf,
+    /// Serializes ID allocation between concurrent uploads
+    allocate: Mutex<()>,
+}
+
+impl LocalDirBackend {
+    pub fn new(root: PathBuf) -> Self {
+        Self {
+            root,
+            allocate: Mutex::new(()),
+        }
+    }
+
+    fn data_path(&self, id: ObjectId) -> PathBuf {
+        self.root.join(format!("{}.bin", object_name(id)))
+    }
+
+    fn partial_path(&self, id: ObjectId) -> PathBuf {
+        self.root.join(format!("{}.bin.partial", object_name(id)))
+    }
+
+    fn record_path(&self, id: ObjectId) -> PathBuf {
+        self.root.join(format!("{}.json", object_name(id)))
+    }
+
+    /// Claim the next free ID by creating its partial data file, which fails if another
+    /// device sharing the directory got there first. The last claimed ID is kept in a
+    /// counter file so IDs of deleted objects are never reused, which would hide new
+    /// uploads from incremental sync.
+    async fn claim_id(&self) -> Result<(ObjectId, tokio::fs::File), String> {
+        let _allocating = self.allocate.lock().await;
+        let mut id = self.last_claimed().await? + 1;
+        let file = loop {
+            let taken = tokio::fs::try_exists(self.record_path(id))
+                .await
+                .map_err(|e| e.to_string())?
+                || tokio::fs::try_exists(self.data_path(id))
+                    .await
+                    .map_err(|e| e.to_string())?;
+            if taken {
+                id += 1;
+                continue;
+            }
+            match tokio::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(self.partial_path(id))
+                .await
+            {
+                Ok(file) => break file,
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => id += 1,
+                Err(e) => return Err(e.to_string()),
+            }
+        };
+        tokio::fs::write(self.root.join(LAST_ID_FILE), id.to_string())
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok((id, file))
+    }
+
+    /// The counter's last claimed ID, or the newest object in a directory without one.
+    async fn last_claimed(&self) -> Result<ObjectId, String> {
+        let counter = tokio::fs::read_to_string(self.root.join(LAST_ID_FILE))
+            .await
+            .ok()
+            .and_then(|v| v.trim().parse::<ObjectId>().ok());
+        if let Some(counter) = counter {
+            return Ok(counter);
+        }
+        let mut newest = 0;
+        let mut entries = match tokio::fs::read_dir(&self.root).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(newest),
+            Err(e) => return Err(e.to_string()),
+        };
+        while let Some(entry) = entries.next_entry().await.map_err(|e| e.to_string())? {
+            if let Some((id, _)) = entry.file_name().to_str().and_then(parse_object_name) {
+                newest = newest.max(id);
+            }
+        }
+        Ok(newest)
+    }
+
+    /// Whether `id` has been claimed without a record recently enough that its upload
+    /// may still finish. The partial file's time moves on as data is written.
+    async fn in_flight(&self, id: ObjectId) -> bool {
+        for path in [self.partial_path(id), self.data_path(id)] {
+            if let Ok(modified) = tokio::fs::metadata(&path).await.and_then(|m| m.modified()) {
+                return modified.elapsed().map_or(true, |age| age < CLAIM_TIMEOUT);
+            }
+        }
+        false
+    }
+}
+
+#[async_trait]
+impl StorageBackend for LocalDirBackend {
+    fn kind(&self) -> &'static str {
+        "local"
+    }
+
+    async fn upload(
+        &self,
+        path: &Path,
+        caption: String,
+        pacing: &UploadPacing,
+    ) -> Result<ObjectId, String> {
+        tokio::fs::create_dir_all(&self.root)
+            .await
+            .map_err(|e| format!("Storage directory unavailable: {}", e))?;
+
+        let (id, partial) = self.claim_id().await?;
+        let stored = async {
+            // The partial name keeps a half-written file from being listed
+            let size = copy_paced(path, partial, pacing)
+                .await
+                .map_err(|e| e.to_string())?;
+            tokio::fs::rename(self.partial_path(id), self.data_path(id))
+                .await
+                .map_err(|e| e.to_string())?;
+
+            let record = ObjectRecord::for_file(path, size, caption);
+            let json = serde_json::to_vec(&record).map_err(|e| e.to_string())?;
+            tokio::fs::write(self.record_path(id), json)
+                .await
+                .map_err(|e| e.to_string())
+        }
+        .await;
+        if let Err(e) = stored {
+            let _ = self.delete(&[id]).await;
+            return Err(e);
+        }
+        Ok(id)
+    }
+
+    async fn download(&self, id: ObjectId, dest: &Path) -> Result<(), String> {
+        tokio::fs::copy(self.data_path(id), dest)
+            .await
+            .map(|_| ())
+            .map_err(|e| format!("Object {} not available: {}", id, e))
+    }
+
+    async fn delete(&self, ids: &[ObjectId]) -> Result<usize, String> {
+        let mut deleted = 0;
+        for id in ids {
+            let _ = tokio::fs::remove_file(self.data_path(*id)).await;
+            let _ = tokio::fs::remove_file(self.partial_path(*id)).await;
+            if tokio::fs::remove_file(self.record_path(*id)).await.is_ok() {
+                deleted += 1;
+            }
+        }
+        Ok(deleted)
+    }
+
+    /// Reads records by ID from `after` up to the last claimed ID, so a page costs
+    /// about `limit` file reads however many objects the directory holds.
+    async fn list(&self, after: ObjectId, limit: usize) -> Result<Vec<StoredObject>, String> {
+        let last_claimed = self.last_claimed().await?;
+        let mut objects = Vec::new();
+        for id in after.max(0) + 1..=last_claimed {
+            if objects.len() == limit {
+                break;
+            }
+            match tokio::fs::read(self.record_path(id)).await {
+                Ok(json) => {
+                    let record: ObjectRecord = serde_json::from_slice(&json)
+                        .map_err(|e| format!("Corrupt record for object {}: {}", id, e))?;
+                    objects.push(record.into_object(id));
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    if self.in_flight(id).await {
+                        break;
+                    }
+                }
+                Err(e) => return Err(e.to_string()),
+            }
+        }
+        Ok(objects)
+    }
+}
+
+/// Copy `from` into `dest` at the pace `pacing` allows, returning the bytes written.
+async fn copy_paced(
+    from: &Path,
+    mut dest: tokio::fs::File,
+    pacing: &UploadPacing,
+) -> std::io::Result<u64> {
+    let source = tokio::fs::File::open(from).await?;
+    let mut chunks = std::pin::pin!(pacing.read_chunks(source));
+    let mut size = 0;
+    while let Some(chunk) = chunks.try_next().await? {
+        dest.write_all(&chunk).await?;
+        size += chunk.len() as u64;
+    }
+    dest.flush().await?;
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_local_backend_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("wanderer-local-store-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let source =
+            std::env::temp_dir().join(format!("wanderer-local-src-{}.jpg", std::process::id()));
+        std::fs::write(&source, b"photo bytes").unwrap();
+
+        let backend = LocalDirBackend::new(dir.join("store"));
+        let first = backend
+            .upload(&source, "caption one".to_string(), &UploadPacing::default())
+            .await
+            .unwrap();
+        let second = backend
+            .upload(&source, "caption two".to_string(), &UploadPacing::default())
+            .await
+            .unwrap();
+        assert_eq!((first, second), (1, 2));
+
+        assert_eq!(backend.list(0, 1).await.unwrap()[0].id, first);
+        let listed = backend.list(first, 10).await.unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].id, second);
+        assert_eq!(listed[0].caption, "caption two");
+        assert_eq!(listed[0].size, 11);
+        assert_eq!(listed[0].mime_type.as_deref(), Some("image/jpeg"));
+
+        let out = dir.join("out.jpg");
+        backend.download(second, &out).await.unwrap();
+        assert_eq!(std::fs::read(&out).unwrap(), b"photo bytes");
+
+        assert_eq!(backend.delete(&[second, 99]).await.unwrap(), 1);
+        assert_eq!(backend.list(0, 10).await.unwrap().len(), 1);
+
+        // IDs of deleted objects are not handed out again
+        let third = backend
+            .upload(
+                &source,
+                "caption three".to_string(),
+                &UploadPacing::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(third, 3);
+        assert!(dir.join("store").join("0000000003.json").exists());
+
+        // Pages end before an upload still being written, and resume once it is done
+        let store = dir.join("store");
+        std::fs::write(store.join("0000000004.bin.partial"), b"half").unwrap();
+        let fifth = backend
+            .upload(
+                &source,
+                "caption five".to_string(),
+                &UploadPacing::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(fifth, 5);
+        assert!(backend.list(third, 10).await.unwrap().is_empty());
+        std::fs::remove_file(store.join("0000000004.bin.partial")).unwrap();
+        let listed = backend.list(third, 10).await.unwrap();
+        assert_eq!(listed.iter().map(|o| o.id).collect::<Vec<_>>(), vec![fifth]);
+
+        let _ = std::fs::remove_dir_all(&dir);
+        let _ = std::fs::remove_file(&source);
+    }
+}
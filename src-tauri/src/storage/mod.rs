@@ -0,0 +1,319 @@
+//! Cloud storage backends.
+//!
+//! Telegram is the default target. A local directory (e.g. a mounted NAS share) or an
+//! S3-compatible bucket can be selected instead with the `storage_backend` config key.
+//! Object IDs are positive integers like Telegram message IDs, so every backend fits the
+//! existing `telegram_media_id` column and sync high-water mark.
+
+mod local;
+mod s3;
+mod telegram;
+
+use crate::database::Database;
+use crate::rate_limit::{BandwidthLimiter, UploadGate};
+use crate::security::secret_store;
+use crate::telegram::TelegramService;
+use async_trait::async_trait;
+use futures_util::Stream;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+
+pub use local::LocalDirBackend;
+pub use s3::{S3Backend, S3Config};
+
+/// Identifier of a stored object (the message ID for Telegram)
+pub type ObjectId = i32;
+
+/// Config key selecting the backend: "telegram" (default), "local" or "s3"
+pub const STORAGE_BACKEND_KEY: &str = "storage_backend";
+pub const STORAGE_LOCAL_DIR_KEY: &str = "storage_local_dir";
+pub const STORAGE_S3_ENDPOINT_KEY: &str = "storage_s3_endpoint";
+pub const STORAGE_S3_REGION_KEY: &str = "storage_s3_region";
+pub const STORAGE_S3_BUCKET_KEY: &str = "storage_s3_bucket";
+pub const STORAGE_S3_PREFIX_KEY: &str = "storage_s3_prefix";
+pub const STORAGE_S3_ACCESS_KEY_KEY: &str = "storage_s3_access_key";
+pub const STORAGE_S3_SECRET_KEY_KEY: &str = "storage_s3_secret_key";
+
+/// A file held by a backend, as returned by [`StorageBackend::list`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredObject {
+    pub id: ObjectId,
+    pub file_name: Option<String>,
+    pub mime_type: Option<String>,
+    pub size: u64,
+    /// Caption text, carrying the Wander(er) metadata line for files it uploaded
+    pub caption: String,
+    /// Unix timestamp of the upload
+    pub uploaded_at: i64,
+}
+
+/// Bytes read per chunk by [`UploadPacing::read_chunks`]
+const PACED_CHUNK_BYTES: usize = 64 * 1024;
+
+/// The upload bandwidth cap and pause switch, shared with the Telegram accounts so
+/// every backend honours the same settings.
+#[derive(Debug, Clone, Default)]
+pub struct UploadPacing {
+    bandwidth: Arc<BandwidthLimiter>,
+    gate: Arc<UploadGate>,
+}
+
+impl UploadPacing {
+    pub fn new(bandwidth: Arc<BandwidthLimiter>, gate: Arc<UploadGate>) -> Self {
+        Self { bandwidth, gate }
+    }
+
+    /// Read `file` in chunks, waiting before each one while uploads are paused and
+    /// after it for as long as the bandwidth cap asks.
+    pub fn read_chunks(
+        &self,
+        file: tokio::fs::File,
+    ) -> impl Stream<Item = std::io::Result<Vec<u8>>> + Send + 'static {
+        futures_util::stream::try_unfold((file, self.clone()), |(mut file, pacing)| async move {
+            pacing.gate.wait_open().await;
+            let mut chunk = vec![0u8; PACED_CHUNK_BYTES];
+            let read = file.read(&mut chunk).await?;
+            if read == 0 {
+                return Ok(None);
+            }
+            chunk.truncate(read);
+            let wait = pacing.bandwidth.consume(read as u64);
+            if !wait.is_zero() {
+                tokio::time::sleep(wait).await;
+            }
+            Ok(Some((chunk, (file, pacing))))
+        })
+    }
+}
+
+/// Where library files are backed up.
+#[async_trait]
+pub trait StorageBackend: Send + Sync {
+    /// Short name used in logs and settings ("telegram", "local", "s3")
+    fn kind(&self) -> &'static str;
+
+    /// Store the file at `path` with `caption`, returning the new object's ID. The
+    /// upload follows `pacing`'s bandwidth cap and waits while uploads are paused.
+    async fn upload(
+        &self,
+        path: &Path,
+        caption: String,
+        pacing: &UploadPacing,
+    ) -> Result<ObjectId, String>;
+
+    /// Write the object's bytes to `dest`.
+    async fn download(&self, id: ObjectId, dest: &Path) -> Result<(), String>;
+
+    /// Delete objects, returning how many were removed.
+    async fn delete(&self, ids: &[ObjectId]) -> Result<usize, String>;
+
+    /// Up to `limit` objects with an ID greater than `after`, oldest first. A shorter
+    /// page means nothing newer is stored.
+    async fn list(&self, after: ObjectId, limit: usize) -> Result<Vec<StoredObject>, String>;
+}
+
+/// Objects requested per [`StorageBackend::list`] call by [`list_all`]
+pub const LIST_PAGE_SIZE: usize = 100;
+
+/// Every object with an ID greater than `after`, oldest first.
+pub async fn list_all(
+    backend: &dyn StorageBackend,
+    after: ObjectId,
+) -> Result<Vec<StoredObject>, String> {
+    let mut objects = Vec::new();
+    let mut mark = after;
+    loop {
+        let page = backend.list(mark, LIST_PAGE_SIZE).await?;
+        let done = page.len() < LIST_PAGE_SIZE;
+        if let Some(newest) = page.last() {
+            mark = newest.id;
+        }
+        objects.extend(page);
+        if done {
+            return Ok(objects);
+        }
+    }
+}
+
+/// Sidecar record kept next to each object by the local and S3 backends.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ObjectRecord {
+    name: String,
+    mime_type: Option<String>,
+    size: u64,
+    caption: String,
+    uploaded_at: i64,
+}
+
+impl ObjectRecord {
+    fn for_file(path: &Path, size: u64, caption: String) -> Self {
+        Self {
+            name: path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or("file")
+                .to_string(),
+            mime_type: mime_guess::from_path(path).first().map(|m| m.to_string()),
+            size,
+            caption,
+            uploaded_at: time::OffsetDateTime::now_utc().unix_timestamp(),
+        }
+    }
+
+    fn into_object(self, id: ObjectId) -> StoredObject {
+        StoredObject {
+            id,
+            file_name: Some(self.name),
+            mime_type: self.mime_type,
+            size: self.size,
+            caption: self.caption,
+            uploaded_at: self.uploaded_at,
+        }
+    }
+}
+
+/// An ID claimed this long ago without a record belongs to an abandoned upload. Until
+/// then [`StorageBackend::list`] ends its page before the ID, so incremental readers
+/// don't move past an upload that is still running.
+const CLAIM_TIMEOUT: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Base name of an object's files in the local and S3 backends: the ID zero-padded so
+/// names sort in ID order.
+fn object_name(id: ObjectId) -> String {
+    format!("{:010}", id)
+}
+
+/// ID and extension of a file named by [`object_name`], e.g. `0000000042.json`.
+fn parse_object_name(name: &str) -> Option<(ObjectId, &str)> {
+    let (id, extension) = name.split_once('.')?;
+    let id = id.parse::<ObjectId>().ok().filter(|id| *id > 0)?;
+    Some((id, extension))
+}
+
+/// Storage settings as shown in the UI. The S3 secret is never sent back; it is kept in
+/// the secret store rather than the config table.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageSettings {
+    pub backend: String,
+    pub local_dir: Option<String>,
+    pub s3_endpoint: Option<String>,
+    pub s3_region: Option<String>,
+    pub s3_bucket: Option<String>,
+    pub s3_prefix: Option<String>,
+    pub s3_access_key: Option<String>,
+    pub s3_secret_key: Option<String>,
+}
+
+impl StorageSettings {
+    pub fn load(db: &Database) -> Self {
+        let get = |key: &str| db.get_config(key).ok().flatten().filter(|v| !v.is_empty());
+        Self {
+            backend: get(STORAGE_BACKEND_KEY).unwrap_or_else(|| "telegram".to_string()),
+            local_dir: get(STORAGE_LOCAL_DIR_KEY),
+            s3_endpoint: get(STORAGE_S3_ENDPOINT_KEY),
+            s3_region: get(STORAGE_S3_REGION_KEY),
+            s3_bucket: get(STORAGE_S3_BUCKET_KEY),
+            s3_prefix: get(STORAGE_S3_PREFIX_KEY),
+            s3_access_key: get(STORAGE_S3_ACCESS_KEY_KEY),
+            // Plain config holds the secret only until the secret store is first opened
+            s3_secret_key: secret_store::opened_string(STORAGE_S3_SECRET_KEY_KEY)
+                .filter(|v| !v.is_empty())
+                .or_else(|| get(STORAGE_S3_SECRET_KEY_KEY)),
+        }
+    }
+
+    /// Persist the settings. An empty S3 secret keeps the stored one.
+    pub fn save(&self, db: &Database) -> Result<(), String> {
+        let fields = [
+            (STORAGE_BACKEND_KEY, Some(&self.backend)),
+            (STORAGE_LOCAL_DIR_KEY, self.local_dir.as_ref()),
+            (STORAGE_S3_ENDPOINT_KEY, self.s3_endpoint.as_ref()),
+            (STORAGE_S3_REGION_KEY, self.s3_region.as_ref()),
+            (STORAGE_S3_BUCKET_KEY, self.s3_bucket.as_ref()),
+            (STORAGE_S3_PREFIX_KEY, self.s3_prefix.as_ref()),
+            (STORAGE_S3_ACCESS_KEY_KEY, self.s3_access_key.as_ref()),
+        ];
+        for (key, value) in fields {
+            match value.filter(|v| !v.is_empty()) {
+                Some(value) => db.set_config(key, value),
+                None => db.remove_config(key),
+            }
+            .map_err(|e| e.to_string())?;
+        }
+        if let Some(secret) = self.s3_secret_key.as_ref().filter(|v| !v.is_empty()) {
+            secret_store::save_opened_string(STORAGE_S3_SECRET_KEY_KEY, Some(secret))
+                .map_err(|e| e.to_string())?;
+            db.remove_config(STORAGE_S3_SECRET_KEY_KEY)
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    /// Copy for the UI with the secret removed.
+    pub fn redacted(mut self) -> Self {
+        self.s3_secret_key = None;
+        self
+    }
+
+    /// Build the configured backend, or `None` when Telegram is selected.
+    pub fn open_external(&self) -> Result<Option<Arc<dyn StorageBackend>>, String> {
+        match self.backend.as_str() {
+            "telegram" => Ok(None),
+            "local" => {
+                let dir = self
+                    .local_dir
+                    .as_ref()
+                    .ok_or("Local storage directory is not configured")?;
+                Ok(Some(Arc::new(LocalDirBackend::new(PathBuf::from(dir)))))
+            }
+            "s3" => {
+                let required = |value: &Option<String>, what: &str| {
+                    value
+                        .clone()
+                        .ok_or_else(|| format!("S3 {} is not configured", what))
+                };
+                let config = S3Config {
+                    endpoint: required(&self.s3_endpoint, "endpoint")?,
+                    region: self
+                        .s3_region
+                        .clone()
+                        .unwrap_or_else(|| "us-east-1".to_string()),
+                    bucket: required(&self.s3_bucket, "bucket")?,
+                    prefix: self.s3_prefix.clone().unwrap_or_default(),
+                    access_key: required(&self.s3_access_key, "access key")?,
+                    secret_key: match &self.s3_secret_key {
+                        Some(secret) => secret.clone(),
+                        None if secret_store::opened().is_none() => {
+                            return Err("Unlock the secret store to use S3 storage".to_string())
+                        }
+                        None => return Err("S3 secret key is not configured".to_string()),
+                    },
+                };
+                Ok(Some(Arc::new(S3Backend::new(config)?)))
+            }
+            other => Err(format!("Unknown storage backend: {}", other)),
+        }
+    }
+}
+
+/// The configured non-Telegram backend, or `None` when Telegram is selected.
+///
+/// Callers with Telegram-specific paths (resumable uploads, split parts) use those when
+/// this returns `None`.
+pub fn open_external(db: &Database) -> Result<Option<Arc<dyn StorageBackend>>, String> {
+    StorageSettings::load(db).open_external()
+}
+
+/// The configured backend, falling back to Telegram.
+pub fn active_backend(
+    db: &Database,
+    telegram: &Arc<TelegramService>,
+) -> Result<Arc<dyn StorageBackend>, String> {
+    Ok(open_external(db)?.unwrap_or_else(|| telegram.clone() as Arc<dyn StorageBackend>))
+}
@@ -0,0 +1,762 @@
+//! [`StorageBackend`] for S3-compatible object stores (AWS S3, MinIO, Garage, ...).
+//!
+//! Objects are stored as `<prefix><id>.bin` with a `<prefix><id>.json` [`ObjectRecord`],
+//! named by [`object_name`] and using path-style requests signed with AWS Signature
+//! Version 4. New IDs are claimed with a conditional PUT of an empty `<id>.claim`
+//! marker, so two devices never write the same ID. The data is uploaded before the
+//! record, and listings skip claimed IDs until their record appears.
+
+use super::{
+    object_name, parse_object_name, ObjectId, ObjectRecord, StorageBackend, StoredObject,
+    UploadPacing, CLAIM_TIMEOUT,
+};
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use hmac::{Hmac, Mac};
+use reqwest::{Body, Method, Response, StatusCode};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use time::format_description::well_known::Rfc3339;
+use time::macros::format_description;
+use time::OffsetDateTime;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+/// Object holding the last issued ID, so IDs of deleted objects are not reused
+const LAST_ID_OBJECT: &str = "last_id";
+/// Conditional PUTs tried before giving up on allocating an ID
+const MAX_CLAIM_ATTEMPTS: u32 = 16;
+/// Largest `max-keys` S3 accepts for one listing request
+const MAX_LIST_KEYS: usize = 1000;
+
+#[derive(Debug, Clone)]
+pub struct S3Config {
+    /// Base URL, e.g. `http://nas.local:9000`
+    pub endpoint: String,
+    pub region: String,
+    pub bucket: String,
+    /// Key prefix, e.g. `wanderer/`
+    pub prefix: String,
+    pub access_key: String,
+    pub secret_key: String,
+}
+
+pub struct S3Backend {
+    config: S3Config,
+    endpoint: reqwest::Url,
+    client: reqwest::Client,
+    /// Last ID this process issued, to avoid re-listing the bucket for every upload
+    last_issued: Mutex<Option<ObjectId>>,
+}
+
+impl S3Backend {
+    pub fn new(mut config: S3Config) -> Result<Self, String> {
+        let endpoint = reqwest::Url::parse(config.endpoint.trim_end_matches('/'))
+            .map_err(|e| format!("Invalid S3 endpoint: {}", e))?;
+        if endpoint.host_str().is_none() {
+            return Err("Invalid S3 endpoint: missing host".to_string());
+        }
+        let prefix = config.prefix.trim_matches('/');
+        config.prefix = if prefix.is_empty() {
+            String::new()
+        } else {
+            format!("{}/", prefix)
+        };
+        Ok(Self {
+            config,
+            endpoint,
+            client: reqwest::Client::new(),
+            last_issued: Mutex::new(None),
+        })
+    }
+
+    fn data_key(&self, id: ObjectId) -> String {
+        format!("{}{}.bin", self.config.prefix, object_name(id))
+    }
+
+    fn record_key(&self, id: ObjectId) -> String {
+        format!("{}{}.json", self.config.prefix, object_name(id))
+    }
+
+    fn claim_key(&self, id: ObjectId) -> String {
+        format!("{}{}.claim", self.config.prefix, object_name(id))
+    }
+
+    /// Listing position just past every key of object `id`.
+    fn start_after(&self, id: ObjectId) -> String {
+        format!("{}{}~", self.config.prefix, object_name(id))
+    }
+
+    /// Send a signed request for `key` (empty for bucket-level requests).
+    async fn send(
+        &self,
+        method: Method,
+        key: &str,
+        query: &[(&str, &str)],
+        extra_headers: &[(&str, String)],
+        body: Body,
+        payload_hash: &str,
+    ) -> Result<Response, String> {
+        let path = if key.is_empty() {
+            format!("/{}", self.config.bucket)
+        } else {
+            format!("/{}/{}", self.config.bucket, key)
+        };
+        let canonical_uri = uri_encode(&path, false);
+        let mut query: Vec<(String, String)> = query
+            .iter()
+            .map(|(k, v)| (uri_encode(k, true), uri_encode(v, true)))
+            .collect();
+        query.sort();
+        let canonical_query = query
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        let host = match self.endpoint.port() {
+            Some(port) => format!("{}:{}", self.endpoint.host_str().unwrap_or_default(), port),
+            None => self.endpoint.host_str().unwrap_or_default().to_string(),
+        };
+        let amz_date = time::OffsetDateTime::now_utc()
+            .format(format_description!(
+                "[year][month][day]T[hour][minute][second]Z"
+            ))
+            .map_err(|e| e.to_string())?;
+
+        let mut headers: Vec<(String, String)> = vec![
+            ("host".to_string(), host),
+            ("x-amz-content-sha256".to_string(), payload_hash.to_string()),
+            ("x-amz-date".to_string(), amz_date.clone()),
+        ];
+        headers.extend(
+            extra_headers
+                .iter()
+                .map(|(k, v)| (k.to_ascii_lowercase(), v.trim().to_string())),
+        );
+        headers.sort();
+
+        let (canonical, signed_headers) = canonical_request(
+            method.as_str(),
+            &canonical_uri,
+            &canonical_query,
+            &headers,
+            payload_hash,
+        );
+        let scope = format!("{}/{}/s3/aws4_request", &amz_date[..8], self.config.region);
+        let signature = sign(
+            &self.config.secret_key,
+            &self.config.region,
+            &amz_date,
+            &canonical,
+        );
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.config.access_key, scope, signed_headers, signature
+        );
+
+        let mut url = format!(
+            "{}{}",
+            self.endpoint.as_str().trim_end_matches('/'),
+            canonical_uri
+        );
+        if !canonical_query.is_empty() {
+            url = format!("{}?{}", url, canonical_query);
+        }
+
+        let mut request = self
+            .client
+            .request(method, url)
+            .header("authorization", authorization)
+            .body(body);
+        for (name, value) in headers.iter().filter(|(name, _)| name != "host") {
+            request = request.header(name, value);
+        }
+        request
+            .send()
+            .await
+            .map_err(|e| format!("S3 request failed: {}", e))
+    }
+
+    /// One page of up to `max_keys` keys after `start_after`, in key order, with their
+    /// last-modified times and the token for the next page if there is one.
+    async fn list_keys_page(
+        &self,
+        start_after: &str,
+        continuation: Option<&str>,
+        max_keys: usize,
+    ) -> Result<(Vec<(String, Option<OffsetDateTime>)>, Option<String>), String> {
+        let max_keys = max_keys.clamp(1, MAX_LIST_KEYS).to_string();
+        let mut query = vec![
+            ("list-type", "2"),
+            ("prefix", self.config.prefix.as_str()),
+            ("start-after", start_after),
+            ("max-keys", max_keys.as_str()),
+        ];
+        if let Some(token) = continuation {
+            query.push(("continuation-token", token));
+        }
+        let response = self
+            .send(
+                Method::GET,
+                "",
+                &query,
+                &[],
+                Body::from(""),
+                &sha256_hex(b""),
+            )
+            .await?;
+        let xml = ensure_success(response)
+            .await?
+            .text()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let keys = xml_values(&xml, "Contents")
+            .iter()
+            .filter_map(|contents| {
+                let key = xml_values(contents, "Key").into_iter().next()?;
+                let modified = xml_values(contents, "LastModified")
+                    .first()
+                    .and_then(|v| OffsetDateTime::parse(v, &Rfc3339).ok());
+                Some((key, modified))
+            })
+            .collect();
+        let truncated = xml_values(&xml, "IsTruncated")
+            .first()
+            .is_some_and(|v| v == "true");
+        let next = xml_values(&xml, "NextContinuationToken")
+            .into_iter()
+            .next()
+            .filter(|_| truncated);
+        Ok((keys, next))
+    }
+
+    /// Newest claimed ID: the counter, or a newer ID claimed by a device that failed
+    /// to update it.
+    async fn newest_id(&self) -> Result<ObjectId, String> {
+        let mut newest = self
+            .get_bytes(&format!("{}{}", self.config.prefix, LAST_ID_OBJECT))
+            .await?
+            .and_then(|b| String::from_utf8(b).ok())
+            .and_then(|v| v.trim().parse::<ObjectId>().ok())
+            .unwrap_or(0);
+        let start_after = self.start_after(newest);
+        let mut continuation: Option<String> = None;
+        loop {
+            let (keys, next) = self
+                .list_keys_page(&start_after, continuation.as_deref(), MAX_LIST_KEYS)
+                .await?;
+            for (key, _) in &keys {
+                if let Some((id, _)) = self.object_id(key) {
+                    newest = newest.max(id);
+                }
+            }
+            match next {
+                Some(token) => continuation = Some(token),
+                None => return Ok(newest),
+            }
+        }
+    }
+
+    /// ID and extension of an object key under the prefix.
+    fn object_id<'a>(&self, key: &'a str) -> Option<(ObjectId, &'a str)> {
+        key.strip_prefix(self.config.prefix.as_str())
+            .and_then(parse_object_name)
+    }
+
+    async fn get_bytes(&self, key: &str) -> Result<Option<Vec<u8>>, String> {
+        let response = self
+            .send(Method::GET, key, &[], &[], Body::from(""), &sha256_hex(b""))
+            .await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let bytes = ensure_success(response)
+            .await?
+            .bytes()
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(Some(bytes.to_vec()))
+    }
+
+    async fn put_bytes(
+        &self,
+        key: &str,
+        bytes: Vec<u8>,
+        extra_headers: &[(&str, String)],
+    ) -> Result<Response, String> {
+        let hash = sha256_hex(&bytes);
+        self.send(
+            Method::PUT,
+            key,
+            &[],
+            extra_headers,
+            Body::from(bytes),
+            &hash,
+        )
+        .await
+    }
+
+    /// Claim the next free ID by writing its marker only if the key does not exist yet.
+    /// Markers stay as reservations, so IDs are never reused.
+    async fn claim_id(&self) -> Result<ObjectId, String> {
+        let mut last_issued = self.last_issued.lock().await;
+        let mut candidate = match *last_issued {
+            Some(id) => id + 1,
+            None => self.newest_id().await? + 1,
+        };
+
+        for _ in 0..MAX_CLAIM_ATTEMPTS {
+            let response = self
+                .put_bytes(
+                    &self.claim_key(candidate),
+                    Vec::new(),
+                    &[("if-none-match", "*".to_string())],
+                )
+                .await?;
+            match response.status() {
+                status if status.is_success() => {
+                    *last_issued = Some(candidate);
+                    let _ = self
+                        .put_bytes(
+                            &format!("{}{}", self.config.prefix, LAST_ID_OBJECT),
+                            candidate.to_string().into_bytes(),
+                            &[],
+                        )
+                        .await;
+                    return Ok(candidate);
+                }
+                // Taken by another device in the meantime
+                StatusCode::PRECONDITION_FAILED | StatusCode::CONFLICT => candidate += 1,
+                _ => return Err(ensure_success(response).await.err().unwrap_or_default()),
+            }
+        }
+        Err("Could not allocate an object ID in the S3 bucket".to_string())
+    }
+
+    async fn store(
+        &self,
+        id: ObjectId,
+        path: &Path,
+        size: u64,
+        record: &[u8],
+        pacing: &UploadPacing,
+    ) -> Result<(), String> {
+        let file = tokio::fs::File::open(path)
+            .await
+            .map_err(|e| e.to_string())?;
+        let response = self
+            .send(
+                Method::PUT,
+                &self.data_key(id),
+                &[],
+                &[("content-length", size.to_string())],
+                Body::wrap_stream(pacing.read_chunks(file)),
+                UNSIGNED_PAYLOAD,
+            )
+            .await?;
+        ensure_success(response).await?;
+
+        // The record goes last so listings never show an object without its data
+        let response = self
+            .put_bytes(&self.record_key(id), record.to_vec(), &[])
+            .await?;
+        ensure_success(response).await.map(|_| ())
+    }
+}
+
+#[async_trait]
+impl StorageBackend for S3Backend {
+    fn kind(&self) -> &'static str {
+        "s3"
+    }
+
+    async fn upload(
+        &self,
+        path: &Path,
+        caption: String,
+        pacing: &UploadPacing,
+    ) -> Result<ObjectId, String> {
+        let size = tokio::fs::metadata(path)
+            .await
+            .map_err(|e| e.to_string())?
+            .len();
+        let record = ObjectRecord::for_file(path, size, caption);
+        let json = serde_json::to_vec(&record).map_err(|e| e.to_string())?;
+        let id = self.claim_id().await?;
+
+        if let Err(e) = self.store(id, path, size, &json, pacing).await {
+            // Release the partial upload rather than leave it to time out
+            let _ = self.delete(&[id]).await;
+            return Err(e);
+        }
+        Ok(id)
+    }
+
+    async fn download(&self, id: ObjectId, dest: &Path) -> Result<(), String> {
+        let response = self
+            .send(
+                Method::GET,
+                &self.data_key(id),
+                &[],
+                &[],
+                Body::from(""),
+                &sha256_hex(b""),
+            )
+            .await?;
+        let response = ensure_success(response).await?;
+
+        let mut file = tokio::fs::File::create(dest)
+            .await
+            .map_err(|e| e.to_string())?;
+        let mut stream = response.bytes_stream();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|e| e.to_string())?;
+            file.write_all(&chunk).await.map_err(|e| e.to_string())?;
+        }
+        file.flush().await.map_err(|e| e.to_string())
+    }
+
+    async fn delete(&self, ids: &[ObjectId]) -> Result<usize, String> {
+        let mut deleted = 0;
+        for id in ids {
+            for key in [
+                self.record_key(*id),
+                self.data_key(*id),
+                self.claim_key(*id),
+            ] {
+                let response = self
+                    .send(
+                        Method::DELETE,
+                        &key,
+                        &[],
+                        &[],
+                        Body::from(""),
+                        &sha256_hex(b""),
+                    )
+                    .await?;
+                ensure_success(response).await?;
+            }
+            deleted += 1;
+        }
+        Ok(deleted)
+    }
+
+    /// Lists keys from just past `after`, so a page costs about one listing request
+    /// plus a GET per record however many objects the bucket holds.
+    async fn list(&self, after: ObjectId, limit: usize) -> Result<Vec<StoredObject>, String> {
+        let start_after = self.start_after(after.max(0));
+        let mut objects = Vec::new();
+        // Keys of one object are adjacent: ID, whether it has a record, newest claim or
+        // data time
+        let mut group: Option<(ObjectId, bool, Option<OffsetDateTime>)> = None;
+        let mut continuation: Option<String> = None;
+        loop {
+            let wanted = (limit - objects.len()) * 3 + 3;
+            let (keys, next) = self
+                .list_keys_page(&start_after, continuation.as_deref(), wanted)
+                .await?;
+            let mut groups = Vec::new();
+            for (key, modified) in keys {
+                let Some((id, extension)) = self.object_id(&key) else {
+                    continue;
+                };
+                match &mut group {
+                    Some((current, record, newest)) if *current == id => {
+                        *record |= extension == "json";
+                        *newest = (*newest).max(modified);
+                    }
+                    _ => {
+                        groups.extend(group.take());
+                        group = Some((id, extension == "json", modified));
+                    }
+                }
+            }
+            // The last group may continue on the next page
+            if next.is_none() {
+                groups.extend(group.take());
+            }
+
+            for (id, has_record, newest) in groups {
+                if has_record {
+                    let Some(json) = self.get_bytes(&self.record_key(id)).await? else {
+                        continue;
+                    };
+                    let record: ObjectRecord = serde_json::from_slice(&json)
+                        .map_err(|e| format!("Corrupt record for object {}: {}", id, e))?;
+                    objects.push(record.into_object(id));
+                    if objects.len() == limit {
+                        return Ok(objects);
+                    }
+                } else if newest.is_none_or(|t| OffsetDateTime::now_utc() - t < CLAIM_TIMEOUT) {
+                    // Still uploading; later objects wait for it
+                    return Ok(objects);
+                }
+            }
+            match next {
+                Some(token) => continuation = Some(token),
+                None => return Ok(objects),
+            }
+        }
+    }
+}
+
+async fn ensure_success(response: Response) -> Result<Response, String> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().await.unwrap_or_default();
+    let code = xml_values(&body, "Code").into_iter().next();
+    Err(format!(
+        "S3 request failed ({}){}",
+        status,
+        code.map(|c| format!(": {}", c)).unwrap_or_default()
+    ))
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+fn hmac_sha256(key: &[u8], message: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(message.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Percent-encode per SigV4 rules. Path encoding keeps `/`; query encoding doesn't.
+fn uri_encode(value: &str, encode_slash: bool) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b'/' if !encode_slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// Build the canonical request. `headers` must be lowercase and sorted by name.
+/// Returns the request and the `SignedHeaders` list.
+fn canonical_request(
+    method: &str,
+    canonical_uri: &str,
+    canonical_query: &str,
+    headers: &[(String, String)],
+    payload_hash: &str,
+) -> (String, String) {
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value))
+        .collect();
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>()
+        .join(";");
+    let request = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        method, canonical_uri, canonical_query, canonical_headers, signed_headers, payload_hash
+    );
+    (request, signed_headers)
+}
+
+/// SigV4 signature of a canonical request made at `amz_date` (`YYYYMMDDTHHMMSSZ`).
+fn sign(secret_key: &str, region: &str, amz_date: &str, canonical_request: &str) -> String {
+    let date = &amz_date[..8];
+    let scope = format!("{}/{}/s3/aws4_request", date, region);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        sha256_hex(canonical_request.as_bytes())
+    );
+
+    let date_key = hmac_sha256(format!("AWS4{}", secret_key).as_bytes(), date);
+    let region_key = hmac_sha256(&date_key, region);
+    let service_key = hmac_sha256(&region_key, "s3");
+    let signing_key = hmac_sha256(&service_key, "aws4_request");
+    hex::encode(hmac_sha256(&signing_key, &string_to_sign))
+}
+
+/// Text of every `<tag>...</tag>` element, unescaped. S3 responses are flat enough
+/// that this avoids pulling in an XML parser.
+fn xml_values(xml: &str, tag: &str) -> Vec<String> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let mut values = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find(&open) {
+        rest = &rest[start + open.len()..];
+        let Some(end) = rest.find(&close) else {
+            break;
+        };
+        values.push(
+            rest[..end]
+                .replace("&lt;", "<")
+                .replace("&gt;", ">")
+                .replace("&quot;", "\"")
+                .replace("&apos;", "'")
+                .replace("&amp;", "&"),
+        );
+        rest = &rest[end + close.len()..];
+    }
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Round trip against a real server, e.g. `docker run -p 9000:9000 minio/minio
+    /// server /data` with `MINIO_ENDPOINT=http://127.0.0.1:9000`. Skipped when unset.
+    #[tokio::test]
+    async fn test_s3_backend_roundtrip() {
+        let Ok(endpoint) = std::env::var("MINIO_ENDPOINT") else {
+            return;
+        };
+        let env_or =
+            |name: &str, default: &str| std::env::var(name).unwrap_or_else(|_| default.to_string());
+        let nanos = OffsetDateTime::now_utc().unix_timestamp_nanos();
+        let backend = S3Backend::new(S3Config {
+            endpoint,
+            region: env_or("MINIO_REGION", "us-east-1"),
+            bucket: env_or("MINIO_BUCKET", "wanderer-test"),
+            prefix: format!("roundtrip-{}-{}", std::process::id(), nanos),
+            access_key: env_or("MINIO_ACCESS_KEY", "minioadmin"),
+            secret_key: env_or("MINIO_SECRET_KEY", "minioadmin"),
+        })
+        .unwrap();
+        // Create the bucket; it may already exist
+        let _ = backend
+            .send(Method::PUT, "", &[], &[], Body::from(""), &sha256_hex(b""))
+            .await;
+
+        let source =
+            std::env::temp_dir().join(format!("wanderer-s3-src-{}.jpg", std::process::id()));
+        std::fs::write(&source, b"photo bytes").unwrap();
+
+        let first = backend
+            .upload(&source, "caption one".to_string(), &UploadPacing::default())
+            .await
+            .unwrap();
+        let second = backend
+            .upload(&source, "caption two".to_string(), &UploadPacing::default())
+            .await
+            .unwrap();
+        assert_eq!((first, second), (1, 2));
+
+        assert_eq!(backend.list(0, 1).await.unwrap()[0].id, first);
+        let listed = backend.list(first, 10).await.unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].id, second);
+        assert_eq!(listed[0].caption, "caption two");
+        assert_eq!(listed[0].size, 11);
+        assert_eq!(listed[0].mime_type.as_deref(), Some("image/jpeg"));
+
+        let out = std::env::temp_dir().join(format!("wanderer-s3-out-{}.jpg", nanos));
+        backend.download(second, &out).await.unwrap();
+        assert_eq!(std::fs::read(&out).unwrap(), b"photo bytes");
+
+        assert_eq!(backend.delete(&[second]).await.unwrap(), 1);
+        assert_eq!(backend.list(0, 10).await.unwrap().len(), 1);
+
+        // IDs of deleted objects are not handed out again
+        let third = backend
+            .upload(
+                &source,
+                "caption three".to_string(),
+                &UploadPacing::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(third, 3);
+
+        // Pages end before an upload still being written, and resume once it is done
+        let claim = backend
+            .put_bytes(&backend.claim_key(4), Vec::new(), &[])
+            .await
+            .unwrap();
+        ensure_success(claim).await.unwrap();
+        let fifth = backend
+            .upload(
+                &source,
+                "caption five".to_string(),
+                &UploadPacing::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(fifth, 5);
+        assert!(backend.list(third, 10).await.unwrap().is_empty());
+        backend.delete(&[4]).await.unwrap();
+        let listed = backend.list(third, 10).await.unwrap();
+        assert_eq!(listed.iter().map(|o| o.id).collect::<Vec<_>>(), vec![fifth]);
+
+        backend.delete(&[first, third, fifth]).await.unwrap();
+        let counter = format!("{}{}", backend.config.prefix, LAST_ID_OBJECT);
+        let _ = backend
+            .send(
+                Method::DELETE,
+                &counter,
+                &[],
+                &[],
+                Body::from(""),
+                &sha256_hex(b""),
+            )
+            .await;
+        let _ = std::fs::remove_file(&out);
+        let _ = std::fs::remove_file(&source);
+    }
+
+    #[test]
+    fn test_sigv4_matches_aws_example() {
+        // "GET Object" example from the AWS Signature Version 4 documentation
+        let empty_hash = sha256_hex(b"");
+        let headers = vec![
+            (
+                "host".to_string(),
+                "examplebucket.s3.amazonaws.com".to_string(),
+            ),
+            ("range".to_string(), "bytes=0-9".to_string()),
+            ("x-amz-content-sha256".to_string(), empty_hash.clone()),
+            ("x-amz-date".to_string(), "20130524T000000Z".to_string()),
+        ];
+        let (request, signed_headers) =
+            canonical_request("GET", "/test.txt", "", &headers, &empty_hash);
+        assert_eq!(signed_headers, "host;range;x-amz-content-sha256;x-amz-date");
+        assert_eq!(
+            sign(
+                "wJalrXUtnFEMI/K7MDENG/bPxRfiCYEXAMPLEKEY",
+                "us-east-1",
+                "20130524T000000Z",
+                &request
+            ),
+            "f0e8bdb87c964420e857bd35b5d6ed310bd44f0170aba48dd91039c6036bdb41"
+        );
+    }
+
+    #[test]
+    fn test_uri_encode() {
+        assert_eq!(
+            uri_encode("/bucket/a b+c.json", false),
+            "/bucket/a%20b%2Bc.json"
+        );
+        assert_eq!(uri_encode("wanderer/", true), "wanderer%2F");
+    }
+
+    #[test]
+    fn test_xml_values() {
+        let xml = "<ListBucketResult><IsTruncated>false</IsTruncated>\
+                   <Contents><Key>lib/1.json</Key></Contents>\
+                   <Contents><Key>lib/a&amp;b.json</Key></Contents></ListBucketResult>";
+        assert_eq!(xml_values(xml, "Key"), vec!["lib/1.json", "lib/a&b.json"]);
+        assert_eq!(xml_values(xml, "IsTruncated"), vec!["false"]);
+        assert!(xml_values(xml, "NextContinuationToken").is_empty());
+    }
+}
@@ -0,0 +1,79 @@
+//! [`StorageBackend`] for the configured Telegram storage destination.
+
+use super::{ObjectId, StorageBackend, StoredObject, UploadPacing};
+use crate::telegram::TelegramService;
+use async_trait::async_trait;
+use grammers_client::media::Media;
+use grammers_client::message::Message;
+use std::path::Path;
+
+/// Message IDs requested per history page while listing
+const HISTORY_PAGE_SIZE: usize = 100;
+
+#[async_trait]
+impl StorageBackend for TelegramService {
+    fn kind(&self) -> &'static str {
+        "telegram"
+    }
+
+    /// The service's own bandwidth limiter and upload gate pace the upload; they are
+    /// the ones `pacing` is built from.
+    async fn upload(
+        &self,
+        path: &Path,
+        caption: String,
+        _pacing: &UploadPacing,
+    ) -> Result<ObjectId, String> {
+        self.upload_file_with_progress(&path.to_string_lossy(), caption, |_, _, _| {})
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    async fn download(&self, id: ObjectId, dest: &Path) -> Result<(), String> {
+        self.download_by_message_id(id, &dest.to_string_lossy())
+            .await
+    }
+
+    async fn delete(&self, ids: &[ObjectId]) -> Result<usize, String> {
+        self.delete_messages(ids).await
+    }
+
+    /// Pages forward through the history from `after`, so a call only reads the messages
+    /// it returns. Messages without a file are skipped.
+    async fn list(&self, after: ObjectId, limit: usize) -> Result<Vec<StoredObject>, String> {
+        let mut objects = Vec::new();
+        let mut mark = after;
+        while objects.len() < limit {
+            let ids = self.message_ids_after(mark, HISTORY_PAGE_SIZE).await?;
+            let Some(&newest) = ids.last() else {
+                break;
+            };
+            for msg in self.get_messages_by_ids(&ids).await?.into_iter().flatten() {
+                objects.extend(stored_object(&msg));
+            }
+            mark = newest;
+        }
+        objects.truncate(limit);
+        Ok(objects)
+    }
+}
+
+fn stored_object(msg: &Message) -> Option<StoredObject> {
+    let (file_name, mime_type, size) = match msg.media()? {
+        Media::Photo(_) => (None, Some("image/jpeg".to_string()), 0),
+        Media::Document(doc) => (
+            doc.name().filter(|n| !n.is_empty()).map(str::to_string),
+            doc.mime_type().map(str::to_string),
+            doc.size().max(0) as u64,
+        ),
+        _ => (None, None, 0),
+    };
+    Some(StoredObject {
+        id: msg.id(),
+        file_name,
+        mime_type,
+        size,
+        caption: msg.text().to_string(),
+        uploaded_at: msg.date().timestamp(),
+    })
+}
@@ -1,17 +1,51 @@
 use crate::cache::ThumbnailCache;
+use crate::caption::{self, CaptionMetadata};
 use crate::database::Database;
+use crate::local_originals::{self, PlainOriginal};
 use crate::media_utils;
 use crate::security::{self, RuntimeState};
-use crate::telegram::TelegramService;
+use crate::storage::{self, ObjectId, StorageBackend, StoredObject};
+use crate::telegram::{CloudEvent, TelegramService};
 use log::{debug, error, info, warn};
 use mime_guess;
+use serde::Serialize;
 use std::fs;
+use std::path::Path;
 use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tauri::{AppHandle, Emitter};
+use tokio::sync::broadcast::error::RecvError;
 use tokio::sync::Mutex;
 use tokio_util::sync::CancellationToken;
 
+/// Config key holding the newest object (message) ID that has been synced
+pub const SYNC_HIGH_WATER_MARK_KEY: &str = "sync_high_water_mark";
+
+/// Time between sync cycles when the update stream reports nothing new
+const SYNC_POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Failed cycles before a message is skipped, so one bad download can't stall sync forever
+const MAX_MESSAGE_RETRIES: u32 = 5;
+
+/// Whether the sync position may advance past a message
+#[derive(Debug, PartialEq, Eq)]
+enum MessageOutcome {
+    /// Handled (or deliberately skipped)
+    Done,
+    /// Transient failure; retry from this message next cycle
+    Retry,
+}
+
+/// Event payload when cloud copies were deleted outside the app
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CloudMissingEvent {
+    /// Items queued for upload again from their local file
+    pub requeued: usize,
+    /// Items with no local copy left, now lost
+    pub missing: usize,
+}
+
 pub struct SyncWorker {
     db: Arc<Database>,
     telegram: Arc<TelegramService>,
@@ -19,6 +53,8 @@ pub struct SyncWorker {
     app_handle: AppHandle,
     cache: ThumbnailCache,
     security_runtime: Arc<Mutex<RuntimeState>>,
+    /// `(message_id, failed_attempts)` for the message currently holding back the mark
+    stalled: std::sync::Mutex<Option<(i32, u32)>>,
 }
 
 impl SyncWorker {
@@ -37,11 +73,13 @@ impl SyncWorker {
             app_handle,
             cache,
             security_runtime,
+            stalled: std::sync::Mutex::new(None),
         }
     }
 
     pub async fn run(&self, cancel: CancellationToken) {
         info!("SyncWorker: Started.");
+        let mut events = self.telegram.subscribe_events();
         loop {
             // Check for cancellation
             if cancel.is_cancelled() {
@@ -52,10 +90,123 @@ impl SyncWorker {
             if let Err(e) = self.sync_once().await {
                 error!("SyncWorker: Error in sync loop: {}", e);
             }
-            tokio::time::sleep(tokio::time::Duration::from_secs(60)).await;
+
+            // Sleep until the next poll, waking early when new media arrives
+            let poll = tokio::time::sleep(SYNC_POLL_INTERVAL);
+            tokio::pin!(poll);
+            loop {
+                tokio::select! {
+                    _ = &mut poll => break,
+                    _ = cancel.cancelled() => break,
+                    event = events.recv() => match event {
+                        Ok(event) => {
+                            if self.handle_event(event).await {
+                                break;
+                            }
+                        }
+                        Err(RecvError::Lagged(skipped)) => {
+                            warn!("SyncWorker: Missed {} Telegram updates", skipped);
+                            break;
+                        }
+                        Err(RecvError::Closed) => {
+                            (&mut poll).await;
+                            break;
+                        }
+                    },
+                }
+            }
         }
     }
 
+    /// React to an update stream event. Returns true when a sync should run right away.
+    async fn handle_event(&self, event: CloudEvent) -> bool {
+        // Telegram events say nothing about another storage backend
+        if !matches!(storage::open_external(&self.db), Ok(None)) {
+            return false;
+        }
+        match event {
+            CloudEvent::NewMessage {
+                chat_id,
+                message_id,
+                has_media,
+            } => {
+                if has_media && self.telegram.is_destination_chat(chat_id).await {
+                    debug!("SyncWorker: New media message {}; syncing now", message_id);
+                    return true;
+                }
+            }
+            CloudEvent::MessagesDeleted {
+                channel_id,
+                message_ids,
+            } => {
+                if self.telegram.is_destination_deletion(channel_id).await {
+                    self.handle_remote_deletion(&message_ids).await;
+                }
+            }
+        }
+        false
+    }
+
+    /// Flag library items whose messages were deleted in another Telegram client and
+    /// upload them again when the local file is still there.
+    async fn handle_remote_deletion(&self, message_ids: &[i32]) {
+        let flagged = match self.db.flag_cloud_missing(message_ids) {
+            Ok(flagged) => flagged,
+            Err(e) => {
+                error!("SyncWorker: Failed to flag deleted cloud copies: {}", e);
+                return;
+            }
+        };
+        if flagged.is_empty() {
+            return;
+        }
+
+        let mut event = CloudMissingEvent {
+            requeued: 0,
+            missing: 0,
+        };
+        for item in flagged {
+            if item.is_cloud_only || !Path::new(&item.file_path).exists() {
+                warn!(
+                    "SyncWorker: Cloud copy of {} was deleted and no local copy exists",
+                    item.file_path
+                );
+                event.missing += 1;
+                continue;
+            }
+
+            // The remaining parts of a split upload are useless without the deleted one
+            let leftover: Vec<i32> = item
+                .surviving_ids
+                .iter()
+                .filter_map(|id| id.parse().ok())
+                .collect();
+            if !leftover.is_empty() {
+                if let Err(e) = self.telegram.delete_messages(&leftover).await {
+                    warn!("SyncWorker: Failed to delete leftover parts: {}", e);
+                }
+            }
+
+            match self
+                .db
+                .requeue_missing_upload(item.media_id, &item.file_path)
+            {
+                Ok(()) => {
+                    info!(
+                        "SyncWorker: Cloud copy of {} was deleted; queued for upload again",
+                        item.file_path
+                    );
+                    event.requeued += 1;
+                }
+                Err(e) => {
+                    error!("SyncWorker: Failed to requeue {}: {}", item.file_path, e);
+                    event.missing += 1;
+                }
+            }
+        }
+        let _ = self.app_handle.emit("cloud-copies-missing", event);
+    }
+
     async fn sync_once(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         let encrypted_mode = self
             .db
@@ -64,185 +215,311 @@ impl SyncWorker {
             .flatten()
             .map(|v| v.eq_ignore_ascii_case("encrypted"))
             .unwrap_or(false);
-        let master_key = if encrypted_mode {
-            let maybe = self.security_runtime.lock().await.master_key;
-            if maybe.is_none() {
+        // Uploads from before an unfinished key rotation open with the retired key
+        let read_keys = if encrypted_mode {
+            let keys = self.security_runtime.lock().await.read_keys();
+            if keys.is_empty() {
                 debug!("SyncWorker: encryption enabled but vault locked; waiting for unlock.");
                 return Ok(());
             }
-            maybe
+            keys
         } else {
-            None
+            Vec::new()
         };
 
-        if !self.telegram.has_credentials().await {
-            return Ok(());
+        let backend = storage::active_backend(&self.db, &self.telegram)?;
+        if backend.kind() == "telegram" {
+            if !self.telegram.has_credentials().await {
+                return Ok(());
+            }
+
+            if !self.telegram.is_authorized().await {
+                debug!("SyncWorker: Telegram not authorized yet; skipping sync cycle.");
+                return Ok(());
+            }
         }
 
-        if !self.telegram.is_authorized().await {
-            debug!("SyncWorker: Telegram not authorized yet; skipping sync cycle.");
-            return Ok(());
+        // One page at a time, forward from the mark
+        let mut high_water_mark = self.high_water_mark();
+        loop {
+            let objects = backend
+                .list(high_water_mark, storage::LIST_PAGE_SIZE)
+                .await?;
+            if !objects.is_empty() {
+                debug!(
+                    "SyncWorker: {} new objects in {} storage since {}",
+                    objects.len(),
+                    backend.kind(),
+                    high_water_mark
+                );
+            }
+
+            for object in &objects {
+                let outcome = self
+                    .sync_object(backend.as_ref(), object, encrypted_mode, &read_keys)
+                    .await;
+                if outcome == MessageOutcome::Retry && !self.give_up_on(object.id) {
+                    // Keep the mark before this object so the next cycle retries it.
+                    return Ok(());
+                }
+                if let Err(e) = self
+                    .db
+                    .set_config(SYNC_HIGH_WATER_MARK_KEY, &object.id.to_string())
+                {
+                    error!("SyncWorker: Failed to persist sync position: {}", e);
+                    return Ok(());
+                }
+                high_water_mark = object.id;
+            }
+            if objects.len() < storage::LIST_PAGE_SIZE {
+                return Ok(());
+            }
         }
+    }
 
-        debug!("SyncWorker: Checking for new messages...");
-        let messages = self.telegram.get_history(0, 20).await?;
-
-        for msg in messages {
-            if let Some(_media) = msg.media() {
-                let msg_id = msg.id();
-                // Naive extension guess, ideally usage mime from media
-                let mime_type = match &_media {
-                    grammers_client::media::Media::Photo(_) => "image/jpeg",
-                    grammers_client::media::Media::Document(doc) => {
-                        doc.mime_type().unwrap_or("application/octet-stream")
-                    }
-                    _ => "application/octet-stream",
-                };
+    /// Record a failed attempt; true once `msg_id` has failed too often to keep retrying.
+    fn give_up_on(&self, msg_id: ObjectId) -> bool {
+        let mut stalled = self.stalled.lock().unwrap_or_else(|e| e.into_inner());
+        let attempts = match *stalled {
+            Some((id, n)) if id == msg_id => n + 1,
+            _ => 1,
+        };
+        if attempts >= MAX_MESSAGE_RETRIES {
+            warn!(
+                "SyncWorker: Skipping message {} after {} failed attempts",
+                msg_id, attempts
+            );
+            *stalled = None;
+            true
+        } else {
+            *stalled = Some((msg_id, attempts));
+            false
+        }
+    }
 
-                // Force jpg for photos to avoid .jfif issues and ensure Watcher/AI support
-                let extension = if mime_type == "image/jpeg" {
-                    "jpg"
-                } else {
-                    mime_guess::get_mime_extensions_str(mime_type)
-                        .and_then(|exts| exts.first())
-                        .unwrap_or(&"bin")
-                };
-
-                let filename = format!("tg_{}.{}", msg_id, extension);
-                let final_path_buf = std::path::Path::new(&self.backup_path).join(&filename);
-
-                // Check if this file is marked as cloud-only in the database
-                // If so, we should NOT download it again (user explicitly removed local copy)
-                let tg_id_str = msg_id.to_string();
-                match self.db.is_cloud_only_by_telegram_id(&tg_id_str) {
-                    Ok(true) => {
-                        debug!(
-                            "SyncWorker: Skipping re-download of cloud-only media: {}",
-                            filename
-                        );
-                        continue;
+    fn high_water_mark(&self) -> ObjectId {
+        self.db
+            .get_config(SYNC_HIGH_WATER_MARK_KEY)
+            .ok()
+            .flatten()
+            .and_then(|v| v.parse::<i32>().ok())
+            .unwrap_or(0)
+    }
+
+    /// Bring one object from the storage backend into the library.
+    async fn sync_object(
+        &self,
+        backend: &dyn StorageBackend,
+        object: &StoredObject,
+        encrypted_mode: bool,
+        read_keys: &[[u8; 32]],
+    ) -> MessageOutcome {
+        let msg_id = object.id;
+
+        // Captioned uploads identify themselves, so match them to the library by hash
+        // instead of downloading again.
+        let (caption_meta, caption_key) =
+            match caption::parse_caption_with_keys(&object.caption, read_keys) {
+                Ok(Some((meta, opened_with))) => (Some(meta), opened_with),
+                Ok(None) => (None, None),
+                Err(e) => {
+                    debug!(
+                        "SyncWorker: Unreadable caption on message {}: {}",
+                        msg_id, e
+                    );
+                    (None, None)
+                }
+            };
+        if let Some(meta) = caption_meta.as_ref() {
+            if self
+                .db
+                .media_exists_by_hash(&meta.file_hash)
+                .unwrap_or(false)
+            {
+                if meta.part_index.unwrap_or(0) == 0 {
+                    let _ = self
+                        .db
+                        .link_telegram_id_if_missing(&meta.file_hash, &msg_id.to_string());
+                }
+                return MessageOutcome::Done;
+            }
+            if meta.is_split() {
+                debug!(
+                    "SyncWorker: Skipping part of split upload {} (message {})",
+                    meta.file_name, msg_id
+                );
+                return MessageOutcome::Done;
+            }
+        }
+
+        let mime_type = caption_meta
+            .as_ref()
+            .and_then(|m| m.mime_type.as_deref())
+            .or(object.mime_type.as_deref())
+            .unwrap_or("application/octet-stream");
+
+        // Force jpg for photos to avoid .jfif issues and ensure Watcher/AI support
+        let extension = if mime_type == "image/jpeg" {
+            "jpg"
+        } else {
+            mime_guess::get_mime_extensions_str(mime_type)
+                .and_then(|exts| exts.first())
+                .unwrap_or(&"bin")
+        };
+
+        let filename = match caption_meta.as_ref() {
+            Some(meta) => self.synced_file_name(msg_id, &meta.file_name),
+            None => format!("tg_{}.{}", msg_id, extension),
+        };
+        let final_path_buf = std::path::Path::new(&self.backup_path).join(&filename);
+
+        // Check if this file is marked as cloud-only in the database
+        // If so, we should NOT download it again (user explicitly removed local copy)
+        let tg_id_str = msg_id.to_string();
+        match self.db.is_cloud_only_by_telegram_id(&tg_id_str) {
+            Ok(true) => {
+                debug!(
+                    "SyncWorker: Skipping re-download of cloud-only media: {}",
+                    filename
+                );
+                return MessageOutcome::Done;
+            }
+            Err(e) => {
+                error!(
+                    "SyncWorker: Failed to check cloud-only status for {}: {}",
+                    filename, e
+                );
+                // Continue anyway to be safe? Or skip?
+                // Start conservatively: continue with download attempts if DB check fails might be safer than missing data,
+                // but if DB is broken, maybe we shouldn't spam.
+                // Let's log error and proceed to normal existence check.
+            }
+            Ok(false) => {}
+        }
+
+        if !final_path_buf.exists() {
+            info!("SyncWorker: Downloading new file {:?}", filename);
+
+            let temp_filename = format!("tg_{}.{}.tmp", msg_id, extension);
+            let temp_path_buf = std::path::Path::new(&self.backup_path).join(&temp_filename);
+
+            // Download to temp
+            if let Err(e) = backend.download(object.id, &temp_path_buf).await {
+                error!("SyncWorker: Failed to download: {}", e);
+                // Clean up temp if exists
+                let _ = fs::remove_file(&temp_path_buf);
+                return MessageOutcome::Retry;
+            }
+
+            info!(
+                "SyncWorker: Downloaded to temp {:?}. Processing...",
+                temp_filename
+            );
+
+            let (processing_path, sealed_with) = if encrypted_mode {
+                let decrypt_tmp = std::path::Path::new(&self.backup_path)
+                    .join(format!("tg_{}.{}.dec.tmp", msg_id, extension));
+                match security::decrypt_file_with_keys_if_needed(
+                    &temp_path_buf,
+                    &decrypt_tmp,
+                    read_keys,
+                ) {
+                    Ok(opened_with) => {
+                        let _ = fs::remove_file(&temp_path_buf);
+                        (decrypt_tmp, opened_with.or(caption_key))
                     }
                     Err(e) => {
                         error!(
-                            "SyncWorker: Failed to check cloud-only status for {}: {}",
-                            filename, e
+                            "SyncWorker: Failed to decrypt synced payload {:?}: {}",
+                            temp_filename, e
                         );
-                        // Continue anyway to be safe? Or skip?
-                        // Start conservatively: continue with download attempts if DB check fails might be safer than missing data,
-                        // but if DB is broken, maybe we shouldn't spam.
-                        // Let's log error and proceed to normal existence check.
-                    }
-                    Ok(false) => {}
-                }
-
-                if !final_path_buf.exists() {
-                    info!("SyncWorker: Downloading new file {:?}", filename);
-
-                    let temp_filename = format!("tg_{}.{}.tmp", msg_id, extension);
-                    let temp_path_buf =
-                        std::path::Path::new(&self.backup_path).join(&temp_filename);
-
-                    // Download to temp
-                    if let Err(e) = self
-                        .telegram
-                        .download_file(&msg, temp_path_buf.to_str().unwrap())
-                        .await
-                    {
-                        error!("SyncWorker: Failed to download: {}", e);
-                        // Clean up temp if exists
                         let _ = fs::remove_file(&temp_path_buf);
-                        continue;
+                        return MessageOutcome::Done;
                     }
+                }
+            } else {
+                (temp_path_buf.clone(), caption_key)
+            };
 
-                    info!(
-                        "SyncWorker: Downloaded to temp {:?}. Processing...",
-                        temp_filename
-                    );
-
-                    let processing_path = if encrypted_mode {
-                        let decrypt_tmp = std::path::Path::new(&self.backup_path)
-                            .join(format!("tg_{}.{}.dec.tmp", msg_id, extension));
-                        match security::decrypt_file_if_needed(
-                            &temp_path_buf,
-                            &decrypt_tmp,
-                            master_key.as_ref(),
-                        ) {
-                            Ok(_) => {
-                                let _ = fs::remove_file(&temp_path_buf);
-                                decrypt_tmp
-                            }
-                            Err(e) => {
+            // Process (Hash, Thumb, DB Insert for FINAL path), then Rename
+            if let Err(e) = self
+                .process_and_finalize_download(
+                    &processing_path,
+                    &final_path_buf,
+                    msg_id,
+                    caption_meta.as_ref(),
+                    sealed_with,
+                )
+                .await
+            {
+                error!(
+                    "SyncWorker: Failed to process downloaded file {:?}: {}",
+                    filename, e
+                );
+                // Cleanup temp on failure
+                let _ = fs::remove_file(&processing_path);
+            }
+        } else {
+            // File exists locally. Ensure DB has the Telegram ID.
+            let keys = self.security_runtime.lock().await.read_keys();
+            match local_originals::content_hash(&final_path_buf, &keys) {
+                Ok(hash) => {
+                    match self.db.media_exists_by_hash(&hash) {
+                        Ok(true) => {
+                            // Exists in DB. Update media ID if needed.
+                            let tg_id_str = msg_id.to_string();
+                            if let Err(e) = self.db.update_telegram_id(&hash, &tg_id_str) {
                                 error!(
-                                    "SyncWorker: Failed to decrypt synced payload {:?}: {}",
-                                    temp_filename, e
+                                    "SyncWorker: Failed to update telegram ID for {:?}: {}",
+                                    filename, e
                                 );
-                                let _ = fs::remove_file(&temp_path_buf);
-                                continue;
+                            } else {
+                                info!("SyncWorker: Updated existing file DB entry with Telegram ID: {:?}", filename);
                             }
                         }
-                    } else {
-                        temp_path_buf.clone()
-                    };
-
-                    // Process (Hash, Thumb, DB Insert for FINAL path), then Rename
-                    if let Err(e) = self
-                        .process_and_finalize_download(&processing_path, &final_path_buf, msg_id)
-                        .await
-                    {
-                        error!(
-                            "SyncWorker: Failed to process downloaded file {:?}: {}",
-                            filename, e
-                        );
-                        // Cleanup temp on failure
-                        let _ = fs::remove_file(&processing_path);
-                    }
-                } else {
-                    // File exists locally. Ensure DB has the Telegram ID.
-                    match media_utils::hash_file_streaming(&final_path_buf) {
-                        Ok(hash) => {
-                            match self.db.media_exists_by_hash(&hash) {
-                                Ok(true) => {
-                                    // Exists in DB. Update media ID if needed.
-                                    let tg_id_str = msg_id.to_string();
-                                    if let Err(e) = self.db.update_telegram_id(&hash, &tg_id_str) {
-                                        error!(
-                                            "SyncWorker: Failed to update telegram ID for {:?}: {}",
-                                            filename, e
-                                        );
-                                    } else {
-                                        info!("SyncWorker: Updated existing file DB entry with Telegram ID: {:?}", filename);
-                                    }
-                                }
-                                Ok(false) => {
-                                    info!("SyncWorker: Found existing file NOT in DB: {:?}. Importing...", filename);
-                                    // Re-import (Generating thumb etc.)
-                                    if let Err(e) = self
-                                        .process_and_finalize_download(
-                                            &final_path_buf,
-                                            &final_path_buf, // Same path -> process_and_finalize skips rename
-                                            msg_id,
-                                        )
-                                        .await
-                                    {
-                                        error!("SyncWorker: Failed to import existing file: {}", e);
-                                    }
-                                }
-                                Err(e) => {
-                                    error!("SyncWorker: DB check failed for {:?}: {}", filename, e);
-                                }
+                        Ok(false) => {
+                            info!(
+                                "SyncWorker: Found existing file NOT in DB: {:?}. Importing...",
+                                filename
+                            );
+                            // Re-import (Generating thumb etc.)
+                            if let Err(e) = self
+                                .process_and_finalize_download(
+                                    &final_path_buf,
+                                    &final_path_buf, // Same path -> process_and_finalize skips rename
+                                    msg_id,
+                                    caption_meta.as_ref(),
+                                    caption_key,
+                                )
+                                .await
+                            {
+                                error!("SyncWorker: Failed to import existing file: {}", e);
                             }
                         }
                         Err(e) => {
-                            error!(
-                                "SyncWorker: Failed to hash existing file {:?}: {}",
-                                filename, e
-                            );
+                            error!("SyncWorker: DB check failed for {:?}: {}", filename, e);
                         }
                     }
                 }
+                Err(e) => {
+                    error!(
+                        "SyncWorker: Failed to hash existing file {:?}: {}",
+                        filename, e
+                    );
+                }
             }
         }
-        Ok(())
+        MessageOutcome::Done
+    }
+
+    /// Local name for a captioned download: the original filename, unless it is already taken.
+    fn synced_file_name(&self, msg_id: i32, original: &str) -> String {
+        let name = media_utils::safe_file_name(original);
+        if std::path::Path::new(&self.backup_path).join(&name).exists() {
+            format!("tg_{}_{}", msg_id, name)
+        } else {
+            name
+        }
     }
 
     async fn process_and_finalize_download(
@@ -250,13 +527,19 @@ impl SyncWorker {
         temp_path: &std::path::Path,
         final_path: &std::path::Path,
         telegram_msg_id: i32,
+        caption_meta: Option<&CaptionMetadata>,
+        sealed_with: Option<usize>,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         let db_clone = self.db.clone();
         let app_handle_clone = self.app_handle.clone();
         let final_path_str = final_path.to_string_lossy().to_string();
 
+        // An existing file being imported may be an encrypted original
+        let keys = self.security_runtime.lock().await.read_keys();
+        let plain = PlainOriginal::open(temp_path, &keys)?;
+
         // 1. Hash (the temp file) using streaming hasher
-        let hash = media_utils::hash_file_streaming(temp_path)?;
+        let hash = media_utils::hash_file_streaming(plain.path())?;
 
         // 2. Check if hash exists in DB (Dedupe)
         // If it exists, we might still want to keep the file or delete it?
@@ -278,7 +561,7 @@ impl SyncWorker {
             .unwrap_or_else(|| std::path::PathBuf::from(".").join("cache"));
 
         let mut thumbnail_path =
-            match media_utils::generate_thumbnail(temp_path, &cache_dir, &hash, 300).await {
+            match media_utils::generate_thumbnail(plain.path(), &cache_dir, &hash, 300).await {
                 Ok(Some(thumb_path)) => {
                     // Insert into LRU Cache
                     self.cache.insert(hash.clone(), thumb_path.clone()).await;
@@ -324,20 +607,30 @@ impl SyncWorker {
             }
         }
 
-        // 4. Mime
-        let mime_type = mime_guess::from_path(temp_path)
-            .first_or_octet_stream()
-            .to_string();
+        // 4. Mime (the caption knows the original type; temp names don't)
+        let mime_type = caption_meta
+            .and_then(|m| m.mime_type.clone())
+            .unwrap_or_else(|| {
+                mime_guess::from_path(temp_path)
+                    .first_or_octet_stream()
+                    .to_string()
+            });
 
         let created_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
         let uploaded_at = created_at; // Mark as uploaded now
 
         // Extract Metadata
-        let metadata = if !mime_type.starts_with("video/") {
-            Some(crate::metadata::extract_metadata(temp_path))
+        let mut metadata = if !mime_type.starts_with("video/") {
+            Some(crate::metadata::extract_metadata(plain.path()))
         } else {
             None
         };
+        // The extracted date falls back to the download's mtime; the caption carries the original.
+        if let Some(date_taken) = caption_meta.and_then(|m| m.date_taken.clone()) {
+            metadata
+                .get_or_insert_with(crate::metadata::Metadata::default)
+                .date_taken = Some(date_taken);
+        }
 
         // 5. DB Insert (Use FINAL path)
         // Store telegram message ID for later deletion
@@ -353,15 +646,32 @@ impl SyncWorker {
             metadata,
         )?;
         if encrypted_mode {
-            let _ = db_clone.mark_media_encrypted_by_path(&final_path_str);
+            // Objects sealed with the retired key keep its generation, so a pending key
+            // rotation still re-encrypts them
+            let container = caption_meta.map_or(1, |m| m.container_version());
+            let key_generation = self
+                .security_runtime
+                .lock()
+                .await
+                .read_key_generation(sealed_with.unwrap_or(0));
+            let _ =
+                db_clone.mark_media_encrypted_by_path(&final_path_str, container, key_generation);
         }
 
         info!("SyncWorker: Registered synced file in DB. Renaming to final.");
 
         // 6. Finalize (Move generic -> specific)
+        drop(plain);
         if temp_path != final_path {
             fs::rename(temp_path, final_path)?;
         }
+        let key = self.security_runtime.lock().await.master_key;
+        if let Err(e) = local_originals::protect_new(&db_clone, final_path, key.as_ref()) {
+            warn!(
+                "SyncWorker: Failed to encrypt original {:?}: {}",
+                final_path, e
+            );
+        }
 
         // 7. Emit Event
         let _ = app_handle_clone.emit("media-added", ());
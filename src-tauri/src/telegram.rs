@@ -1,15 +1,27 @@
-use grammers_client::client::{LoginToken, UpdatesConfiguration};
-use grammers_client::message::InputMessage;
+use crate::album_batch::AlbumBatcher;
+use crate::rate_limit::{BandwidthLimiter, FloodGovernor, UploadGate};
+use crate::storage::UploadPacing;
+use grammers_client::client::{LoginToken, PasswordToken, UpdatesConfiguration};
+use grammers_client::media::{InputMedia, Uploaded};
+use grammers_client::message::{InputMessage, Message};
+use grammers_client::peer::Peer;
 use grammers_client::update::Update;
-use grammers_client::{Client, SenderPool};
+use grammers_client::{tl, Client, ConnectionParams, SenderPool, SignInError};
 use grammers_session::storages::SqliteSession;
+use grammers_session::types::PeerRef;
+use grammers_session::Session;
 use log::info;
+use serde::Serialize;
 use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{broadcast, Mutex, Notify};
+
+/// Buffered update events per subscriber before the oldest are dropped
+const EVENT_CHANNEL_CAPACITY: usize = 256;
 
 /// Error type for upload operations supporting rate limit detection
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum UploadError {
     /// Telegram rate limit - wait for specified seconds
     RateLimit(u64),
@@ -63,12 +75,249 @@ fn parse_flood_wait(err: &str) -> Option<u64> {
     }
 }
 
+/// Maximum IDs per `messages.getMessages` / `channels.getMessages` request
+const MESSAGES_BY_ID_BATCH: usize = 100;
+
+/// Size of each `upload.saveFilePart` chunk (the largest Telegram accepts)
+pub const UPLOAD_CHUNK_BYTES: u64 = 512 * 1024;
+
+/// Files above this size must be sent with `upload.saveBigFilePart`
+const BIG_FILE_THRESHOLD: u64 = 10 * 1024 * 1024;
+
+/// Chunks of one upload sent to Telegram at the same time
+const PARALLEL_CHUNKS: usize = 4;
+
+/// Confirmed chunks between two `on_chunk` checkpoints (8 MiB)
+const CHECKPOINT_CHUNKS: i32 = 16;
+
+/// How long a pending album waits for another finished upload to join
+const ALBUM_LINGER: Duration = Duration::from_secs(3);
+/// Longest the first upload of an album waits before the album is sent
+const ALBUM_MAX_WAIT: Duration = Duration::from_secs(30);
+
+/// A finished upload waiting in an album, with its caption
+type AlbumItem = (tl::enums::InputFile, String);
+
+/// Position of a chunked upload: the random file ID Telegram collects the chunks under
+/// and how many of them it has confirmed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkCursor {
+    pub file_id: i64,
+    pub chunks_sent: i32,
+}
+
+impl ChunkCursor {
+    pub fn fresh() -> Self {
+        Self {
+            file_id: rand::random(),
+            chunks_sent: 0,
+        }
+    }
+}
+
+/// Whether a send failed because Telegram no longer has the chunks of a resumed upload.
+pub fn is_expired_upload(err: &str) -> bool {
+    err.contains("FILE_PART")
+}
+
+/// A channel or group the library can be stored in instead of Saved Messages
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DestinationPeer {
+    /// Bot-API style dialog ID (negative for groups and channels)
+    pub id: i64,
+    pub name: String,
+    /// "channel" or "group"
+    pub kind: String,
+}
+
+/// Result of a login step that did not fail.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "step", rename_all = "camelCase")]
+pub enum SignInOutcome {
+    /// Login finished; `name` is the account's display name
+    SignedIn { name: String },
+    /// The account has a cloud password; finish with `check_password`
+    PasswordRequired { hint: Option<String> },
+}
+
+/// State of a QR code login.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "step", rename_all = "camelCase")]
+pub enum QrLoginStep {
+    /// Show `url` (a `tg://login` link, also rendered as `svg`) until `expires`
+    /// (unix seconds)
+    Token {
+        url: String,
+        svg: String,
+        expires: i64,
+    },
+    SignedIn {
+        name: String,
+    },
+    /// The phone accepted the code but the account has a cloud password
+    PasswordRequired {
+        hint: Option<String>,
+    },
+}
+
+/// Seconds before a QR token expires at which a fresh one is exported
+const QR_REFRESH_MARGIN_SECS: i64 = 5;
+
+/// `tg://login` link for an exported login token, scanned from Settings > Devices on
+/// a logged-in phone.
+fn qr_login_url(token: &[u8]) -> String {
+    use base64::Engine;
+    format!(
+        "tg://login?token={}",
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(token)
+    )
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+fn qr_svg(url: &str) -> Result<String, String> {
+    let code = qrcode::QrCode::new(url.as_bytes()).map_err(|e| e.to_string())?;
+    Ok(code
+        .render::<qrcode::render::svg::Color>()
+        .min_dimensions(256, 256)
+        .build())
+}
+
+/// Why a login step failed, serialized for the UI as `{ kind, message, retryAfterSecs }`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoginErrorKind {
+    NotConnected,
+    /// No code was requested, or the login it belonged to already finished
+    NoPendingLogin,
+    InvalidCode,
+    CodeExpired,
+    InvalidPassword,
+    /// The phone number has no Telegram account yet
+    SignUpRequired,
+    FloodWait,
+    Other,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoginError {
+    pub kind: LoginErrorKind,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_after_secs: Option<u64>,
+}
+
+impl LoginError {
+    pub fn new(kind: LoginErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            retry_after_secs: None,
+        }
+    }
+
+    fn sign_up_required() -> Self {
+        Self::new(
+            LoginErrorKind::SignUpRequired,
+            "This phone number has no Telegram account. Sign up in an official app first.",
+        )
+    }
+
+    /// Classify a failed `sign_in` or `check_password` call.
+    fn from_sign_in(err: SignInError) -> Self {
+        match err {
+            SignInError::InvalidCode => {
+                Self::new(LoginErrorKind::InvalidCode, "The login code is incorrect")
+            }
+            SignInError::InvalidPassword => {
+                Self::new(LoginErrorKind::InvalidPassword, "The password is incorrect")
+            }
+            SignInError::SignUpRequired { .. } => Self::sign_up_required(),
+            other => Self::from_message(other.to_string()),
+        }
+    }
+
+    /// Classify a failed login request by its RPC error text.
+    fn from_message(message: String) -> Self {
+        if message.contains("PHONE_CODE_EXPIRED") {
+            return Self::new(
+                LoginErrorKind::CodeExpired,
+                "The login code has expired. Request a new one.",
+            );
+        }
+        match parse_flood_wait(&message) {
+            Some(secs) => Self {
+                retry_after_secs: Some(secs),
+                ..Self::new(
+                    LoginErrorKind::FloodWait,
+                    format!("Too many attempts. Try again in {} seconds.", secs),
+                )
+            },
+            None => Self::new(LoginErrorKind::Other, message),
+        }
+    }
+}
+
+impl std::fmt::Display for LoginError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Changes to stored messages reported by the Telegram update stream.
+#[derive(Debug, Clone)]
+pub enum CloudEvent {
+    /// A message arrived in `chat_id` (bot-API dialog ID)
+    NewMessage {
+        chat_id: i64,
+        message_id: i32,
+        has_media: bool,
+    },
+    /// Messages were deleted. `channel_id` (bot-API dialog ID) is set for channels and
+    /// supergroups; other deletions carry only account-wide message IDs.
+    MessagesDeleted {
+        channel_id: Option<i64>,
+        message_ids: Vec<i32>,
+    },
+}
+
 pub struct TelegramService {
     client: Mutex<Option<Client>>,
     pending_token: Mutex<Option<LoginToken>>, // Store token between request_code and sign_in
+    /// SRP parameters for the cloud password step
+    pending_password: Mutex<Option<PasswordToken>>,
+    /// Signalled by `updateLoginToken` when a phone accepts the current QR code
+    qr_accepted: Arc<Notify>,
+    /// Expiry (unix seconds) of the QR token currently shown
+    qr_expires: Mutex<Option<i64>>,
+    /// Session storage of the current client, for switching the home DC
+    session: Mutex<Option<Arc<SqliteSession>>>,
     backend_handle: Mutex<Option<tokio::task::JoinHandle<()>>>,
     update_handle: Mutex<Option<tokio::task::JoinHandle<()>>>,
     credentials: Mutex<Option<(i32, String)>>,
+    /// Dialog ID of the storage peer; `None` means Saved Messages
+    destination: Mutex<Option<i64>>,
+    /// Resolved reference for `destination`, keyed by the destination it was resolved for
+    peer_cache: Mutex<Option<(Option<i64>, PeerRef)>>,
+    /// FLOOD_WAIT pause shared by every request made through this service
+    governor: Arc<FloodGovernor>,
+    /// Upload rate cap shared by parallel uploads
+    bandwidth: Arc<BandwidthLimiter>,
+    /// Closed while uploads are paused or outside their schedule
+    upload_gate: Arc<UploadGate>,
+    /// Fan-out of update stream events to interested workers
+    events: broadcast::Sender<CloudEvent>,
+    /// SOCKS5 URL that new connections dial through
+    proxy_url: Mutex<Option<String>>,
+    /// Uploads waiting to be sent together as one grouped message
+    album: AlbumBatcher<AlbumItem, Result<i32, UploadError>>,
 }
 
 impl TelegramService {
@@ -76,10 +325,78 @@ impl TelegramService {
         Self {
             client: Mutex::new(None),
             pending_token: Mutex::new(None),
+            pending_password: Mutex::new(None),
+            qr_accepted: Arc::new(Notify::new()),
+            qr_expires: Mutex::new(None),
+            session: Mutex::new(None),
             backend_handle: Mutex::new(None),
             update_handle: Mutex::new(None),
             credentials: Mutex::new(None),
+            destination: Mutex::new(None),
+            peer_cache: Mutex::new(None),
+            governor: Arc::new(FloodGovernor::new()),
+            bandwidth: Arc::new(BandwidthLimiter::new()),
+            upload_gate: Arc::new(UploadGate::new()),
+            events: broadcast::channel(EVENT_CHANNEL_CAPACITY).0,
+            proxy_url: Mutex::new(None),
+            album: AlbumBatcher::new(ALBUM_LINGER, ALBUM_MAX_WAIT),
+        }
+    }
+
+    /// A service for another account that shares this one's credentials, proxy,
+    /// bandwidth cap and upload gate. FLOOD_WAIT pauses stay per account.
+    pub async fn linked(&self) -> Self {
+        let service = Self {
+            bandwidth: self.bandwidth.clone(),
+            upload_gate: self.upload_gate.clone(),
+            ..Self::new()
+        };
+        *service.credentials.lock().await = self.credentials.lock().await.clone();
+        *service.proxy_url.lock().await = self.proxy_url.lock().await.clone();
+        service
+    }
+
+    /// Receive message events from the update stream of the current and later sessions.
+    pub fn subscribe_events(&self) -> broadcast::Receiver<CloudEvent> {
+        self.events.subscribe()
+    }
+
+    pub fn governor(&self) -> Arc<FloodGovernor> {
+        self.governor.clone()
+    }
+
+    pub fn bandwidth(&self) -> Arc<BandwidthLimiter> {
+        self.bandwidth.clone()
+    }
+
+    pub fn upload_gate(&self) -> Arc<UploadGate> {
+        self.upload_gate.clone()
+    }
+
+    /// The cap and pause as applied to uploads sent to other storage backends.
+    pub fn upload_pacing(&self) -> UploadPacing {
+        UploadPacing::new(self.bandwidth(), self.upload_gate())
+    }
+
+    /// Wait out any FLOOD_WAIT pause, then clone the connected client.
+    /// Cloning instead of holding the lock lets several transfers run at once.
+    async fn client(&self) -> Result<Client, String> {
+        self.governor.wait().await;
+        self.client
+            .lock()
+            .await
+            .clone()
+            .ok_or_else(|| "Client not connected".to_string())
+    }
+
+    /// Convert a request error to a string, pausing all traffic if it is a FLOOD_WAIT.
+    fn rpc_error(&self, err: impl std::fmt::Display) -> String {
+        let err_str = err.to_string();
+        if let Some(secs) = parse_flood_wait(&err_str) {
+            log::warn!("Telegram FLOOD_WAIT: pausing all requests for {}s", secs);
+            self.governor.pause_for(secs);
         }
+        err_str
     }
 
     pub async fn set_credentials(&self, api_id: i32, api_hash: String) {
@@ -94,6 +411,128 @@ impl TelegramService {
         self.credentials.lock().await.is_some()
     }
 
+    /// Proxy for connections opened from now on; `None` dials directly.
+    pub async fn set_proxy_url(&self, url: Option<String>) {
+        *self.proxy_url.lock().await = url;
+    }
+
+    pub async fn is_connected(&self) -> bool {
+        self.client.lock().await.is_some()
+    }
+
+    /// Set the storage peer used by every upload, download, delete and history call.
+    /// `None` selects Saved Messages.
+    pub async fn set_destination(&self, peer_id: Option<i64>) {
+        *self.destination.lock().await = peer_id;
+        *self.peer_cache.lock().await = None;
+    }
+
+    pub async fn destination(&self) -> Option<i64> {
+        *self.destination.lock().await
+    }
+
+    /// Whether `chat_id` (bot-API dialog ID) is the storage destination.
+    pub async fn is_destination_chat(&self, chat_id: i64) -> bool {
+        if let Some(destination) = *self.destination.lock().await {
+            return destination == chat_id;
+        }
+        // Saved Messages is the chat with ourselves
+        let Ok(client) = self.client().await else {
+            return false;
+        };
+        match self.resolve_destination(&client).await {
+            Ok(peer) => peer.id.bot_api_dialog_id() == chat_id,
+            Err(_) => false,
+        }
+    }
+
+    /// Whether a deletion reported for `channel_id` can affect the storage destination.
+    /// Message IDs outside channels are unique per account, so those deletions always may.
+    pub async fn is_destination_deletion(&self, channel_id: Option<i64>) -> bool {
+        match *self.destination.lock().await {
+            Some(peer_id) if peer_id <= -1_000_000_000_000 => channel_id == Some(peer_id),
+            _ => channel_id.is_none(),
+        }
+    }
+
+    /// Resolve the configured storage peer to a reference usable in requests.
+    /// The result is cached until the destination changes or the session ends.
+    async fn resolve_destination(&self, client: &Client) -> Result<PeerRef, String> {
+        let destination = *self.destination.lock().await;
+        if let Some((cached_for, peer)) = self.peer_cache.lock().await.as_ref() {
+            if *cached_for == destination {
+                return Ok(*peer);
+            }
+        }
+
+        let peer = Self::lookup_destination(client, destination).await?;
+        *self.peer_cache.lock().await = Some((destination, peer));
+        Ok(peer)
+    }
+
+    async fn lookup_destination(
+        client: &Client,
+        destination: Option<i64>,
+    ) -> Result<PeerRef, String> {
+        let Some(peer_id) = destination else {
+            let me = client.get_me().await.map_err(|e| e.to_string())?;
+            return me
+                .to_ref()
+                .ok_or_else(|| "Could not get peer reference".to_string());
+        };
+
+        let mut dialogs = client.iter_dialogs();
+        while let Some(dialog) = dialogs.next().await.map_err(|e| e.to_string())? {
+            let peer = dialog.peer();
+            if peer.id().bot_api_dialog_id() == peer_id {
+                return peer
+                    .to_ref()
+                    .ok_or_else(|| "Could not get peer reference".to_string());
+            }
+        }
+
+        Err(format!(
+            "Storage destination {} not found among your chats",
+            peer_id
+        ))
+    }
+
+    /// List the channels and groups the signed-in user can pick as storage destination.
+    pub async fn list_destination_peers(&self) -> Result<Vec<DestinationPeer>, String> {
+        let client = self.client().await?;
+
+        let mut peers = Vec::new();
+        let mut dialogs = client.iter_dialogs();
+        while let Some(dialog) = dialogs.next().await.map_err(|e| self.rpc_error(e))? {
+            let peer = dialog.peer();
+            let kind = match peer {
+                Peer::Channel(_) => "channel",
+                Peer::Group(_) => "group",
+                Peer::User(_) => continue,
+            };
+            peers.push(DestinationPeer {
+                id: peer.id().bot_api_dialog_id(),
+                name: peer.name().unwrap_or_default().to_string(),
+                kind: kind.to_string(),
+            });
+        }
+
+        Ok(peers)
+    }
+
+    /// Build a link that opens `message_id` in the Telegram client.
+    pub async fn share_link(&self, message_id: i32) -> String {
+        match *self.destination.lock().await {
+            // Channel and supergroup dialog IDs are -100<bare id>
+            Some(peer_id) if peer_id <= -1_000_000_000_000 => {
+                let bare_id = -peer_id - 1_000_000_000_000;
+                format!("https://t.me/c/{}/{}", bare_id, message_id)
+            }
+            // Using the "me" domain which represents Saved Messages
+            _ => format!("tg://openmessage?user_id=me&message_id={}", message_id),
+        }
+    }
+
     pub async fn connect(&self, app_data_dir: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
         let (api_id, _api_hash) = self
             .credentials
@@ -102,7 +541,7 @@ impl TelegramService {
             .clone()
             .ok_or("Telegram API credentials not configured")?;
 
-        let session_path = app_data_dir.join("session.db");
+        let session_path = app_data_dir.join(crate::session_vault::SESSION_FILE);
         info!(
             "Connecting to Telegram using session at: {:?}",
             session_path
@@ -113,7 +552,19 @@ impl TelegramService {
 
         // 2. Initialize SenderPool
         let session_handle = Arc::new(session);
-        let sender_pool = SenderPool::new(session_handle, api_id);
+        *self.session.lock().await = Some(session_handle.clone());
+        let proxy_url = self.proxy_url.lock().await.clone();
+        if proxy_url.is_some() {
+            info!("Connecting to Telegram through a proxy");
+        }
+        let sender_pool = SenderPool::with_configuration(
+            session_handle,
+            api_id,
+            ConnectionParams {
+                proxy_url,
+                ..Default::default()
+            },
+        );
 
         // 3. Initialize Client
         let client = Client::new(&sender_pool);
@@ -133,19 +584,39 @@ impl TelegramService {
         // 6. Spawn update listener
         let mut update_stream = client.stream_updates(updates, UpdatesConfiguration::default());
 
+        let events = self.events.clone();
+        let qr_accepted = self.qr_accepted.clone();
         let updates_handle = tokio::spawn(async move {
             while let Ok(update) = update_stream.next().await {
-                match update {
-                    Update::NewMessage(message) => {
-                        info!("New message: {:?}", message.text());
+                // Sending only fails when nobody is subscribed
+                let _ = match update {
+                    Update::NewMessage(message) => events.send(CloudEvent::NewMessage {
+                        chat_id: message.peer_id().bot_api_dialog_id(),
+                        message_id: message.id(),
+                        has_media: message.media().is_some(),
+                    }),
+                    Update::MessageDeleted(deletion) => events.send(CloudEvent::MessagesDeleted {
+                        channel_id: deletion
+                            .channel_id()
+                            .map(|bare_id| -1_000_000_000_000 - bare_id),
+                        message_ids: deletion.messages().to_vec(),
+                    }),
+                    Update::Raw(raw) if matches!(raw.raw, tl::enums::Update::LoginToken) => {
+                        qr_accepted.notify_one();
+                        continue;
                     }
-                    _ => {}
-                }
+                    _ => continue,
+                };
             }
         });
 
-        *self.backend_handle.lock().await = Some(runner_handle);
-        *self.update_handle.lock().await = Some(updates_handle);
+        // Reconnecting (e.g. after a proxy change) replaces the previous connection
+        if let Some(previous) = self.backend_handle.lock().await.replace(runner_handle) {
+            previous.abort();
+        }
+        if let Some(previous) = self.update_handle.lock().await.replace(updates_handle) {
+            previous.abort();
+        }
 
         *self.client.lock().await = Some(client);
         info!("Connected to Telegram");
@@ -178,36 +649,188 @@ impl TelegramService {
         // pass api_hash now required
         let token = client.request_login_code(phone, &api_hash).await?;
         *self.pending_token.lock().await = Some(token);
+        *self.pending_password.lock().await = None;
         Ok(())
     }
 
-    pub async fn sign_in(&self, code: &str) -> Result<String, String> {
+    /// Submit the login code. Accounts with two-step verification answer with
+    /// `PasswordRequired`; a wrong code can be retried without requesting a new one.
+    pub async fn sign_in(&self, code: &str) -> Result<SignInOutcome, LoginError> {
         let client_guard = self.client.lock().await;
         let client = client_guard
             .as_ref()
-            .ok_or("Client not connected".to_string())?;
+            .ok_or_else(|| LoginError::new(LoginErrorKind::NotConnected, "Client not connected"))?;
 
         let mut token_guard = self.pending_token.lock().await;
-        let token = token_guard
-            .take()
-            .ok_or("No pending login request".to_string())?;
+        let token = token_guard.as_ref().ok_or_else(|| {
+            LoginError::new(LoginErrorKind::NoPendingLogin, "No pending login request")
+        })?;
 
-        match client.sign_in(&token, code).await {
+        match client.sign_in(token, code).await {
             Ok(user) => {
                 info!("Signed in as: {}", user.full_name());
+                *token_guard = None;
+                Ok(SignInOutcome::SignedIn {
+                    name: user.full_name(),
+                })
+            }
+            Err(SignInError::PasswordRequired(password_token)) => {
+                info!("Account has two-step verification, waiting for password");
+                let hint = password_token.hint().map(str::to_string);
+                *self.pending_password.lock().await = Some(password_token);
+                Ok(SignInOutcome::PasswordRequired { hint })
+            }
+            Err(e) => {
+                let err = LoginError::from_sign_in(e);
+                // Only a mistyped code leaves the login token usable
+                if err.kind != LoginErrorKind::InvalidCode {
+                    *token_guard = None;
+                }
+                Err(err)
+            }
+        }
+    }
+
+    /// Finish a login that stopped at `PasswordRequired` by proving the cloud password
+    /// over SRP.
+    pub async fn check_password(&self, password: &str) -> Result<String, LoginError> {
+        let client_guard = self.client.lock().await;
+        let client = client_guard
+            .as_ref()
+            .ok_or_else(|| LoginError::new(LoginErrorKind::NotConnected, "Client not connected"))?;
+
+        let mut password_guard = self.pending_password.lock().await;
+        let password_token = password_guard.take().ok_or_else(|| {
+            LoginError::new(
+                LoginErrorKind::NoPendingLogin,
+                "No pending password request",
+            )
+        })?;
+
+        match client.check_password(password_token, password).await {
+            Ok(user) => {
+                info!("Signed in as: {}", user.full_name());
+                *self.pending_token.lock().await = None;
                 Ok(user.full_name())
             }
-            // Error handling remains similar
             Err(e) => {
-                // If failed, we might need to put the token back if it's retryable?
-                // Grammers consumes token on use usually.
-                // For now, assume we need to request code again on failure or handle specific errors.
-                // Assuming token is consumed.
-                Err(e.to_string())
+                let err = LoginError::from_sign_in(e);
+                if err.kind == LoginErrorKind::InvalidPassword {
+                    // The SRP parameters are single-use; fetch fresh ones so the user
+                    // can retry the password
+                    *password_guard = client.get_password_information().await.ok();
+                }
+                Err(err)
             }
         }
     }
 
+    /// Start a QR code login, connecting first if needed.
+    pub async fn start_qr_login(&self, app_data_dir: PathBuf) -> Result<QrLoginStep, LoginError> {
+        let needs_connect = { self.client.lock().await.is_none() };
+        if needs_connect {
+            self.connect(app_data_dir)
+                .await
+                .map_err(|e| LoginError::new(LoginErrorKind::NotConnected, e.to_string()))?;
+        }
+        *self.pending_token.lock().await = None;
+        *self.pending_password.lock().await = None;
+        self.export_login_token().await
+    }
+
+    /// Wait until a phone accepts the QR code or the code is about to expire, then return
+    /// the outcome or a fresh code to show.
+    pub async fn wait_qr_login(&self) -> Result<QrLoginStep, LoginError> {
+        let expires = self.qr_expires.lock().await.ok_or_else(|| {
+            LoginError::new(LoginErrorKind::NoPendingLogin, "No pending QR login")
+        })?;
+        let refresh_in = (expires - QR_REFRESH_MARGIN_SECS - unix_now()).max(1) as u64;
+        tokio::select! {
+            _ = self.qr_accepted.notified() => info!("QR login token accepted"),
+            _ = tokio::time::sleep(Duration::from_secs(refresh_in)) => {}
+        }
+        self.export_login_token().await
+    }
+
+    /// Call `auth.exportLoginToken`, which returns a new token until a phone accepts one
+    /// and the authorization afterwards. Accounts homed in another DC finish there.
+    async fn export_login_token(&self) -> Result<QrLoginStep, LoginError> {
+        let client =
+            self.client.lock().await.clone().ok_or_else(|| {
+                LoginError::new(LoginErrorKind::NotConnected, "Client not connected")
+            })?;
+        let (api_id, api_hash) = self.credentials.lock().await.clone().ok_or_else(|| {
+            LoginError::new(
+                LoginErrorKind::NotConnected,
+                "Telegram API credentials not configured",
+            )
+        })?;
+
+        let request = tl::functions::auth::ExportLoginToken {
+            api_id,
+            api_hash,
+            except_ids: Vec::new(),
+        };
+        let result = match client.invoke(&request).await {
+            Ok(tl::enums::auth::LoginToken::MigrateTo(migrate)) => {
+                info!("QR login continues in DC {}", migrate.dc_id);
+                let import = tl::functions::auth::ImportLoginToken {
+                    token: migrate.token,
+                };
+                let result = client.invoke_in_dc(migrate.dc_id, &import).await;
+                if result.is_ok() {
+                    if let Some(session) = self.session.lock().await.as_ref() {
+                        session.set_home_dc_id(migrate.dc_id).await;
+                    }
+                }
+                result
+            }
+            other => other,
+        };
+
+        match result {
+            Ok(tl::enums::auth::LoginToken::Token(token)) => {
+                let url = qr_login_url(&token.token);
+                let svg = qr_svg(&url).map_err(|e| LoginError::new(LoginErrorKind::Other, e))?;
+                *self.qr_expires.lock().await = Some(token.expires as i64);
+                Ok(QrLoginStep::Token {
+                    url,
+                    svg,
+                    expires: token.expires as i64,
+                })
+            }
+            Ok(tl::enums::auth::LoginToken::Success(success)) => {
+                *self.qr_expires.lock().await = None;
+                if let tl::enums::auth::Authorization::SignUpRequired(_) = success.authorization {
+                    return Err(LoginError::sign_up_required());
+                }
+                let name = client
+                    .get_me()
+                    .await
+                    .map(|me| me.full_name())
+                    .map_err(|e| LoginError::from_message(e.to_string()))?;
+                info!("Signed in with QR code as: {}", name);
+                Ok(QrLoginStep::SignedIn { name })
+            }
+            Ok(tl::enums::auth::LoginToken::MigrateTo(_)) => Err(LoginError::new(
+                LoginErrorKind::Other,
+                "Telegram asked to switch data centers twice",
+            )),
+            Err(e) if e.to_string().contains("SESSION_PASSWORD_NEEDED") => {
+                *self.qr_expires.lock().await = None;
+                info!("Account has two-step verification, waiting for password");
+                let password_token = client
+                    .get_password_information()
+                    .await
+                    .map_err(|e| LoginError::from_message(e.to_string()))?;
+                let hint = password_token.hint().map(str::to_string);
+                *self.pending_password.lock().await = Some(password_token);
+                Ok(QrLoginStep::PasswordRequired { hint })
+            }
+            Err(e) => Err(LoginError::from_message(self.rpc_error(e))),
+        }
+    }
+
     pub async fn get_me(&self) -> Result<String, String> {
         let client_guard = self.client.lock().await;
         let client = client_guard
@@ -232,29 +855,23 @@ impl TelegramService {
             false
         }
     }
-    pub async fn upload_file(&self, path: &str) -> Result<(), String> {
-        let client_guard = self.client.lock().await;
-        // Check connection
-        let client = client_guard.as_ref().ok_or("Client not connected")?;
-
-        // Upload logic
-        // We reuse the client instance
-        let uploaded_file = client.upload_file(path).await.map_err(|e| e.to_string())?;
+    pub async fn upload_file(&self, path: &str, caption: String) -> Result<(), String> {
+        let client = self.client().await?;
 
-        // Send to self (Saved Messages)
-        let me = client.get_me().await.map_err(|e| e.to_string())?;
+        let uploaded_file = client
+            .upload_file(path)
+            .await
+            .map_err(|e| self.rpc_error(e))?;
 
-        let message = InputMessage::new()
-            .text("Uploaded via Wander(er)")
-            .file(uploaded_file);
+        // Send to the storage destination (Saved Messages by default)
+        let peer = self.resolve_destination(&client).await?;
 
-        // Ensure we convert me to a PeerRef
-        let peer = me.to_ref().ok_or("Could not get peer reference")?;
+        let message = InputMessage::new().text(caption).file(uploaded_file);
 
         client
             .send_message(peer, message)
             .await
-            .map_err(|e| e.to_string())?;
+            .map_err(|e| self.rpc_error(e))?;
         Ok(())
     }
 
@@ -263,128 +880,430 @@ impl TelegramService {
     pub async fn upload_file_with_progress<F>(
         &self,
         path: &str,
+        caption: String,
         on_progress: F,
     ) -> Result<i32, UploadError>
     where
         F: Fn(u64, u64, f64) + Send + Sync + 'static,
     {
-        use crate::progress_stream::ProgressStream;
-        use std::sync::Arc;
-        use tokio::fs::File;
-        use tokio::io::BufReader;
-
-        let client_guard = self.client.lock().await;
-        let client = client_guard
-            .as_ref()
-            .ok_or_else(|| UploadError::Other("Client not connected".to_string()))?;
-
-        // Get file metadata
-        let file = File::open(path)
-            .await
-            .map_err(|e| UploadError::Other(e.to_string()))?;
-        let metadata = file
-            .metadata()
+        let total_bytes = tokio::fs::metadata(path)
             .await
-            .map_err(|e| UploadError::Other(e.to_string()))?;
-        let total_bytes = metadata.len();
+            .map_err(|e| UploadError::Other(e.to_string()))?
+            .len();
         let file_name = std::path::Path::new(path)
             .file_name()
             .and_then(|n| n.to_str())
             .unwrap_or("file")
             .to_string();
 
-        // Create progress-wrapped stream
-        let callback = Arc::new(on_progress);
-        let reader = BufReader::new(file);
-        let mut progress_stream = ProgressStream::new(reader, total_bytes, callback);
+        self.upload_range_with_progress(
+            path,
+            0,
+            total_bytes,
+            file_name,
+            caption,
+            ChunkCursor::fresh(),
+            |_| {},
+            on_progress,
+        )
+        .await
+    }
 
-        // Upload using stream - check for rate limit errors
-        let uploaded_file = match client
-            .upload_stream(&mut progress_stream, total_bytes as usize, file_name)
+    /// Upload `len` bytes of a file starting at `offset` and send it as a standalone
+    /// document. See [`Self::upload_range`] for how chunks are resumed.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn upload_range_with_progress<C, F>(
+        &self,
+        path: &str,
+        offset: u64,
+        len: u64,
+        file_name: String,
+        caption: String,
+        cursor: ChunkCursor,
+        on_chunk: C,
+        on_progress: F,
+    ) -> Result<i32, UploadError>
+    where
+        C: FnMut(ChunkCursor),
+        F: Fn(u64, u64, f64) + Send + Sync + 'static,
+    {
+        let input_file = self
+            .upload_range(path, offset, len, file_name, cursor, on_chunk, on_progress)
+            .await?;
+        self.send_uploaded(input_file, caption).await
+    }
+
+    /// Upload `len` bytes of a file starting at `offset` without sending a message,
+    /// returning the file to attach with [`Self::send_uploaded`] or [`Self::send_grouped`].
+    ///
+    /// The range is sent in [`UPLOAD_CHUNK_BYTES`] chunks starting after
+    /// `cursor.chunks_sent`, [`PARALLEL_CHUNKS`] at a time. `on_chunk` is called every
+    /// [`CHECKPOINT_CHUNKS`] confirmed chunks and once all are confirmed, so callers can
+    /// persist the cursor and resume the upload after a restart.
+    /// Progress is reported for the whole range, including chunks sent earlier.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn upload_range<C, F>(
+        &self,
+        path: &str,
+        offset: u64,
+        len: u64,
+        file_name: String,
+        cursor: ChunkCursor,
+        mut on_chunk: C,
+        on_progress: F,
+    ) -> Result<tl::enums::InputFile, UploadError>
+    where
+        C: FnMut(ChunkCursor),
+        F: Fn(u64, u64, f64) + Send + Sync + 'static,
+    {
+        use crate::progress_stream::ProgressStream;
+        use futures_util::stream::{FuturesUnordered, StreamExt};
+        use std::collections::BTreeSet;
+        use tokio::fs::File;
+        use tokio::io::{AsyncReadExt, AsyncSeekExt, BufReader};
+
+        let client = self.client().await.map_err(UploadError::Other)?;
+
+        let total_chunks = len.div_ceil(UPLOAD_CHUNK_BYTES).max(1) as i32;
+        let big = len > BIG_FILE_THRESHOLD;
+        let mut cursor = if cursor.file_id == 0 || cursor.chunks_sent > total_chunks {
+            ChunkCursor::fresh()
+        } else {
+            cursor
+        };
+        let resumed_bytes = (cursor.chunks_sent as u64 * UPLOAD_CHUNK_BYTES).min(len);
+        if resumed_bytes > 0 {
+            info!(
+                "Resuming upload of {} at chunk {}/{}",
+                file_name, cursor.chunks_sent, total_chunks
+            );
+        }
+
+        let mut file = File::open(path)
             .await
-        {
-            Ok(f) => f,
-            Err(e) => {
-                let err_str = e.to_string();
-                if let Some(secs) = parse_flood_wait(&err_str) {
-                    return Err(UploadError::RateLimit(secs));
-                }
-                return Err(UploadError::Other(err_str));
+            .map_err(|e| UploadError::Other(e.to_string()))?;
+        if offset + resumed_bytes > 0 {
+            file.seek(std::io::SeekFrom::Start(offset + resumed_bytes))
+                .await
+                .map_err(|e| UploadError::Other(e.to_string()))?;
+        }
+
+        // Create progress-wrapped stream limited to the rest of the range
+        let remaining = len - resumed_bytes;
+        let callback: crate::progress_stream::ProgressCallback =
+            Arc::new(move |bytes, _total, speed| on_progress(resumed_bytes + bytes, len, speed));
+        callback(0, remaining, 0.0);
+        let reader = BufReader::new(file).take(remaining);
+        let mut progress_stream =
+            ProgressStream::new(reader, remaining, callback).with_limiter(self.bandwidth.clone());
+
+        // Chunks may be confirmed out of order; the cursor only covers the unbroken run
+        // from the start, so a resumed upload never skips a chunk
+        let mut in_flight = FuturesUnordered::new();
+        let mut confirmed = BTreeSet::new();
+        let mut next_chunk = cursor.chunks_sent;
+        let mut checkpointed = cursor.chunks_sent;
+        while next_chunk < total_chunks || !in_flight.is_empty() {
+            if next_chunk < total_chunks && in_flight.len() < PARALLEL_CHUNKS {
+                let chunk_start = next_chunk as u64 * UPLOAD_CHUNK_BYTES;
+                let chunk_len = UPLOAD_CHUNK_BYTES.min(len - chunk_start) as usize;
+                let mut bytes = vec![0u8; chunk_len];
+                progress_stream
+                    .read_exact(&mut bytes)
+                    .await
+                    .map_err(|e| UploadError::Other(e.to_string()))?;
+
+                // Pausing uploads or a FLOOD_WAIT hit elsewhere holds this upload between
+                // chunks
+                self.upload_gate.wait_open().await;
+                self.governor.wait().await;
+                in_flight.push(self.save_chunk(
+                    &client,
+                    cursor.file_id,
+                    next_chunk,
+                    big.then_some(total_chunks),
+                    bytes,
+                ));
+                next_chunk += 1;
+                continue;
+            }
+
+            let Some((chunk, saved)) = in_flight.next().await else {
+                break;
+            };
+            if !saved? {
+                return Err(UploadError::Other(format!(
+                    "Telegram did not accept chunk {} of {}",
+                    chunk + 1,
+                    total_chunks
+                )));
+            }
+            confirmed.insert(chunk);
+            while confirmed.remove(&cursor.chunks_sent) {
+                cursor.chunks_sent += 1;
+            }
+            if cursor.chunks_sent - checkpointed >= CHECKPOINT_CHUNKS
+                || cursor.chunks_sent == total_chunks
+            {
+                checkpointed = cursor.chunks_sent;
+                on_chunk(cursor);
+            }
+        }
+
+        Ok(if big {
+            tl::types::InputFileBig {
+                id: cursor.file_id,
+                parts: total_chunks,
+                name: file_name,
+            }
+            .into()
+        } else {
+            tl::types::InputFile {
+                id: cursor.file_id,
+                parts: total_chunks,
+                name: file_name,
+                md5_checksum: String::new(),
+            }
+            .into()
+        })
+    }
+
+    /// Send one chunk of an upload. `total_chunks` is given for big files, which use
+    /// `upload.saveBigFilePart`.
+    async fn save_chunk(
+        &self,
+        client: &Client,
+        file_id: i64,
+        chunk: i32,
+        total_chunks: Option<i32>,
+        bytes: Vec<u8>,
+    ) -> (i32, Result<bool, UploadError>) {
+        let saved = match total_chunks {
+            Some(file_total_parts) => {
+                client
+                    .invoke(&tl::functions::upload::SaveBigFilePart {
+                        file_id,
+                        file_part: chunk,
+                        file_total_parts,
+                        bytes,
+                    })
+                    .await
+            }
+            None => {
+                client
+                    .invoke(&tl::functions::upload::SaveFilePart {
+                        file_id,
+                        file_part: chunk,
+                        bytes,
+                    })
+                    .await
             }
         };
+        (chunk, saved.map_err(|e| self.upload_error(e)))
+    }
 
-        // Send to self (Saved Messages)
-        let me = client
-            .get_me()
+    /// Send an uploaded file as its own message, returning the message ID.
+    pub async fn send_uploaded(
+        &self,
+        input_file: tl::enums::InputFile,
+        caption: String,
+    ) -> Result<i32, UploadError> {
+        let client = self.client().await.map_err(UploadError::Other)?;
+        // Send to the storage destination (Saved Messages by default)
+        let peer = self
+            .resolve_destination(&client)
             .await
-            .map_err(|e| UploadError::Other(e.to_string()))?;
-        let message = grammers_client::message::InputMessage::new()
-            .text("Uploaded via Wander(er)")
-            .file(uploaded_file);
-        let peer = me
-            .to_ref()
-            .ok_or_else(|| UploadError::Other("Could not get peer reference".to_string()))?;
+            .map_err(UploadError::Other)?;
+        let message = InputMessage::new()
+            .text(caption)
+            .file(Uploaded::from_raw(input_file));
 
         // send_message can also rate limit
         match client.send_message(peer, message).await {
             Ok(sent_msg) => Ok(sent_msg.id()),
-            Err(e) => {
-                let err_str = e.to_string();
-                if let Some(secs) = parse_flood_wait(&err_str) {
-                    return Err(UploadError::RateLimit(secs));
+            Err(e) => Err(self.upload_error(e)),
+        }
+    }
+
+    /// Send an uploaded file as part of an album with other uploads finishing around
+    /// the same time, returning its own message ID. A failure is only reported for
+    /// the files it affects.
+    pub async fn send_grouped(
+        self: &Arc<Self>,
+        input_file: tl::enums::InputFile,
+        caption: String,
+    ) -> Result<i32, UploadError> {
+        self.album
+            .submit((input_file, caption), |batch| {
+                let service = self.clone();
+                async move { service.send_album(batch).await }
+            })
+            .await
+            .unwrap_or_else(|| {
+                Err(UploadError::Other(
+                    "The album with this file was interrupted before it was sent".to_string(),
+                ))
+            })
+    }
+
+    /// Send a batch of uploads as one grouped message, one result per upload in order.
+    /// When Telegram rejects the album for anything but a rate limit, every file is
+    /// sent on its own so only the broken ones fail.
+    async fn send_album(&self, batch: Vec<AlbumItem>) -> Vec<Result<i32, UploadError>> {
+        if batch.len() == 1 {
+            let (input_file, caption) = batch.into_iter().next().unwrap();
+            return vec![self.send_uploaded(input_file, caption).await];
+        }
+
+        let count = batch.len();
+        let sent = async {
+            let client = self.client().await.map_err(UploadError::Other)?;
+            let peer = self
+                .resolve_destination(&client)
+                .await
+                .map_err(UploadError::Other)?;
+            let medias = batch
+                .iter()
+                .map(|(input_file, caption)| {
+                    InputMedia::new()
+                        .caption(caption.clone())
+                        .file(Uploaded::from_raw(input_file.clone()))
+                })
+                .collect();
+            client
+                .send_album(peer, medias)
+                .await
+                .map_err(|e| self.upload_error(e))
+        }
+        .await;
+
+        match sent {
+            Ok(messages) => {
+                info!("Sent album of {} files", count);
+                messages
+                    .into_iter()
+                    .map(|message| {
+                        message.map(|m| m.id()).ok_or_else(|| {
+                            UploadError::Other(
+                                "Telegram did not return the message for this file".to_string(),
+                            )
+                        })
+                    })
+                    .collect()
+            }
+            Err(e @ UploadError::RateLimit(_)) => (0..count).map(|_| Err(e.clone())).collect(),
+            Err(UploadError::Other(e)) => {
+                log::warn!(
+                    "Album of {} files failed ({}); sending them one by one",
+                    count,
+                    e
+                );
+                let mut results = Vec::with_capacity(count);
+                for (input_file, caption) in batch {
+                    results.push(self.send_uploaded(input_file, caption).await);
                 }
-                Err(UploadError::Other(err_str))
+                results
             }
         }
     }
 
+    fn upload_error(&self, err: impl std::fmt::Display) -> UploadError {
+        let err_str = self.rpc_error(err);
+        match parse_flood_wait(&err_str) {
+            Some(secs) => UploadError::RateLimit(secs),
+            None => UploadError::Other(err_str),
+        }
+    }
+
+    /// Fetch up to `limit` messages from the storage destination, newest first.
+    /// With a non-zero `offset_id`, only messages older than that ID are returned,
+    /// so callers can page backwards through the whole history.
     pub async fn get_history(
         &self,
-        _offset_id: i32,
+        offset_id: i32,
         limit: usize,
     ) -> Result<Vec<grammers_client::message::Message>, String> {
-        let client_guard = self.client.lock().await;
-        let client = client_guard.as_ref().ok_or("Client not connected")?;
-
-        let me = client.get_me().await.map_err(|e| e.to_string())?;
-        let peer = me.to_ref().ok_or("Could not get peer error")?;
+        let client = self.client().await?;
+        let peer = self.resolve_destination(&client).await?;
 
         // Grammers `iter_messages` returns an async iterator
         let mut messages = Vec::new();
         let mut row_iter = client.iter_messages(peer).limit(limit);
+        if offset_id > 0 {
+            row_iter = row_iter.offset_id(offset_id);
+        }
 
-        while let Some(msg) = row_iter.next().await.map_err(|e| e.to_string())? {
+        while let Some(msg) = row_iter.next().await.map_err(|e| self.rpc_error(e))? {
             messages.push(msg);
         }
 
         Ok(messages)
     }
 
+    /// IDs of up to `limit` messages in the storage destination newer than `after`,
+    /// oldest first.
+    pub async fn message_ids_after(&self, after: i32, limit: usize) -> Result<Vec<i32>, String> {
+        let client = self.client().await?;
+        let peer = self.resolve_destination(&client).await?;
+
+        // A negative `add_offset` turns the page towards newer messages: with it set to
+        // `-limit`, the page holds the `limit` messages starting at `offset_id`
+        let limit = limit as i32;
+        let request = tl::functions::messages::GetHistory {
+            peer: peer.into(),
+            offset_id: after.saturating_add(1),
+            offset_date: 0,
+            add_offset: -limit,
+            limit,
+            max_id: 0,
+            min_id: after,
+            hash: 0,
+        };
+        let response = client
+            .invoke(&request)
+            .await
+            .map_err(|e| self.rpc_error(e))?;
+        let messages = match response {
+            tl::enums::messages::Messages::Messages(m) => m.messages,
+            tl::enums::messages::Messages::Slice(m) => m.messages,
+            tl::enums::messages::Messages::ChannelMessages(m) => m.messages,
+            tl::enums::messages::Messages::NotModified(_) => Vec::new(),
+        };
+        let mut ids: Vec<i32> = messages
+            .iter()
+            .map(|msg| match msg {
+                tl::enums::Message::Empty(m) => m.id,
+                tl::enums::Message::Message(m) => m.id,
+                tl::enums::Message::Service(m) => m.id,
+            })
+            .filter(|id| *id > after)
+            .collect();
+        ids.sort_unstable();
+        Ok(ids)
+    }
+
     pub async fn download_file(
         &self,
         message: &grammers_client::message::Message,
         path: &str,
     ) -> Result<(), String> {
-        let client_guard = self.client.lock().await;
-        let client = client_guard.as_ref().ok_or("Client not connected")?;
+        let client = self.client().await?;
 
         // Check if message has media
         if let Some(media) = message.media() {
             client
                 .download_media(&media, path)
                 .await
-                .map_err(|e| e.to_string())?;
+                .map_err(|e| self.rpc_error(e))?;
             Ok(())
         } else {
             Err("Message has no media".to_string())
         }
     }
 
-    /// Delete messages from Saved Messages by message IDs
+    /// Delete messages from the storage destination by message IDs
     /// Note: telegram_media_id is stored as String but Telegram uses i32 message IDs
     pub async fn delete_messages(&self, message_ids: &[i32]) -> Result<usize, String> {
-        use grammers_client::tl;
-
         if message_ids.is_empty() {
             return Ok(0);
         }
@@ -395,74 +1314,135 @@ impl TelegramService {
             message_ids
         );
 
-        let client_guard = self.client.lock().await;
-        let client = client_guard.as_ref().ok_or("Client not connected")?;
+        let client = self.client().await?;
+        let peer = self.resolve_destination(&client).await?;
 
-        // For Saved Messages (self-chat), we use messages::DeleteMessages with revoke=true
-        // This works for private chats including Saved Messages
-        let request = tl::functions::messages::DeleteMessages {
-            revoke: true,
-            id: message_ids.to_vec(),
-        };
+        // grammers picks messages::DeleteMessages or channels::DeleteMessages
+        // depending on the peer, always revoking for everyone.
+        let deleted_count = client
+            .delete_messages(peer, message_ids)
+            .await
+            .map_err(|e| {
+                let e = self.rpc_error(e);
+                log::error!("Telegram delete_messages failed: {}", e);
+                format!("Failed to delete messages: {}", e)
+            })?;
 
-        let result = client.invoke(&request).await.map_err(|e| {
-            log::error!("Telegram delete_messages failed: {}", e);
-            format!("Failed to delete messages: {}", e)
-        })?;
+        log::info!(
+            "Telegram: Deleted {} messages (requested: {})",
+            deleted_count,
+            message_ids.len()
+        );
+        Ok(deleted_count)
+    }
 
-        // AffectedMessages contains the pts_count indicating how many were deleted
-        match result {
-            tl::enums::messages::AffectedMessages::Messages(affected) => {
-                let deleted_count = affected.pts_count as usize;
-                log::info!(
-                    "Telegram: Deleted {} messages (requested: {}, pts_count: {})",
-                    deleted_count,
-                    message_ids.len(),
-                    affected.pts_count
-                );
-                Ok(deleted_count)
-            }
+    /// Fetch messages from the storage destination by ID, in batches.
+    /// The result is aligned with `message_ids`; deleted or unknown IDs yield `None`.
+    pub async fn get_messages_by_ids(
+        &self,
+        message_ids: &[i32],
+    ) -> Result<Vec<Option<Message>>, String> {
+        let client = self.client().await?;
+        let peer = self.resolve_destination(&client).await?;
+
+        let mut messages = Vec::with_capacity(message_ids.len());
+        for batch in message_ids.chunks(MESSAGES_BY_ID_BATCH) {
+            let fetched = client
+                .get_messages_by_id(peer, batch)
+                .await
+                .map_err(|e| self.rpc_error(e))?;
+            messages.extend(fetched);
         }
+        Ok(messages)
     }
 
     /// Download a file by message ID
-    /// Fetches the message from saved messages and downloads its media to the specified path
+    /// Fetches the message from the storage destination and downloads its media to the specified path
     pub async fn download_by_message_id(&self, message_id: i32, path: &str) -> Result<(), String> {
-        let client_guard = self.client.lock().await;
-        let client = client_guard.as_ref().ok_or("Client not connected")?;
+        let message = self
+            .get_messages_by_ids(&[message_id])
+            .await?
+            .into_iter()
+            .next()
+            .flatten()
+            .ok_or_else(|| format!("Message with ID {} not found", message_id))?;
+        self.download_file(&message, path).await
+    }
 
-        // Get the "me" user for Saved Messages
-        let me = client.get_me().await.map_err(|e| e.to_string())?;
-        let peer = me.to_ref().ok_or("Could not get peer")?;
-
-        // Iterate through messages to find the one with matching ID
-        // We start from message_id + 1 and limit to 10 to find the message efficiently
-        let mut iter = client
-            .iter_messages(peer)
-            .offset_id(message_id + 1)
-            .limit(10);
-
-        while let Some(msg) = iter.next().await.map_err(|e| e.to_string())? {
-            if msg.id() == message_id {
-                if let Some(media) = msg.media() {
-                    client
-                        .download_media(&media, path)
-                        .await
-                        .map_err(|e| e.to_string())?;
-                    return Ok(());
-                } else {
-                    return Err("Message has no media".to_string());
+    /// Download a file that was uploaded as several parts and reassemble it at `path`.
+    /// A single ID behaves exactly like `download_by_message_id`.
+    pub async fn download_message_parts(
+        &self,
+        message_ids: &[i32],
+        path: &str,
+    ) -> Result<(), String> {
+        if message_ids.len() <= 1 {
+            let msg_id = message_ids.first().copied().ok_or("No message IDs given")?;
+            return self.download_by_message_id(msg_id, path).await;
+        }
+
+        // Resolve every part up front so a missing part fails before anything is downloaded.
+        let messages = self.get_messages_by_ids(message_ids).await?;
+        let mut parts = Vec::with_capacity(messages.len());
+        for (idx, message) in messages.into_iter().enumerate() {
+            match message {
+                Some(message) => parts.push(message),
+                None => {
+                    return Err(format!(
+                        "Part {} (message {}) not found",
+                        idx + 1,
+                        message_ids[idx]
+                    ))
                 }
             }
         }
 
-        Err(format!("Message with ID {} not found", message_id))
+        let mut part_paths = Vec::with_capacity(parts.len());
+        let mut result = Ok(());
+        for (idx, message) in parts.iter().enumerate() {
+            let part_path = PathBuf::from(format!("{}.part{:03}", path, idx + 1));
+            part_paths.push(part_path.clone());
+            if let Err(e) = self
+                .download_file(message, &part_path.to_string_lossy())
+                .await
+            {
+                result = Err(format!("Failed to download part {}: {}", idx + 1, e));
+                break;
+            }
+        }
+
+        if result.is_ok() {
+            result = crate::file_parts::join_files(&part_paths, std::path::Path::new(path))
+                .map(|_| ())
+                .map_err(|e| format!("Failed to reassemble parts: {}", e));
+        }
+
+        for part_path in &part_paths {
+            let _ = std::fs::remove_file(part_path);
+        }
+        result
+    }
+
+    /// Drop the client and stop its background tasks, closing the session database.
+    /// The login stays valid and `connect` picks it up again.
+    pub async fn disconnect(&self) {
+        *self.client.lock().await = None;
+        *self.peer_cache.lock().await = None;
+        *self.session.lock().await = None;
+        *self.qr_expires.lock().await = None;
+        if let Some(handle) = self.backend_handle.lock().await.take() {
+            handle.abort();
+        }
+        if let Some(handle) = self.update_handle.lock().await.take() {
+            handle.abort();
+        }
+        info!("Client disconnected");
     }
 
     pub async fn logout(&self, app_data_dir: PathBuf) -> Result<(), String> {
         // 1. Graceful Sign Out
         {
-            let mut client_guard = self.client.lock().await;
+            let client_guard = self.client.lock().await;
             if let Some(client) = client_guard.as_ref() {
                 info!("Attempting graceful sign out...");
                 match client.sign_out().await {
@@ -470,22 +1450,12 @@ impl TelegramService {
                     Err(e) => log::error!("Failed to sign out gracefully: {}", e),
                 }
             }
-            // 2. Disconnect (Drop Client) - inside the same lock or re-acquire?
-            // Better to keep it locked or just set to None immediately.
-            *client_guard = None;
-        }
-        info!("Client disconnected");
-
-        // 3. Abort background tasks (Wait until after sign out so network is available)
-        if let Some(handle) = self.backend_handle.lock().await.take() {
-            handle.abort();
-        }
-        if let Some(handle) = self.update_handle.lock().await.take() {
-            handle.abort();
         }
+        // 2. Disconnect (Wait until after sign out so network is available)
+        self.disconnect().await;
 
-        // 4. Delete Session File
-        let session_path = app_data_dir.join("session.db");
+        // 3. Delete Session File
+        let session_path = app_data_dir.join(crate::session_vault::SESSION_FILE);
         if session_path.exists() {
             let mut deleted = false;
             for i in 0..5 {
@@ -0,0 +1,221 @@
+//! Upload controls: global pause, bandwidth cap and time-of-day upload windows.
+//!
+//! All three are kept in the config table so they survive restarts. The upload worker
+//! re-reads them before claiming each item and applies them to the shared
+//! [`BandwidthLimiter`](crate::rate_limit::BandwidthLimiter) and
+//! [`UploadGate`](crate::rate_limit::UploadGate).
+
+use crate::database::Database;
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+use time::{OffsetDateTime, UtcOffset};
+
+pub const UPLOAD_PAUSED_KEY: &str = "upload_paused";
+/// Upload cap in KiB/s; 0 or missing means unlimited
+pub const UPLOAD_BANDWIDTH_KEY: &str = "upload_bandwidth_kbps";
+/// Comma-separated windows such as "22:00-07:00"; empty means any time
+pub const UPLOAD_SCHEDULE_KEY: &str = "upload_schedule";
+
+/// A daily window in minutes after local midnight. Windows may wrap past midnight,
+/// and a window that starts and ends at the same time covers the whole day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScheduleWindow {
+    pub start: u16,
+    pub end: u16,
+}
+
+impl ScheduleWindow {
+    pub fn contains(&self, minute: u16) -> bool {
+        if self.start == self.end {
+            true
+        } else if self.start < self.end {
+            minute >= self.start && minute < self.end
+        } else {
+            minute >= self.start || minute < self.end
+        }
+    }
+}
+
+/// Parse "HH:MM-HH:MM" windows separated by commas.
+pub fn parse_schedule(text: &str) -> Result<Vec<ScheduleWindow>, String> {
+    text.split(',')
+        .map(str::trim)
+        .filter(|w| !w.is_empty())
+        .map(|window| {
+            let (start, end) = window.split_once('-').ok_or_else(|| {
+                format!("Invalid upload window '{}': expected HH:MM-HH:MM", window)
+            })?;
+            Ok(ScheduleWindow {
+                start: parse_time(start)?,
+                end: parse_time(end)?,
+            })
+        })
+        .collect()
+}
+
+fn parse_time(text: &str) -> Result<u16, String> {
+    let invalid = || format!("Invalid time '{}': expected HH:MM", text.trim());
+    let (hours, minutes) = text.trim().split_once(':').ok_or_else(invalid)?;
+    let hours: u16 = hours.parse().map_err(|_| invalid())?;
+    let minutes: u16 = minutes.parse().map_err(|_| invalid())?;
+    if hours > 23 || minutes > 59 {
+        return Err(invalid());
+    }
+    Ok(hours * 60 + minutes)
+}
+
+/// Local offset read at startup, while the process was still single-threaded
+static LOCAL_OFFSET: OnceLock<UtcOffset> = OnceLock::new();
+
+/// Read the local offset for [`local_minute_of_day`]. Call before any other thread
+/// starts: the `time` crate refuses to read it in multi-threaded processes on Linux
+/// and macOS.
+pub fn init_local_offset() {
+    if let Ok(offset) = UtcOffset::current_local_offset() {
+        let _ = LOCAL_OFFSET.set(offset);
+    }
+}
+
+/// Minutes since local midnight. Uses the offset read at startup where the current
+/// one can't be read, and UTC if neither could.
+pub fn local_minute_of_day() -> u16 {
+    let offset = UtcOffset::current_local_offset()
+        .ok()
+        .or_else(|| LOCAL_OFFSET.get().copied())
+        .unwrap_or(UtcOffset::UTC);
+    minute_of_day(OffsetDateTime::now_utc(), offset)
+}
+
+fn minute_of_day(now: OffsetDateTime, offset: UtcOffset) -> u16 {
+    let local = now.to_offset(offset);
+    local.hour() as u16 * 60 + local.minute() as u16
+}
+
+/// Whether uploads may run, as reported to the UI.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UploadState {
+    #[default]
+    Running,
+    Paused,
+    OutsideSchedule,
+}
+
+/// Upload controls as shown in the UI.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadControls {
+    pub paused: bool,
+    /// Upload cap in KiB/s; 0 means unlimited
+    pub bandwidth_kbps: u64,
+    /// Windows in "HH:MM-HH:MM" form, comma-separated; empty means any time
+    pub schedule: String,
+}
+
+impl UploadControls {
+    pub fn load(db: &Database) -> Self {
+        let get = |key: &str| db.get_config(key).ok().flatten().unwrap_or_default();
+        Self {
+            paused: get(UPLOAD_PAUSED_KEY) == "true",
+            bandwidth_kbps: get(UPLOAD_BANDWIDTH_KEY).parse().unwrap_or(0),
+            schedule: get(UPLOAD_SCHEDULE_KEY),
+        }
+    }
+
+    /// Validate and persist the controls.
+    pub fn save(&self, db: &Database) -> Result<(), String> {
+        parse_schedule(&self.schedule)?;
+        db.set_config(
+            UPLOAD_PAUSED_KEY,
+            if self.paused { "true" } else { "false" },
+        )
+        .map_err(|e| e.to_string())?;
+        db.set_config(UPLOAD_BANDWIDTH_KEY, &self.bandwidth_kbps.to_string())
+            .map_err(|e| e.to_string())?;
+        db.set_config(UPLOAD_SCHEDULE_KEY, self.schedule.trim())
+            .map_err(|e| e.to_string())
+    }
+
+    /// Cap for the bandwidth limiter in bytes per second (0 = unlimited).
+    pub fn bytes_per_sec(&self) -> u64 {
+        self.bandwidth_kbps.saturating_mul(1024)
+    }
+
+    /// The upload state at `minute` past local midnight. A schedule that fails to
+    /// parse does not hold uploads back.
+    pub fn state(&self, minute: u16) -> UploadState {
+        if self.paused {
+            return UploadState::Paused;
+        }
+        let windows = parse_schedule(&self.schedule).unwrap_or_default();
+        if windows.is_empty() || windows.iter().any(|w| w.contains(minute)) {
+            UploadState::Running
+        } else {
+            UploadState::OutsideSchedule
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::{datetime, offset};
+
+    #[test]
+    fn test_minute_of_day_in_local_time() {
+        let now = datetime!(2025-03-01 23:30 UTC);
+        assert_eq!(minute_of_day(now, offset!(UTC)), 23 * 60 + 30);
+        // Past local midnight east of UTC, still evening west of it
+        assert_eq!(minute_of_day(now, offset!(+2)), 60 + 30);
+        assert_eq!(minute_of_day(now, offset!(-5:30)), 18 * 60);
+    }
+
+    #[test]
+    fn test_parse_schedule() {
+        assert_eq!(
+            parse_schedule("22:00-07:00, 12:30-13:00").unwrap(),
+            vec![
+                ScheduleWindow {
+                    start: 22 * 60,
+                    end: 7 * 60
+                },
+                ScheduleWindow {
+                    start: 12 * 60 + 30,
+                    end: 13 * 60
+                },
+            ]
+        );
+        assert!(parse_schedule("").unwrap().is_empty());
+        assert!(parse_schedule("22:00").is_err());
+        assert!(parse_schedule("24:00-07:00").is_err());
+    }
+
+    #[test]
+    fn test_window_wraps_past_midnight() {
+        let night = parse_schedule("22:00-07:00").unwrap()[0];
+        assert!(night.contains(23 * 60));
+        assert!(night.contains(6 * 60 + 59));
+        assert!(!night.contains(7 * 60));
+        assert!(!night.contains(12 * 60));
+    }
+
+    #[test]
+    fn test_controls_state() {
+        let mut controls = UploadControls {
+            schedule: "22:00-07:00".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(controls.state(23 * 60), UploadState::Running);
+        assert_eq!(controls.state(9 * 60), UploadState::OutsideSchedule);
+        controls.paused = true;
+        assert_eq!(controls.state(23 * 60), UploadState::Paused);
+        assert_eq!(
+            UploadControls::default().state(9 * 60),
+            UploadState::Running
+        );
+        assert_eq!(
+            serde_json::to_value(UploadState::OutsideSchedule).unwrap(),
+            "outside_schedule"
+        );
+    }
+}
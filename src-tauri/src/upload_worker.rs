@@ -1,18 +1,31 @@
-use crate::database::Database;
+use crate::accounts::{self, AccountPool, Placement, PlacementSettings, PRIMARY_ACCOUNT};
+use crate::caption::{self, CaptionMetadata};
+use crate::cloud_index::{CloudIndex, DedupStats};
+use crate::database::{Database, QueueItem, UploadCheckpoint};
+use crate::file_parts;
+use crate::local_originals::PlainOriginal;
 use crate::media_utils;
-use crate::security::{self, RuntimeState};
-use crate::telegram::{TelegramService, UploadError};
+use crate::security::{self, FileHeader, RuntimeState};
+use crate::storage::{self, StorageBackend};
+use crate::telegram::{self, ChunkCursor, TelegramService, UploadError};
+use crate::upload_schedule::{self, UploadControls, UploadState};
 use log::{error, info, warn};
 use serde::Serialize;
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tauri::{AppHandle, Emitter};
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, OwnedSemaphorePermit, Semaphore};
 use tokio::time::{sleep, Duration};
 use tokio_util::sync::CancellationToken;
 
-/// Artificial delay between successful uploads to avoid rate limiting (seconds)
-const UPLOAD_COOLDOWN_SECS: u64 = 2;
+/// Parallel uploads when `upload_concurrency` is not configured
+const DEFAULT_UPLOAD_CONCURRENCY: usize = 2;
+const MAX_UPLOAD_CONCURRENCY: usize = 8;
+/// How often a paused or out-of-schedule worker checks whether it may resume
+const HOLD_CHECK_SECS: u64 = 30;
+/// Error of an upload stopped by its interrupt, which is handled before it is reported
+const INTERRUPTED: &str = "Upload interrupted";
 
 /// Event payload for upload status changes
 #[derive(Clone, Serialize)]
@@ -46,14 +59,97 @@ pub struct RateLimitEvent {
     pub wait_seconds: u64,
 }
 
+/// Cancellation handles for uploads in flight, keyed by queue item ID, so a single
+/// upload can be paused or cancelled from the UI.
+#[derive(Default)]
+pub struct ActiveUploads {
+    tokens: std::sync::Mutex<HashMap<i64, CancellationToken>>,
+}
+
+impl ActiveUploads {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn register(&self, id: i64) -> CancellationToken {
+        let token = CancellationToken::new();
+        self.tokens
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(id, token.clone());
+        token
+    }
+
+    fn finish(&self, id: i64) {
+        self.tokens
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&id);
+    }
+
+    /// Stop the in-flight upload of `id`. Returns false when it isn't uploading.
+    pub fn interrupt(&self, id: i64) -> bool {
+        match self
+            .tokens
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&id)
+        {
+            Some(token) => {
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Stop every upload in flight, e.g. when the vault locks.
+    pub fn interrupt_all(&self) {
+        for token in self
+            .tokens
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .values()
+        {
+            token.cancel();
+        }
+    }
+
+    /// Whether no upload is in flight.
+    pub fn is_idle(&self) -> bool {
+        self.tokens
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .is_empty()
+    }
+}
+
 pub async fn run_upload_worker(
     db: Arc<Database>,
-    telegram: Arc<TelegramService>,
+    accounts: Arc<AccountPool>,
     security_runtime: Arc<Mutex<RuntimeState>>,
+    active: Arc<ActiveUploads>,
     app_handle: AppHandle,
     cancel: CancellationToken,
 ) {
-    info!("Starting upload worker...");
+    let concurrency = upload_concurrency(&db);
+    info!(
+        "Starting upload worker ({} parallel uploads)...",
+        concurrency
+    );
+    // Items left mid-upload by a previous run resume from their checkpoint
+    match db.reset_interrupted_uploads() {
+        Ok(0) => {}
+        Ok(count) => info!("Resuming {} interrupted upload(s)", count),
+        Err(e) => error!("Failed to reset interrupted uploads: {}", e),
+    }
+    let slots = Arc::new(Semaphore::new(concurrency));
+    let cloud_index = Arc::new(CloudIndex::new());
+    let telegram = accounts.primary().clone();
+    let governor = telegram.governor();
+    let bandwidth = telegram.bandwidth();
+    let gate = telegram.upload_gate();
+    let mut last_state = None;
 
     loop {
         // Check for cancellation
@@ -62,246 +158,952 @@ pub async fn run_upload_worker(
             break;
         }
 
-        // 1. Fetch next pending item
-        match db.get_next_pending_item() {
+        // Apply the persisted pause, schedule and bandwidth cap. Closing the gate also
+        // holds uploads already in flight between chunks.
+        let controls = UploadControls::load(&db);
+        bandwidth.set_rate(controls.bytes_per_sec());
+        let state = controls.state(upload_schedule::local_minute_of_day());
+        gate.set_open(state == UploadState::Running);
+        if last_state != Some(state) {
+            info!("Upload worker state: {:?}", state);
+            last_state = Some(state);
+        }
+        if state != UploadState::Running {
+            // Resuming from the UI opens the gate right away
+            tokio::select! {
+                _ = sleep(Duration::from_secs(HOLD_CHECK_SECS)) => {}
+                _ = gate.wait_open() => {}
+                _ = cancel.cancelled() => {}
+            }
+            continue;
+        }
+
+        // Don't start new uploads while Telegram has the account throttled
+        governor.wait().await;
+
+        let permit = tokio::select! {
+            permit = slots.clone().acquire_owned() => match permit {
+                Ok(permit) => permit,
+                Err(_) => break,
+            },
+            _ = cancel.cancelled() => continue,
+        };
+
+        // 1. Claim next pending item (marks it as uploading)
+        match db.claim_next_pending_item() {
             Ok(Some(item)) => {
                 info!(
                     "Processing pending upload: {} (ID: {})",
                     item.file_path, item.id
                 );
+                let db = db.clone();
+                let accounts = accounts.clone();
+                let cloud_index = cloud_index.clone();
+                let security_runtime = security_runtime.clone();
+                let app_handle = app_handle.clone();
+                let interrupt = active.register(item.id);
+                let active = active.clone();
+                tokio::spawn(async move {
+                    let id = item.id;
+                    process_item(
+                        &db,
+                        &accounts,
+                        &cloud_index,
+                        &security_runtime,
+                        &app_handle,
+                        item,
+                        interrupt,
+                        permit,
+                    )
+                    .await;
+                    active.finish(id);
+                });
+            }
+            Ok(None) => {
+                // Queue empty
+                drop(permit);
+                sleep(Duration::from_secs(5)).await;
+            }
+            Err(e) => {
+                drop(permit);
+                error!("Database error fetching queue: {}", e);
+                sleep(Duration::from_secs(5)).await;
+            }
+        }
+    }
 
-                // 2. Mark as uploading
-                if let Err(e) = db.update_queue_status(item.id, "uploading", None) {
-                    error!("Failed to update status to uploading: {}", e);
-                }
+    // Let in-flight uploads finish before reporting shutdown
+    let _ = slots.acquire_many(concurrency as u32).await;
+}
 
-                // Defensive dedupe at worker time: if current bytes already match an uploaded
-                // media hash, skip re-upload. This protects against transient watcher races.
-                if let Ok(hash) = media_utils::hash_file_streaming(std::path::Path::new(&item.file_path)) {
-                    if let Ok(true) = db.is_media_uploaded(&hash) {
-                        info!(
-                            "Skipping upload for {} (hash already uploaded)",
-                            item.file_path
-                        );
-                        let _ = db.update_queue_status(item.id, "completed", None);
-                        let _ = app_handle.emit(
-                            "upload-completed",
-                            UploadEvent {
-                                id: item.id,
-                                file_path: item.file_path.clone(),
-                                status: "completed".to_string(),
-                                error: None,
-                            },
-                        );
-                        continue;
-                    }
-                }
+/// Number of parallel uploads, overridable via the `upload_concurrency` config key.
+fn upload_concurrency(db: &Database) -> usize {
+    db.get_config("upload_concurrency")
+        .ok()
+        .flatten()
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_UPLOAD_CONCURRENCY)
+        .clamp(1, MAX_UPLOAD_CONCURRENCY)
+}
 
-                // Emit upload-started event
-                let _ = app_handle.emit(
-                    "upload-started",
-                    UploadEvent {
-                        id: item.id,
-                        file_path: item.file_path.clone(),
-                        status: "uploading".to_string(),
-                        error: None,
-                    },
-                );
+/// The account a queue item uploads to.
+struct UploadTarget {
+    account_id: i64,
+    /// Whether this upload is a mirror copy of an item already stored elsewhere
+    mirror: bool,
+    telegram: Arc<TelegramService>,
+}
 
-                // 3. Attempt upload with progress
-                let progress_handle = app_handle.clone();
-                let progress_id = item.id;
-                let progress_path = item.file_path.clone();
-                let security_mode = db
-                    .get_config("security_mode")
+/// Pick the account for a queue item and pin it, so a resumed upload continues on the
+/// account that holds its checkpointed parts. Other backends ignore accounts.
+async fn resolve_target(
+    db: &Database,
+    accounts: &AccountPool,
+    item: &QueueItem,
+) -> Result<UploadTarget, String> {
+    let (pinned, mirror) = db
+        .get_queue_item_target(item.id)
+        .map_err(|e| e.to_string())?;
+    if storage::open_external(db)?.is_some() {
+        if mirror {
+            return Err("Mirror copies are only kept on Telegram accounts".to_string());
+        }
+        return Ok(UploadTarget {
+            account_id: PRIMARY_ACCOUNT,
+            mirror,
+            telegram: accounts.primary().clone(),
+        });
+    }
+
+    let account_id = match pinned {
+        Some(account_id) => account_id,
+        None => {
+            let settings = PlacementSettings::load(db);
+            let album_account = match settings.mode {
+                Placement::Album => db
+                    .get_album_account_for_path(&item.file_path)
                     .ok()
-                    .flatten()
-                    .unwrap_or_else(|| "unset".to_string());
-                let should_encrypt = security_mode == "encrypted";
-                let mut upload_path = item.file_path.clone();
-                let mut encrypted_temp: Option<PathBuf> = None;
-
-                if should_encrypt {
-                    let maybe_key = security_runtime.lock().await.master_key;
-                    let key = match maybe_key {
-                        Some(k) => k,
-                        None => {
-                            warn!(
-                                "Skipping upload {} because encryption vault is locked",
-                                item.file_path
-                            );
-                            let _ = db.update_queue_status(item.id, "pending", None);
-                            sleep(Duration::from_secs(5)).await;
-                            continue;
-                        }
-                    };
-
-                    let temp_dir = std::env::temp_dir().join("wanderer-encrypted-uploads");
-                    if let Err(e) = std::fs::create_dir_all(&temp_dir) {
-                        let err_msg = format!("Failed to create temp encrypted upload dir: {}", e);
-                        let _ = db.update_queue_status(item.id, "failed", Some(&err_msg));
-                        continue;
-                    }
-
-                    let temp_path = temp_dir.join(format!("upload_{}_enc.wbenc", item.id));
-                    match security::encrypt_file(
-                        std::path::Path::new(&item.file_path),
-                        &temp_path,
-                        &key,
-                    ) {
-                        Ok(_) => {
-                            upload_path = temp_path.to_string_lossy().to_string();
-                            encrypted_temp = Some(temp_path);
-                        }
-                        Err(e) => {
-                            let err_msg = format!("Failed to encrypt file before upload: {}", e);
-                            error!("{}", err_msg);
-                            let _ = db.update_queue_status(item.id, "failed", Some(&err_msg));
-                            continue;
-                        }
-                    }
-                }
+                    .flatten(),
+                _ => None,
+            };
+            let account_id = accounts::choose_account(
+                settings.mode,
+                &accounts.ids().await,
+                item.id,
+                album_account,
+            );
+            db.set_queue_item_account(item.id, account_id)
+                .map_err(|e| e.to_string())?;
+            account_id
+        }
+    };
+    let telegram = accounts
+        .get(account_id)
+        .await
+        .ok_or_else(|| format!("Telegram account {} is no longer registered", account_id))?;
+    Ok(UploadTarget {
+        account_id,
+        mirror,
+        telegram,
+    })
+}
 
-                let upload_result = telegram
-                    .upload_file_with_progress(&upload_path, move |bytes, total, speed| {
-                        let eta = if speed > 0.0 {
-                            ((total - bytes) as f64 / speed) as u64
-                        } else {
-                            0
-                        };
-                        let percent = if total > 0 {
-                            (bytes as f64 / total as f64) * 100.0
-                        } else {
-                            0.0
-                        };
-
-                        let _ = progress_handle.emit(
-                            "upload-progress",
-                            UploadProgressEvent {
-                                id: progress_id,
-                                file_path: progress_path.clone(),
-                                bytes_uploaded: bytes,
-                                total_bytes: total,
-                                speed_bps: speed,
-                                eta_seconds: eta,
-                                percent,
-                            },
-                        );
-                    })
-                    .await;
+/// Upload one claimed queue item and record the outcome. `interrupt` fires when the
+/// item is paused or cancelled while uploading. `slot` is the worker's upload slot,
+/// given back once the item's bytes are sent.
+#[allow(clippy::too_many_arguments)]
+async fn process_item(
+    db: &Database,
+    accounts: &AccountPool,
+    cloud_index: &CloudIndex,
+    security_runtime: &Mutex<RuntimeState>,
+    app_handle: &AppHandle,
+    item: QueueItem,
+    interrupt: CancellationToken,
+    slot: OwnedSemaphorePermit,
+) {
+    let mut slot = Some(slot);
+    let target = match resolve_target(db, accounts, &item).await {
+        Ok(target) => target,
+        Err(e) => {
+            error!("Upload failed for {}: {}", item.file_path, e);
+            let _ = db.update_queue_status(item.id, "failed", Some(&e));
+            let _ = app_handle.emit(
+                "upload-failed",
+                UploadEvent {
+                    id: item.id,
+                    file_path: item.file_path.clone(),
+                    status: "failed".to_string(),
+                    error: Some(e),
+                },
+            );
+            return;
+        }
+    };
+    let telegram = target.telegram.as_ref();
 
-                if let Some(temp) = encrypted_temp {
-                    let _ = std::fs::remove_file(temp);
-                }
+    // Encrypted originals upload from a decrypted copy, and plain ones local encryption
+    // may rewrite from a pinned one; it is read throughout and removed once the item is
+    // done. A missing file fails further on.
+    let read_keys = security_runtime.lock().await.read_keys();
+    let original = Path::new(&item.file_path);
+    if read_keys.is_empty() && security::is_encrypted_file(original).unwrap_or(false) {
+        warn!(
+            "Skipping upload {} because encryption vault is locked",
+            item.file_path
+        );
+        let _ = db.update_queue_status(item.id, "pending", None);
+        sleep(Duration::from_secs(5)).await;
+        return;
+    }
+    let plain = match PlainOriginal::open_pinned(db, original, &read_keys) {
+        Ok(plain) => Some(plain),
+        Err(e) if original.exists() => {
+            let e = format!("Failed to read local original: {}", e);
+            error!("Upload failed for {}: {}", item.file_path, e);
+            let _ = db.update_queue_status(item.id, "failed", Some(&e));
+            let _ = app_handle.emit(
+                "upload-failed",
+                UploadEvent {
+                    id: item.id,
+                    file_path: item.file_path.clone(),
+                    status: "failed".to_string(),
+                    error: Some(e),
+                },
+            );
+            return;
+        }
+        Err(_) => None,
+    };
+    let source = plain.as_ref().map_or(original, |plain| plain.path());
 
-                match upload_result {
-                    Ok(telegram_msg_id) => {
-                        info!(
-                            "Successfully uploaded: {} (Telegram ID: {})",
-                            item.file_path, telegram_msg_id
-                        );
-
-                        // Store the Telegram message ID for later deletion
-                        if let Err(e) = db.update_telegram_id_by_path(
-                            &item.file_path,
-                            &telegram_msg_id.to_string(),
-                        ) {
-                            error!("Failed to store Telegram message ID: {}", e);
-                        }
+    // Defensive dedupe at worker time: if current bytes already match an uploaded
+    // media hash, skip re-upload. This protects against transient watcher races.
+    // Mirror copies are of uploaded items by definition.
+    let file_hash = media_utils::hash_file_streaming(source).ok();
+    if let Some(hash) = file_hash.as_deref().filter(|_| !target.mirror) {
+        if let Ok(true) = db.is_media_uploaded(hash) {
+            info!(
+                "Skipping upload for {} (hash already uploaded)",
+                item.file_path
+            );
+            let _ = db.update_queue_status(item.id, "completed", None);
+            let _ = app_handle.emit(
+                "upload-completed",
+                UploadEvent {
+                    id: item.id,
+                    file_path: item.file_path.clone(),
+                    status: "completed".to_string(),
+                    error: None,
+                },
+            );
+            return;
+        }
+    }
 
-                        // 4. Success: Update queue and media
-                        if let Err(e) = db.update_queue_status(item.id, "completed", None) {
-                            error!("Failed to mark queue item completed: {}", e);
-                        }
+    // Emit upload-started event
+    let _ = app_handle.emit(
+        "upload-started",
+        UploadEvent {
+            id: item.id,
+            file_path: item.file_path.clone(),
+            status: "uploading".to_string(),
+            error: None,
+        },
+    );
 
-                        if let Err(e) = db.mark_media_uploaded_by_path(&item.file_path) {
-                            error!("Failed to mark media uploaded: {}", e);
-                        }
-                        if should_encrypt {
-                            if let Err(e) = db.mark_media_encrypted_by_path(&item.file_path) {
-                                error!("Failed to mark media encrypted: {}", e);
-                            }
-                        }
+    // 3. Attempt upload with progress
+    let security_mode = db
+        .get_config("security_mode")
+        .ok()
+        .flatten()
+        .unwrap_or_else(|| "unset".to_string());
+    let should_encrypt = security_mode == "encrypted";
+    let mut upload_path = source.to_string_lossy().to_string();
+    let mut encrypted_temp: Option<PathBuf> = None;
+    let mut caption_key: Option<[u8; 32]> = None;
 
-                        // Emit upload-completed event
-                        let _ = app_handle.emit(
-                            "upload-completed",
-                            UploadEvent {
-                                id: item.id,
-                                file_path: item.file_path.clone(),
-                                status: "completed".to_string(),
-                                error: None,
-                            },
-                        );
-
-                        // Artificial cooldown to avoid rate limiting
-                        info!(
-                            "Cooldown: waiting {}s before next upload",
-                            UPLOAD_COOLDOWN_SECS
-                        );
-                        sleep(Duration::from_secs(UPLOAD_COOLDOWN_SECS)).await;
-                    }
-                    Err(UploadError::RateLimit(wait_secs)) => {
-                        warn!("Rate limited by Telegram! Waiting {} seconds...", wait_secs);
-
-                        // Emit rate-limit event for UI
-                        let _ = app_handle.emit(
-                            "upload-rate-limited",
-                            RateLimitEvent {
-                                id: item.id,
-                                file_path: item.file_path.clone(),
-                                wait_seconds: wait_secs,
-                            },
-                        );
-
-                        // Update status to rate_limited
-                        let _ = db.update_queue_status(item.id, "rate_limited", None);
-
-                        // Wait for the required duration
-                        sleep(Duration::from_secs(wait_secs)).await;
-
-                        // Reset status back to pending for retry
-                        let _ = db.update_queue_status(item.id, "pending", None);
-                        continue;
-                    }
-                    Err(UploadError::Other(e)) => {
-                        // Check for connection error
-                        if e.contains("Client not connected") {
-                            error!("Worker waiting for Telegram connection...");
-                            sleep(Duration::from_secs(5)).await;
-                            // Reset status back to pending for retry
-                            let _ = db.update_queue_status(item.id, "pending", None);
-                            continue;
-                        }
+    // Encrypted payloads are staged under a stable name and kept until the upload
+    // completes, because re-encrypting would produce different bytes than the chunks
+    // already sent.
+    let staged_path = staged_upload_path(item.id);
+
+    // Continue from the last checkpoint unless the source or the payload changed since
+    let source_hash = file_hash.clone().unwrap_or_default();
+    let mut checkpoint = db
+        .get_upload_checkpoint(item.id)
+        .ok()
+        .flatten()
+        .unwrap_or_default();
+    // The key and its generation are read together, so a key rotation can't come between
+    let (master_key, key_generation) = {
+        let runtime = security_runtime.lock().await;
+        (runtime.master_key, runtime.key_generation)
+    };
+    // A payload staged before a key rotation is sealed with the retired key, and one
+    // staged by an earlier version may use the old container
+    let staged_is_current = match master_key {
+        Some(key) if staged_path.exists() => {
+            security::container_version(&staged_path).ok().flatten()
+                == Some(security::CONTAINER_VERSION)
+                && security::read_file_header(&staged_path, &key).is_ok()
+        }
+        _ => true,
+    };
+    let payload_matches = staged_path.exists() == should_encrypt && staged_is_current;
+    if checkpoint.source_hash != source_hash || (checkpoint.is_started() && !payload_matches) {
+        if checkpoint.is_started() {
+            info!(
+                "Discarding upload progress for {} (file or encryption changed)",
+                item.file_path
+            );
+        }
+        discard_checkpoint(telegram, &checkpoint).await;
+        let _ = std::fs::remove_file(&staged_path);
+        checkpoint = UploadCheckpoint {
+            source_hash,
+            ..Default::default()
+        };
+    }
 
-                        error!("Upload failed for {}: {}", item.file_path, e);
+    // Another device may have stored the same file already on the target account; link
+    // to its copy instead.
+    let existing = match file_hash.as_deref() {
+        Some(hash) if !target.mirror => {
+            find_cloud_copy(
+                db,
+                cloud_index,
+                security_runtime,
+                &target,
+                hash,
+                should_encrypt,
+            )
+            .await
+        }
+        _ => None,
+    };
+    if existing.is_some() {
+        discard_checkpoint(telegram, &checkpoint).await;
+        let _ = std::fs::remove_file(&staged_path);
+    }
 
-                        // 5. Failure: Update queue with error
-                        if let Err(db_err) = db.update_queue_status(item.id, "failed", Some(&e)) {
-                            error!("Failed to log upload error to db: {}", db_err);
-                        }
+    let caption_meta = caption::metadata_for_file(
+        db,
+        &item.file_path,
+        file_hash.as_deref().unwrap_or_default(),
+        should_encrypt,
+    );
 
-                        // Emit upload-failed event
-                        let _ = app_handle.emit(
-                            "upload-failed",
-                            UploadEvent {
-                                id: item.id,
-                                file_path: item.file_path.clone(),
-                                status: "failed".to_string(),
-                                error: Some(e),
-                            },
-                        );
-                    }
-                }
-            }
-            Ok(None) => {
-                // Queue empty
+    if should_encrypt && existing.is_none() {
+        let key = match master_key {
+            Some(k) => k,
+            None => {
+                warn!(
+                    "Skipping upload {} because encryption vault is locked",
+                    item.file_path
+                );
+                let _ = db.update_queue_status(item.id, "pending", None);
                 sleep(Duration::from_secs(5)).await;
+                return;
+            }
+        };
+
+        if let Some(temp_dir) = staged_path.parent() {
+            if let Err(e) = std::fs::create_dir_all(temp_dir) {
+                let err_msg = format!("Failed to create temp encrypted upload dir: {}", e);
+                let _ = db.update_queue_status(item.id, "failed", Some(&err_msg));
+                return;
+            }
+        }
+
+        let staged = if checkpoint.is_started() && staged_path.exists() && staged_is_current {
+            info!("Reusing staged encrypted payload for {}", item.file_path);
+            Ok(())
+        } else {
+            let header = FileHeader::new(
+                &caption_meta.file_name,
+                caption_meta.mime_type.clone(),
+                &caption_meta.file_hash,
+            );
+            security::encrypt_file_with_header(source, &staged_path, &key, &header)
+        };
+        match staged {
+            Ok(_) => {
+                upload_path = staged_path.to_string_lossy().to_string();
+                encrypted_temp = Some(staged_path);
+                caption_key = Some(key);
             }
             Err(e) => {
-                error!("Database error fetching queue: {}", e);
+                let _ = std::fs::remove_file(&staged_path);
+                let err_msg = format!("Failed to encrypt file before upload: {}", e);
+                error!("{}", err_msg);
+                let _ = db.update_queue_status(item.id, "failed", Some(&err_msg));
+                return;
+            }
+        }
+    }
+
+    let external = storage::open_external(db);
+    let upload = async {
+        match &external {
+            // Dropping a backend upload part-way leaves nothing that needs recording
+            Ok(Some(backend)) => tokio::select! {
+                result = upload_to_backend(
+                    backend.as_ref(),
+                    &target.telegram.upload_pacing(),
+                    app_handle,
+                    item.id,
+                    &item.file_path,
+                    &upload_path,
+                    &caption_meta,
+                    caption_key.as_ref(),
+                ) => result,
+                _ = interrupt.cancelled() => Err(UploadError::Other(INTERRUPTED.to_string())),
+            },
+            Ok(None) => {
+                upload_payload(
+                    db,
+                    &target.telegram,
+                    app_handle,
+                    item.id,
+                    &item.file_path,
+                    &upload_path,
+                    split_part_bytes(db),
+                    &caption_meta,
+                    caption_key.as_ref(),
+                    checkpoint,
+                    &mut slot,
+                    &interrupt,
+                )
+                .await
+            }
+            Err(e) => Err(UploadError::Other(e.clone())),
+        }
+    };
+    let upload_result = match existing {
+        Some(parts) => {
+            let saved: u64 = parts.iter().map(|(_, size)| *size).sum();
+            info!(
+                "Linked {} to the copy already in storage ({} bytes not uploaded)",
+                item.file_path, saved
+            );
+            if let Err(e) = DedupStats::record(db, saved) {
+                error!("Failed to record deduplication savings: {}", e);
+            }
+            Ok(parts)
+        }
+        None => {
+            let result = upload.await;
+            // Parts sent before the interrupt are in the checkpoint, where pausing keeps
+            // them and cancelling deletes them. A finished backend upload is kept.
+            if interrupt.is_cancelled() && (result.is_err() || matches!(external, Ok(None))) {
+                finish_interrupted(db, telegram, app_handle, &item, encrypted_temp).await;
+                return;
+            }
+            result
+        }
+    };
+
+    // Uploads held up by a rate limit or a lost connection keep their staged payload and
+    // checkpoint so the retry resumes
+    if upload_result.is_ok() {
+        if let Some(temp) = &encrypted_temp {
+            let _ = std::fs::remove_file(temp);
+        }
+        if let Err(e) = db.clear_upload_checkpoint(item.id) {
+            error!("Failed to clear upload checkpoint: {}", e);
+        }
+    }
+
+    match upload_result {
+        Ok(part_msg_ids) if target.mirror => {
+            let ids: Vec<i32> = part_msg_ids.iter().map(|(id, _)| *id).collect();
+            info!(
+                "Mirrored {} to account {} (parts: {})",
+                item.file_path,
+                target.account_id,
+                ids.len()
+            );
+            if let Err(e) = db.add_media_copy_by_path(&item.file_path, target.account_id, &ids) {
+                error!("Failed to record mirror copy: {}", e);
+            }
+            if let Err(e) = db.update_queue_status(item.id, "completed", None) {
+                error!("Failed to mark queue item completed: {}", e);
+            }
+            let _ = app_handle.emit(
+                "upload-completed",
+                UploadEvent {
+                    id: item.id,
+                    file_path: item.file_path.clone(),
+                    status: "completed".to_string(),
+                    error: None,
+                },
+            );
+        }
+        Ok(part_msg_ids) => {
+            let telegram_msg_id = part_msg_ids.first().map(|(id, _)| *id).unwrap_or(0);
+            info!(
+                "Successfully uploaded: {} (Telegram ID: {}, account: {}, parts: {})",
+                item.file_path,
+                telegram_msg_id,
+                target.account_id,
+                part_msg_ids.len()
+            );
+
+            // Store the Telegram message ID for later deletion
+            if let Err(e) =
+                db.update_telegram_id_by_path(&item.file_path, &telegram_msg_id.to_string())
+            {
+                error!("Failed to store Telegram message ID: {}", e);
+            }
+            if part_msg_ids.len() > 1 {
+                let parts: Vec<(String, i64)> = part_msg_ids
+                    .iter()
+                    .map(|(id, size)| (id.to_string(), *size as i64))
+                    .collect();
+                if let Err(e) = db.set_media_parts_by_path(&item.file_path, &parts) {
+                    error!("Failed to store part layout: {}", e);
+                }
+            }
+            if let Err(e) = db.set_media_account_by_path(&item.file_path, target.account_id) {
+                error!("Failed to store upload account: {}", e);
+            }
+            queue_mirror_copy(db, accounts, &item.file_path, target.account_id).await;
+
+            // 4. Success: Update queue and media
+            if let Err(e) = db.update_queue_status(item.id, "completed", None) {
+                error!("Failed to mark queue item completed: {}", e);
+            }
+
+            if let Err(e) = db.mark_media_uploaded_by_path(&item.file_path) {
+                error!("Failed to mark media uploaded: {}", e);
+            }
+            if should_encrypt {
+                if let Err(e) = db.mark_media_encrypted_by_path(
+                    &item.file_path,
+                    security::CONTAINER_VERSION,
+                    key_generation,
+                ) {
+                    error!("Failed to mark media encrypted: {}", e);
+                }
+            }
+
+            // Emit upload-completed event
+            let _ = app_handle.emit(
+                "upload-completed",
+                UploadEvent {
+                    id: item.id,
+                    file_path: item.file_path.clone(),
+                    status: "completed".to_string(),
+                    error: None,
+                },
+            );
+        }
+        Err(UploadError::RateLimit(wait_secs)) => {
+            warn!("Rate limited by Telegram! Waiting {} seconds...", wait_secs);
+
+            // Emit rate-limit event for UI
+            let _ = app_handle.emit(
+                "upload-rate-limited",
+                RateLimitEvent {
+                    id: item.id,
+                    file_path: item.file_path.clone(),
+                    wait_seconds: wait_secs,
+                },
+            );
+
+            // Update status to rate_limited
+            let _ = db.update_queue_status(item.id, "rate_limited", None);
+
+            // The pause is shared: every other upload, sync and download waits too
+            telegram.governor().wait().await;
+
+            // Reset status back to pending for retry
+            let _ = db.update_queue_status(item.id, "pending", None);
+        }
+        Err(UploadError::Other(e)) => {
+            // Check for connection error
+            if e.contains("Client not connected") {
+                error!("Worker waiting for Telegram connection...");
                 sleep(Duration::from_secs(5)).await;
+                // Reset status back to pending for retry
+                let _ = db.update_queue_status(item.id, "pending", None);
+                return;
             }
+
+            error!("Upload failed for {}: {}", item.file_path, e);
+            // A retry of a failed item starts over
+            discard_queue_progress(db, telegram, item.id).await;
+            if let Some(temp) = &encrypted_temp {
+                let _ = std::fs::remove_file(temp);
+            }
+
+            // 5. Failure: Update queue with error
+            if let Err(db_err) = db.update_queue_status(item.id, "failed", Some(&e)) {
+                error!("Failed to log upload error to db: {}", db_err);
+            }
+
+            // Emit upload-failed event
+            let _ = app_handle.emit(
+                "upload-failed",
+                UploadEvent {
+                    id: item.id,
+                    file_path: item.file_path.clone(),
+                    status: "failed".to_string(),
+                    error: Some(e),
+                },
+            );
         }
     }
 }
+
+/// Look up a complete copy of the file in the storage destination. Failures only cost
+/// the deduplication, so they are logged and the file is uploaded as usual.
+async fn find_cloud_copy(
+    db: &Database,
+    cloud_index: &CloudIndex,
+    security_runtime: &Mutex<RuntimeState>,
+    target: &UploadTarget,
+    file_hash: &str,
+    encrypted: bool,
+) -> Option<Vec<(i32, u64)>> {
+    let key = security_runtime.lock().await.master_key;
+    if encrypted && key.is_none() {
+        return None;
+    }
+    match cloud_index
+        .find_copy(
+            db,
+            target.account_id,
+            &target.telegram,
+            file_hash,
+            encrypted,
+            key.as_ref(),
+        )
+        .await
+    {
+        Ok(copy) => copy,
+        Err(e) => {
+            warn!("Cloud deduplication check failed: {}", e);
+            None
+        }
+    }
+}
+
+/// In mirror placement, queue a second copy of a finished upload on another account.
+async fn queue_mirror_copy(db: &Database, accounts: &AccountPool, file_path: &str, main: i64) {
+    let settings = PlacementSettings::load(db);
+    if settings.mode != Placement::Mirror || !matches!(storage::open_external(db), Ok(None)) {
+        return;
+    }
+    let ids = accounts.ids().await;
+    match accounts::mirror_account(&ids, main, settings.mirror_account_id) {
+        Some(account_id) => {
+            if let Err(e) = db.add_mirror_to_queue(file_path, account_id) {
+                error!("Failed to queue mirror copy: {}", e);
+            }
+        }
+        None => warn!("Mirror placement needs a second Telegram account"),
+    }
+}
+
+/// Where the encrypted payload of queue item `id` is staged between attempts.
+pub fn staged_upload_path(id: i64) -> PathBuf {
+    std::env::temp_dir()
+        .join("wanderer-encrypted-uploads")
+        .join(format!("upload_{}_enc.wbenc", id))
+}
+
+/// Record an upload stopped by `pause_upload`, `cancel_upload` or the vault locking. A
+/// paused item keeps its checkpoint and staged payload so it resumes where it stopped,
+/// and one stopped by the lock goes back to the queue with them; a cancelled one has
+/// the parts already sent deleted.
+async fn finish_interrupted(
+    db: &Database,
+    telegram: &TelegramService,
+    app_handle: &AppHandle,
+    item: &QueueItem,
+    encrypted_temp: Option<PathBuf>,
+) {
+    let mut status = db
+        .get_queue_item_status(item.id)
+        .ok()
+        .flatten()
+        .unwrap_or_else(|| "cancelled".to_string());
+    match status.as_str() {
+        "paused" => {}
+        // Still marked as running, so nothing but the lock asked it to stop
+        "uploading" | "rate_limited" => {
+            let _ = db.update_queue_status(item.id, "pending", None);
+            status = "pending".to_string();
+        }
+        _ => {
+            discard_queue_progress(db, telegram, item.id).await;
+            if let Some(temp) = encrypted_temp {
+                let _ = std::fs::remove_file(temp);
+            }
+        }
+    }
+    info!("Upload of {} stopped ({})", item.file_path, status);
+    let _ = app_handle.emit(
+        "upload-interrupted",
+        UploadEvent {
+            id: item.id,
+            file_path: item.file_path.clone(),
+            status,
+            error: None,
+        },
+    );
+}
+
+/// Delete the parts sent for a queue item and forget its checkpoint.
+pub async fn discard_queue_progress(db: &Database, telegram: &TelegramService, id: i64) {
+    if let Ok(Some(checkpoint)) = db.get_upload_checkpoint(id) {
+        discard_checkpoint(telegram, &checkpoint).await;
+    }
+    if let Err(e) = db.clear_upload_checkpoint(id) {
+        error!("Failed to clear upload checkpoint: {}", e);
+    }
+}
+
+/// Part size for split uploads, overridable via the `upload_split_part_mb` config key.
+fn split_part_bytes(db: &Database) -> u64 {
+    db.get_config("upload_split_part_mb")
+        .ok()
+        .flatten()
+        .and_then(|v| v.parse::<u64>().ok())
+        .filter(|mb| *mb > 0)
+        .map(|mb| mb * 1024 * 1024)
+        .unwrap_or(file_parts::DEFAULT_PART_BYTES)
+        .min(file_parts::TELEGRAM_MAX_FILE_BYTES)
+}
+
+/// Build a progress callback that reports bytes relative to the whole payload,
+/// so split uploads show one continuous progress bar.
+fn progress_emitter(
+    app_handle: AppHandle,
+    id: i64,
+    file_path: String,
+    base_offset: u64,
+    grand_total: u64,
+) -> impl Fn(u64, u64, f64) + Send + Sync + 'static {
+    move |bytes, _total, speed| {
+        let bytes = base_offset + bytes;
+        let eta = if speed > 0.0 {
+            (grand_total.saturating_sub(bytes) as f64 / speed) as u64
+        } else {
+            0
+        };
+        let percent = if grand_total > 0 {
+            (bytes as f64 / grand_total as f64) * 100.0
+        } else {
+            0.0
+        };
+
+        let _ = app_handle.emit(
+            "upload-progress",
+            UploadProgressEvent {
+                id,
+                file_path: file_path.clone(),
+                bytes_uploaded: bytes,
+                total_bytes: grand_total,
+                speed_bps: speed,
+                eta_seconds: eta,
+                percent,
+            },
+        );
+    }
+}
+
+/// Upload the payload to a non-Telegram backend as a single object.
+/// These backends have no size limit, so nothing is split or checkpointed.
+async fn upload_to_backend(
+    backend: &dyn StorageBackend,
+    pacing: &storage::UploadPacing,
+    app_handle: &AppHandle,
+    id: i64,
+    file_path: &str,
+    upload_path: &str,
+    caption_meta: &CaptionMetadata,
+    caption_key: Option<&[u8; 32]>,
+) -> Result<Vec<(i32, u64)>, UploadError> {
+    let total_bytes = std::fs::metadata(upload_path)
+        .map_err(|e| UploadError::Other(e.to_string()))?
+        .len();
+    let caption = caption::build_caption(caption_meta, caption_key)
+        .map_err(|e| UploadError::Other(e.to_string()))?;
+    let report = progress_emitter(
+        app_handle.clone(),
+        id,
+        file_path.to_string(),
+        0,
+        total_bytes,
+    );
+
+    report(0, total_bytes, 0.0);
+    let started = std::time::Instant::now();
+    let object_id = backend
+        .upload(std::path::Path::new(upload_path), caption, pacing)
+        .await
+        .map_err(UploadError::Other)?;
+    let speed = total_bytes as f64 / started.elapsed().as_secs_f64().max(0.001);
+    report(total_bytes, total_bytes, speed);
+
+    Ok(vec![(object_id, total_bytes)])
+}
+
+/// Drop a checkpoint that can no longer be resumed, deleting parts already sent for it.
+async fn discard_checkpoint(telegram: &TelegramService, checkpoint: &UploadCheckpoint) {
+    let orphaned: Vec<i32> = checkpoint.sent_parts.iter().map(|(id, _)| *id).collect();
+    if !orphaned.is_empty() {
+        let _ = telegram.delete_messages(&orphaned).await;
+    }
+}
+
+/// Upload the (possibly encrypted) payload, splitting it into numbered parts when it
+/// exceeds Telegram's per-file limit. Returns `(message_id, part_size)` per part in order.
+///
+/// Every message carries caption metadata; `caption_key` encrypts it in encrypted mode.
+/// Progress is saved to the queue item after every chunk, and parts already recorded in
+/// `checkpoint` are skipped, so an interrupted upload continues where it stopped.
+///
+/// Unsplit files are sent in albums with other uploads finishing around the same time.
+/// `slot` is released while the file waits for its album, so the next upload can start.
+///
+/// `interrupt` stops the upload between chunks. A part whose message is already being
+/// sent is waited for and recorded in the checkpoint first, since Telegram may post it
+/// whether or not the result is awaited.
+#[allow(clippy::too_many_arguments)]
+async fn upload_payload(
+    db: &Database,
+    telegram: &Arc<TelegramService>,
+    app_handle: &AppHandle,
+    id: i64,
+    file_path: &str,
+    upload_path: &str,
+    part_bytes: u64,
+    caption_meta: &CaptionMetadata,
+    caption_key: Option<&[u8; 32]>,
+    mut checkpoint: UploadCheckpoint,
+    slot: &mut Option<OwnedSemaphorePermit>,
+    interrupt: &CancellationToken,
+) -> Result<Vec<(i32, u64)>, UploadError> {
+    let total_bytes = std::fs::metadata(upload_path)
+        .map_err(|e| UploadError::Other(e.to_string()))?
+        .len();
+
+    let split = total_bytes > file_parts::TELEGRAM_MAX_FILE_BYTES;
+    let parts = if split {
+        file_parts::plan_parts(total_bytes, part_bytes)
+    } else {
+        file_parts::plan_parts(total_bytes, file_parts::TELEGRAM_MAX_FILE_BYTES)
+    };
+    let part_count = parts.len() as u32;
+    if split {
+        info!(
+            "Splitting {} ({} bytes) into {} parts",
+            file_path, total_bytes, part_count
+        );
+    }
+
+    // Parts sent under a different layout (e.g. a changed part size) can't be reused
+    let layout_matches = checkpoint.sent_parts.len() <= parts.len()
+        && checkpoint
+            .sent_parts
+            .iter()
+            .zip(&parts)
+            .all(|((_, size), part)| *size == part.len);
+    if !layout_matches {
+        discard_checkpoint(telegram, &checkpoint).await;
+        checkpoint = UploadCheckpoint {
+            source_hash: checkpoint.source_hash,
+            ..Default::default()
+        };
+    }
+
+    // Single files keep the payload's own name; split parts are numbered after the original
+    let payload_name = std::path::Path::new(if split { file_path } else { upload_path })
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("file")
+        .to_string();
+
+    for part in parts.iter().skip(checkpoint.sent_parts.len()) {
+        let (name, meta) = if split {
+            (
+                file_parts::part_file_name(&payload_name, part.index),
+                caption_meta.for_part(part.index, part_count),
+            )
+        } else {
+            (payload_name.clone(), caption_meta.clone())
+        };
+        let caption = caption::build_caption(&meta, caption_key)
+            .map_err(|e| UploadError::Other(e.to_string()))?;
+
+        let mut cursor = ChunkCursor {
+            file_id: checkpoint.file_id,
+            chunks_sent: checkpoint.chunks_sent,
+        };
+        let msg_id = loop {
+            // Dropping the upload mid-chunk is safe: progress is checkpointed per chunk
+            let uploaded = tokio::select! {
+                uploaded = telegram.upload_range(
+                    upload_path,
+                    part.offset,
+                    part.len,
+                    name.clone(),
+                    cursor,
+                    |sent: ChunkCursor| {
+                        checkpoint.file_id = sent.file_id;
+                        checkpoint.chunks_sent = sent.chunks_sent;
+                        if let Err(e) = db.save_upload_checkpoint(id, &checkpoint) {
+                            warn!("Failed to save upload checkpoint: {}", e);
+                        }
+                    },
+                    progress_emitter(
+                        app_handle.clone(),
+                        id,
+                        file_path.to_string(),
+                        part.offset,
+                        total_bytes,
+                    ),
+                ) => uploaded,
+                _ = interrupt.cancelled() => {
+                    return Err(UploadError::Other(INTERRUPTED.to_string()));
+                }
+            };
+            let result = match uploaded {
+                Ok(input_file) if !split => {
+                    drop(slot.take());
+                    telegram.send_grouped(input_file, caption.clone()).await
+                }
+                Ok(input_file) => telegram.send_uploaded(input_file, caption.clone()).await,
+                Err(e) => Err(e),
+            };
+
+            match result {
+                // Telegram only keeps unfinished chunks for a while; start this part over
+                Err(UploadError::Other(e))
+                    if cursor.chunks_sent > 0 && telegram::is_expired_upload(&e) =>
+                {
+                    warn!(
+                        "Uploaded chunks of {} expired; restarting part {}",
+                        file_path,
+                        part.index + 1
+                    );
+                    cursor = ChunkCursor::fresh();
+                }
+                result => break result?,
+            }
+        };
+
+        checkpoint.sent_parts.push((msg_id, part.len));
+        checkpoint.file_id = 0;
+        checkpoint.chunks_sent = 0;
+        if let Err(e) = db.save_upload_checkpoint(id, &checkpoint) {
+            warn!("Failed to save upload checkpoint: {}", e);
+        }
+    }
+
+    Ok(checkpoint.sent_parts)
+}
@@ -3,6 +3,29 @@ use std::fs;
 use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 
+/// Cache file holding the encrypted copy of a cloud-only item in an encrypted library.
+pub fn encrypted_blob_path(cache_dir: &Path, media_id: i64, file_path: &str) -> PathBuf {
+    let filename = Path::new(file_path)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("cache_file");
+    cache_dir.join(format!("{}_{}.wbenc", media_id, filename))
+}
+
+/// Delete the encrypted cache files, which a new master key can't open.
+pub fn remove_encrypted_blobs(cache_dir: &Path) -> std::io::Result<()> {
+    if !cache_dir.exists() {
+        return Ok(());
+    }
+    for entry in fs::read_dir(cache_dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "wbenc") {
+            fs::remove_file(&path)?;
+        }
+    }
+    Ok(())
+}
+
 pub fn cleanup_cache(
     cache_dir: &Path,
     max_size_bytes: u64,
@@ -1,10 +1,13 @@
 use crate::cache::ThumbnailCache;
 use crate::database::Database;
+use crate::local_originals::{self, PlainOriginal};
 use crate::media_utils;
 use crate::security::{self, RuntimeState};
 use log::{error, info, warn};
 use mime_guess;
+use notify::event::CreateKind;
 use notify::{Config, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashSet;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
@@ -14,10 +17,15 @@ use tauri::Emitter;
 use tokio::sync::mpsc;
 use tokio::sync::Mutex;
 
+/// Encrypted originals skipped because the vault was locked, to be processed on unlock
+type LockedSkips = Arc<std::sync::Mutex<HashSet<PathBuf>>>;
+
 pub struct FileWatcher {
     #[allow(dead_code)]
     watcher: RecommendedWatcher,
     cache: ThumbnailCache,
+    events: mpsc::Sender<Event>,
+    skipped_while_locked: LockedSkips,
 }
 
 impl FileWatcher {
@@ -30,6 +38,8 @@ impl FileWatcher {
         security_runtime: Arc<Mutex<RuntimeState>>,
     ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
         let (tx, mut rx) = mpsc::channel(100);
+        let events = tx.clone();
+        let skipped_while_locked = LockedSkips::default();
 
         let watcher_config = Config::default()
             .with_poll_interval(Duration::from_secs(2))
@@ -57,6 +67,7 @@ impl FileWatcher {
         let cache_for_event = cache.clone();
         let runtime_for_scan = security_runtime.clone();
         let runtime_for_event = security_runtime.clone();
+        let skipped = skipped_while_locked.clone();
 
         tokio::spawn(async move {
             info!("Starting initial scan of {:?}", path_clone);
@@ -73,6 +84,7 @@ impl FileWatcher {
                                 Some(&app_handle_scan),
                                 &cache_for_scan,
                                 &runtime_for_scan,
+                                &skipped,
                             )
                             .await
                             {
@@ -101,6 +113,7 @@ impl FileWatcher {
                                     Some(&app_handle),
                                     &cache_for_event,
                                     &runtime_for_event,
+                                    &skipped,
                                 )
                                 .await
                                 {
@@ -114,7 +127,39 @@ impl FileWatcher {
             }
         });
 
-        Ok(Self { watcher, cache })
+        Ok(Self {
+            watcher,
+            cache,
+            events,
+            skipped_while_locked,
+        })
+    }
+
+    /// Process the encrypted originals that arrived while the vault was locked. Call
+    /// once the keys are available again.
+    pub fn rescan_skipped(&self) {
+        let paths: Vec<PathBuf> = self
+            .skipped_while_locked
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .drain()
+            .collect();
+        if paths.is_empty() {
+            return;
+        }
+        info!(
+            "Rescanning {} original(s) skipped while the vault was locked",
+            paths.len()
+        );
+        let events = self.events.clone();
+        tokio::spawn(async move {
+            for path in paths {
+                let event = Event::new(EventKind::Create(CreateKind::File)).add_path(path);
+                if events.send(event).await.is_err() {
+                    break;
+                }
+            }
+        });
     }
 }
 
@@ -125,6 +170,7 @@ async fn process_file(
     app_handle: Option<&tauri::AppHandle>,
     cache: &ThumbnailCache,
     security_runtime: &Arc<Mutex<RuntimeState>>,
+    skipped_while_locked: &LockedSkips,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     // 0. Ignore temp files
     if let Some(ext) = path.extension() {
@@ -135,13 +181,32 @@ async fn process_file(
         }
     }
 
+    // Encrypted originals are identified by the plaintext hash in their header
+    let (keys, key) = {
+        let runtime = security_runtime.lock().await;
+        let keys = runtime.read_keys();
+        // Recorded under the runtime lock, so an unlock in the meantime still rescans it
+        if keys.is_empty() && security::is_encrypted_file(path).unwrap_or(false) {
+            info!(
+                "Skipping encrypted original while the vault is locked: {:?}",
+                path
+            );
+            skipped_while_locked
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(path.to_path_buf());
+            return Ok(());
+        }
+        (keys, runtime.master_key)
+    };
+
     // Retry loop for file access (Windows file locking/copying delay)
     let mut retries = 0;
     let max_retries = 5;
     let mut hash = String::new();
 
     while retries < max_retries {
-        match media_utils::hash_file_streaming(path) {
+        match local_originals::content_hash(path, &keys) {
             Ok(h) => {
                 hash = h;
                 break;
@@ -149,7 +214,7 @@ async fn process_file(
             Err(e) => {
                 if retries == max_retries - 1 {
                     error!("Failed to hash file after retries {:?}: {}", path, e);
-                    return Err(Box::new(e));
+                    return Err(e.into());
                 }
                 warn!(
                     "File busy or inaccessible, retrying ({}/{}): {:?}",
@@ -165,6 +230,11 @@ async fn process_file(
 
     // 2. Check deduplication
     if db.media_exists_by_hash(&hash)? {
+        // E.g. a local copy restored from the cloud. Encrypted before it is queued, so
+        // the upload worker never reads it mid-rewrite.
+        if let Err(e) = local_originals::protect_new(db, path, key.as_ref()) {
+            warn!("Failed to encrypt original {:?}: {}", path, e);
+        }
         if !db.is_media_uploaded(&hash)? {
             info!("File exists but NOT uploaded. Re-queueing: {:?}", path);
             let path_str = path.to_string_lossy().to_string();
@@ -184,6 +254,7 @@ async fn process_file(
     // 3. Generate Thumbnail using shared utility
     // Wait slightly to ensure file handle is mostly free
     tokio::time::sleep(Duration::from_millis(100)).await;
+    let plain = PlainOriginal::open(path, &keys)?;
 
     // Determine Mime Type early to check if video
     let mime_type = mime_guess::from_path(path)
@@ -193,7 +264,7 @@ async fn process_file(
 
     let mut thumbnail_path = if is_video {
         // Use FFmpeg for video thumbnails
-        match media_utils::generate_video_thumbnail(path, cache_dir, &hash, 300).await {
+        match media_utils::generate_video_thumbnail(plain.path(), cache_dir, &hash, 300).await {
             Ok(Some(thumb_path)) => {
                 cache.insert(hash.clone(), thumb_path.clone()).await;
                 Some(thumb_path.to_string_lossy().to_string())
@@ -206,7 +277,7 @@ async fn process_file(
         }
     } else {
         // Use image library for image thumbnails
-        match media_utils::generate_thumbnail(path, cache_dir, &hash, 300).await {
+        match media_utils::generate_thumbnail(plain.path(), cache_dir, &hash, 300).await {
             Ok(Some(thumb_path)) => {
                 cache.insert(hash.clone(), thumb_path.clone()).await;
                 Some(thumb_path.to_string_lossy().to_string())
@@ -255,14 +326,14 @@ async fn process_file(
 
     // 4. Extract Metadata
     let metadata = if !is_video {
-        Some(crate::metadata::extract_metadata(path))
+        Some(crate::metadata::extract_metadata(plain.path()))
     } else {
         None
     };
 
     // 4.5 Generate Perceptual Hash (for duplicates) unless video
     let phash = if !is_video {
-        media_utils::generate_phash(path)
+        media_utils::generate_phash(plain.path())
     } else {
         None
     };
@@ -278,6 +349,12 @@ async fn process_file(
         phash.as_deref(),
     )?;
 
+    // Encrypted before it is queued, as above
+    drop(plain);
+    if let Err(e) = local_originals::protect_new(db, path, key.as_ref()) {
+        warn!("Failed to encrypt original {:?}: {}", path, e);
+    }
+
     // 6. Add to upload queue
     db.add_to_queue(&path_str)?;
     info!("Added to upload queue: {:?}", path);
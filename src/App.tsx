@@ -29,6 +29,10 @@ function App() {
     encryptionConfigured: boolean;
     encryptionLocked: boolean;
     telegramCredentialsConfigured: boolean;
+    secretStoreKind?: string | null;
+    secretStoreLocked: boolean;
+    secretStoreCreated: boolean;
+    localEncryption: boolean;
     migration: {
       running: boolean;
       total: number;
@@ -75,7 +79,8 @@ function App() {
   const requiresGate =
     securityStatus &&
     (!securityStatus.onboardingComplete ||
-      (securityStatus.securityMode === "encrypted" && securityStatus.encryptionLocked));
+      (securityStatus.securityMode === "encrypted" && securityStatus.encryptionLocked) ||
+      securityStatus.secretStoreLocked);
 
   return (
     <ErrorBoundary>
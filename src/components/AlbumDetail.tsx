@@ -1,8 +1,9 @@
 import { useState, useEffect } from "react";
-import { Album, MediaItem } from "../types";
+import { Album, MediaItem, TelegramAccount } from "../types";
 import { api } from "../lib/api";
 import { MediaGrid } from "./MediaGrid";
 import { Button } from "./ui/button";
+import { Select, SelectContent, SelectItem, SelectTrigger, SelectValue } from "./ui/select";
 import { ArrowLeft } from "lucide-react";
 
 interface AlbumDetailProps {
@@ -14,6 +15,22 @@ export function AlbumDetail({ album, onBack }: AlbumDetailProps) {
     const [items, setItems] = useState<MediaItem[]>([]);
     const [hasNextPage, setHasNextPage] = useState(true);
     const [isNextPageLoading, setIsNextPageLoading] = useState(false);
+    const [accounts, setAccounts] = useState<TelegramAccount[]>([]);
+    const [accountId, setAccountId] = useState<number | null>(album.account_id ?? null);
+
+    useEffect(() => {
+        api.listTelegramAccounts().then(setAccounts).catch(console.error);
+    }, []);
+
+    const handleAccountChange = async (value: string) => {
+        const next = value === "default" ? null : Number(value);
+        try {
+            await api.setAlbumAccount(album.id, next);
+            setAccountId(next);
+        } catch (e) {
+            console.error("Failed to set album account", e);
+        }
+    };
 
     useEffect(() => {
         const loadInitial = async () => {
@@ -64,7 +81,20 @@ export function AlbumDetail({ album, onBack }: AlbumDetailProps) {
                     <ArrowLeft className="h-4 w-4" />
                 </Button>
                 <h1 className="text-xl font-bold">{album.name}</h1>
-                <span className="text-muted-foreground ml-auto text-sm">
+                {accounts.length > 1 && (
+                    <Select value={accountId?.toString() ?? "default"} onValueChange={handleAccountChange}>
+                        <SelectTrigger className="ml-auto w-48">
+                            <SelectValue />
+                        </SelectTrigger>
+                        <SelectContent>
+                            <SelectItem value="default">Primary account</SelectItem>
+                            {accounts.filter((a) => !a.isPrimary).map((account) => (
+                                <SelectItem key={account.id} value={account.id.toString()}>{account.label}</SelectItem>
+                            ))}
+                        </SelectContent>
+                    </Select>
+                )}
+                <span className={`text-muted-foreground text-sm ${accounts.length > 1 ? "" : "ml-auto"}`}>
                     {new Date(album.created_at * 1000).toLocaleDateString()}
                 </span>
             </div>
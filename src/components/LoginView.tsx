@@ -5,14 +5,17 @@ import { Input } from "@/components/ui/input";
 import { Label } from "@/components/ui/label";
 import { Card, CardHeader, CardTitle, CardDescription, CardContent } from "@/components/ui/card";
 import { Alert, AlertDescription, AlertTitle } from "@/components/ui/alert";
-import { Loader2, Phone, KeyRound } from "lucide-react";
+import { Loader2, Phone, KeyRound, Lock } from "lucide-react";
+import { LoginError, SignInOutcome } from "@/types";
 
-type LoginStep = "PHONE" | "CODE" | "SUCCESS";
+type LoginStep = "PHONE" | "CODE" | "PASSWORD" | "SUCCESS";
 
 export function LoginView() {
     const [step, setStep] = useState<LoginStep>("PHONE");
     const [phone, setPhone] = useState("");
     const [code, setCode] = useState("");
+    const [password, setPassword] = useState("");
+    const [passwordHint, setPasswordHint] = useState<string | null>(null);
     const [isLoading, setIsLoading] = useState(false);
     const [error, setError] = useState<string | null>(null);
     const [userName, setUserName] = useState("");
@@ -37,12 +40,34 @@ export function LoginView() {
         setError(null);
         setIsLoading(true);
         try {
-            const name = await invoke<string>("login_sign_in", { code });
+            const outcome = await invoke<SignInOutcome>("login_sign_in", { code });
+            if (outcome.step === "passwordRequired") {
+                setPasswordHint(outcome.hint);
+                setStep("PASSWORD");
+                return;
+            }
+            setUserName(outcome.name);
+            setStep("SUCCESS");
+        } catch (err) {
+            console.error(err);
+            setError((err as LoginError).message ?? "Failed to sign in. Check console.");
+        } finally {
+            setIsLoading(false);
+        }
+    };
+
+    const handleCheckPassword = async (e: React.FormEvent) => {
+        e.preventDefault();
+        setError(null);
+        setIsLoading(true);
+        try {
+            const name = await invoke<string>("login_check_password", { password });
+            setPassword("");
             setUserName(name);
             setStep("SUCCESS");
         } catch (err) {
             console.error(err);
-            setError(typeof err === "string" ? err : "Failed to sign in. Check console.");
+            setError((err as LoginError).message ?? "Failed to sign in. Check console.");
         } finally {
             setIsLoading(false);
         }
@@ -65,11 +90,15 @@ export function LoginView() {
     return (
         <Card className="w-[350px] m-auto mt-20">
             <CardHeader>
-                <CardTitle>{step === "PHONE" ? "Login to Telegram" : "Enter Code"}</CardTitle>
+                <CardTitle>
+                    {step === "PHONE" ? "Login to Telegram" : step === "PASSWORD" ? "Enter Password" : "Enter Code"}
+                </CardTitle>
                 <CardDescription>
                     {step === "PHONE"
                         ? "Enter your phone number to continue."
-                        : `We sent a code to ${phone}.`}
+                        : step === "PASSWORD"
+                            ? "This account is protected by two-step verification."
+                            : `We sent a code to ${phone}.`}
                 </CardDescription>
             </CardHeader>
             <CardContent>
@@ -101,6 +130,33 @@ export function LoginView() {
                             Send Code
                         </Button>
                     </form>
+                ) : step === "PASSWORD" ? (
+                    <form onSubmit={handleCheckPassword} className="space-y-4">
+                        <div className="space-y-2">
+                            <Label htmlFor="password">Password</Label>
+                            <div className="relative">
+                                <Lock className="absolute left-2 top-2.5 h-4 w-4 text-muted-foreground" />
+                                <Input
+                                    id="password"
+                                    type="password"
+                                    value={password}
+                                    onChange={(e) => setPassword(e.target.value)}
+                                    className="pl-8"
+                                    required
+                                />
+                            </div>
+                            {passwordHint && <p className="text-sm text-muted-foreground">Hint: {passwordHint}</p>}
+                        </div>
+                        <div className="flex flex-col gap-2">
+                            <Button type="submit" className="w-full" disabled={isLoading}>
+                                {isLoading && <Loader2 className="mr-2 h-4 w-4 animate-spin" />}
+                                Sign In
+                            </Button>
+                            <Button type="button" variant="ghost" className="w-full" onClick={() => setStep("PHONE")} disabled={isLoading}>
+                                Back
+                            </Button>
+                        </div>
+                    </form>
                 ) : (
                     <form onSubmit={handleSignIn} className="space-y-4">
                         <div className="space-y-2">
@@ -16,6 +16,13 @@ interface TouchPoint {
     y: number;
 }
 
+/** Encrypted cloud videos stay sealed in the view cache and are decrypted as they play. */
+function videoSrc(mediaId: number, viewPath: string) {
+    return viewPath.endsWith(".wbenc")
+        ? convertFileSrc(String(mediaId), "wbstream")
+        : convertFileSrc(viewPath);
+}
+
 export function MediaViewer({ item, open, onClose }: MediaViewerProps) {
     const [faces, setFaces] = useState<Face[]>([]);
     const [tags, setTags] = useState<string[]>([]);
@@ -92,8 +99,15 @@ export function MediaViewer({ item, open, onClose }: MediaViewerProps) {
                     })
                     .finally(() => setIsLoadingCloud(false));
             } else {
-                setViewPath(item.file_path);
-                setIsLoadingCloud(false);
+                // Encrypted local originals come back decrypted; plain ones as they are
+                setViewPath("");
+                api.downloadForView(item.id)
+                    .then(setViewPath)
+                    .catch(err => {
+                        console.error("Failed to open local file:", err);
+                        setViewPath(item.file_path);
+                    })
+                    .finally(() => setIsLoadingCloud(false));
             }
 
             // Load faces
@@ -296,7 +310,7 @@ export function MediaViewer({ item, open, onClose }: MediaViewerProps) {
                             viewPath && (
                                 item.mime_type?.startsWith('video/') ? (
                                     <video
-                                        src={convertFileSrc(viewPath)}
+                                        src={videoSrc(item.id, viewPath)}
                                         controls
                                         autoPlay
                                         className="max-h-[85vh] max-w-full object-contain rounded-md"
@@ -1,5 +1,8 @@
 import { useMemo, useState } from "react";
 import { api } from "@/lib/api";
+import { LoginError } from "@/types";
+import { QrLogin } from "@/components/QrLogin";
+import { ProxySettingsForm } from "@/components/ProxySettingsForm";
 import { Card, CardContent, CardDescription, CardHeader, CardTitle } from "@/components/ui/card";
 import { Button } from "@/components/ui/button";
 import { Input } from "@/components/ui/input";
@@ -16,6 +19,10 @@ type SecurityStatus = {
     encryptionConfigured: boolean;
     encryptionLocked: boolean;
     telegramCredentialsConfigured: boolean;
+    secretStoreKind?: string | null;
+    secretStoreLocked: boolean;
+    secretStoreCreated: boolean;
+    localEncryption: boolean;
     migration: {
         running: boolean;
         total: number;
@@ -60,13 +67,19 @@ export function Onboarding({ status, onReady }: OnboardingProps) {
 
     const [phone, setPhone] = useState("");
     const [code, setCode] = useState("");
-    const [telegramStep, setTelegramStep] = useState<"phone" | "code">("phone");
+    const [telegramPassword, setTelegramPassword] = useState("");
+    const [passwordHint, setPasswordHint] = useState<string | null>(null);
+    const [telegramStep, setTelegramStep] = useState<"phone" | "code" | "qr" | "password">("phone");
 
     const [unlockPassphrase, setUnlockPassphrase] = useState("");
     const [showRecoveryUnlock, setShowRecoveryUnlock] = useState(false);
+    const [showConnectionSettings, setShowConnectionSettings] = useState(false);
     const [unlockRecoveryKey, setUnlockRecoveryKey] = useState("");
     const [unlockNewPassphrase, setUnlockNewPassphrase] = useState("");
 
+    const [secretsPassphrase, setSecretsPassphrase] = useState("");
+    const [confirmSecretsPassphrase, setConfirmSecretsPassphrase] = useState("");
+
     const withBusy = async (fn: () => Promise<void>) => {
         setIsBusy(true);
         try {
@@ -82,7 +95,9 @@ export function Onboarding({ status, onReady }: OnboardingProps) {
                 ? err
                 : err instanceof Error
                     ? err.message
-                    : String(err);
+                    : typeof err === "object" && err !== null && "message" in err
+                        ? String((err as { message: unknown }).message)
+                        : String(err);
         return raw.startsWith("Error:") ? raw.slice(6).trim() : raw;
     };
 
@@ -238,14 +253,39 @@ export function Onboarding({ status, onReady }: OnboardingProps) {
         }
         try {
             await withBusy(async () => {
-                await api.loginSignIn(code.trim());
+                const outcome = await api.loginSignIn(code.trim());
+                if (outcome.step === "passwordRequired") {
+                    setPasswordHint(outcome.hint);
+                    setTelegramStep("password");
+                    return;
+                }
                 toast.success("Telegram login successful.");
                 setStep("finish");
             });
         } catch (e) {
-            const message = toErrorMessage(e);
-            toast.error(`Sign in failed: ${message}`);
-            if (message.includes("No pending login request")) {
+            toast.error(`Sign in failed: ${toErrorMessage(e)}`);
+            const kind = (e as LoginError).kind;
+            if (kind === "no_pending_login" || kind === "code_expired") {
+                setTelegramStep("phone");
+            }
+        }
+    };
+
+    const handleCheckPassword = async () => {
+        if (!telegramPassword) {
+            toast.error("Password is required.");
+            return;
+        }
+        try {
+            await withBusy(async () => {
+                await api.loginCheckPassword(telegramPassword);
+                setTelegramPassword("");
+                toast.success("Telegram login successful.");
+                setStep("finish");
+            });
+        } catch (e) {
+            toast.error(`Sign in failed: ${toErrorMessage(e)}`);
+            if ((e as LoginError).kind === "no_pending_login") {
                 setTelegramStep("phone");
             }
         }
@@ -305,6 +345,32 @@ export function Onboarding({ status, onReady }: OnboardingProps) {
         }
     };
 
+    const handleSecretStoreUnlock = async () => {
+        if (!status.secretStoreCreated) {
+            if (secretsPassphrase.length < 8) {
+                toast.error("Passphrase must be at least 8 characters.");
+                return;
+            }
+            if (secretsPassphrase !== confirmSecretsPassphrase) {
+                toast.error("Passphrases do not match.");
+                return;
+            }
+        } else if (!secretsPassphrase) {
+            toast.error("Passphrase is required.");
+            return;
+        }
+        try {
+            await withBusy(async () => {
+                await api.unlockSecretStore(secretsPassphrase);
+                setSecretsPassphrase("");
+                setConfirmSecretsPassphrase("");
+                await onReady();
+            });
+        } catch (e) {
+            toast.error(`Unlock failed: ${toErrorMessage(e)}`);
+        }
+    };
+
     if (needsUnlockOnly) {
         return (
             <div className="h-screen w-screen flex items-center justify-center bg-background p-6">
@@ -381,6 +447,54 @@ export function Onboarding({ status, onReady }: OnboardingProps) {
         );
     }
 
+    if (status.secretStoreLocked) {
+        return (
+            <div className="h-screen w-screen flex items-center justify-center bg-background p-6">
+                <Card className="w-full max-w-lg">
+                    <CardHeader>
+                        <CardTitle className="flex items-center gap-2">
+                            <KeyRound className="h-5 w-5" />
+                            Unlock Secret Storage
+                        </CardTitle>
+                        <CardDescription>
+                            {status.secretStoreCreated
+                                ? "No system keychain is available. Enter the passphrase protecting your stored Telegram API credentials."
+                                : "No system keychain is available. Choose a passphrase to protect your Telegram API credentials on this device."}
+                        </CardDescription>
+                    </CardHeader>
+                    <CardContent className="space-y-4">
+                        <div className="space-y-2">
+                            <Label htmlFor="secrets-passphrase">Passphrase</Label>
+                            <Input
+                                id="secrets-passphrase"
+                                type="password"
+                                value={secretsPassphrase}
+                                onChange={(e) => setSecretsPassphrase(e.target.value)}
+                                placeholder={status.secretStoreCreated ? "Enter passphrase" : "At least 8 characters"}
+                            />
+                        </div>
+                        {!status.secretStoreCreated && (
+                            <div className="space-y-2">
+                                <Label htmlFor="secrets-passphrase-confirm">Confirm Passphrase</Label>
+                                <Input
+                                    id="secrets-passphrase-confirm"
+                                    type="password"
+                                    value={confirmSecretsPassphrase}
+                                    onChange={(e) => setConfirmSecretsPassphrase(e.target.value)}
+                                    placeholder="Repeat passphrase"
+                                />
+                            </div>
+                        )}
+                        <Button className="w-full" onClick={handleSecretStoreUnlock} disabled={isBusy}>
+                            {isBusy && <Loader2 className="mr-2 h-4 w-4 animate-spin" />}
+                            {status.secretStoreCreated ? "Unlock" : "Create Secret Storage"}
+                        </Button>
+                    </CardContent>
+                </Card>
+            </div>
+        );
+    }
+
     return (
         <div className="h-screen w-screen flex items-center justify-center bg-background p-6">
             <Card className="w-full max-w-2xl">
@@ -651,6 +765,55 @@ export function Onboarding({ status, onReady }: OnboardingProps) {
                                         {isBusy && <Loader2 className="mr-2 h-4 w-4 animate-spin" />}
                                         Send Code
                                     </Button>
+                                    <Button variant="outline" className="w-full" onClick={() => setTelegramStep("qr")}>
+                                        Log in with QR code
+                                    </Button>
+                                    <Button
+                                        variant="link"
+                                        className="w-full"
+                                        onClick={() => setShowConnectionSettings(!showConnectionSettings)}
+                                    >
+                                        Connection settings
+                                    </Button>
+                                    {showConnectionSettings && <ProxySettingsForm />}
+                                </div>
+                            ) : telegramStep === "qr" ? (
+                                <QrLogin
+                                    onSignedIn={() => {
+                                        toast.success("Telegram login successful.");
+                                        setStep("finish");
+                                    }}
+                                    onPasswordRequired={(hint) => {
+                                        setPasswordHint(hint);
+                                        setTelegramStep("password");
+                                    }}
+                                    onCancel={() => setTelegramStep("phone")}
+                                />
+                            ) : telegramStep === "password" ? (
+                                <div className="space-y-3">
+                                    <div className="space-y-2">
+                                        <Label htmlFor="telegram-password">Two-Step Verification Password</Label>
+                                        <Input
+                                            id="telegram-password"
+                                            type="password"
+                                            value={telegramPassword}
+                                            onChange={(e) => setTelegramPassword(e.target.value)}
+                                        />
+                                        {passwordHint && (
+                                            <p className="text-sm text-muted-foreground">Hint: {passwordHint}</p>
+                                        )}
+                                    </div>
+                                    <Button className="w-full" onClick={handleCheckPassword} disabled={isBusy}>
+                                        {isBusy && <Loader2 className="mr-2 h-4 w-4 animate-spin" />}
+                                        Sign In
+                                    </Button>
+                                    <Button
+                                        variant="outline"
+                                        className="w-full"
+                                        onClick={() => setTelegramStep("phone")}
+                                    >
+                                        Back
+                                    </Button>
                                 </div>
                             ) : (
                                 <div className="space-y-3">
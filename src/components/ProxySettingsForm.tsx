@@ -0,0 +1,171 @@
+import { useEffect, useState } from "react";
+import { api } from "@/lib/api";
+import { ConnectionTestResult, ProxyKind, ProxySettings } from "@/types";
+import { Label } from "@/components/ui/label";
+import { Input } from "@/components/ui/input";
+import { Button } from "@/components/ui/button";
+import { Select, SelectContent, SelectItem, SelectTrigger, SelectValue } from "@/components/ui/select";
+import { Loader2 } from "lucide-react";
+import { toast } from "sonner";
+
+const FAILURE_LABELS: Record<NonNullable<ConnectionTestResult["failure"]>, string> = {
+    invalid_settings: "Invalid settings",
+    proxy_unreachable: "Proxy unreachable",
+    proxy_auth_failed: "Proxy login failed",
+    proxy_rejected: "Proxy refused the connection",
+    telegram_unreachable: "Telegram unreachable",
+    handshake_failed: "Telegram did not answer",
+    timeout: "Timed out",
+};
+
+const EMPTY_SETTINGS: ProxySettings = {
+    kind: 'none',
+    host: null,
+    port: null,
+    username: null,
+    password: null,
+    secret: null,
+};
+
+/** SOCKS5 / MTProxy settings for the Telegram connection, with a connection test. */
+export function ProxySettingsForm() {
+    const [settings, setSettings] = useState<ProxySettings>(EMPTY_SETTINGS);
+    const [result, setResult] = useState<ConnectionTestResult | null>(null);
+    const [isTesting, setIsTesting] = useState(false);
+    const [isSaving, setIsSaving] = useState(false);
+
+    useEffect(() => {
+        api.getProxySettings().then(setSettings).catch(console.error);
+    }, []);
+
+    const update = (changes: Partial<ProxySettings>) => {
+        setSettings((prev) => ({ ...prev, ...changes }));
+        setResult(null);
+    };
+
+    const handleTest = async () => {
+        setIsTesting(true);
+        try {
+            setResult(await api.testTelegramConnection(settings));
+        } catch (err) {
+            toast.error(`Connection test failed: ${err}`);
+        } finally {
+            setIsTesting(false);
+        }
+    };
+
+    const handleSave = async () => {
+        setIsSaving(true);
+        try {
+            await api.setProxySettings(settings);
+            toast.success("Connection settings saved");
+        } catch (err) {
+            toast.error(`${err}`);
+        } finally {
+            setIsSaving(false);
+        }
+    };
+
+    return (
+        <div className="space-y-4">
+            <div className="space-y-2">
+                <Label>Connection</Label>
+                <Select value={settings.kind} onValueChange={(kind) => update({ kind: kind as ProxyKind })}>
+                    <SelectTrigger>
+                        <SelectValue />
+                    </SelectTrigger>
+                    <SelectContent>
+                        <SelectItem value="none">Direct</SelectItem>
+                        <SelectItem value="socks5">SOCKS5 proxy</SelectItem>
+                        <SelectItem value="mtproxy">MTProxy</SelectItem>
+                    </SelectContent>
+                </Select>
+            </div>
+
+            {settings.kind !== 'none' && (
+                <div className="grid grid-cols-3 gap-2">
+                    <div className="col-span-2 space-y-2">
+                        <Label htmlFor="proxy-host">Host</Label>
+                        <Input
+                            id="proxy-host"
+                            value={settings.host ?? ""}
+                            onChange={(e) => update({ host: e.target.value })}
+                            placeholder="proxy.example.com"
+                        />
+                    </div>
+                    <div className="space-y-2">
+                        <Label htmlFor="proxy-port">Port</Label>
+                        <Input
+                            id="proxy-port"
+                            type="number"
+                            value={settings.port ?? ""}
+                            onChange={(e) => update({ port: e.target.value ? Number(e.target.value) : null })}
+                            placeholder={settings.kind === 'socks5' ? "1080" : "443"}
+                        />
+                    </div>
+                </div>
+            )}
+
+            {settings.kind === 'socks5' && (
+                <div className="grid grid-cols-2 gap-2">
+                    <div className="space-y-2">
+                        <Label htmlFor="proxy-username">Username</Label>
+                        <Input
+                            id="proxy-username"
+                            value={settings.username ?? ""}
+                            onChange={(e) => update({ username: e.target.value })}
+                            placeholder="Optional"
+                        />
+                    </div>
+                    <div className="space-y-2">
+                        <Label htmlFor="proxy-password">Password</Label>
+                        <Input
+                            id="proxy-password"
+                            type="password"
+                            value={settings.password ?? ""}
+                            onChange={(e) => update({ password: e.target.value })}
+                            placeholder="Unchanged"
+                        />
+                    </div>
+                </div>
+            )}
+
+            {settings.kind === 'mtproxy' && (
+                <div className="space-y-2">
+                    <Label htmlFor="proxy-secret">Secret</Label>
+                    <Input
+                        id="proxy-secret"
+                        type="password"
+                        value={settings.secret ?? ""}
+                        onChange={(e) => update({ secret: e.target.value })}
+                        placeholder="Unchanged"
+                    />
+                    <p className="text-xs text-muted-foreground">
+                        32 hex digits, optionally starting with "dd". Fake-TLS ("ee") secrets are not supported.
+                    </p>
+                </div>
+            )}
+
+            {result && (
+                <div
+                    className={`p-3 rounded text-sm ${result.ok ? "bg-green-50 text-green-700" : "bg-red-50 text-red-500"}`}
+                >
+                    {result.ok
+                        ? `Connected to Telegram in ${result.latencyMs} ms`
+                        : `${result.failure ? FAILURE_LABELS[result.failure] : "Failed"}: ${result.message}`}
+                </div>
+            )}
+
+            <div className="flex gap-2">
+                <Button variant="outline" className="flex-1" onClick={handleTest} disabled={isTesting}>
+                    {isTesting && <Loader2 className="mr-2 h-4 w-4 animate-spin" />}
+                    Test Connection
+                </Button>
+                <Button className="flex-1" onClick={handleSave} disabled={isSaving}>
+                    {isSaving && <Loader2 className="mr-2 h-4 w-4 animate-spin" />}
+                    Save
+                </Button>
+            </div>
+        </div>
+    );
+}
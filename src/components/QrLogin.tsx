@@ -0,0 +1,68 @@
+import { useEffect, useState } from "react";
+import { api } from "@/lib/api";
+import { LoginError } from "@/types";
+import { Button } from "@/components/ui/button";
+import { Loader2 } from "lucide-react";
+
+interface QrLoginProps {
+    onSignedIn: (name: string) => void;
+    onPasswordRequired: (hint: string | null) => void;
+    onCancel: () => void;
+    /** Extra storage account to log in; the primary account when omitted */
+    accountId?: number;
+}
+
+/** Shows a login QR code, refreshing it until a phone accepts it. */
+export function QrLogin({ onSignedIn, onPasswordRequired, onCancel, accountId }: QrLoginProps) {
+    const [svg, setSvg] = useState<string | null>(null);
+    const [error, setError] = useState<string | null>(null);
+
+    useEffect(() => {
+        let active = true;
+        const run = async () => {
+            try {
+                let next = await api.loginQrStart(accountId);
+                while (active && next.step === "token") {
+                    setSvg(next.svg);
+                    next = await api.loginQrWait(accountId);
+                }
+                if (!active) return;
+                if (next.step === "signedIn") {
+                    onSignedIn(next.name);
+                } else if (next.step === "passwordRequired") {
+                    onPasswordRequired(next.hint);
+                }
+            } catch (err) {
+                console.error(err);
+                if (active) setError((err as LoginError).message ?? "QR login failed");
+            }
+        };
+        run();
+        return () => {
+            active = false;
+        };
+    }, []);
+
+    return (
+        <div className="space-y-3">
+            {error && <div className="bg-red-50 text-red-500 p-3 rounded text-sm">{error}</div>}
+            <div className="flex justify-center">
+                {svg ? (
+                    <img
+                        src={`data:image/svg+xml;utf8,${encodeURIComponent(svg)}`}
+                        alt="Telegram login QR code"
+                        className="h-56 w-56 rounded-md bg-white p-2"
+                    />
+                ) : (
+                    !error && <Loader2 className="h-8 w-8 animate-spin text-muted-foreground" />
+                )}
+            </div>
+            <p className="text-xs text-muted-foreground text-center">
+                In Telegram on your phone, open Settings &gt; Devices &gt; Link Desktop Device and scan this code.
+            </p>
+            <Button variant="outline" type="button" className="w-full" onClick={onCancel}>
+                Back
+            </Button>
+        </div>
+    );
+}
@@ -1,5 +1,9 @@
 import { useState, useEffect } from "react";
 import { api } from "../lib/api";
+import { KeyRotationStatus, LoginError } from "../types";
+import { QrLogin } from "./QrLogin";
+import { StorageAccounts } from "./StorageAccounts";
+import { ProxySettingsForm } from "./ProxySettingsForm";
 import { Card, CardHeader, CardTitle, CardDescription, CardContent, CardFooter } from "./ui/card";
 import { Label } from "./ui/label";
 import { Input } from "./ui/input";
@@ -59,7 +63,9 @@ export function Settings() {
     const [code, setCode] = useState("");
     const [isLoading, setIsLoading] = useState(false);
     const [error, setError] = useState<string | null>(null);
-    const [step, setStep] = useState<'phone' | 'code' | 'authenticated'>('phone');
+    const [password, setPassword] = useState("");
+    const [passwordHint, setPasswordHint] = useState<string | null>(null);
+    const [step, setStep] = useState<'phone' | 'code' | 'qr' | 'password' | 'authenticated'>('phone');
     const [config, setConfig] = useState<AppConfig>(DEFAULT_CONFIG);
     const [isSaving, setIsSaving] = useState(false);
     const [backupPath, setBackupPath] = useState<string>("");
@@ -70,6 +76,10 @@ export function Settings() {
         encryptionConfigured: boolean;
         encryptionLocked: boolean;
         telegramCredentialsConfigured: boolean;
+        secretStoreKind?: string | null;
+        secretStoreLocked: boolean;
+        secretStoreCreated: boolean;
+        localEncryption: boolean;
         migration: {
             running: boolean;
             total: number;
@@ -90,6 +100,8 @@ export function Settings() {
         failed: number;
         lastError?: string | null;
     } | null>(null);
+    const [rotationStatus, setRotationStatus] = useState<KeyRotationStatus | null>(null);
+    const [rotationPassphrase, setRotationPassphrase] = useState("");
 
     // CLIP State
     const [clipInstalled, setClipInstalled] = useState(false);
@@ -216,11 +228,21 @@ export function Settings() {
         }
     };
 
+    const loadRotationStatus = async () => {
+        try {
+            setRotationStatus(await api.getKeyRotationStatus());
+        } catch (e) {
+            console.error("Failed to load key rotation status:", e);
+        }
+    };
+
     useEffect(() => {
         if (securityStatus?.securityMode !== "encrypted") return;
         loadMigrationStatus();
+        loadRotationStatus();
         const id = setInterval(() => {
             loadMigrationStatus();
+            loadRotationStatus();
         }, 3000);
         return () => clearInterval(id);
     }, [securityStatus?.securityMode]);
@@ -263,6 +285,26 @@ export function Settings() {
         }
     };
 
+    const rotateMasterKey = async () => {
+        if (!rotationPassphrase) {
+            toast.error("Enter your passphrase to rotate the key");
+            return;
+        }
+
+        setIsSaving(true);
+        try {
+            const result = await api.startKeyRotation(rotationPassphrase);
+            setGeneratedRecoveryKey(result.recoveryKey);
+            setRotationPassphrase("");
+            toast.success("Master key rotated. Save your new recovery key now.");
+            await loadRotationStatus();
+        } catch (e) {
+            toast.error(`Failed to rotate key: ${e}`);
+        } finally {
+            setIsSaving(false);
+        }
+    };
+
     const enableEncryption = async () => {
         if (securityPassphrase.length < 8) {
             toast.error("Passphrase must be at least 8 characters");
@@ -333,13 +375,36 @@ export function Settings() {
         setIsLoading(true);
         setError(null);
         try {
-            const loggedInUser = await api.loginSignIn(code);
+            const outcome = await api.loginSignIn(code);
+            if (outcome.step === 'passwordRequired') {
+                setPasswordHint(outcome.hint);
+                setStep('password');
+                return;
+            }
+            setUser(outcome.name);
+            setStep('authenticated');
+            window.dispatchEvent(new Event('auth-changed'));
+        } catch (err) {
+            console.error(err);
+            setError((err as LoginError).message || "Failed to sign in");
+        } finally {
+            setIsLoading(false);
+        }
+    };
+
+    const handleCheckPassword = async (e: React.FormEvent) => {
+        e.preventDefault();
+        setIsLoading(true);
+        setError(null);
+        try {
+            const loggedInUser = await api.loginCheckPassword(password);
+            setPassword("");
             setUser(loggedInUser);
             setStep('authenticated');
             window.dispatchEvent(new Event('auth-changed'));
-        } catch (err: any) {
+        } catch (err) {
             console.error(err);
-            setError(err.toString() || "Failed to sign in");
+            setError((err as LoginError).message || "Failed to sign in");
         } finally {
             setIsLoading(false);
         }
@@ -440,6 +505,48 @@ export function Settings() {
                                             {isLoading && <Loader2 className="mr-2 h-4 w-4 animate-spin" />}
                                             Send Code
                                         </Button>
+                                        <Button variant="link" onClick={() => setStep('qr')} type="button" className="w-full">
+                                            Log in with QR code
+                                        </Button>
+                                    </form>
+                                ) : step === 'qr' ? (
+                                    <QrLogin
+                                        onSignedIn={(name) => {
+                                            setUser(name);
+                                            setStep('authenticated');
+                                            window.dispatchEvent(new Event('auth-changed'));
+                                        }}
+                                        onPasswordRequired={(hint) => {
+                                            setPasswordHint(hint);
+                                            setStep('password');
+                                        }}
+                                        onCancel={() => setStep('phone')}
+                                    />
+                                ) : step === 'password' ? (
+                                    <form onSubmit={handleCheckPassword} className="space-y-4">
+                                        {error && (
+                                            <div className="bg-red-50 text-red-500 p-3 rounded text-sm">{error}</div>
+                                        )}
+                                        <div className="space-y-2">
+                                            <Label htmlFor="telegram-password">Two-Step Verification Password</Label>
+                                            <Input
+                                                id="telegram-password"
+                                                type="password"
+                                                value={password}
+                                                onChange={(e) => setPassword(e.target.value)}
+                                                required
+                                            />
+                                            {passwordHint && (
+                                                <p className="text-xs text-muted-foreground">Hint: {passwordHint}</p>
+                                            )}
+                                        </div>
+                                        <Button type="submit" className="w-full" disabled={isLoading}>
+                                            {isLoading && <Loader2 className="mr-2 h-4 w-4 animate-spin" />}
+                                            Sign In
+                                        </Button>
+                                        <Button variant="link" onClick={() => setStep('phone')} type="button" className="w-full">
+                                            Back to Phone Number
+                                        </Button>
                                     </form>
                                 ) : (
                                     <form onSubmit={handleSignIn} className="space-y-4">
@@ -475,6 +582,20 @@ export function Settings() {
                             )}
                         </Card>
 
+                        {step === 'authenticated' && <StorageAccounts />}
+
+                        <Card>
+                            <CardHeader>
+                                <CardTitle>Network Proxy</CardTitle>
+                                <CardDescription>
+                                    Reach Telegram through a SOCKS5 proxy or an MTProxy server.
+                                </CardDescription>
+                            </CardHeader>
+                            <CardContent>
+                                <ProxySettingsForm />
+                            </CardContent>
+                        </Card>
+
                         <Card>
                             <CardHeader>
                                 <CardTitle>Security</CardTitle>
@@ -494,6 +615,13 @@ export function Settings() {
                                     </span>
                                 </div>
 
+                                <div className="flex items-center justify-between">
+                                    <Label>Secrets Stored In</Label>
+                                    <span className="text-sm font-medium">
+                                        {securityStatus?.secretStoreKind ?? "Locked"}
+                                    </span>
+                                </div>
+
                                 {securityStatus?.securityMode === "encrypted" && (
                                     <Alert>
                                         <AlertTitle>Encryption Enabled</AlertTitle>
@@ -503,6 +631,37 @@ export function Settings() {
                                     </Alert>
                                 )}
 
+                                {securityStatus?.securityMode === "encrypted" && (
+                                    <div className="flex items-center justify-between">
+                                        <div className="space-y-1">
+                                            <Label htmlFor="local-encryption">Encrypt Local Originals</Label>
+                                            <p className="text-xs text-muted-foreground">
+                                                Keep photos and videos in the backup folder encrypted. They are decrypted only while viewing, exporting or indexing.
+                                            </p>
+                                        </div>
+                                        <Switch
+                                            id="local-encryption"
+                                            checked={securityStatus.localEncryption}
+                                            disabled={isSaving || securityStatus.encryptionLocked}
+                                            onCheckedChange={async (enabled) => {
+                                                setIsSaving(true);
+                                                try {
+                                                    await api.setLocalEncryption(enabled);
+                                                    toast.success(enabled
+                                                        ? "Encrypting local originals in the background."
+                                                        : "Decrypting local originals in the background.");
+                                                    await loadMigrationStatus();
+                                                    await loadSecurityStatus();
+                                                } catch (e) {
+                                                    toast.error(`Failed to change local encryption: ${e}`);
+                                                } finally {
+                                                    setIsSaving(false);
+                                                }
+                                            }}
+                                        />
+                                    </div>
+                                )}
+
                                 {securityStatus?.securityMode === "encrypted" && (
                                     <div className="space-y-3 rounded-md border p-3">
                                         <div className="flex items-center justify-between">
@@ -563,6 +722,91 @@ export function Settings() {
                                     </div>
                                 )}
 
+                                {securityStatus?.securityMode === "encrypted" && (
+                                    <div className="space-y-3 rounded-md border p-3">
+                                        <div className="flex items-center justify-between">
+                                            <Label>Master Key</Label>
+                                            <span className="text-xs text-muted-foreground">
+                                                Generation {rotationStatus?.keyGeneration ?? 1}
+                                            </span>
+                                        </div>
+                                        {rotationStatus?.pending ? (
+                                            <>
+                                                <p className="text-xs text-muted-foreground">
+                                                    {rotationStatus.progress.running
+                                                        ? "Re-encrypting your library with the new key. The old key is kept until this finishes."
+                                                        : "The key rotation has not finished. The old key is kept until everything is re-encrypted."}
+                                                </p>
+                                                <Progress
+                                                    value={
+                                                        rotationStatus.progress.total > 0
+                                                            ? Math.min(
+                                                                100,
+                                                                (rotationStatus.progress.processed / rotationStatus.progress.total) * 100
+                                                            )
+                                                            : 0
+                                                    }
+                                                />
+                                                <div className="grid grid-cols-2 gap-2 text-xs text-muted-foreground">
+                                                    <p>Total: {rotationStatus.progress.total}</p>
+                                                    <p>Processed: {rotationStatus.progress.processed}</p>
+                                                    <p>Succeeded: {rotationStatus.progress.succeeded}</p>
+                                                    <p>Failed: {rotationStatus.progress.failed}</p>
+                                                </div>
+                                                {rotationStatus.progress.lastError && (
+                                                    <Alert>
+                                                        <AlertTitle>Last Rotation Error</AlertTitle>
+                                                        <AlertDescription className="text-xs break-all">
+                                                            {rotationStatus.progress.lastError}
+                                                        </AlertDescription>
+                                                    </Alert>
+                                                )}
+                                                <Button
+                                                    variant="outline"
+                                                    className="w-full"
+                                                    disabled={rotationStatus.progress.running || securityStatus?.encryptionLocked}
+                                                    onClick={async () => {
+                                                        try {
+                                                            await api.resumeKeyRotation();
+                                                            toast.success("Key rotation resumed");
+                                                            await loadRotationStatus();
+                                                        } catch (e) {
+                                                            toast.error(`Failed to resume key rotation: ${e}`);
+                                                        }
+                                                    }}
+                                                >
+                                                    Resume Key Rotation
+                                                </Button>
+                                            </>
+                                        ) : (
+                                            <>
+                                                <p className="text-xs text-muted-foreground">
+                                                    Replace the master key and re-encrypt every upload and thumbnail in the background. You get a new recovery key; the old one stops working.
+                                                </p>
+                                                <div className="space-y-2">
+                                                    <Label htmlFor="rotation-passphrase">Passphrase</Label>
+                                                    <Input
+                                                        id="rotation-passphrase"
+                                                        type="password"
+                                                        value={rotationPassphrase}
+                                                        onChange={(e) => setRotationPassphrase(e.target.value)}
+                                                        placeholder="Current passphrase"
+                                                    />
+                                                </div>
+                                                <Button
+                                                    variant="outline"
+                                                    className="w-full"
+                                                    onClick={rotateMasterKey}
+                                                    disabled={isSaving || securityStatus?.encryptionLocked || migrationStatus?.running}
+                                                >
+                                                    {isSaving && <Loader2 className="mr-2 h-4 w-4 animate-spin" />}
+                                                    Rotate Master Key
+                                                </Button>
+                                            </>
+                                        )}
+                                    </div>
+                                )}
+
                                 {securityStatus?.securityMode !== "encrypted" && (
                                     <div className="space-y-3 rounded-md border p-3">
                                         <Alert>
@@ -0,0 +1,299 @@
+import { useEffect, useState } from "react";
+import { api } from "@/lib/api";
+import { LoginError, Placement, PlacementSettings, TelegramAccount } from "@/types";
+import { QrLogin } from "@/components/QrLogin";
+import { Card, CardHeader, CardTitle, CardDescription, CardContent } from "@/components/ui/card";
+import { Label } from "@/components/ui/label";
+import { Input } from "@/components/ui/input";
+import { Button } from "@/components/ui/button";
+import { Select, SelectContent, SelectItem, SelectTrigger, SelectValue } from "@/components/ui/select";
+import { Loader2, Trash2 } from "lucide-react";
+import { toast } from "sonner";
+
+const PLACEMENT_LABELS: Record<Placement, string> = {
+    primary: "Primary account only",
+    round_robin: "Round robin across accounts",
+    album: "Per album",
+    mirror: "Mirror to a second account",
+};
+
+type LoginStep = 'phone' | 'code' | 'qr' | 'password';
+
+/** Extra Telegram accounts used as storage, and how uploads are spread over them. */
+export function StorageAccounts() {
+    const [accounts, setAccounts] = useState<TelegramAccount[]>([]);
+    const [placement, setPlacement] = useState<PlacementSettings>({ mode: 'primary', mirrorAccountId: null });
+    const [newLabel, setNewLabel] = useState("");
+    const [loginAccount, setLoginAccount] = useState<number | null>(null);
+    const [step, setStep] = useState<LoginStep>('phone');
+    const [phone, setPhone] = useState("");
+    const [code, setCode] = useState("");
+    const [password, setPassword] = useState("");
+    const [passwordHint, setPasswordHint] = useState<string | null>(null);
+    const [isLoading, setIsLoading] = useState(false);
+    const [error, setError] = useState<string | null>(null);
+
+    const refresh = async () => {
+        try {
+            const [list, settings] = await Promise.all([api.listTelegramAccounts(), api.getAccountPlacement()]);
+            setAccounts(list);
+            setPlacement(settings);
+        } catch (err) {
+            console.error("Failed to load storage accounts", err);
+        }
+    };
+
+    useEffect(() => {
+        refresh();
+    }, []);
+
+    const startLogin = (accountId: number) => {
+        setLoginAccount(accountId);
+        setStep('phone');
+        setPhone("");
+        setCode("");
+        setPassword("");
+        setError(null);
+    };
+
+    const finishLogin = (name: string) => {
+        toast.success(`Signed in as ${name}`);
+        setLoginAccount(null);
+        refresh();
+    };
+
+    const handleAdd = async (e: React.FormEvent) => {
+        e.preventDefault();
+        try {
+            const id = await api.addTelegramAccount(newLabel);
+            setNewLabel("");
+            await refresh();
+            startLogin(id);
+        } catch (err) {
+            toast.error(`Failed to add account: ${err}`);
+        }
+    };
+
+    const handleRemove = async (account: TelegramAccount) => {
+        if (!confirm(`Remove "${account.label}" and sign it out?`)) return;
+        try {
+            await api.removeTelegramAccount(account.id);
+            await refresh();
+        } catch (err) {
+            toast.error(`${err}`);
+        }
+    };
+
+    const savePlacement = async (settings: PlacementSettings) => {
+        setPlacement(settings);
+        try {
+            await api.setAccountPlacement(settings);
+        } catch (err) {
+            toast.error(`Failed to save placement: ${err}`);
+        }
+    };
+
+    const runStep = async (action: () => Promise<void>) => {
+        setIsLoading(true);
+        setError(null);
+        try {
+            await action();
+        } catch (err) {
+            setError((err as LoginError).message ?? `${err}`);
+        } finally {
+            setIsLoading(false);
+        }
+    };
+
+    const handleRequestCode = (e: React.FormEvent) => {
+        e.preventDefault();
+        runStep(async () => {
+            await api.loginRequestCode(phone, loginAccount ?? undefined);
+            setStep('code');
+        });
+    };
+
+    const handleSignIn = (e: React.FormEvent) => {
+        e.preventDefault();
+        runStep(async () => {
+            const outcome = await api.loginSignIn(code, loginAccount ?? undefined);
+            if (outcome.step === 'passwordRequired') {
+                setPasswordHint(outcome.hint);
+                setStep('password');
+            } else {
+                finishLogin(outcome.name);
+            }
+        });
+    };
+
+    const handleCheckPassword = (e: React.FormEvent) => {
+        e.preventDefault();
+        runStep(async () => {
+            finishLogin(await api.loginCheckPassword(password, loginAccount ?? undefined));
+        });
+    };
+
+    const others = accounts.filter((a) => !a.isPrimary);
+
+    return (
+        <Card>
+            <CardHeader>
+                <CardTitle>Storage Accounts</CardTitle>
+                <CardDescription>
+                    Add more Telegram accounts to spread uploads over them or keep a second copy.
+                </CardDescription>
+            </CardHeader>
+            <CardContent className="space-y-4">
+                <div className="space-y-2">
+                    {accounts.map((account) => (
+                        <div key={account.id} className="flex items-center justify-between rounded-md border p-3">
+                            <div className="text-sm">
+                                <div className="font-medium">{account.label}</div>
+                                <div className="text-xs text-muted-foreground">
+                                    {account.name ?? "Not signed in"} · {account.mediaCount} item(s)
+                                </div>
+                            </div>
+                            {!account.isPrimary && (
+                                <div className="flex items-center gap-2">
+                                    {!account.name && (
+                                        <Button size="sm" variant="outline" onClick={() => startLogin(account.id)}>
+                                            Sign In
+                                        </Button>
+                                    )}
+                                    <Button size="sm" variant="ghost" onClick={() => handleRemove(account)}>
+                                        <Trash2 className="h-4 w-4" />
+                                    </Button>
+                                </div>
+                            )}
+                        </div>
+                    ))}
+                </div>
+
+                {loginAccount !== null && (
+                    <div className="space-y-3 rounded-md border p-3">
+                        {error && <div className="bg-red-50 text-red-500 p-3 rounded text-sm">{error}</div>}
+                        {step === 'qr' ? (
+                            <QrLogin
+                                accountId={loginAccount}
+                                onSignedIn={finishLogin}
+                                onPasswordRequired={(hint) => {
+                                    setPasswordHint(hint);
+                                    setStep('password');
+                                }}
+                                onCancel={() => setStep('phone')}
+                            />
+                        ) : step === 'phone' ? (
+                            <form onSubmit={handleRequestCode} className="space-y-3">
+                                <Label htmlFor="account-phone">Phone Number</Label>
+                                <Input
+                                    id="account-phone"
+                                    placeholder="+1234567890"
+                                    value={phone}
+                                    onChange={(e) => setPhone(e.target.value)}
+                                    required
+                                />
+                                <Button type="submit" className="w-full" disabled={isLoading}>
+                                    {isLoading && <Loader2 className="mr-2 h-4 w-4 animate-spin" />}
+                                    Send Code
+                                </Button>
+                                <Button variant="link" onClick={() => setStep('qr')} type="button" className="w-full">
+                                    Log in with QR code
+                                </Button>
+                            </form>
+                        ) : step === 'code' ? (
+                            <form onSubmit={handleSignIn} className="space-y-3">
+                                <Label htmlFor="account-code">Verification Code</Label>
+                                <Input
+                                    id="account-code"
+                                    placeholder="123456"
+                                    value={code}
+                                    onChange={(e) => setCode(e.target.value)}
+                                    required
+                                />
+                                <Button type="submit" className="w-full" disabled={isLoading}>
+                                    {isLoading && <Loader2 className="mr-2 h-4 w-4 animate-spin" />}
+                                    Sign In
+                                </Button>
+                            </form>
+                        ) : (
+                            <form onSubmit={handleCheckPassword} className="space-y-3">
+                                <Label htmlFor="account-password">Two-Step Verification Password</Label>
+                                <Input
+                                    id="account-password"
+                                    type="password"
+                                    value={password}
+                                    onChange={(e) => setPassword(e.target.value)}
+                                    required
+                                />
+                                {passwordHint && (
+                                    <p className="text-xs text-muted-foreground">Hint: {passwordHint}</p>
+                                )}
+                                <Button type="submit" className="w-full" disabled={isLoading}>
+                                    {isLoading && <Loader2 className="mr-2 h-4 w-4 animate-spin" />}
+                                    Sign In
+                                </Button>
+                            </form>
+                        )}
+                        <Button variant="outline" className="w-full" onClick={() => setLoginAccount(null)}>
+                            Cancel
+                        </Button>
+                    </div>
+                )}
+
+                <form onSubmit={handleAdd} className="flex gap-2">
+                    <Input
+                        placeholder="Label, e.g. Work phone"
+                        value={newLabel}
+                        onChange={(e) => setNewLabel(e.target.value)}
+                        required
+                    />
+                    <Button type="submit" variant="outline">Add Account</Button>
+                </form>
+
+                <div className="space-y-2">
+                    <Label>Upload Placement</Label>
+                    <Select
+                        value={placement.mode}
+                        onValueChange={(mode) => savePlacement({ ...placement, mode: mode as Placement })}
+                    >
+                        <SelectTrigger>
+                            <SelectValue />
+                        </SelectTrigger>
+                        <SelectContent>
+                            {(Object.keys(PLACEMENT_LABELS) as Placement[]).map((mode) => (
+                                <SelectItem key={mode} value={mode}>{PLACEMENT_LABELS[mode]}</SelectItem>
+                            ))}
+                        </SelectContent>
+                    </Select>
+                    {placement.mode === 'album' && (
+                        <p className="text-xs text-muted-foreground">
+                            Assign accounts from an album's menu. Items outside assigned albums go to the primary account.
+                        </p>
+                    )}
+                </div>
+
+                {placement.mode === 'mirror' && (
+                    <div className="space-y-2">
+                        <Label>Mirror Account</Label>
+                        <Select
+                            value={placement.mirrorAccountId?.toString() ?? "any"}
+                            onValueChange={(value) =>
+                                savePlacement({ ...placement, mirrorAccountId: value === "any" ? null : Number(value) })
+                            }
+                        >
+                            <SelectTrigger>
+                                <SelectValue />
+                            </SelectTrigger>
+                            <SelectContent>
+                                <SelectItem value="any">First other account</SelectItem>
+                                {others.map((account) => (
+                                    <SelectItem key={account.id} value={account.id.toString()}>{account.label}</SelectItem>
+                                ))}
+                            </SelectContent>
+                        </Select>
+                    </div>
+                )}
+            </CardContent>
+        </Card>
+    );
+}
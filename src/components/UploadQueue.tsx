@@ -1,6 +1,6 @@
 import { useState, useEffect, useCallback } from "react";
 import { api } from "@/lib/api";
-import { QueueItem, UploadEvent, UploadProgressEvent, QueueCounts, RateLimitEvent } from "@/types";
+import { QueueItem, UploadEvent, UploadProgressEvent, QueueCounts, RateLimitEvent, DedupStats } from "@/types";
 import { listen } from "@tauri-apps/api/event";
 import { Progress } from "@/components/ui/progress";
 import { Badge } from "@/components/ui/badge";
@@ -29,15 +29,18 @@ export function UploadQueue() {
     const [currentProgress, setCurrentProgress] = useState<UploadProgressEvent | null>(null);
     const [rateLimitCountdown, setRateLimitCountdown] = useState<number | null>(null);
     const [rateLimitFile, setRateLimitFile] = useState<string | null>(null);
+    const [dedupStats, setDedupStats] = useState<DedupStats | null>(null);
 
     const loadData = useCallback(async () => {
         try {
-            const [queueItems, queueCounts] = await Promise.all([
+            const [queueItems, queueCounts, stats] = await Promise.all([
                 api.getUploadQueue(),
                 api.getQueueCounts(),
+                api.getDedupStats(),
             ]);
             setItems(queueItems);
             setCounts(queueCounts);
+            setDedupStats(stats);
         } catch (e) {
             console.error("Failed to load queue:", e);
         } finally {
@@ -167,6 +170,12 @@ export function UploadQueue() {
                                     ? `${counts.failed} failed`
                                     : "All uploads complete"}
                         </p>
+                        {dedupStats && dedupStats.items > 0 && (
+                            <p className="text-xs text-muted-foreground">
+                                {dedupStats.items} already in storage,{" "}
+                                {(dedupStats.savedBytes / (1024 * 1024)).toFixed(1)} MB not re-uploaded
+                            </p>
+                        )}
                     </div>
                 </div>
                 <Button variant="ghost" size="icon" onClick={loadData}>
@@ -1,5 +1,5 @@
 import { invoke } from "@tauri-apps/api/core";
-import { MediaItem, Album, QueueItem, Face, QueueCounts, SearchFilters, Tag, Person } from "../types";
+import { MediaItem, Album, QueueItem, Face, QueueCounts, SearchFilters, Tag, Person, DestinationPeer, RecoveryReport, StorageSettings, AuditReport, AuditRepair, UploadControls, SignInOutcome, QrLoginStep, TelegramAccount, PlacementSettings, ProxySettings, ConnectionTestResult, DedupStats, KeyRotationStatus } from "../types";
 
 export const api = {
     getSecurityStatus: async (): Promise<{
@@ -8,6 +8,10 @@ export const api = {
         encryptionConfigured: boolean;
         encryptionLocked: boolean;
         telegramCredentialsConfigured: boolean;
+        secretStoreKind?: string | null;
+        secretStoreLocked: boolean;
+        secretStoreCreated: boolean;
+        localEncryption: boolean;
         migration: {
             running: boolean;
             total: number;
@@ -56,10 +60,18 @@ export const api = {
         return await invoke("clear_telegram_api_credentials");
     },
 
+    unlockSecretStore: async (passphrase: string): Promise<void> => {
+        return await invoke("unlock_secret_store", { passphrase });
+    },
+
     startEncryptionMigration: async (): Promise<void> => {
         return await invoke("start_encryption_migration");
     },
 
+    setLocalEncryption: async (enabled: boolean): Promise<void> => {
+        return await invoke("set_local_encryption", { enabled });
+    },
+
     getEncryptionMigrationStatus: async (): Promise<{
         running: boolean;
         total: number;
@@ -71,6 +83,18 @@ export const api = {
         return await invoke("get_encryption_migration_status");
     },
 
+    startKeyRotation: async (passphrase: string): Promise<{ recoveryKey: string }> => {
+        return await invoke("start_key_rotation", { passphrase });
+    },
+
+    resumeKeyRotation: async (): Promise<void> => {
+        return await invoke("resume_key_rotation");
+    },
+
+    getKeyRotationStatus: async (): Promise<KeyRotationStatus> => {
+        return await invoke("get_key_rotation_status");
+    },
+
     getMe: async (): Promise<string> => {
         return await invoke("get_me");
     },
@@ -103,12 +127,89 @@ export const api = {
         return await invoke("get_album_media", { albumId, limit, offset });
     },
 
-    loginRequestCode: async (phone: string): Promise<void> => {
-        return await invoke("login_request_code", { phone });
+    /** Login commands act on the primary account unless `accountId` is given */
+    loginRequestCode: async (phone: string, accountId?: number): Promise<void> => {
+        return await invoke("login_request_code", { phone, accountId });
+    },
+
+    /** Rejects with a `LoginError` */
+    loginSignIn: async (code: string, accountId?: number): Promise<SignInOutcome> => {
+        return await invoke("login_sign_in", { code, accountId });
+    },
+
+    /** Rejects with a `LoginError` */
+    loginCheckPassword: async (password: string, accountId?: number): Promise<string> => {
+        return await invoke("login_check_password", { password, accountId });
+    },
+
+    /** Rejects with a `LoginError` */
+    loginQrStart: async (accountId?: number): Promise<QrLoginStep> => {
+        return await invoke("login_qr_start", { accountId });
+    },
+
+    /** Resolves when the QR code is accepted or refreshed. Rejects with a `LoginError` */
+    loginQrWait: async (accountId?: number): Promise<QrLoginStep> => {
+        return await invoke("login_qr_wait", { accountId });
+    },
+
+    listTelegramAccounts: async (): Promise<TelegramAccount[]> => {
+        return await invoke("list_telegram_accounts");
+    },
+
+    /** Returns the new account's id; log it in with the login calls */
+    addTelegramAccount: async (label: string): Promise<number> => {
+        return await invoke("add_telegram_account", { label });
+    },
+
+    removeTelegramAccount: async (accountId: number): Promise<void> => {
+        return await invoke("remove_telegram_account", { accountId });
+    },
+
+    getAccountPlacement: async (): Promise<PlacementSettings> => {
+        return await invoke("get_account_placement");
+    },
+
+    setAccountPlacement: async (settings: PlacementSettings): Promise<void> => {
+        return await invoke("set_account_placement", { settings });
+    },
+
+    /** `null` clears the assignment */
+    setAlbumAccount: async (albumId: number, accountId: number | null): Promise<void> => {
+        return await invoke("set_album_account", { albumId, accountId });
+    },
+
+    getProxySettings: async (): Promise<ProxySettings> => {
+        return await invoke("get_proxy_settings");
     },
 
-    loginSignIn: async (code: string): Promise<string> => {
-        return await invoke("login_sign_in", { code });
+    /** Saves and reconnects connected accounts through the proxy */
+    setProxySettings: async (settings: ProxySettings): Promise<void> => {
+        return await invoke("set_proxy_settings", { settings });
+    },
+
+    /** Tests `settings`, or the saved settings when omitted */
+    testTelegramConnection: async (settings?: ProxySettings): Promise<ConnectionTestResult> => {
+        return await invoke("test_telegram_connection", { settings });
+    },
+
+    listDestinationPeers: async (): Promise<DestinationPeer[]> => {
+        return await invoke("list_destination_peers");
+    },
+
+    getUploadDestination: async (): Promise<{ peerId: number | null; name: string | null }> => {
+        return await invoke("get_upload_destination");
+    },
+
+    setUploadDestination: async (peerId: number | null, force: boolean): Promise<void> => {
+        return await invoke("set_upload_destination", { peerId, force });
+    },
+
+    getStorageSettings: async (): Promise<StorageSettings> => {
+        return await invoke("get_storage_settings");
+    },
+
+    setStorageSettings: async (settings: StorageSettings, force: boolean): Promise<void> => {
+        return await invoke("set_storage_settings", { settings, force });
     },
 
     logout: async (): Promise<void> => {
@@ -166,10 +267,50 @@ export const api = {
         return await invoke("get_queue_counts");
     },
 
+    getDedupStats: async (): Promise<DedupStats> => {
+        return await invoke("get_dedup_stats");
+    },
+
     retryUpload: async (id: number): Promise<void> => {
         return await invoke("retry_upload", { id });
     },
 
+    retryAllFailedUploads: async (): Promise<number> => {
+        return await invoke("retry_all_failed_uploads");
+    },
+
+    clearFinishedUploads: async (): Promise<number> => {
+        return await invoke("clear_finished_uploads");
+    },
+
+    prioritizeUpload: async (id: number): Promise<void> => {
+        return await invoke("prioritize_upload", { id });
+    },
+
+    pauseUpload: async (id: number): Promise<void> => {
+        return await invoke("pause_upload", { id });
+    },
+
+    resumeUpload: async (id: number): Promise<void> => {
+        return await invoke("resume_upload", { id });
+    },
+
+    cancelUpload: async (id: number): Promise<void> => {
+        return await invoke("cancel_upload", { id });
+    },
+
+    getUploadControls: async (): Promise<UploadControls> => {
+        return await invoke("get_upload_controls");
+    },
+
+    setUploadControls: async (controls: UploadControls): Promise<void> => {
+        return await invoke("set_upload_controls", { controls });
+    },
+
+    setUploadsPaused: async (paused: boolean): Promise<void> => {
+        return await invoke("set_uploads_paused", { paused });
+    },
+
     // Phase 5: Bulk Operations
     bulkSetFavorite: async (mediaIds: number[], isFavorite: boolean): Promise<number> => {
         return await invoke("bulk_set_favorite", { mediaIds, isFavorite });
@@ -228,6 +369,8 @@ export const api = {
         invoke<void>("unarchive_media", { mediaId }),
     getArchivedMedia: (limit: number, offset: number) =>
         invoke<MediaItem[]>("get_archived_media", { limit, offset }),
+    getCloudMissingMedia: () =>
+        invoke<MediaItem[]>("get_cloud_missing_media"),
     // Permanent Delete
     permanentDeleteMedia: (mediaId: number, deleteFromTelegram: boolean) =>
         invoke<void>("permanent_delete_media", { mediaId, deleteFromTelegram }),
@@ -238,6 +381,12 @@ export const api = {
         invoke<string>("get_backup_path"),
     backupDatabase: (destination?: string, uploadToTelegram?: boolean) =>
         invoke<string>("backup_database", { destination, uploadToTelegram: uploadToTelegram ?? false }),
+    recoverLibraryFromTelegram: (backupPath?: string, identifyUnknown?: boolean) =>
+        invoke<RecoveryReport>("recover_library_from_telegram", { backupPath, identifyUnknown }),
+    runCloudAudit: (verifyHeaders?: boolean) =>
+        invoke<AuditReport>("run_cloud_audit", { verifyHeaders }),
+    repairCloudAuditIssue: (repair: AuditRepair) =>
+        invoke<void>("repair_cloud_audit_issue", { repair }),
     // Cloud-Only Mode
     removeLocalCopy: (mediaId: number) =>
         invoke<void>("remove_local_copy", { mediaId }),
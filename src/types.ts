@@ -31,15 +31,18 @@ export interface Album {
     name: string;
     created_at: number;
     cover_path?: string;
+    /** Storage account for the album's uploads in album placement mode */
+    account_id?: number | null;
 }
 
 export interface QueueItem {
     id: number;
     file_path: string;
-    status: string; // 'pending', 'uploading', 'completed', 'failed'
+    status: string; // 'pending', 'uploading', 'rate_limited', 'paused', 'completed', 'failed', 'cancelled'
     retries: number;
     error_msg?: string;
     added_at: number;
+    priority: number;
 }
 
 export interface Face {
@@ -54,12 +57,42 @@ export interface QueueCounts {
     pending: number;
     uploading: number;
     failed: number;
+    paused: number;
+    upload_state: 'running' | 'paused' | 'outside_schedule';
+    bandwidth_kbps: number;
+}
+
+export interface DedupStats {
+    items: number;
+    savedBytes: number;
+}
+
+export interface KeyRotationStatus {
+    keyGeneration: number;
+    /** The old key is kept until everything is re-encrypted */
+    pending: boolean;
+    progress: {
+        running: boolean;
+        total: number;
+        processed: number;
+        succeeded: number;
+        failed: number;
+        lastError?: string | null;
+    };
+}
+
+export interface UploadControls {
+    paused: boolean;
+    /** KiB/s, 0 = unlimited */
+    bandwidthKbps: number;
+    /** Comma-separated "HH:MM-HH:MM" windows; empty = any time */
+    schedule: string;
 }
 
 export interface UploadEvent {
     id: number;
     filePath: string;
-    status: 'uploading' | 'completed' | 'failed';
+    status: 'uploading' | 'completed' | 'failed' | 'paused' | 'cancelled';
     error?: string;
 }
 
@@ -101,3 +134,139 @@ export interface Person {
     face_count: number;
     cover_path: string | null;
 }
+
+export interface DestinationPeer {
+    id: number;
+    name: string;
+    kind: 'channel' | 'group';
+}
+
+export interface StorageSettings {
+    backend: 'telegram' | 'local' | 's3';
+    localDir?: string | null;
+    s3Endpoint?: string | null;
+    s3Region?: string | null;
+    s3Bucket?: string | null;
+    s3Prefix?: string | null;
+    s3AccessKey?: string | null;
+    /** Write-only: never returned; leave empty to keep the stored secret */
+    s3SecretKey?: string | null;
+}
+
+export interface RecoveryReport {
+    scannedMessages: number;
+    restored: number;
+    linked: number;
+    unidentified: number;
+    incomplete: number;
+    failed: number;
+    backupMessageId: number | null;
+    lastError: string | null;
+}
+
+export interface RecoveryProgress {
+    phase: 'scanning' | 'restoring_backup' | 'rebuilding' | 'done';
+    scannedMessages: number;
+    processed: number;
+    total: number;
+}
+
+export interface StoredObject {
+    id: number;
+    fileName: string | null;
+    mimeType: string | null;
+    size: number;
+    caption: string;
+    uploadedAt: number;
+}
+
+export type AuditRepair =
+    | { action: 'requeue'; mediaId: number; filePath: string; staleIds: number[] }
+    | { action: 'import'; object: StoredObject; parts: number[] }
+    | { action: 'fix_flag'; mediaId: number; encrypted: boolean };
+
+export interface AuditIssue {
+    kind: 'missing_in_cloud' | 'orphan' | 'encryption_mismatch' | 'size_mismatch';
+    mediaId: number | null;
+    filePath: string | null;
+    objectIds: number[];
+    detail: string;
+    repair: AuditRepair | null;
+}
+
+export interface AuditReport {
+    scannedObjects: number;
+    checkedItems: number;
+    unidentified: number;
+    issues: AuditIssue[];
+}
+
+export type SignInOutcome =
+    | { step: 'signedIn'; name: string }
+    | { step: 'passwordRequired'; hint: string | null };
+
+export interface LoginError {
+    kind:
+        | 'not_connected'
+        | 'no_pending_login'
+        | 'invalid_code'
+        | 'code_expired'
+        | 'invalid_password'
+        | 'sign_up_required'
+        | 'flood_wait'
+        | 'other';
+    message: string;
+    retryAfterSecs?: number;
+}
+
+export type QrLoginStep =
+    | { step: 'token'; url: string; svg: string; expires: number }
+    | { step: 'signedIn'; name: string }
+    | { step: 'passwordRequired'; hint: string | null };
+
+
+export interface TelegramAccount {
+    id: number;
+    label: string;
+    /** Signed-in user; null until the account is logged in */
+    name: string | null;
+    isPrimary: boolean;
+    /** Items with a main or mirror copy on this account */
+    mediaCount: number;
+}
+
+export type Placement = 'primary' | 'round_robin' | 'album' | 'mirror';
+
+export interface PlacementSettings {
+    mode: Placement;
+    /** Account receiving mirror copies; any other account when null */
+    mirrorAccountId: number | null;
+}
+
+export type ProxyKind = 'none' | 'socks5' | 'mtproxy';
+
+export interface ProxySettings {
+    kind: ProxyKind;
+    host: string | null;
+    port: number | null;
+    username: string | null;
+    /** Never returned; leave empty to keep the saved one */
+    password: string | null;
+    /** MTProxy secret in hex. Never returned; leave empty to keep the saved one */
+    secret: string | null;
+}
+
+export interface ConnectionTestResult {
+    ok: boolean;
+    latencyMs: number | null;
+    failure:
+        | 'invalid_settings'
+        | 'proxy_unreachable'
+        | 'proxy_auth_failed'
+        | 'proxy_rejected'
+        | 'telegram_unreachable'
+        | 'handshake_failed'
+        | 'timeout'
+        | null;
+    message: string | null;
+}
\ No newline at end of file